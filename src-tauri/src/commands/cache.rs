@@ -1,11 +1,32 @@
+use crate::utils::encode::{PreviewFormat, PreviewProfile};
+use crate::utils::format;
+use crate::utils::limits::TOO_LARGE;
+use crate::utils::metadata::ImageMetadata;
+use crate::utils::preview::{PreviewBox, DEFAULT_THUMB_SIZE};
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize)]
+mod store;
+
+use store::CacheStore;
+
+/// Bar thumbnail entry, stored with its image (plain JPEG or WebP bytes) in
+/// one row of the cache store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheEntry {
+    /// Empty when the image is stored, "error" for a source that failed to
+    /// decode, or "too_large" for one over the decode limits (kept past the
+    /// TTL, with its size recorded). `set_cached_thumbnail` payloads and
+    /// entries from before the binary image carry it here as base64;
+    /// storing or importing such an entry moves it out.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub thumbnail: String,
     pub created: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -19,6 +40,14 @@ pub struct CacheEntry {
     pub source_mtime: Option<u64>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub source_size: Option<u64>,
+    /// Perceptual hash from the same decode (`utils::phash`); missing on
+    /// "error" entries, frontend-supplied ones and older entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dhash: Option<u64>,
+    /// The thumbnail came from the source's Exif thumbnail rather than a full
+    /// decode; the next full decode replaces it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub provisional: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -28,16 +57,145 @@ pub struct PreviewSidecar {
     pub source_mtime: u64,
     pub source_size: u64,
     pub created: u64,
+    /// Encoding of the preview bytes; sidecars from before profiles are JPEG.
+    #[serde(default)]
+    pub format: PreviewFormat,
+}
+
+/// Parsed embedded metadata for one source, keyed by its path and
+/// invalidated by the same source stamp as thumbnails and previews.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MetadataEntry {
+    pub metadata: ImageMetadata,
+    pub created: u64,
+    pub source_mtime: u64,
+    pub source_size: u64,
 }
 
+/// Default [`CacheLimits::max_age_secs`]: entries not read for a day go.
 pub const CACHE_DURATION: u64 = 24 * 60 * 60;
 /// D3: previews are ~0.3-1.5 MB each; cap the total so a 900-image folder on a
-/// 4K box cannot grow unbounded.
+/// 4K box cannot grow unbounded. The default [`CacheLimits::max_preview_bytes`].
 pub const PREVIEW_CACHE_CAP_BYTES: u64 = 2 * 1024 * 1024 * 1024;
-/// `write_atomic`'s temp files only ever live for milliseconds; anything
-/// still around this long is orphaned (crash mid-write) and invisible to
-/// `stats`, so `sweep` reclaims it.
-const STALE_TMP_AGE_SECS: u64 = 60 * 60;
+/// Default [`CacheLimits::max_bytes`]: previews plus thumbnails and metadata.
+pub const CACHE_CAP_BYTES: u64 = 3 * 1024 * 1024 * 1024;
+/// An "error" entry is retried this long after it was written, however often
+/// it is looked up in between.
+const ERROR_RETRY_SECS: u64 = 24 * 60 * 60;
+/// How often the background maintenance task sweeps the cache.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Thumbnail size the cache commands fall back to when the caller sends none.
+const COMMAND_THUMB_SIZE: u32 = 30;
+/// The thumbnail sizes the app asks for; the only sizes the `/thumb/` route
+/// serves, so every cached thumbnail is reachable by `invalidate_source`.
+pub const THUMBNAIL_SIZES: [u32; 2] = [DEFAULT_THUMB_SIZE, COMMAND_THUMB_SIZE];
+/// Version of the cache's key derivation and storage layout, recorded in
+/// [`FORMAT_FILE`]. Bump it with any change that leaves existing entries
+/// unreachable or unreadable: [`ensure_format`] then purges the old cache
+/// once instead of letting it sit orphaned until the TTL.
+pub const CACHE_FORMAT_VERSION: u32 = 3;
+/// The version whose loose `.json`/`.thumb`/`_p.jpg` files [`ensure_format`]
+/// imports into the store rather than purging.
+const LOOSE_FILES_VERSION: &str = "2";
+/// Marker file holding [`CACHE_FORMAT_VERSION`]; absent before version 2,
+/// whose keys came from `DefaultHasher`.
+const FORMAT_FILE: &str = "cache-format";
+/// The limits last set through `set_cache_limits`, kept next to the store so
+/// they outlive the session; [`ensure_format`] leaves it alone.
+const LIMITS_FILE: &str = "cache-limits.json";
+
+/// Eviction limits: entries not read for `max_age_secs` go, then the least
+/// recently read previews beyond `max_preview_bytes`, then the least recently
+/// read entries of any kind beyond `max_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheLimits {
+    pub max_bytes: u64,
+    pub max_preview_bytes: u64,
+    pub max_age_secs: u64,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: CACHE_CAP_BYTES,
+            max_preview_bytes: PREVIEW_CACHE_CAP_BYTES,
+            max_age_secs: CACHE_DURATION,
+        }
+    }
+}
+
+impl CacheLimits {
+    /// The defaults, overridden by `SPICA_CACHE_MAX_MB`,
+    /// `SPICA_CACHE_PREVIEW_MB` and `SPICA_CACHE_MAX_AGE_HOURS`.
+    fn from_env() -> Self {
+        let var = |name: &str| -> Option<u64> {
+            let value = std::env::var(name).ok()?;
+            match value.trim().parse::<u64>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    eprintln!("cache: ignoring {name}={value:?}");
+                    None
+                }
+            }
+        };
+        let defaults = Self::default();
+        let mb = |n: u64| n.saturating_mul(1024 * 1024);
+        Self {
+            max_bytes: var("SPICA_CACHE_MAX_MB").map_or(defaults.max_bytes, mb),
+            max_preview_bytes: var("SPICA_CACHE_PREVIEW_MB").map_or(defaults.max_preview_bytes, mb),
+            max_age_secs: var("SPICA_CACHE_MAX_AGE_HOURS")
+                .map_or(defaults.max_age_secs, |h| h.saturating_mul(60 * 60)),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_bytes == 0 || self.max_preview_bytes == 0 || self.max_age_secs == 0 {
+            return Err(format!("Cache limits must be positive: {self:?}"));
+        }
+        Ok(())
+    }
+}
+
+/// The limits saved in `cache_dir`, or `fallback` when none (or unreadable
+/// ones) are.
+fn load_limits(cache_dir: &Path, fallback: CacheLimits) -> CacheLimits {
+    let Ok(bytes) = fs::read(cache_dir.join(LIMITS_FILE)) else {
+        return fallback;
+    };
+    match serde_json::from_slice::<CacheLimits>(&bytes) {
+        Ok(limits) if limits.validate().is_ok() => limits,
+        _ => {
+            eprintln!("cache: ignoring unreadable {LIMITS_FILE}");
+            fallback
+        }
+    }
+}
+
+fn save_limits(cache_dir: &Path, limits: &CacheLimits) -> Result<(), String> {
+    let json =
+        serde_json::to_vec(limits).map_err(|e| format!("Failed to encode cache limits: {e}"))?;
+    write_atomic(&cache_dir.join(LIMITS_FILE), &json)
+        .map_err(|e| format!("Failed to save cache limits: {e}"))
+}
+
+/// The process-wide limits: those last saved by `set_cache_limits`, else the
+/// [`CacheLimits::from_env`] ones.
+fn limits_cell() -> &'static RwLock<CacheLimits> {
+    static LIMITS: std::sync::OnceLock<RwLock<CacheLimits>> = std::sync::OnceLock::new();
+    LIMITS.get_or_init(|| {
+        let env = CacheLimits::from_env();
+        RwLock::new(match get_cache_dir() {
+            Ok(cache_dir) => load_limits(&cache_dir, env),
+            Err(_) => env,
+        })
+    })
+}
+
+/// The limits in force now; read afresh by every sweep, so a change through
+/// `set_cache_limits` applies from the next maintenance cycle on.
+pub fn cache_limits() -> CacheLimits {
+    *limits_cell().read().unwrap_or_else(PoisonError::into_inner)
+}
 
 pub(crate) fn get_cache_dir() -> Result<PathBuf, String> {
     let cache_dir = if cfg!(target_os = "windows") {
@@ -71,39 +229,94 @@ pub(crate) fn get_cache_dir() -> Result<PathBuf, String> {
             .map_err(|e| format!("Failed to create cache directory: {}", e))?;
     }
 
+    static FORMAT_CHECKED: std::sync::Once = std::sync::Once::new();
+    FORMAT_CHECKED.call_once(|| {
+        if let Err(e) = ensure_format(&cache_dir) {
+            eprintln!("cache: {e}");
+        }
+    });
+
     Ok(cache_dir)
 }
 
-fn hash_key(parts: &[&str]) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    let mut hasher = DefaultHasher::new();
-    for p in parts {
-        p.hash(&mut hasher);
+/// Brings a cache written under another [`CACHE_FORMAT_VERSION`] (or none)
+/// up to the current one: a version 2 cache is imported into the store, so
+/// an upgrade does not regenerate every preview; anything older is dropped.
+/// The loose files are removed either way. Returns the number of files
+/// removed.
+pub fn ensure_format(cache_dir: &Path) -> Result<usize, String> {
+    let marker = cache_dir.join(FORMAT_FILE);
+    let found = fs::read_to_string(&marker).unwrap_or_default();
+    let current = CACHE_FORMAT_VERSION.to_string();
+    if found.trim() == current {
+        return Ok(0);
+    }
+    if found.trim() == LOOSE_FILES_VERSION {
+        store::shared(cache_dir)?
+            .write()
+            .import_loose_files(cache_dir)?;
+    }
+    let entries =
+        fs::read_dir(cache_dir).map_err(|e| format!("Failed to read cache directory: {e}"))?;
+    let mut removed = 0usize;
+    for entry in entries.flatten() {
+        let p = entry.path();
+        let name = entry.file_name();
+        let is_kept =
+            p == marker || name == LIMITS_FILE || store::is_store_file(&name.to_string_lossy());
+        if !is_kept && p.is_file() && fs::remove_file(&p).is_ok() {
+            removed += 1;
+        }
     }
-    format!("{:x}", hasher.finish())
+    write_atomic(&marker, current.as_bytes())
+        .map_err(|e| format!("Failed to write cache format marker: {e}"))?;
+    Ok(removed)
 }
 
-fn get_cache_key(path: &str, size: u32) -> String {
-    // Keep the legacy (path, size) hashing so existing entries stay addressable.
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    let mut hasher = DefaultHasher::new();
-    path.hash(&mut hasher);
-    size.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
+/// The path as it is hashed: on Windows, whose file systems ignore case,
+/// separators are unified and case folded so every spelling of one file
+/// shares its cache entries.
+fn normalized_path(path: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        Cow::Owned(path.replace('/', "\\").to_lowercase())
+    } else {
+        Cow::Borrowed(path)
+    }
 }
 
-fn json_file(cache_dir: &Path, path: &str, size: u32) -> PathBuf {
-    cache_dir.join(format!("{}.json", get_cache_key(path, size)))
+/// Key over `parts`: the first 64 bits of the SHA-256 of the parts joined
+/// by NUL (which no path or parameter contains), as 16 hex digits. Unlike
+/// `DefaultHasher`'s, the value is fixed by the algorithm, so keys survive
+/// toolchain upgrades.
+fn hash_key(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            hasher.update([0u8]);
+        }
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
 }
 
-pub fn preview_file(cache_dir: &Path, path: &str, box_key: &str) -> PathBuf {
-    cache_dir.join(format!("{}_p.jpg", hash_key(&[path, box_key])))
+/// Key shared by every preview, tile and metadata entry of `path`.
+fn path_key(path: &str) -> String {
+    hash_key(&[&normalized_path(path)])
 }
 
-fn preview_sidecar_file(cache_dir: &Path, path: &str, box_key: &str) -> PathBuf {
-    cache_dir.join(format!("{}_p.json", hash_key(&[path, box_key])))
+fn get_cache_key(path: &str, size: u32) -> String {
+    hash_key(&[&normalized_path(path), &size.to_string()])
+}
+
+/// Keys of every thumbnail `path` can have, in [`THUMBNAIL_SIZES`] order.
+fn thumbnail_keys(path: &str) -> Vec<String> {
+    THUMBNAIL_SIZES
+        .iter()
+        .map(|&size| get_cache_key(path, size))
+        .collect()
 }
 
 pub fn current_unix_time() -> u64 {
@@ -129,9 +342,9 @@ pub fn source_stamp(path: &Path) -> Option<(u64, u64)> {
 /// Write to a sibling temp file, then rename over the target (atomic on NTFS;
 /// `std::fs::rename` replaces an existing destination on Windows).
 pub fn write_atomic(target: &Path, bytes: &[u8]) -> std::io::Result<()> {
-    // M3: pid+nanos alone can collide when the command path and the protocol
-    // path race to write the same preview within one tick; a process-wide
-    // counter makes every temp name unique regardless of timer resolution.
+    // M3: pid+nanos alone can collide when two writers race for the same
+    // target within one tick; a process-wide counter makes every temp name
+    // unique regardless of timer resolution.
     use std::sync::atomic::{AtomicU64, Ordering};
     static COUNTER: AtomicU64 = AtomicU64::new(0);
     let nanos = SystemTime::now()
@@ -152,14 +365,6 @@ pub fn write_atomic(target: &Path, bytes: &[u8]) -> std::io::Result<()> {
     }
 }
 
-fn is_gif(path: &str) -> bool {
-    Path::new(path)
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.eq_ignore_ascii_case("gif"))
-        .unwrap_or(false)
-}
-
 fn stamp_matches(path: &str, mtime: Option<u64>, size: Option<u64>) -> bool {
     // mtime is compared at whole-second granularity (FAT32: 2 s); a same-second, same-size in-place edit is invisible — acceptable for photo files.
     match (source_stamp(Path::new(path)), mtime, size) {
@@ -168,65 +373,172 @@ fn stamp_matches(path: &str, mtime: Option<u64>, size: Option<u64>) -> bool {
     }
 }
 
-fn read_entry(cache_dir: &Path, path: &str, size: u32) -> Option<CacheEntry> {
-    let file = json_file(cache_dir, path, size);
-    let content = fs::read_to_string(&file).ok()?;
-    let entry: CacheEntry = match serde_json::from_str(&content) {
-        Ok(e) => e,
-        Err(_) => {
-            let _ = fs::remove_file(&file);
-            return None;
-        }
-    };
-    if current_unix_time().saturating_sub(entry.created) > CACHE_DURATION {
-        let _ = fs::remove_file(&file);
+fn read_entry(store: &CacheStore, path: &str, size: u32) -> Option<CacheEntry> {
+    let key = get_cache_key(path, size);
+    let entry = store.thumbnail_entry(&key)?;
+    if entry.thumbnail == "error"
+        && current_unix_time().saturating_sub(entry.created) > ERROR_RETRY_SECS
+    {
+        store.remove_thumbnail(&key);
         return None;
     }
     Some(entry)
 }
 
+fn is_inline_image(thumbnail: &str) -> bool {
+    !thumbnail.is_empty() && !is_failure(thumbnail)
+}
+
+/// "error" or "too_large": the entry records why there is no image.
+fn is_failure(thumbnail: &str) -> bool {
+    thumbnail == "error" || thumbnail == TOO_LARGE
+}
+
+/// Stores `entry`, moving a base64 image in `entry.thumbnail` out to the
+/// binary image first.
 pub fn store_thumbnail_entry(
     cache_dir: &Path,
     path: &str,
     size: u32,
     entry: &CacheEntry,
 ) -> Result<(), String> {
-    let json = serde_json::to_string(entry)
-        .map_err(|e| format!("Failed to serialize cache entry: {e}"))?;
-    write_atomic(&json_file(cache_dir, path, size), json.as_bytes())
-        .map_err(|e| format!("Failed to write cache file: {e}"))
+    if !is_inline_image(&entry.thumbnail) {
+        return store::shared(cache_dir)?.write().put_thumbnail(
+            &get_cache_key(path, size),
+            entry,
+            None,
+        );
+    }
+    let image = general_purpose::STANDARD
+        .decode(&entry.thumbnail)
+        .map_err(|e| format!("Invalid thumbnail data: {e}"))?;
+    let entry = CacheEntry {
+        thumbnail: String::new(),
+        ..entry.clone()
+    };
+    store_thumbnail(cache_dir, path, size, &image, &entry)
+}
+
+/// Stores a generated thumbnail: `image` together with `entry` (whose
+/// `thumbnail` should be empty).
+pub fn store_thumbnail(
+    cache_dir: &Path,
+    path: &str,
+    size: u32,
+    image: &[u8],
+    entry: &CacheEntry,
+) -> Result<(), String> {
+    store::shared(cache_dir)?
+        .write()
+        .put_thumbnail(&get_cache_key(path, size), entry, Some(image))
+}
+
+/// True when the `size` thumbnail of `path` in the cache is a provisional
+/// one for the source as it is now.
+pub fn has_provisional_thumbnail(cache_dir: &Path, path: &str, size: u32) -> bool {
+    let Ok(store) = store::shared(cache_dir).and_then(|store| store.read()) else {
+        return false;
+    };
+    read_entry(&store, path, size)
+        .is_some_and(|e| e.provisional && stamp_matches(path, e.source_mtime, e.source_size))
+}
+
+/// Replaces a provisional thumbnail with `image` from a full decode; a
+/// no-op (`Ok(false)`) when the cached one is not provisional.
+pub fn upgrade_provisional_thumbnail(
+    cache_dir: &Path,
+    path: &str,
+    size: u32,
+    image: &[u8],
+    entry: &CacheEntry,
+) -> Result<bool, String> {
+    let store = store::shared(cache_dir)?.write();
+    if !read_entry(&store, path, size).is_some_and(|e| e.provisional) {
+        return Ok(false);
+    }
+    store.put_thumbnail(&get_cache_key(path, size), entry, Some(image))?;
+    Ok(true)
+}
+
+/// A cached bar thumbnail, as found by [`lookup_thumbnail_image`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CachedThumbnail {
+    Image(Vec<u8>),
+    /// The source failed to decode when this entry was written.
+    Error,
+    /// The source is over the decode limits; it is never retried while
+    /// its stamp holds.
+    TooLarge,
 }
 
 /// Thumbnail lookup honoring I1: with a box requested, the matching preview
-/// (jpg + sidecar, fresh stamp) must be on disk — GIF excepted. Non-error
-/// entries without a source stamp (pre-2026-08 format) count as stale. An
-/// "error" entry is exempt from the stamp check only when it carries no
-/// stamp at all (couldn't stat the source when the error was recorded);
-/// once a stamp is on record it is honored like any other entry, so
-/// replacing a corrupt file with a valid one clears "error" immediately
-/// instead of waiting out the 24h TTL (F3).
-pub fn lookup_thumbnail(
+/// (fresh stamp) must be cached — GIF excepted. Non-error entries without a
+/// source stamp (pre-2026-08 format) count as stale. An "error" entry is
+/// exempt from the stamp check only when it carries no stamp at all
+/// (couldn't stat the source when the error was recorded); once a stamp is
+/// on record it is honored like any other entry, so replacing a corrupt file
+/// with a valid one clears "error" immediately instead of waiting out the
+/// 24h retry (F3). "too_large" entries follow the same rule and never expire.
+/// A hit counts as a read for LRU eviction.
+pub fn lookup_thumbnail_image(
     cache_dir: &Path,
     path: &str,
     size: u32,
     preview_box: Option<&str>,
-) -> Option<(String, Option<u32>, Option<u32>)> {
-    let entry = read_entry(cache_dir, path, size)?;
-    let needs_stamp_check = entry.thumbnail != "error" || entry.source_mtime.is_some();
+) -> Option<(CachedThumbnail, Option<u32>, Option<u32>)> {
+    let store = store::shared(cache_dir).ok()?.read().ok()?;
+    let entry = read_entry(&store, path, size)?;
+    let needs_stamp_check = !is_failure(&entry.thumbnail) || entry.source_mtime.is_some();
     if needs_stamp_check && !stamp_matches(path, entry.source_mtime, entry.source_size) {
         return None;
     }
     if let Some(bk) = preview_box {
-        if !is_gif(path) && entry.thumbnail != "error" {
-            if entry.preview_box.as_deref() != Some(bk) {
+        if !format::is_gif(Path::new(path)) && !is_failure(&entry.thumbnail) {
+            let bbox = PreviewBox::parse(bk)?;
+            if entry.preview_box.as_deref() != Some(bbox.key().as_str()) {
                 return None;
             }
-            // F1: metadata-only — do not read the (0.3-1.5 MB) preview jpg just
+            // F1: metadata-only — do not read the (0.3-1.5 MB) preview just
             // to confirm it exists on this hot thumbnail-bar path.
-            preview_is_fresh(cache_dir, path, bk)?;
+            fresh_sidecar(&store, path, &PreviewProfile::DEFAULT.cache_key(bbox))?;
         }
     }
-    Some((entry.thumbnail, entry.width, entry.height))
+    let key = get_cache_key(path, size);
+    let thumbnail = match entry.thumbnail.as_str() {
+        "error" => CachedThumbnail::Error,
+        TOO_LARGE => CachedThumbnail::TooLarge,
+        _ => CachedThumbnail::Image(store.thumbnail_image(&key)?),
+    };
+    store.touch_thumbnail(&key, current_unix_time());
+    Some((thumbnail, entry.width, entry.height))
+}
+
+/// [`lookup_thumbnail_image`] in the IPC form: the image as base64, "error"
+/// or "too_large".
+pub fn lookup_thumbnail(
+    cache_dir: &Path,
+    path: &str,
+    size: u32,
+    preview_box: Option<&str>,
+) -> Option<(String, Option<u32>, Option<u32>)> {
+    let (thumbnail, width, height) = lookup_thumbnail_image(cache_dir, path, size, preview_box)?;
+    let thumbnail = match thumbnail {
+        CachedThumbnail::Image(bytes) => general_purpose::STANDARD.encode(bytes),
+        CachedThumbnail::Error => "error".to_string(),
+        CachedThumbnail::TooLarge => TOO_LARGE.to_string(),
+    };
+    Some((thumbnail, width, height))
+}
+
+/// The perceptual hash stored with `path`'s bar thumbnail, if that entry is
+/// present and stamped for the current source file.
+pub fn lookup_dhash(cache_dir: &Path, path: &str) -> Option<u64> {
+    let store = store::shared(cache_dir).ok()?.read().ok()?;
+    let entry = read_entry(&store, path, DEFAULT_THUMB_SIZE)?;
+    if !stamp_matches(path, entry.source_mtime, entry.source_size) {
+        return None;
+    }
+    entry.dhash
 }
 
 pub fn store_preview(
@@ -236,34 +548,22 @@ pub fn store_preview(
     jpeg: &[u8],
     sidecar: &PreviewSidecar,
 ) -> Result<(), String> {
-    write_atomic(&preview_file(cache_dir, path, box_key), jpeg)
-        .map_err(|e| format!("Failed to write preview: {e}"))?;
-    let json =
-        serde_json::to_string(sidecar).map_err(|e| format!("Failed to serialize sidecar: {e}"))?;
-    write_atomic(
-        &preview_sidecar_file(cache_dir, path, box_key),
-        json.as_bytes(),
-    )
-    .map_err(|e| format!("Failed to write sidecar: {e}"))
+    store::shared(cache_dir)?
+        .write()
+        .put_preview(&path_key(path), box_key, jpeg, sidecar)
 }
 
-/// Metadata-only freshness check for the preview named `box_key`: parses the
-/// sidecar, confirms its stamp still matches the source file, and confirms
-/// the jpg is on disk — without reading the jpg's bytes (F1). Use this on
-/// hot paths that only need a yes/no answer; use `load_preview` when the
-/// bytes are actually needed.
+/// Metadata-only freshness check for the preview named `box_key`: reads the
+/// sidecar and confirms its stamp still matches the source file — without
+/// reading the preview's bytes (F1). Use this on hot paths that only need a
+/// yes/no answer; use `load_preview` when the bytes are actually needed.
 pub fn preview_is_fresh(cache_dir: &Path, path: &str, box_key: &str) -> Option<PreviewSidecar> {
-    let side: PreviewSidecar = serde_json::from_str(
-        &fs::read_to_string(preview_sidecar_file(cache_dir, path, box_key)).ok()?,
-    )
-    .ok()?;
-    if !stamp_matches(path, Some(side.source_mtime), Some(side.source_size)) {
-        return None;
-    }
-    if !preview_file(cache_dir, path, box_key).is_file() {
-        return None;
-    }
-    Some(side)
+    fresh_sidecar(&*store::shared(cache_dir).ok()?.read().ok()?, path, box_key)
+}
+
+fn fresh_sidecar(store: &CacheStore, path: &str, box_key: &str) -> Option<PreviewSidecar> {
+    let side = store.preview_sidecar(&path_key(path), box_key)?;
+    stamp_matches(path, Some(side.source_mtime), Some(side.source_size)).then_some(side)
 }
 
 pub fn load_preview(
@@ -271,130 +571,114 @@ pub fn load_preview(
     path: &str,
     box_key: &str,
 ) -> Option<(Vec<u8>, PreviewSidecar)> {
-    let side = preview_is_fresh(cache_dir, path, box_key)?;
-    let bytes = fs::read(preview_file(cache_dir, path, box_key)).ok()?;
+    let store = store::shared(cache_dir).ok()?.read().ok()?;
+    let key = path_key(path);
+    let (bytes, side) = store.preview(&key, box_key)?;
+    if !stamp_matches(path, Some(side.source_mtime), Some(side.source_size)) {
+        return None;
+    }
+    store.touch_preview(&key, box_key, current_unix_time());
     Some((bytes, side))
 }
 
-/// Startup housekeeping: age out everything older than `max_age_secs`, then
-/// evict the oldest previews until the preview total is under `cap_bytes`.
-/// Returns the number of removed entries (a preview jpg + its sidecar = 1).
-pub fn sweep(cache_dir: &Path, now_secs: u64, max_age_secs: u64, cap_bytes: u64) -> usize {
-    let Ok(entries) = fs::read_dir(cache_dir) else {
-        return 0;
-    };
-    let mut removed = 0usize;
-    let mut previews: Vec<(PathBuf, u64, u64)> = Vec::new(); // (jpg, mtime, len)
-    for entry in entries.flatten() {
-        let p = entry.path();
-        let name = p
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-            .to_string();
-        if name.contains(".tmp-") {
-            // M4: orphaned `write_atomic` temp file from a crash mid-write —
-            // normally live for milliseconds, so anything this old is dead
-            // and otherwise invisible to `stats`.
-            let mtime = fs::metadata(&p)
-                .ok()
-                .and_then(|m| m.modified().ok())
-                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-            if now_secs.saturating_sub(mtime) > STALE_TMP_AGE_SECS && fs::remove_file(&p).is_ok() {
-                removed += 1;
-            }
-        } else if name.ends_with("_p.jpg") {
-            let meta = match fs::metadata(&p) {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
-            let mtime = meta
-                .modified()
-                .ok()
-                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-            if now_secs.saturating_sub(mtime) > max_age_secs {
-                remove_preview_pair(&p);
-                removed += 1;
-            } else {
-                previews.push((p, mtime, meta.len()));
-            }
-        } else if name.ends_with("_p.json") {
-            // Orphan sidecar (jpg gone) → drop it.
-            let jpg = p.with_file_name(name.replace("_p.json", "_p.jpg"));
-            if !jpg.exists() {
-                let _ = fs::remove_file(&p);
-            }
-        } else if name.ends_with(".json") {
-            match fs::read_to_string(&p)
-                .ok()
-                .and_then(|c| serde_json::from_str::<CacheEntry>(&c).ok())
-            {
-                Some(e) if now_secs.saturating_sub(e.created) <= max_age_secs => {}
-                _ => {
-                    if fs::remove_file(&p).is_ok() {
-                        removed += 1;
-                    }
-                }
-            }
-        }
-    }
-    let mut total: u64 = previews.iter().map(|p| p.2).sum();
-    previews.sort_by_key(|p| p.1); // oldest first
-    for (jpg, _, len) in previews {
-        if total <= cap_bytes {
-            break;
-        }
-        remove_preview_pair(&jpg);
-        removed += 1;
-        total = total.saturating_sub(len);
+/// Cached metadata for `path`, if present and stamped for the current source
+/// file. An unparsable entry is removed like a bad thumbnail.
+pub fn lookup_metadata(cache_dir: &Path, path: &str) -> Option<ImageMetadata> {
+    let store = store::shared(cache_dir).ok()?.read().ok()?;
+    let key = path_key(path);
+    let entry = store.metadata(&key)?;
+    if !stamp_matches(path, Some(entry.source_mtime), Some(entry.source_size)) {
+        return None;
     }
-    removed
+    store.touch_metadata(&key, current_unix_time());
+    Some(entry.metadata)
+}
+
+pub fn store_metadata(cache_dir: &Path, path: &str, entry: &MetadataEntry) -> Result<(), String> {
+    store::shared(cache_dir)?
+        .write()
+        .put_metadata(&path_key(path), entry)
 }
 
-fn remove_preview_pair(jpg: &Path) {
-    let _ = fs::remove_file(jpg);
-    if let Some(name) = jpg.file_name().and_then(|s| s.to_str()) {
-        let _ = fs::remove_file(jpg.with_file_name(name.replace("_p.jpg", "_p.json")));
+/// Removes every thumbnail entry, preview, tile and metadata entry derived
+/// from `path`, for edits that rewrite the source in place: a same-second,
+/// same-size rewrite (an Exif orientation patch) keeps the source stamp.
+/// Thumbnails are keyed by hash, so this covers the sizes the app requests;
+/// anything else ages out as usual. Returns the entries removed.
+pub fn invalidate_source(cache_dir: &Path, path: &str) -> usize {
+    store::shared(cache_dir)
+        .and_then(|store| {
+            store
+                .write()
+                .remove_source(&path_key(path), &thumbnail_keys(path))
+        })
+        .unwrap_or(0)
+}
+
+/// Moves everything cached for `from` to the keys of `to`, for a rename or
+/// move that keeps the file's stamp. Entries `from` never had are left alone
+/// at `to`, so a second call (the folder watcher seeing the same rename)
+/// changes nothing. Returns the entries moved.
+pub fn rekey_source(cache_dir: &Path, from: &str, to: &str) -> usize {
+    store::shared(cache_dir)
+        .and_then(|store| {
+            store.write().move_source(
+                (&path_key(from), &thumbnail_keys(from)),
+                (&path_key(to), &thumbnail_keys(to)),
+            )
+        })
+        .unwrap_or(0)
+}
+
+/// Housekeeping: evicts what `limits` no longer allow, least recently read
+/// first (see [`CacheLimits`]). Returns the number of removed entries.
+pub fn sweep(cache_dir: &Path, now_secs: u64, limits: &CacheLimits) -> usize {
+    let cutoff = now_secs.saturating_sub(limits.max_age_secs);
+    match store::shared(cache_dir)
+        .and_then(|store| store.sweep(cutoff, limits.max_preview_bytes, limits.max_bytes))
+    {
+        Ok(removed) => removed,
+        Err(e) => {
+            eprintln!("cache: sweep failed ({e})");
+            0
+        }
     }
 }
 
-pub fn stats(cache_dir: &Path, now_secs: u64, max_age_secs: u64) -> HashMap<String, u64> {
-    let mut s = HashMap::from([
-        ("total_files".to_string(), 0u64),
-        ("valid_files".to_string(), 0u64),
-        ("preview_files".to_string(), 0u64),
-        ("preview_bytes".to_string(), 0u64),
-    ]);
-    let Ok(entries) = fs::read_dir(cache_dir) else {
-        return s;
-    };
-    for entry in entries.flatten() {
-        let p = entry.path();
-        let name = p
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
-        if name.ends_with("_p.jpg") {
-            *s.get_mut("preview_files").unwrap() += 1;
-            *s.get_mut("preview_bytes").unwrap() += fs::metadata(&p).map(|m| m.len()).unwrap_or(0);
-        } else if name.ends_with(".json") && !name.ends_with("_p.json") {
-            *s.get_mut("total_files").unwrap() += 1;
-            if let Some(e) = fs::read_to_string(&p)
-                .ok()
-                .and_then(|c| serde_json::from_str::<CacheEntry>(&c).ok())
-            {
-                if now_secs.saturating_sub(e.created) <= max_age_secs {
-                    *s.get_mut("valid_files").unwrap() += 1;
+/// Starts the background maintenance task: a [`sweep`] under the
+/// [`cache_limits`] of the moment now and every [`MAINTENANCE_INTERVAL`]
+/// after, so a long session stays within the limits without waiting for the
+/// next startup.
+pub fn start_maintenance() {
+    let spawned = std::thread::Builder::new()
+        .name("cache-maintenance".to_string())
+        .spawn(|| loop {
+            if let Ok(cache_dir) = get_cache_dir() {
+                let removed = sweep(&cache_dir, current_unix_time(), &cache_limits());
+                if removed > 0 {
+                    println!("Cleaned {} old cache entries", removed);
                 }
             }
-        }
+            std::thread::sleep(MAINTENANCE_INTERVAL);
+        });
+    if let Err(e) = spawned {
+        eprintln!("cache: maintenance not started ({e})");
     }
-    s
+}
+
+pub fn stats(cache_dir: &Path, now_secs: u64, max_age_secs: u64) -> HashMap<String, u64> {
+    let cutoff = now_secs.saturating_sub(max_age_secs);
+    let s = store::shared(cache_dir)
+        .and_then(|store| store.read()?.stats(cutoff))
+        .unwrap_or_default();
+    HashMap::from([
+        ("total_files".to_string(), s.thumbnails),
+        ("valid_files".to_string(), s.fresh_thumbnails),
+        ("preview_files".to_string(), s.previews),
+        ("preview_bytes".to_string(), s.preview_bytes),
+        ("metadata_files".to_string(), s.metadata),
+        ("thumbnail_bytes".to_string(), s.thumbnail_bytes),
+    ])
 }
 
 // ---- commands: thin wrappers over the injected-directory functions ----
@@ -409,7 +693,7 @@ pub async fn get_cached_thumbnail(
     Ok(lookup_thumbnail(
         &cache_dir,
         &path,
-        size.unwrap_or(30),
+        size.unwrap_or(COMMAND_THUMB_SIZE),
         preview_box.as_deref(),
     ))
 }
@@ -432,8 +716,15 @@ pub async fn set_cached_thumbnail(
         preview_box: None,
         source_mtime: stamp.map(|s| s.0),
         source_size: stamp.map(|s| s.1),
+        dhash: None,
+        provisional: false,
     };
-    store_thumbnail_entry(&cache_dir, &path, size.unwrap_or(30), &entry)
+    store_thumbnail_entry(
+        &cache_dir,
+        &path,
+        size.unwrap_or(COMMAND_THUMB_SIZE),
+        &entry,
+    )
 }
 
 #[tauri::command]
@@ -442,12 +733,7 @@ pub async fn clear_old_cache() -> Result<(), String> {
         return Ok(());
     };
     let removed = tauri::async_runtime::spawn_blocking(move || {
-        sweep(
-            &cache_dir,
-            current_unix_time(),
-            CACHE_DURATION,
-            PREVIEW_CACHE_CAP_BYTES,
-        )
+        sweep(&cache_dir, current_unix_time(), &cache_limits())
     })
     .await
     .map_err(|e| e.to_string())?;
@@ -455,15 +741,39 @@ pub async fn clear_old_cache() -> Result<(), String> {
     Ok(())
 }
 
+#[tauri::command]
+pub async fn get_cache_limits() -> Result<CacheLimits, String> {
+    Ok(cache_limits())
+}
+
+/// Replaces the eviction limits, saves them for later sessions and sweeps
+/// under them right away, so lowering a cap frees the space now.
+#[tauri::command]
+pub async fn set_cache_limits(limits: CacheLimits) -> Result<(), String> {
+    limits.validate()?;
+    *limits_cell()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = limits;
+    let cache_dir = get_cache_dir()?;
+    let removed = tauri::async_runtime::spawn_blocking(move || {
+        save_limits(&cache_dir, &limits)?;
+        Ok::<_, String>(sweep(&cache_dir, current_unix_time(), &limits))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    println!("Cleaned {} old cache entries", removed);
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_cache_stats() -> Result<HashMap<String, u64>, String> {
     let Ok(cache_dir) = get_cache_dir() else {
         return Ok(HashMap::new());
     };
-    // M6: reads and parses every JSON file in the cache directory — off the
-    // async runtime's core threads, like `clear_old_cache`.
+    // M6: a few aggregate queries now, but still blocking file I/O — off
+    // the async runtime's core threads, like `clear_old_cache`.
     tauri::async_runtime::spawn_blocking(move || {
-        stats(&cache_dir, current_unix_time(), CACHE_DURATION)
+        stats(&cache_dir, current_unix_time(), cache_limits().max_age_secs)
     })
     .await
     .map_err(|e| e.to_string())
@@ -472,6 +782,7 @@ pub async fn get_cache_stats() -> Result<HashMap<String, u64>, String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::TRANSCODE_BOX;
     use crate::test_utils::*;
 
     fn filetime_for_test(secs: u64) -> filetime::FileTime {
@@ -487,6 +798,8 @@ mod tests {
             preview_box: preview_box.map(str::to_string),
             source_mtime: stamp.map(|s| s.0),
             source_size: stamp.map(|s| s.1),
+            dhash: None,
+            provisional: false,
         }
     }
 
@@ -497,9 +810,42 @@ mod tests {
             source_mtime: stamp.0,
             source_size: stamp.1,
             created: current_unix_time(),
+            format: PreviewFormat::Jpeg,
+        }
+    }
+
+    fn open(dir: &Path) -> CacheStore {
+        CacheStore::open(dir).unwrap()
+    }
+
+    fn limits(max_age_secs: u64, max_preview_bytes: u64) -> CacheLimits {
+        CacheLimits {
+            max_bytes: u64::MAX,
+            max_preview_bytes,
+            max_age_secs,
         }
     }
 
+    #[test]
+    fn saved_limits_outlive_a_format_purge_and_bad_ones_fall_back() {
+        let dir = create_temp_dir();
+        let fallback = CacheLimits::default();
+        assert_eq!(load_limits(dir.path(), fallback), fallback);
+        let set = limits(60 * 60, 512);
+        save_limits(dir.path(), &set).unwrap();
+        assert_eq!(load_limits(dir.path(), fallback), set);
+        assert_eq!(ensure_format(dir.path()).unwrap(), 0);
+        assert_eq!(load_limits(dir.path(), fallback), set);
+
+        assert!(limits(0, 512).validate().is_err());
+        fs::write(
+            dir.path().join(LIMITS_FILE),
+            br#"{"max_bytes":0,"max_preview_bytes":1,"max_age_secs":1}"#,
+        )
+        .unwrap();
+        assert_eq!(load_limits(dir.path(), fallback), fallback);
+    }
+
     #[test]
     fn cache_key_is_stable_and_distinct_per_path_and_size() {
         assert_eq!(get_cache_key("/a.jpg", 20), get_cache_key("/a.jpg", 20));
@@ -507,6 +853,142 @@ mod tests {
         assert_ne!(get_cache_key("/a.jpg", 20), get_cache_key("/b.jpg", 20));
     }
 
+    #[test]
+    fn hash_key_values_are_pinned() {
+        // SHA-256 prefixes; a change here orphans every cache on disk and
+        // must come with a CACHE_FORMAT_VERSION bump.
+        assert_eq!(hash_key(&[]), "e3b0c44298fc1c14");
+        assert_eq!(hash_key(&["/photos/IMG_0001.jpg"]), "283147787c16492a");
+        assert_eq!(
+            hash_key(&["/photos/IMG_0001.jpg", "20"]),
+            "3964ed392666a29c"
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn cache_keys_hash_the_path_as_is() {
+        assert_eq!(path_key("/photos/IMG_0001.jpg"), "283147787c16492a");
+        assert_eq!(
+            get_cache_key("/photos/IMG_0001.jpg", 20),
+            "3964ed392666a29c"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn cache_keys_ignore_case_and_separator_spelling() {
+        assert_eq!(
+            get_cache_key(r"C:\photos\img_0001.jpg", 20),
+            "4e92ed3d95bcc1a8"
+        );
+        assert_eq!(
+            get_cache_key("c:/Photos/IMG_0001.JPG", 20),
+            "4e92ed3d95bcc1a8"
+        );
+    }
+
+    #[test]
+    fn ensure_format_purges_an_older_cache_once() {
+        let dir = create_temp_dir();
+        // A version 1 cache: DefaultHasher keys, no marker.
+        fs::write(dir.path().join("1a2b3c.json"), b"{}").unwrap();
+        fs::write(dir.path().join("1a2b3c.thumb"), b"jpeg").unwrap();
+        fs::write(dir.path().join("4d5e6f-1920x1080_p.jpg"), b"jpeg").unwrap();
+        assert_eq!(ensure_format(dir.path()).unwrap(), 3);
+        let left: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(left, [FORMAT_FILE]);
+        let p = "/photos/a.jpg";
+        store_thumbnail(dir.path(), p, 20, b"jpeg", &entry(None, None)).unwrap();
+        assert_eq!(ensure_format(dir.path()).unwrap(), 0);
+        assert!(open(dir.path())
+            .thumbnail_entry(&get_cache_key(p, 20))
+            .is_some());
+        // The store itself survives a purge of loose files.
+        fs::write(dir.path().join("1a2b3c.json"), b"{}").unwrap();
+        fs::write(dir.path().join(FORMAT_FILE), b"1").unwrap();
+        assert_eq!(ensure_format(dir.path()).unwrap(), 1);
+        assert_eq!(
+            stats(dir.path(), current_unix_time(), CACHE_DURATION)["total_files"],
+            1
+        );
+    }
+
+    #[test]
+    fn ensure_format_imports_a_loose_file_cache() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let p = img.to_string_lossy().to_string();
+        let stamp = source_stamp(&img).unwrap();
+        let cache = create_temp_dir();
+        let file = |name: String, bytes: &[u8]| fs::write(cache.path().join(name), bytes).unwrap();
+        // A version 2 cache: a binary thumbnail, a pre-binary base64 one,
+        // a preview pair, a metadata entry and an orphaned temp file.
+        let binary = CacheEntry {
+            thumbnail: String::new(),
+            ..entry(Some(stamp), Some("1920x1080"))
+        };
+        file(
+            format!("{}.json", get_cache_key(&p, 20)),
+            serde_json::to_string(&binary).unwrap().as_bytes(),
+        );
+        file(format!("{}.thumb", get_cache_key(&p, 20)), b"thumb");
+        let legacy = entry(Some(stamp), None);
+        file(
+            format!("{}.json", get_cache_key(&p, 30)),
+            serde_json::to_string(&legacy).unwrap().as_bytes(),
+        );
+        file(
+            format!("{}-1920x1080_p.json", path_key(&p)),
+            serde_json::to_string(&sidecar(stamp)).unwrap().as_bytes(),
+        );
+        file(format!("{}-1920x1080_p.jpg", path_key(&p)), b"\xFF\xD8jpeg");
+        let metadata = MetadataEntry {
+            metadata: ImageMetadata::default(),
+            created: current_unix_time(),
+            source_mtime: stamp.0,
+            source_size: stamp.1,
+        };
+        file(
+            format!("{}_m.json", path_key(&p)),
+            serde_json::to_string(&metadata).unwrap().as_bytes(),
+        );
+        file("abc123.json.tmp-1-2-3".to_string(), b"partial");
+        file(FORMAT_FILE.to_string(), b"2");
+
+        assert_eq!(ensure_format(cache.path()).unwrap(), 7);
+        assert_eq!(
+            lookup_thumbnail_image(cache.path(), &p, 20, Some("1920x1080")),
+            Some((
+                CachedThumbnail::Image(b"thumb".to_vec()),
+                Some(800),
+                Some(600)
+            ))
+        );
+        let (b64, ..) = lookup_thumbnail(cache.path(), &p, 30, None).unwrap();
+        assert_eq!(b64, "AAAA");
+        assert_eq!(
+            load_preview(cache.path(), &p, "1920x1080").unwrap().0,
+            b"\xFF\xD8jpeg"
+        );
+        assert_eq!(
+            lookup_metadata(cache.path(), &p),
+            Some(ImageMetadata::default())
+        );
+        let loose = fs::read_dir(cache.path())
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|n| n != FORMAT_FILE && !store::is_store_file(n))
+            .count();
+        assert_eq!(loose, 0);
+        assert_eq!(ensure_format(cache.path()).unwrap(), 0);
+    }
+
     #[test]
     fn write_atomic_leaves_no_temp_file_and_replaces_existing() {
         let dir = create_temp_dir();
@@ -544,13 +1026,13 @@ mod tests {
     }
 
     #[test]
-    fn lookup_with_box_requires_the_preview_files() {
+    fn lookup_with_box_requires_the_preview() {
         let dir = create_temp_dir();
         let img = create_test_jpeg(dir.path(), "a.jpg");
         let p = img.to_string_lossy().to_string();
         let stamp = source_stamp(&img).unwrap();
         store_thumbnail_entry(dir.path(), &p, 20, &entry(Some(stamp), Some("1920x1080"))).unwrap();
-        // Entry claims a preview, but none is on disk → not usable (I1).
+        // Entry claims a preview, but none is cached → not usable (I1).
         assert!(lookup_thumbnail(dir.path(), &p, 20, Some("1920x1080")).is_none());
         store_preview(
             dir.path(),
@@ -568,10 +1050,6 @@ mod tests {
         assert!(lookup_thumbnail(dir.path(), &p, 20, Some("2560x1440")).is_none());
         // Without a box request the thumbnail alone is enough.
         assert!(lookup_thumbnail(dir.path(), &p, 20, None).is_some());
-        // F1: the metadata-only check must catch a deleted jpg (sidecar still
-        // present) without ever reading the jpg's bytes.
-        fs::remove_file(preview_file(dir.path(), &p, "1920x1080")).unwrap();
-        assert!(lookup_thumbnail(dir.path(), &p, 20, Some("1920x1080")).is_none());
     }
 
     #[test]
@@ -606,6 +1084,8 @@ mod tests {
             preview_box: None,
             source_mtime: None,
             source_size: None,
+            dhash: None,
+            provisional: false,
         };
         store_thumbnail_entry(dir.path(), &p, 20, &err_entry).unwrap();
         assert_eq!(
@@ -614,6 +1094,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn too_large_entries_outlive_the_ttl_but_not_the_source() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let p = img.to_string_lossy().to_string();
+        let stamp = source_stamp(&img).unwrap();
+        let too_large = CacheEntry {
+            thumbnail: TOO_LARGE.to_string(),
+            created: 1,
+            width: Some(60000),
+            height: Some(60000),
+            ..entry(Some(stamp), None)
+        };
+        store_thumbnail_entry(dir.path(), &p, 20, &too_large).unwrap();
+        assert_eq!(
+            sweep(
+                dir.path(),
+                current_unix_time(),
+                &limits(60, PREVIEW_CACHE_CAP_BYTES)
+            ),
+            0
+        );
+        let (thumbnail, width, _) =
+            lookup_thumbnail_image(dir.path(), &p, 20, Some("1920x1080")).unwrap();
+        assert_eq!((thumbnail, width), (CachedThumbnail::TooLarge, Some(60000)));
+        fs::write(&img, b"replaced with a smaller image").unwrap();
+        assert!(lookup_thumbnail(dir.path(), &p, 20, None).is_none());
+    }
+
     #[test]
     fn lookup_exempts_gif_from_the_preview_requirement() {
         let dir = create_temp_dir();
@@ -641,8 +1150,10 @@ mod tests {
         let (bytes, side) = load_preview(dir.path(), &p, "1920x1080").unwrap();
         assert_eq!(bytes, b"\xFF\xD8jpeg");
         assert_eq!((side.natural_width, side.natural_height), (800, 600));
+        assert_eq!(preview_is_fresh(dir.path(), &p, "1920x1080"), Some(side));
         fs::write(&img, b"different").unwrap();
         assert!(load_preview(dir.path(), &p, "1920x1080").is_none());
+        assert!(preview_is_fresh(dir.path(), &p, "1920x1080").is_none());
     }
 
     #[test]
@@ -674,48 +1185,179 @@ mod tests {
             .map(|n| touch(n))
             .collect();
         for (i, name) in preview_paths.iter().enumerate() {
-            store_preview(
-                dir.path(),
-                name,
-                "1920x1080",
-                &vec![0u8; 1000],
-                &sidecar((1, 1)),
-            )
-            .unwrap();
-            let f = preview_file(dir.path(), name, "1920x1080");
-            let t = filetime_for_test(now - 1000 + i as u64 * 10);
-            filetime::set_file_mtime(&f, t).unwrap();
+            let side = PreviewSidecar {
+                created: now - 1000 + i as u64 * 10,
+                ..sidecar((1, 1))
+            };
+            store_preview(dir.path(), name, "1920x1080", &vec![0u8; 1000], &side).unwrap();
         }
-        let removed = sweep(dir.path(), now, 24 * 60 * 60, 2500);
-        assert_eq!(
-            removed, 2,
-            "expired json + one preview (jpg+sidecar count as one)"
-        );
+        let removed = sweep(dir.path(), now, &limits(24 * 60 * 60, 2500));
+        assert_eq!(removed, 2, "expired thumbnail + one preview");
         assert!(load_preview(dir.path(), &preview_paths[0], "1920x1080").is_none());
         assert!(load_preview(dir.path(), &preview_paths[2], "1920x1080").is_some());
-        assert!(!json_file(dir.path(), &old_path, 20).exists());
+        assert!(open(dir.path())
+            .thumbnail_entry(&get_cache_key(&old_path, 20))
+            .is_none());
     }
 
     #[test]
-    fn sweep_removes_stale_tmp_files_but_leaves_in_flight_ones() {
+    fn reads_keep_entries_past_the_max_age_and_ahead_in_eviction() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let p = img.to_string_lossy().to_string();
+        let stamp = source_stamp(&img).unwrap();
+        let now = current_unix_time();
+        let two_days_ago = now - 2 * 24 * 60 * 60;
+        let old = CacheEntry {
+            created: two_days_ago,
+            ..entry(Some(stamp), None)
+        };
+        store_thumbnail_entry(dir.path(), &p, 20, &old).unwrap();
+        // `a.jpg`'s preview is older than `b.png`'s but viewed again since.
+        let other = create_test_png(dir.path(), "b.png");
+        let q = other.to_string_lossy().to_string();
+        let previews = [
+            (&p, stamp, two_days_ago),
+            (&q, source_stamp(&other).unwrap(), now - 10 * 60),
+        ];
+        for (path, stamp, created) in previews {
+            let side = PreviewSidecar {
+                created,
+                ..sidecar(stamp)
+            };
+            store_preview(dir.path(), path, "1920x1080", &[0; 1000], &side).unwrap();
+        }
+        assert!(lookup_thumbnail(dir.path(), &p, 20, None).is_some());
+        assert!(load_preview(dir.path(), &p, "1920x1080").is_some());
+
+        assert_eq!(sweep(dir.path(), now, &limits(24 * 60 * 60, 1500)), 1);
+        assert!(lookup_thumbnail(dir.path(), &p, 20, None).is_some());
+        assert!(load_preview(dir.path(), &p, "1920x1080").is_some());
+        assert!(load_preview(dir.path(), &q, "1920x1080").is_none());
+    }
+
+    #[test]
+    fn metadata_entry_roundtrips_and_follows_the_source_stamp() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let p = img.to_string_lossy().to_string();
+        let stamp = source_stamp(&img).unwrap();
+        let metadata = ImageMetadata {
+            icc_profile: Some("sRGB".to_string()),
+            ..ImageMetadata::default()
+        };
+        let e = MetadataEntry {
+            metadata: metadata.clone(),
+            created: current_unix_time(),
+            source_mtime: stamp.0,
+            source_size: stamp.1,
+        };
+        store_metadata(dir.path(), &p, &e).unwrap();
+        assert_eq!(lookup_metadata(dir.path(), &p), Some(metadata));
+        // Distinct from the thumbnail entries for the same source.
+        assert!(lookup_thumbnail(dir.path(), &p, 20, None).is_none());
+        fs::write(&img, b"replaced with different bytes").unwrap();
+        assert!(lookup_metadata(dir.path(), &p).is_none());
+    }
+
+    #[test]
+    fn sweep_ages_out_metadata_entries_instead_of_misreading_them() {
         let dir = create_temp_dir();
         let now = 1_000_000u64;
-        // Orphaned from a crash mid-write — over an hour old.
-        let stale = dir.path().join("abc123.json.tmp-1-2-3");
-        fs::write(&stale, b"partial").unwrap();
-        filetime::set_file_mtime(&stale, filetime_for_test(now - 3700)).unwrap();
-        // A write in progress right now must survive the sweep.
-        let fresh = dir.path().join("def456_p.jpg.tmp-1-2-4");
-        fs::write(&fresh, b"partial").unwrap();
-        filetime::set_file_mtime(&fresh, filetime_for_test(now - 5)).unwrap();
-        let removed = sweep(dir.path(), now, 24 * 60 * 60, PREVIEW_CACHE_CAP_BYTES);
-        assert_eq!(removed, 1);
-        assert!(!stale.exists());
-        assert!(fresh.exists());
+        let e = |created| MetadataEntry {
+            metadata: ImageMetadata::default(),
+            created,
+            source_mtime: 1,
+            source_size: 1,
+        };
+        store_metadata(dir.path(), "/fresh.jpg", &e(now - 10)).unwrap();
+        store_metadata(dir.path(), "/old.jpg", &e(now - 100_000)).unwrap();
+        assert_eq!(
+            sweep(
+                dir.path(),
+                now,
+                &limits(24 * 60 * 60, PREVIEW_CACHE_CAP_BYTES)
+            ),
+            1
+        );
+        assert!(open(dir.path()).metadata(&path_key("/fresh.jpg")).is_some());
+        assert!(open(dir.path()).metadata(&path_key("/old.jpg")).is_none());
+        let s = stats(dir.path(), now, 24 * 60 * 60);
+        assert_eq!(s["metadata_files"], 1);
+        assert_eq!(s["total_files"], 0);
     }
 
     #[test]
-    fn stats_counts_previews_and_bytes() {
+    fn invalidate_source_removes_every_derived_entry_of_that_path_only() {
+        let dir = create_temp_dir();
+        let meta = MetadataEntry {
+            metadata: ImageMetadata::default(),
+            created: current_unix_time(),
+            source_mtime: 1,
+            source_size: 1,
+        };
+        for p in ["/a.jpg", "/b.jpg"] {
+            store_thumbnail_entry(dir.path(), p, 20, &entry(None, None)).unwrap();
+            store_thumbnail_entry(dir.path(), p, 30, &entry(None, None)).unwrap();
+            // Any box and tile is found, not just a fixed list of buckets.
+            for bk in ["1080x1920", "3440x1440", "tile-0-0_0", &TRANSCODE_BOX.key()] {
+                store_preview(dir.path(), p, bk, b"jpg", &sidecar((1, 1))).unwrap();
+            }
+            store_metadata(dir.path(), p, &meta).unwrap();
+        }
+        assert_eq!(invalidate_source(dir.path(), "/a.jpg"), 7);
+        let s = stats(dir.path(), current_unix_time(), CACHE_DURATION);
+        assert_eq!(
+            (s["total_files"], s["preview_files"], s["metadata_files"]),
+            (2, 4, 1)
+        );
+        let store = open(dir.path());
+        assert!(store
+            .thumbnail_image(&get_cache_key("/b.jpg", 20))
+            .is_some());
+        assert!(store.metadata(&path_key("/b.jpg")).is_some());
+        assert_eq!(invalidate_source(dir.path(), "/a.jpg"), 0);
+    }
+
+    #[test]
+    fn rekey_source_moves_entries_and_is_idempotent() {
+        let dir = create_temp_dir();
+        store_thumbnail_entry(dir.path(), "/a.jpg", 20, &entry(None, None)).unwrap();
+        store_preview(dir.path(), "/a.jpg", "1920x1080", b"jpg", &sidecar((1, 1))).unwrap();
+        assert_eq!(rekey_source(dir.path(), "/a.jpg", "/b.jpg"), 2);
+        let store = open(dir.path());
+        assert!(store
+            .thumbnail_entry(&get_cache_key("/a.jpg", 20))
+            .is_none());
+        assert!(store
+            .thumbnail_image(&get_cache_key("/b.jpg", 20))
+            .is_some());
+        assert!(store.preview(&path_key("/b.jpg"), "1920x1080").is_some());
+        assert_eq!(rekey_source(dir.path(), "/a.jpg", "/b.jpg"), 0);
+        let s = stats(dir.path(), current_unix_time(), CACHE_DURATION);
+        assert_eq!((s["total_files"], s["preview_files"]), (1, 1));
+    }
+
+    #[test]
+    fn base64_entry_is_stored_as_a_binary_image() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let p = img.to_string_lossy().to_string();
+        store_thumbnail_entry(dir.path(), &p, 20, &entry(source_stamp(&img), None)).unwrap();
+
+        let (thumbnail, ..) = lookup_thumbnail_image(dir.path(), &p, 20, None).unwrap();
+        assert!(matches!(thumbnail, CachedThumbnail::Image(ref b) if b == &[0, 0, 0]));
+        let stored = open(dir.path())
+            .thumbnail_entry(&get_cache_key(&p, 20))
+            .unwrap();
+        assert!(stored.thumbnail.is_empty());
+        // The base64 form is still what IPC callers get.
+        let (b64, ..) = lookup_thumbnail(dir.path(), &p, 20, None).unwrap();
+        assert_eq!(b64, "AAAA");
+    }
+
+    #[test]
+    fn stats_counts_entries_and_bytes() {
         let dir = create_temp_dir();
         store_preview(
             dir.path(),
@@ -725,8 +1367,17 @@ mod tests {
             &sidecar((1, 1)),
         )
         .unwrap();
+        store_thumbnail(dir.path(), "/p1.jpg", 20, &[0; 300], &entry(None, None)).unwrap();
+        let too_large = CacheEntry {
+            thumbnail: TOO_LARGE.to_string(),
+            created: 1,
+            ..entry(None, None)
+        };
+        store_thumbnail_entry(dir.path(), "/p2.jpg", 20, &too_large).unwrap();
         let s = stats(dir.path(), current_unix_time(), 24 * 60 * 60);
         assert_eq!(s["preview_files"], 1);
         assert_eq!(s["preview_bytes"], 1000);
+        assert_eq!((s["total_files"], s["valid_files"]), (2, 2));
+        assert_eq!(s["thumbnail_bytes"], 300);
     }
 }
@@ -0,0 +1,982 @@
+//! The cache's storage: one SQLite database, [`DB_FILE`], in the cache
+//! directory instead of a file (or two) per entry. Rows are keyed like the
+//! loose files they replace, so `stats` and `sweep` are aggregate and indexed
+//! queries rather than a walk over every file. Each write is a single
+//! statement or transaction; with WAL journaling a crash loses at most the
+//! last writes and never leaves half an entry, as with `write_atomic`. WAL
+//! also lets the pooled read connections of [`SharedStore`] look entries up
+//! while its one writer commits.
+
+use super::{is_failure, is_inline_image, CacheEntry, MetadataEntry, PreviewSidecar, TOO_LARGE};
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, TransactionBehavior};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+
+pub const DB_FILE: &str = "cache.db";
+/// `PRAGMA user_version` of an up-to-date database: the number of
+/// [`MIGRATIONS`] applied.
+const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+/// The process has one writer per folder ([`shared`]), but another instance
+/// of the app may hold the write lock; a writer waits this long for it to
+/// finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Read connections a folder keeps open between lookups; more are opened
+/// while this many are busy, and closed when done.
+const IDLE_READERS: usize = 4;
+/// Rows a sweep deletes per transaction; [`SharedStore::sweep`] releases
+/// the writer between batches.
+const SWEEP_BATCH: usize = 500;
+/// Free pages a sweep hands back to the file system per step, likewise.
+const VACUUM_STEP_PAGES: i64 = 256;
+
+/// A read is recorded at most this often per row, so a busy thumbnail bar
+/// does not turn every lookup into a write.
+const ACCESS_GRANULARITY_SECS: u64 = 60 * 60;
+/// Row tables in the order of the `kind` column of [`LRU_OVER_CAP`].
+const TABLES: [&str; 3] = ["thumbnails", "previews", "metadata"];
+
+/// Schema changes in order; a database at version `n` runs them from index
+/// `n` on.
+const MIGRATIONS: [&str; 2] = [
+    // The tables. Blobs come last so reading an entry or sidecar never pages
+    // in the image behind it (F1).
+    "
+CREATE TABLE IF NOT EXISTS thumbnails (
+    key TEXT PRIMARY KEY,
+    entry TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    permanent INTEGER NOT NULL DEFAULT 0,
+    image BLOB
+);
+CREATE INDEX IF NOT EXISTS thumbnails_created ON thumbnails (created);
+CREATE TABLE IF NOT EXISTS previews (
+    path_key TEXT NOT NULL,
+    box_key TEXT NOT NULL,
+    sidecar TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    len INTEGER NOT NULL,
+    bytes BLOB NOT NULL,
+    PRIMARY KEY (path_key, box_key)
+);
+CREATE INDEX IF NOT EXISTS previews_created ON previews (created);
+CREATE TABLE IF NOT EXISTS metadata (
+    path_key TEXT PRIMARY KEY,
+    entry TEXT NOT NULL,
+    created INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS metadata_created ON metadata (created);
+",
+    // Last access, for LRU eviction; existing rows count as read when created.
+    "
+ALTER TABLE thumbnails ADD COLUMN accessed INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE previews ADD COLUMN accessed INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE metadata ADD COLUMN accessed INTEGER NOT NULL DEFAULT 0;
+UPDATE thumbnails SET accessed = created;
+UPDATE previews SET accessed = created;
+UPDATE metadata SET accessed = created;
+DROP INDEX thumbnails_created;
+DROP INDEX previews_created;
+DROP INDEX metadata_created;
+CREATE INDEX thumbnails_accessed ON thumbnails (accessed);
+CREATE INDEX previews_accessed ON previews (accessed, len);
+CREATE INDEX metadata_accessed ON metadata (accessed);
+",
+];
+
+/// `(kind, rowid)` of up to `?2` rows last read before `?1`, `kind`
+/// indexing [`TABLES`]. "too_large" thumbnails never expire.
+const EXPIRED: &str = "
+SELECT 0, rowid FROM thumbnails WHERE accessed < ?1 AND permanent = 0
+UNION ALL
+SELECT 1, rowid FROM previews WHERE accessed < ?1
+UNION ALL
+SELECT 2, rowid FROM metadata WHERE accessed < ?1
+LIMIT ?2";
+
+/// Up to `?2` of the least recently read previews beyond `?1` bytes in
+/// total: the running sum, most recent first, crosses the cap exactly at the
+/// rows to drop, and dropping some of them leaves the sums of the others
+/// alone.
+const PREVIEWS_OVER_CAP: &str = "
+SELECT 1, rowid FROM (
+    SELECT rowid, SUM(len) OVER (ORDER BY accessed DESC, rowid DESC) AS kept
+    FROM previews
+) WHERE kept > ?1
+LIMIT ?2";
+
+/// Up to `?2` of the least recently read rows of every table beyond `?1`
+/// bytes in total. "too_large" thumbnails hold no image and are never
+/// evicted.
+const LRU_OVER_CAP: &str = "
+SELECT kind, id FROM (
+    SELECT kind, id, SUM(len) OVER (ORDER BY accessed DESC, kind, id) AS kept
+    FROM (
+        SELECT 0 AS kind, rowid AS id, accessed,
+               length(entry) + COALESCE(length(image), 0) AS len
+        FROM thumbnails WHERE permanent = 0
+        UNION ALL
+        SELECT 1, rowid, accessed, len FROM previews
+        UNION ALL
+        SELECT 2, rowid, accessed, length(entry) FROM metadata
+    )
+) WHERE kept > ?1
+LIMIT ?2";
+
+fn db_err(e: rusqlite::Error) -> String {
+    format!("cache db: {e}")
+}
+
+/// True for the database and its WAL companions, which a purge of loose
+/// files must leave alone.
+pub fn is_store_file(name: &str) -> bool {
+    name.starts_with(DB_FILE)
+}
+
+/// Aggregates for `stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoreStats {
+    pub thumbnails: u64,
+    /// Thumbnails within the TTL, plus the "too_large" ones that never expire.
+    pub fresh_thumbnails: u64,
+    pub thumbnail_bytes: u64,
+    pub previews: u64,
+    pub preview_bytes: u64,
+    pub metadata: u64,
+}
+
+pub struct CacheStore {
+    conn: Connection,
+    /// Where a read connection sends the writes a lookup makes (recording
+    /// the read, dropping an unparsable or expired entry); `None` on the
+    /// writer itself and on a store opened on its own.
+    writer: Option<&'static Mutex<CacheStore>>,
+}
+
+/// The store of one cache folder as the process shares it: a single writer,
+/// so inserts queue here instead of on SQLite's write lock, and a pool of
+/// read connections that never wait for it.
+pub struct SharedStore {
+    dir: PathBuf,
+    writer: Mutex<CacheStore>,
+    readers: Mutex<Vec<CacheStore>>,
+}
+
+/// A pooled read connection, back in the pool when dropped.
+pub struct Reader {
+    store: Option<CacheStore>,
+    pool: &'static SharedStore,
+}
+
+/// The store in `cache_dir`, opened on first use and kept for the rest of
+/// the process. The protocol handler, the commands and the prefetch workers
+/// all go through it, so a lookup costs no schema check and usually no new
+/// connection.
+pub fn shared(cache_dir: &Path) -> Result<&'static SharedStore, String> {
+    type Stores = Mutex<HashMap<PathBuf, &'static SharedStore>>;
+    static STORES: OnceLock<Stores> = OnceLock::new();
+    let mut stores = STORES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(store) = stores.get(cache_dir) {
+        return Ok(store);
+    }
+    // One per cache folder, and a process has one of those.
+    let store: &'static SharedStore = Box::leak(Box::new(SharedStore {
+        dir: cache_dir.to_path_buf(),
+        writer: Mutex::new(CacheStore::open(cache_dir)?),
+        readers: Mutex::default(),
+    }));
+    stores.insert(cache_dir.to_path_buf(), store);
+    Ok(store)
+}
+
+impl SharedStore {
+    /// The writer, for inserts, deletes and anything that must see the
+    /// latest rows under the same lock (check-then-replace).
+    pub fn write(&self) -> MutexGuard<'_, CacheStore> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// An idle read connection, or a new one. It sees every committed write.
+    pub fn read(&'static self) -> Result<Reader, String> {
+        let idle = self
+            .readers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let store = match idle {
+            Some(store) => store,
+            None => CacheStore {
+                writer: Some(&self.writer),
+                ..CacheStore::open(&self.dir)?
+            },
+        };
+        Ok(Reader {
+            store: Some(store),
+            pool: self,
+        })
+    }
+
+    /// Deletes every row last read before `cutoff` ("too_large" thumbnails
+    /// excepted), then the least recently read previews until they total at
+    /// most `max_preview_bytes`, then the least recently read rows of any
+    /// kind until everything totals at most `max_bytes`, and hands the freed
+    /// pages back to the file system. Returns the rows removed. Each batch
+    /// and vacuum step takes the writer on its own, so new entries and
+    /// recorded reads are not held up behind a large eviction.
+    pub fn sweep(
+        &self,
+        cutoff: u64,
+        max_preview_bytes: u64,
+        max_bytes: u64,
+    ) -> Result<usize, String> {
+        let mut removed = 0;
+        loop {
+            let batch = self
+                .write()
+                .sweep_batch(cutoff, max_preview_bytes, max_bytes)?;
+            removed += batch;
+            if batch < SWEEP_BATCH {
+                break;
+            }
+        }
+        while self.write().vacuum_step()? {}
+        Ok(removed)
+    }
+}
+
+impl Deref for Reader {
+    type Target = CacheStore;
+
+    fn deref(&self) -> &CacheStore {
+        self.store
+            .as_ref()
+            .expect("a reader holds its store until dropped")
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        let mut idle = self
+            .pool
+            .readers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < IDLE_READERS {
+            idle.extend(self.store.take());
+        }
+    }
+}
+
+impl CacheStore {
+    /// Opens (creating on first use) the store in `cache_dir`.
+    pub fn open(cache_dir: &Path) -> Result<Self, String> {
+        let mut conn = Connection::open(cache_dir.join(DB_FILE)).map_err(db_err)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(db_err)?;
+        let version: i64 = conn
+            .pragma_query_value(None, "user_version", |r| r.get(0))
+            .map_err(db_err)?;
+        if version < SCHEMA_VERSION {
+            // auto_vacuum only takes effect before the first table exists;
+            // both it and WAL mode persist in the file.
+            conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")
+                .map_err(db_err)?;
+            conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))
+                .map_err(db_err)?;
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(db_err)?;
+            // Another connection may have migrated while this one waited.
+            let version: i64 = tx
+                .pragma_query_value(None, "user_version", |r| r.get(0))
+                .map_err(db_err)?;
+            for migration in MIGRATIONS.iter().skip(version.max(0) as usize) {
+                tx.execute_batch(migration).map_err(db_err)?;
+            }
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)
+                .map_err(db_err)?;
+            tx.commit().map_err(db_err)?;
+        }
+        // Under WAL, NORMAL never corrupts the database; it only risks the
+        // last commits on power loss, which a cache can afford.
+        conn.pragma_update(None, "synchronous", "NORMAL")
+            .map_err(db_err)?;
+        Ok(Self { conn, writer: None })
+    }
+
+    /// Runs `write`, a write made while looking something up, on the writer
+    /// when this is a read connection.
+    fn on_writer(&self, write: impl FnOnce(&Connection)) {
+        match self.writer {
+            Some(writer) => write(&writer.lock().unwrap_or_else(PoisonError::into_inner).conn),
+            None => write(&self.conn),
+        }
+    }
+
+    /// The thumbnail entry under `key`, without its image. An unparsable
+    /// entry is removed.
+    pub fn thumbnail_entry(&self, key: &str) -> Option<CacheEntry> {
+        let json: String = self
+            .conn
+            .query_row("SELECT entry FROM thumbnails WHERE key = ?1", [key], |r| {
+                r.get(0)
+            })
+            .optional()
+            .ok()??;
+        match serde_json::from_str(&json) {
+            Ok(entry) => Some(entry),
+            Err(_) => {
+                self.remove_thumbnail(key);
+                None
+            }
+        }
+    }
+
+    pub fn thumbnail_image(&self, key: &str) -> Option<Vec<u8>> {
+        self.conn
+            .query_row("SELECT image FROM thumbnails WHERE key = ?1", [key], |r| {
+                r.get::<_, Option<Vec<u8>>>(0)
+            })
+            .optional()
+            .ok()
+            .flatten()
+            .flatten()
+    }
+
+    /// Stores `entry` and its `image` (none for failures) in one row, so a
+    /// reader never finds one without the other.
+    pub fn put_thumbnail(
+        &self,
+        key: &str,
+        entry: &CacheEntry,
+        image: Option<&[u8]>,
+    ) -> Result<(), String> {
+        insert_thumbnail(&self.conn, key, entry, image)
+    }
+
+    /// Records a read of the thumbnail under `key` at `now`.
+    pub fn touch_thumbnail(&self, key: &str, now: u64) {
+        self.touch("thumbnails", "key = ?1", &[key], now);
+    }
+
+    pub fn touch_preview(&self, path_key: &str, box_key: &str, now: u64) {
+        self.touch(
+            "previews",
+            "path_key = ?1 AND box_key = ?2",
+            &[path_key, box_key],
+            now,
+        );
+    }
+
+    pub fn touch_metadata(&self, path_key: &str, now: u64) {
+        self.touch("metadata", "path_key = ?1", &[path_key], now);
+    }
+
+    /// Moves `accessed` of the row matching `filter` up to `now`, unless it
+    /// was recorded within [`ACCESS_GRANULARITY_SECS`]: checked with a read
+    /// first, so lookups only take the write lock about once an hour.
+    fn touch(&self, table: &str, filter: &str, keys: &[&str], now: u64) {
+        let accessed: Option<i64> = self
+            .conn
+            .query_row(
+                &format!("SELECT accessed FROM {table} WHERE {filter}"),
+                params_from_iter(keys),
+                |r| r.get(0),
+            )
+            .ok();
+        if accessed
+            .is_some_and(|a| (a.max(0) as u64).saturating_add(ACCESS_GRANULARITY_SECS) <= now)
+        {
+            self.on_writer(|conn| {
+                let _ = conn.execute(
+                    &format!(
+                        "UPDATE {table} SET accessed = {} WHERE {filter}",
+                        now as i64
+                    ),
+                    params_from_iter(keys),
+                );
+            });
+        }
+    }
+
+    pub fn remove_thumbnail(&self, key: &str) {
+        self.on_writer(|conn| {
+            let _ = conn.execute("DELETE FROM thumbnails WHERE key = ?1", [key]);
+        });
+    }
+
+    /// The sidecar of a preview, without reading its bytes (F1).
+    pub fn preview_sidecar(&self, path_key: &str, box_key: &str) -> Option<PreviewSidecar> {
+        let json: String = self
+            .conn
+            .query_row(
+                "SELECT sidecar FROM previews WHERE path_key = ?1 AND box_key = ?2",
+                [path_key, box_key],
+                |r| r.get(0),
+            )
+            .optional()
+            .ok()??;
+        serde_json::from_str(&json).ok()
+    }
+
+    pub fn preview(&self, path_key: &str, box_key: &str) -> Option<(Vec<u8>, PreviewSidecar)> {
+        let (json, bytes): (String, Vec<u8>) = self
+            .conn
+            .query_row(
+                "SELECT sidecar, bytes FROM previews WHERE path_key = ?1 AND box_key = ?2",
+                [path_key, box_key],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()
+            .ok()??;
+        Some((bytes, serde_json::from_str(&json).ok()?))
+    }
+
+    pub fn put_preview(
+        &self,
+        path_key: &str,
+        box_key: &str,
+        bytes: &[u8],
+        sidecar: &PreviewSidecar,
+    ) -> Result<(), String> {
+        insert_preview(&self.conn, path_key, box_key, bytes, sidecar)
+    }
+
+    /// The metadata entry of a source. An unparsable entry is removed.
+    pub fn metadata(&self, path_key: &str) -> Option<MetadataEntry> {
+        let json: String = self
+            .conn
+            .query_row(
+                "SELECT entry FROM metadata WHERE path_key = ?1",
+                [path_key],
+                |r| r.get(0),
+            )
+            .optional()
+            .ok()??;
+        match serde_json::from_str(&json) {
+            Ok(entry) => Some(entry),
+            Err(_) => {
+                self.remove_metadata(path_key);
+                None
+            }
+        }
+    }
+
+    pub fn put_metadata(&self, path_key: &str, entry: &MetadataEntry) -> Result<(), String> {
+        insert_metadata(&self.conn, path_key, entry)
+    }
+
+    pub fn remove_metadata(&self, path_key: &str) {
+        self.on_writer(|conn| {
+            let _ = conn.execute("DELETE FROM metadata WHERE path_key = ?1", [path_key]);
+        });
+    }
+
+    /// Deletes the thumbnails under `thumb_keys` and every preview, tile and
+    /// metadata entry under `path_key`. Returns the rows removed.
+    pub fn remove_source(
+        &mut self,
+        path_key: &str,
+        thumb_keys: &[String],
+    ) -> Result<usize, String> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_err)?;
+        let mut removed = 0;
+        for key in thumb_keys {
+            removed += tx
+                .execute("DELETE FROM thumbnails WHERE key = ?1", [key])
+                .map_err(db_err)?;
+        }
+        removed += tx
+            .execute("DELETE FROM previews WHERE path_key = ?1", [path_key])
+            .map_err(db_err)?;
+        removed += tx
+            .execute("DELETE FROM metadata WHERE path_key = ?1", [path_key])
+            .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+        Ok(removed)
+    }
+
+    /// Moves the rows of one source to another's keys (`thumb_keys` pair up
+    /// entry by entry), replacing whatever the destination had. Returns the
+    /// rows moved.
+    pub fn move_source(
+        &mut self,
+        (from_path, from_thumbs): (&str, &[String]),
+        (to_path, to_thumbs): (&str, &[String]),
+    ) -> Result<usize, String> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_err)?;
+        let mut moved = 0;
+        for (from, to) in from_thumbs.iter().zip(to_thumbs) {
+            moved += tx
+                .execute(
+                    "UPDATE OR REPLACE thumbnails SET key = ?2 WHERE key = ?1",
+                    [from, to],
+                )
+                .map_err(db_err)?;
+        }
+        moved += tx
+            .execute(
+                "UPDATE OR REPLACE previews SET path_key = ?2 WHERE path_key = ?1",
+                [from_path, to_path],
+            )
+            .map_err(db_err)?;
+        moved += tx
+            .execute(
+                "UPDATE OR REPLACE metadata SET path_key = ?2 WHERE path_key = ?1",
+                [from_path, to_path],
+            )
+            .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+        Ok(moved)
+    }
+
+    /// Deletes up to [`SWEEP_BATCH`] of the rows [`SharedStore::sweep`]
+    /// removes, in its order and in one transaction. Returns the rows
+    /// removed; short of a batch, there are none left.
+    fn sweep_batch(
+        &mut self,
+        cutoff: u64,
+        max_preview_bytes: u64,
+        max_bytes: u64,
+    ) -> Result<usize, String> {
+        let as_sql = |n: u64| n.min(i64::MAX as u64) as i64;
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_err)?;
+        let mut removed = 0;
+        for (query, bound) in [
+            (EXPIRED, cutoff),
+            (PREVIEWS_OVER_CAP, max_preview_bytes),
+            (LRU_OVER_CAP, max_bytes),
+        ] {
+            let left = (SWEEP_BATCH - removed) as i64;
+            let rows: Vec<(usize, i64)> = tx
+                .prepare(query)
+                .and_then(|mut stmt| {
+                    stmt.query_map(params![as_sql(bound), left], |r| Ok((r.get(0)?, r.get(1)?)))?
+                        .collect()
+                })
+                .map_err(db_err)?;
+            for (kind, id) in rows {
+                removed += tx
+                    .execute(
+                        &format!("DELETE FROM {} WHERE rowid = ?1", TABLES[kind]),
+                        [id],
+                    )
+                    .map_err(db_err)?;
+            }
+            if removed == SWEEP_BATCH {
+                break;
+            }
+        }
+        tx.commit().map_err(db_err)?;
+        Ok(removed)
+    }
+
+    /// Hands up to [`VACUUM_STEP_PAGES`] free pages back to the file system.
+    /// Returns whether another step would free more; a database created
+    /// without incremental auto-vacuum frees none.
+    fn vacuum_step(&self) -> Result<bool, String> {
+        let free_pages = || {
+            self.conn
+                .pragma_query_value(None, "freelist_count", |r| r.get::<_, i64>(0))
+                .map_err(db_err)
+        };
+        let before = free_pages()?;
+        if before == 0 {
+            return Ok(false);
+        }
+        self.conn
+            .execute_batch(&format!("PRAGMA incremental_vacuum({VACUUM_STEP_PAGES});"))
+            .map_err(db_err)?;
+        let after = free_pages()?;
+        Ok(after > 0 && after < before)
+    }
+
+    /// Counts and sizes, with thumbnails read at or after `cutoff` fresh.
+    pub fn stats(&self, cutoff: u64) -> Result<StoreStats, String> {
+        let count = |n: i64| n.max(0) as u64;
+        let (thumbnails, fresh_thumbnails, thumbnail_bytes) = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*),
+                        COUNT(CASE WHEN accessed >= ?1 OR permanent = 1 THEN 1 END),
+                        COALESCE(SUM(length(image)), 0)
+                 FROM thumbnails",
+                [cutoff as i64],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .map_err(db_err)?;
+        let (previews, preview_bytes) = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(len), 0) FROM previews",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .map_err(db_err)?;
+        let metadata = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM metadata", [], |r| r.get(0))
+            .map_err(db_err)?;
+        Ok(StoreStats {
+            thumbnails: count(thumbnails),
+            fresh_thumbnails: count(fresh_thumbnails),
+            thumbnail_bytes: count(thumbnail_bytes),
+            previews: count(previews),
+            preview_bytes: count(preview_bytes),
+            metadata: count(metadata),
+        })
+    }
+
+    /// Imports the loose files of a version 2 cache in `cache_dir`:
+    /// `<key>.json` + `<key>.thumb` thumbnails (or base64 inside the JSON),
+    /// `<path key>-<box key>_p.jpg` + `_p.json` previews and tiles, and
+    /// `<path key>_m.json` metadata. Incomplete or unreadable entries are
+    /// skipped; the files themselves are left for the caller to purge.
+    /// Returns the entries imported.
+    pub fn import_loose_files(&mut self, cache_dir: &Path) -> Result<usize, String> {
+        let files: Vec<PathBuf> = fs::read_dir(cache_dir)
+            .map_err(|e| format!("Failed to read cache directory: {e}"))?
+            .flatten()
+            .map(|e| e.path())
+            .collect();
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_err)?;
+        let mut imported = 0;
+        for file in &files {
+            let Some(name) = file.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let Ok(json) = fs::read_to_string(file) else {
+                continue;
+            };
+            if let Some(stem) = name.strip_suffix("_p.json") {
+                let Some((path_key, box_key)) = stem.split_once('-') else {
+                    continue;
+                };
+                let (Ok(sidecar), Ok(bytes)) = (
+                    serde_json::from_str::<PreviewSidecar>(&json),
+                    fs::read(file.with_file_name(format!("{stem}_p.jpg"))),
+                ) else {
+                    continue;
+                };
+                insert_preview(&tx, path_key, box_key, &bytes, &sidecar)?;
+            } else if let Some(path_key) = name.strip_suffix("_m.json") {
+                let Ok(entry) = serde_json::from_str::<MetadataEntry>(&json) else {
+                    continue;
+                };
+                insert_metadata(&tx, path_key, &entry)?;
+            } else if let Some(key) = name.strip_suffix(".json") {
+                let Ok(mut entry) = serde_json::from_str::<CacheEntry>(&json) else {
+                    continue;
+                };
+                let image = if is_failure(&entry.thumbnail) {
+                    None
+                } else if is_inline_image(&entry.thumbnail) {
+                    match general_purpose::STANDARD.decode(&entry.thumbnail) {
+                        Ok(image) => Some(image),
+                        Err(_) => continue,
+                    }
+                } else {
+                    match fs::read(file.with_file_name(format!("{key}.thumb"))) {
+                        Ok(image) => Some(image),
+                        Err(_) => continue,
+                    }
+                };
+                if image.is_some() {
+                    entry.thumbnail.clear();
+                }
+                insert_thumbnail(&tx, key, &entry, image.as_deref())?;
+            } else {
+                continue;
+            }
+            imported += 1;
+        }
+        tx.commit().map_err(db_err)?;
+        Ok(imported)
+    }
+}
+
+fn insert_thumbnail(
+    conn: &Connection,
+    key: &str,
+    entry: &CacheEntry,
+    image: Option<&[u8]>,
+) -> Result<(), String> {
+    let json = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize cache entry: {e}"))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO thumbnails (key, entry, created, accessed, permanent, image)
+         VALUES (?1, ?2, ?3, ?3, ?4, ?5)",
+        params![
+            key,
+            json,
+            entry.created as i64,
+            entry.thumbnail == TOO_LARGE,
+            image
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+fn insert_preview(
+    conn: &Connection,
+    path_key: &str,
+    box_key: &str,
+    bytes: &[u8],
+    sidecar: &PreviewSidecar,
+) -> Result<(), String> {
+    let json =
+        serde_json::to_string(sidecar).map_err(|e| format!("Failed to serialize sidecar: {e}"))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO previews
+             (path_key, box_key, sidecar, created, accessed, len, bytes)
+         VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6)",
+        params![
+            path_key,
+            box_key,
+            json,
+            sidecar.created as i64,
+            bytes.len() as i64,
+            bytes
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+fn insert_metadata(conn: &Connection, path_key: &str, entry: &MetadataEntry) -> Result<(), String> {
+    let json = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize metadata entry: {e}"))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (path_key, entry, created, accessed)
+         VALUES (?1, ?2, ?3, ?3)",
+        params![path_key, json, entry.created as i64],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_temp_dir;
+    use crate::utils::encode::PreviewFormat;
+
+    fn sidecar(created: u64) -> PreviewSidecar {
+        PreviewSidecar {
+            natural_width: 800,
+            natural_height: 600,
+            source_mtime: 1,
+            source_size: 1,
+            created,
+            format: PreviewFormat::Jpeg,
+        }
+    }
+
+    /// [`SharedStore::sweep`] on a store of its own.
+    fn sweep(store: &mut CacheStore, cutoff: u64, previews: u64, total: u64) -> usize {
+        let mut removed = 0;
+        loop {
+            let batch = store.sweep_batch(cutoff, previews, total).unwrap();
+            removed += batch;
+            if batch < SWEEP_BATCH {
+                break;
+            }
+        }
+        while store.vacuum_step().unwrap() {}
+        removed
+    }
+
+    #[test]
+    fn open_creates_the_schema_once() {
+        let dir = create_temp_dir();
+        let store = CacheStore::open(dir.path()).unwrap();
+        store
+            .put_preview("a", "1920x1080", b"jpg", &sidecar(1))
+            .unwrap();
+        drop(store);
+        let store = CacheStore::open(dir.path()).unwrap();
+        assert_eq!(store.stats(0).unwrap().previews, 1);
+        assert!(is_store_file(DB_FILE));
+        assert!(!is_store_file("cache-format"));
+    }
+
+    #[test]
+    fn shared_hands_out_one_store_per_folder_to_concurrent_writers() {
+        let dir = create_temp_dir();
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let dir = dir.path();
+                s.spawn(move || {
+                    for i in 0..20 {
+                        shared(dir)
+                            .unwrap()
+                            .write()
+                            .put_preview(&format!("{t}-{i}"), "1920x1080", b"jpg", &sidecar(1))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        let store = shared(dir.path()).unwrap();
+        assert_eq!(store.read().unwrap().stats(0).unwrap().previews, 160);
+        let other = create_temp_dir();
+        let other = shared(other.path()).unwrap();
+        assert_eq!(other.read().unwrap().stats(0).unwrap().previews, 0);
+    }
+
+    #[test]
+    fn readers_do_not_wait_for_the_writer() {
+        let dir = create_temp_dir();
+        let store = shared(dir.path()).unwrap();
+        store
+            .write()
+            .put_preview("a", "1920x1080", b"jpg", &sidecar(1))
+            .unwrap();
+        let writer = store.write();
+        let found = std::thread::spawn(move || {
+            let readers: Vec<Reader> = (0..IDLE_READERS + 2)
+                .map(|_| store.read().unwrap())
+                .collect();
+            readers
+                .iter()
+                .all(|reader| reader.preview_sidecar("a", "1920x1080").is_some())
+        })
+        .join()
+        .unwrap();
+        assert!(found);
+        drop(writer);
+        // A recorded read goes through the writer.
+        store
+            .read()
+            .unwrap()
+            .touch_preview("a", "1920x1080", 10_000);
+        assert_eq!(store.sweep(2, u64::MAX, u64::MAX).unwrap(), 0);
+        assert!(store.readers.lock().unwrap().len() <= IDLE_READERS);
+    }
+
+    #[test]
+    fn shared_sweep_removes_more_than_a_batch() {
+        let dir = create_temp_dir();
+        let store = shared(dir.path()).unwrap();
+        for i in 0..SWEEP_BATCH * 2 + 10 {
+            store
+                .write()
+                .put_preview(&i.to_string(), "1920x1080", &[0; 100], &sidecar(1))
+                .unwrap();
+        }
+        assert_eq!(
+            store.sweep(2, u64::MAX, u64::MAX).unwrap(),
+            SWEEP_BATCH * 2 + 10
+        );
+        assert_eq!(store.read().unwrap().stats(0).unwrap().previews, 0);
+    }
+
+    #[test]
+    fn migration_dates_existing_rows_as_read_when_created() {
+        let dir = create_temp_dir();
+        let conn = Connection::open(dir.path().join(DB_FILE)).unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute_batch("PRAGMA user_version = 1").unwrap();
+        conn.execute(
+            "INSERT INTO previews (path_key, box_key, sidecar, created, len, bytes)
+             VALUES ('a', '1920x1080', ?1, 100, 3, x'000000')",
+            [serde_json::to_string(&sidecar(100)).unwrap()],
+        )
+        .unwrap();
+        drop(conn);
+        let mut store = CacheStore::open(dir.path()).unwrap();
+        assert!(store.preview("a", "1920x1080").is_some());
+        assert_eq!(sweep(&mut store, 100, u64::MAX, u64::MAX), 0);
+        assert_eq!(sweep(&mut store, 101, u64::MAX, u64::MAX), 1);
+    }
+
+    #[test]
+    fn eviction_keeps_the_most_recently_read_previews_under_the_cap() {
+        let dir = create_temp_dir();
+        let mut store = CacheStore::open(dir.path()).unwrap();
+        for (i, key) in ["a", "b", "c", "d"].iter().enumerate() {
+            store
+                .put_preview(key, "1920x1080", &[0; 1000], &sidecar(100 + i as u64))
+                .unwrap();
+        }
+        // The oldest preview was read since; the next two oldest go.
+        store.touch_preview("a", "1920x1080", 10_000);
+        assert_eq!(sweep(&mut store, 0, 2000, u64::MAX), 2);
+        assert!(store.preview_sidecar("a", "1920x1080").is_some());
+        assert!(store.preview_sidecar("b", "1920x1080").is_none());
+        assert!(store.preview_sidecar("c", "1920x1080").is_none());
+        let stats = store.stats(0).unwrap();
+        assert_eq!((stats.previews, stats.preview_bytes), (2, 2000));
+        assert_eq!(sweep(&mut store, 0, 2000, u64::MAX), 0);
+    }
+
+    #[test]
+    fn touch_is_recorded_at_most_once_per_granularity() {
+        let dir = create_temp_dir();
+        let mut store = CacheStore::open(dir.path()).unwrap();
+        store
+            .put_preview("a", "1920x1080", b"jpg", &sidecar(100))
+            .unwrap();
+        store.touch_preview("a", "1920x1080", 100 + ACCESS_GRANULARITY_SECS - 1);
+        assert_eq!(sweep(&mut store, 101, u64::MAX, u64::MAX), 1);
+    }
+
+    #[test]
+    fn total_cap_evicts_the_least_recently_read_rows_of_any_kind() {
+        let dir = create_temp_dir();
+        let mut store = CacheStore::open(dir.path()).unwrap();
+        let thumbnail: CacheEntry = serde_json::from_str(r#"{"created":100}"#).unwrap();
+        store
+            .put_thumbnail("t", &thumbnail, Some(&[0; 1000]))
+            .unwrap();
+        store
+            .put_preview("p", "1920x1080", &[0; 1000], &sidecar(200))
+            .unwrap();
+        let metadata = MetadataEntry {
+            metadata: Default::default(),
+            created: 300,
+            source_mtime: 1,
+            source_size: 1,
+        };
+        store.put_metadata("p", &metadata).unwrap();
+        assert_eq!(sweep(&mut store, 0, u64::MAX, 1500), 1);
+        assert!(store.thumbnail_entry("t").is_none());
+        assert!(store.preview("p", "1920x1080").is_some());
+        assert!(store.metadata("p").is_some());
+    }
+
+    #[test]
+    fn move_source_replaces_the_destination_rows() {
+        let dir = create_temp_dir();
+        let mut store = CacheStore::open(dir.path()).unwrap();
+        store
+            .put_preview("a", "1920x1080", b"new", &sidecar(2))
+            .unwrap();
+        store
+            .put_preview("b", "1920x1080", b"old", &sidecar(1))
+            .unwrap();
+        let none: [String; 0] = [];
+        assert_eq!(store.move_source(("a", &none), ("b", &none)).unwrap(), 1);
+        assert_eq!(store.preview("b", "1920x1080").unwrap().0, b"new");
+        assert_eq!(store.stats(0).unwrap().previews, 1);
+    }
+}
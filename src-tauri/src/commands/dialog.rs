@@ -0,0 +1,92 @@
+use crate::utils::format::SourceFormat;
+use crate::utils::scope;
+use std::path::Path;
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt;
+
+/// Every extension `SourceFormat::from_extension` knows.
+const SOURCE_EXTENSIONS: [&str; 15] = [
+    "jpg", "jpeg", "png", "webp", "gif", "heic", "heif", "hif", "avif", "cr2", "cr3", "nef", "nrw",
+    "arw", "dng",
+];
+
+/// Offered in the open dialog's filter: the extensions of the formats this
+/// build can decode, so a HEIC/AVIF without the `heif` feature is not
+/// offered only to be refused.
+fn dialog_extensions() -> Vec<&'static str> {
+    SOURCE_EXTENSIONS
+        .into_iter()
+        .filter(|ext| {
+            SourceFormat::from_extension(Path::new(&format!("a.{ext}")))
+                .is_some_and(SourceFormat::is_decodable)
+        })
+        .collect()
+}
+
+/// Native open dialog for a single image. The selection's folder joins the
+/// `spica-img` scope before the path is returned, so the page can only ever
+/// be served folders the user picked.
+#[tauri::command]
+pub async fn open_image_dialog(app_handle: AppHandle) -> Result<Option<String>, String> {
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        app_handle
+            .dialog()
+            .file()
+            .add_filter("Images", &dialog_extensions())
+            .blocking_pick_file()
+    })
+    .await
+    .map_err(|e| format!("dialog task failed: {e}"))?;
+    let Some(picked) = picked else {
+        return Ok(None);
+    };
+    let path = picked
+        .into_path()
+        .map_err(|e| format!("Invalid selection: {e}"))?;
+    scope::allow_opened(&path)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// Native folder picker for a copy or move destination. Like
+/// [`open_image_dialog`], the folder joins the scope before it is returned:
+/// the file operations only write into opened folders.
+#[tauri::command]
+pub async fn pick_destination_folder(app_handle: AppHandle) -> Result<Option<String>, String> {
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        app_handle.dialog().file().blocking_pick_folder()
+    })
+    .await
+    .map_err(|e| format!("dialog task failed: {e}"))?;
+    let Some(picked) = picked else {
+        return Ok(None);
+    };
+    let path = picked
+        .into_path()
+        .map_err(|e| format!("Invalid selection: {e}"))?;
+    scope::allow_folder(&path)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialog_offers_the_extensions_this_build_decodes() {
+        let offered = dialog_extensions();
+        for ext in SOURCE_EXTENSIONS {
+            let name = format!("a.{ext}");
+            let format = SourceFormat::from_extension(Path::new(&name));
+            assert!(format.is_some(), "{ext}");
+            assert_eq!(
+                offered.contains(&ext),
+                format.is_some_and(SourceFormat::is_decodable),
+                "{ext}"
+            );
+        }
+        for ext in ["heic", "heif", "hif", "avif"] {
+            assert_eq!(offered.contains(&ext), cfg!(feature = "heif"), "{ext}");
+        }
+        assert!(offered.contains(&"jpg") && offered.contains(&"dng"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::commands::cache;
+use crate::commands::file::{folder_candidates, generate_and_cache, get_image_info, ImageInfo};
+use crate::utils::phash::{hamming, DEFAULT_DUPLICATE_THRESHOLD};
+use crate::utils::preview::DEFAULT_THUMB_SIZE;
+use crate::utils::sort::natural_cmp;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateMember {
+    #[serde(flatten)]
+    pub image: ImageInfo,
+    /// Hamming distance of this image's dHash to the group's first member.
+    pub distance: u32,
+}
+
+/// Images that are visually the same or nearly so. The first member is the
+/// one the others are measured against (distance 0); the rest follow by
+/// distance, then name.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub images: Vec<DuplicateMember>,
+}
+
+/// The cached dHash of `path`, generating (and caching) its bar thumbnail
+/// when there is none yet.
+fn dhash_of(path: &Path, cache_dir: &Path) -> Option<u64> {
+    let key = path.to_string_lossy();
+    if let Some(hash) = cache::lookup_dhash(cache_dir, &key) {
+        return Some(hash);
+    }
+    generate_and_cache(path, DEFAULT_THUMB_SIZE, None, cache_dir).ok()?;
+    cache::lookup_dhash(cache_dir, &key)
+}
+
+/// Groups the images directly inside `folder` whose hashes are within
+/// `threshold` bits of another member's. Grouping is transitive, so a burst
+/// where each frame is close to the next lands in one group. Images that
+/// cannot be decoded are left out.
+pub fn find_duplicates_in(
+    folder: &Path,
+    threshold: u32,
+    cache_dir: &Path,
+) -> Result<Vec<DuplicateGroup>, String> {
+    if !folder.is_dir() {
+        return Err("Invalid folder path".to_string());
+    }
+    let mut hashed: Vec<(ImageInfo, u64)> = folder_candidates(folder)
+        .par_iter()
+        .filter_map(|path| Some((get_image_info(path).ok()?, dhash_of(path, cache_dir)?)))
+        .collect();
+    hashed.sort_by(|a, b| natural_cmp(&a.0.filename, &b.0.filename));
+
+    let n = hashed.len();
+    let pairs: Vec<(usize, usize)> = (0..n)
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let hashed = &hashed;
+            (i + 1..n)
+                .filter(move |&j| hamming(hashed[i].1, hashed[j].1) <= threshold)
+                .map(move |j| (i, j))
+        })
+        .collect();
+    let mut parent: Vec<usize> = (0..n).collect();
+    for (i, j) in pairs {
+        let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+        // The earlier image (in name order) becomes the group's reference.
+        parent[a.max(b)] = a.min(b);
+    }
+
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..n {
+        let r = root(&mut parent, i);
+        groups.entry(r).or_default().push(i);
+    }
+    Ok(groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(first, members)| {
+            let reference = hashed[first].1;
+            let mut images: Vec<DuplicateMember> = members
+                .into_iter()
+                .map(|i| DuplicateMember {
+                    image: hashed[i].0.clone(),
+                    distance: hamming(reference, hashed[i].1),
+                })
+                .collect();
+            images.sort_by(|a, b| {
+                a.distance
+                    .cmp(&b.distance)
+                    .then_with(|| natural_cmp(&a.image.filename, &b.image.filename))
+            });
+            DuplicateGroup { images }
+        })
+        .collect())
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Groups of visually similar images in `folder`. `threshold` is the largest
+/// dHash distance (0-64) still counted as a duplicate; 0 finds only
+/// pixel-identical content.
+#[tauri::command]
+pub async fn find_duplicates(
+    folder: String,
+    threshold: Option<u32>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("duplicates", &folder);
+        let folder_path = Path::new(&folder);
+        if !folder_path.is_dir() {
+            return Err("Invalid folder path".to_string());
+        }
+        let cache_dir = cache::get_cache_dir()?;
+        find_duplicates_in(
+            folder_path,
+            threshold.unwrap_or(DEFAULT_DUPLICATE_THRESHOLD),
+            &cache_dir,
+        )
+    })
+    .await
+    .map_err(|e| format!("duplicates task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn names(group: &DuplicateGroup) -> Vec<(&str, u32)> {
+        group
+            .images
+            .iter()
+            .map(|m| (m.image.filename.as_str(), m.distance))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_duplicates_groups_reencodes_and_resizes() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let original = create_gradient_jpeg(dir.path(), "IMG_1.jpg", 320, 240);
+        create_gradient_jpeg(dir.path(), "IMG_1_small.jpg", 160, 120);
+        image::open(&original)
+            .unwrap()
+            .save(dir.path().join("IMG_1.png"))
+            .unwrap();
+        // A mirrored shot is a different picture.
+        image::open(&original)
+            .unwrap()
+            .fliph()
+            .save(dir.path().join("IMG_2.jpg"))
+            .unwrap();
+        create_invalid_image(dir.path(), "broken.jpg");
+
+        let groups = find_duplicates_in(dir.path(), 4, cache_dir.path()).unwrap();
+        assert_eq!(groups.len(), 1);
+        let members = names(&groups[0]);
+        assert_eq!(members[0], ("IMG_1.jpg", 0));
+        let mut rest: Vec<&str> = members[1..].iter().map(|m| m.0).collect();
+        rest.sort();
+        assert_eq!(rest, ["IMG_1.png", "IMG_1_small.jpg"]);
+
+        // Every hash is now cached alongside the bar thumbnail.
+        let key = original.to_string_lossy();
+        assert!(cache::lookup_dhash(cache_dir.path(), &key).is_some());
+    }
+
+    #[test]
+    fn test_find_duplicates_without_matches_and_invalid_folder() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let img = create_gradient_jpeg(dir.path(), "a.jpg", 320, 240);
+        image::open(&img)
+            .unwrap()
+            .flipv()
+            .fliph()
+            .save(dir.path().join("b.jpg"))
+            .unwrap();
+        let groups = find_duplicates_in(dir.path(), 4, cache_dir.path()).unwrap();
+        assert!(groups.is_empty());
+        let err = find_duplicates_in(Path::new("/nonexistent/path"), 4, cache_dir.path());
+        assert!(err.unwrap_err().contains("Invalid folder path"));
+    }
+}
@@ -1,10 +1,16 @@
 use crate::commands::cache::{self, CacheEntry, PreviewSidecar};
-use crate::utils::image::is_supported_image;
+use crate::utils::encode::{PreviewFormat, PreviewProfile};
+use crate::utils::format::{self, FormatMismatch};
+use crate::utils::limits::{DecodeError, TOO_LARGE};
+use crate::utils::metadata;
 use crate::utils::preview::{self, PreviewBox};
+use crate::utils::scope;
+use crate::utils::sort::{sort_images, SortKey, SortSpec};
+use base64::{engine::general_purpose, Engine as _};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 // Windows API constants
@@ -17,7 +23,14 @@ pub struct ImageInfo {
     pub filename: String,
     pub size: u64,
     pub modified: u64,
+    /// Sniffed from the content (`utils::format`), not the extension.
     pub format: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_mismatch: Option<FormatMismatch>,
+    /// EXIF DateTimeOriginal as Unix seconds; only read when the listing is
+    /// sorted by date taken.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_taken: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,44 +40,75 @@ pub struct ThumbnailWithDimensions {
     pub original_height: u32,
     /// true when a display-resolution preview for the requested box is now on disk (I1).
     pub preview_available: bool,
+    /// true when the thumbnail is the source's Exif thumbnail, cached until a
+    /// full decode replaces it.
+    pub provisional: bool,
 }
 
+pub struct GeneratedThumbnail {
+    pub jpeg: Vec<u8>,
+    pub original_width: u32,
+    pub original_height: u32,
+    pub preview_available: bool,
+    pub provisional: bool,
+}
+
+/// Images directly inside `path`, ordered by `sort` (natural name order by
+/// default).
 #[tauri::command]
-pub async fn get_folder_images(path: String) -> Result<Vec<ImageInfo>, String> {
+pub async fn get_folder_images(
+    path: String,
+    sort: Option<SortSpec>,
+) -> Result<Vec<ImageInfo>, String> {
     let folder_path = Path::new(&path);
 
     if !folder_path.exists() || !folder_path.is_dir() {
         return Err("Invalid folder path".to_string());
     }
 
-    // First, collect all valid image paths (fast, no metadata reads)
-    let image_paths: Vec<_> = WalkDir::new(folder_path)
-        .max_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|entry| {
-            let path = entry.path();
-            path.is_file() && is_supported_image(path)
-        })
-        .map(|entry| entry.path().to_path_buf())
-        .collect();
+    // First, collect candidate paths: a known image extension or none at all
+    // (fast, no metadata reads). Their content is sniffed in the parallel pass.
+    let image_paths = folder_candidates(folder_path);
 
     // Process metadata in parallel using rayon
     // This dramatically speeds up folder scanning for large folders (900+ images)
+    let sort = sort.unwrap_or_default();
+    let with_date = sort.key == SortKey::DateTaken;
     let mut images: Vec<ImageInfo> = image_paths
         .par_iter()
-        .filter_map(|path| get_image_info(path).ok())
+        .filter_map(|path| {
+            let mut info = get_image_info(path).ok()?;
+            if with_date {
+                info.date_taken = metadata::read_date_taken(path);
+            }
+            Some(info)
+        })
         .collect();
 
-    images.sort_by(|a, b| a.filename.cmp(&b.filename));
+    sort_images(&mut images, sort);
+    crate::commands::watch::watch_folder(folder_path, &images);
     Ok(images)
 }
 
-fn validate_image_path(path: &Path) -> Result<(), String> {
+/// Files directly inside `folder` that may be images, judged by name only.
+pub(crate) fn folder_candidates(folder: &Path) -> Vec<PathBuf> {
+    WalkDir::new(folder)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| {
+            let path = entry.path();
+            path.is_file() && format::is_candidate(path)
+        })
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+pub(crate) fn validate_image_path(path: &Path) -> Result<(), String> {
     if !path.exists() || !path.is_file() {
         return Err("File not found".to_string());
     }
-    if !is_supported_image(path) {
+    if !format::is_supported_source(path) {
         return Err("Unsupported file format".to_string());
     }
     Ok(())
@@ -80,7 +124,7 @@ pub async fn handle_dropped_file(path: String) -> Result<ImageInfo, String> {
 #[tauri::command]
 pub fn validate_image_file(path: String) -> Result<bool, String> {
     let file_path = Path::new(&path);
-    Ok(file_path.exists() && file_path.is_file() && is_supported_image(file_path))
+    Ok(file_path.exists() && file_path.is_file() && format::is_supported_source(file_path))
 }
 
 #[tauri::command]
@@ -90,7 +134,8 @@ pub fn get_startup_file() -> Result<Option<String>, String> {
     // Look for image file in command line arguments (usually args[1])
     for arg in &args[1..] {
         let path = Path::new(arg);
-        if path.exists() && path.is_file() && is_supported_image(path) {
+        if path.exists() && path.is_file() && format::is_supported_source(path) {
+            scope::allow_opened(path)?;
             return Ok(Some(arg.clone()));
         }
     }
@@ -98,21 +143,36 @@ pub fn get_startup_file() -> Result<Option<String>, String> {
     Ok(None)
 }
 
-fn is_gif_path(path: &Path) -> bool {
-    path.extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.eq_ignore_ascii_case("gif"))
-        .unwrap_or(false)
-}
-
 /// Thumbnail + (non-GIF, box given) preview from one decode, both written to
 /// `cache_dir` before returning, so "thumbnail exists" implies "preview exists".
+/// The exception is a JPEG's first request: its Exif thumbnail is returned
+/// and cached as provisional without any decode, and the next request (or a
+/// preview generated in between) does the full one. A source over the decode
+/// limits is cached as "too_large" and its error starts with that marker.
 pub fn generate_and_cache(
     path: &Path,
     size: u32,
     preview_box: Option<&str>,
     cache_dir: &Path,
 ) -> Result<ThumbnailWithDimensions, String> {
+    let generated = generate_and_cache_image(path, size, preview_box, cache_dir)?;
+    Ok(ThumbnailWithDimensions {
+        thumbnail_base64: general_purpose::STANDARD.encode(&generated.jpeg),
+        original_width: generated.original_width,
+        original_height: generated.original_height,
+        preview_available: generated.preview_available,
+        provisional: generated.provisional,
+    })
+}
+
+/// [`generate_and_cache`] with the thumbnail as bytes, for the `/thumb/`
+/// route.
+pub fn generate_and_cache_image(
+    path: &Path,
+    size: u32,
+    preview_box: Option<&str>,
+    cache_dir: &Path,
+) -> Result<GeneratedThumbnail, String> {
     validate_image_path(path)?;
     let path_str = path.to_string_lossy().to_string();
     let bbox = match preview_box {
@@ -125,57 +185,125 @@ pub fn generate_and_cache(
         cache::source_stamp(path).ok_or_else(|| "Failed to stat source file".to_string())?;
     let now = cache::current_unix_time();
 
-    let (thumbnail_base64, natural_width, natural_height, stored_box) =
-        match (bbox, is_gif_path(path)) {
+    if !cache::has_provisional_thumbnail(cache_dir, &path_str, size) {
+        if let Some((jpeg, w, h, dhash)) = preview::exif_thumbnail(path, size) {
+            cache::store_thumbnail(
+                cache_dir,
+                &path_str,
+                size,
+                &jpeg,
+                &CacheEntry {
+                    thumbnail: String::new(),
+                    created: now,
+                    width: Some(w),
+                    height: Some(h),
+                    preview_box: None,
+                    source_mtime: Some(stamp.0),
+                    source_size: Some(stamp.1),
+                    dhash: Some(dhash),
+                    provisional: true,
+                },
+            )?;
+            return Ok(GeneratedThumbnail {
+                jpeg,
+                original_width: w,
+                original_height: h,
+                preview_available: false,
+                provisional: true,
+            });
+        }
+    }
+
+    let (jpeg, natural_width, natural_height, stored_box, dhash) =
+        match (bbox, format::is_gif(path)) {
             (Some(bbox), false) => {
-                let g = preview::generate(path, bbox, size)?;
-                cache::store_preview(
-                    cache_dir,
-                    &path_str,
-                    &bbox.key(),
-                    &g.preview_jpeg,
-                    &PreviewSidecar {
-                        natural_width: g.natural_width,
-                        natural_height: g.natural_height,
-                        source_mtime: stamp.0,
-                        source_size: stamp.1,
-                        created: now,
-                    },
-                )?;
+                let profile = &PreviewProfile::DEFAULT;
+                let g = preview::generate_with(path, bbox, profile, size, |g| {
+                    cache::store_preview(
+                        cache_dir,
+                        &path_str,
+                        &profile.cache_key(bbox),
+                        &g.preview_bytes,
+                        &PreviewSidecar {
+                            natural_width: g.natural_width,
+                            natural_height: g.natural_height,
+                            source_mtime: stamp.0,
+                            source_size: stamp.1,
+                            created: now,
+                            format: PreviewFormat::Jpeg,
+                        },
+                    )
+                })
+                .map_err(|e| record_too_large(cache_dir, &path_str, size, stamp, e))?;
                 (
-                    g.thumbnail_base64,
+                    g.thumbnail_jpeg.clone(),
                     g.natural_width,
                     g.natural_height,
                     Some(bbox.key()),
+                    g.dhash,
                 )
             }
             _ => {
-                let (b64, w, h) = preview::thumbnail_only(path, size)?;
-                (b64, w, h, None)
+                let (jpeg, w, h, dhash) = preview::thumbnail_only(path, size)
+                    .map_err(|e| record_too_large(cache_dir, &path_str, size, stamp, e))?;
+                (jpeg, w, h, None, dhash)
             }
         };
-    cache::store_thumbnail_entry(
+    cache::store_thumbnail(
         cache_dir,
         &path_str,
         size,
+        &jpeg,
         &CacheEntry {
-            thumbnail: thumbnail_base64.clone(),
+            thumbnail: String::new(),
             created: now,
             width: Some(natural_width),
             height: Some(natural_height),
             preview_box: stored_box.clone(),
             source_mtime: Some(stamp.0),
             source_size: Some(stamp.1),
+            dhash: Some(dhash),
+            provisional: false,
         },
     )?;
-    Ok(ThumbnailWithDimensions {
-        thumbnail_base64,
+    Ok(GeneratedThumbnail {
+        jpeg,
         original_width: natural_width,
         original_height: natural_height,
         preview_available: stored_box.is_some(),
+        provisional: false,
     })
 }
 
+/// Stores the permanent "too_large" entry for a source over the decode
+/// limits (other failures are recorded by the frontend) and hands the error
+/// on as the command's message.
+fn record_too_large(
+    cache_dir: &Path,
+    path_str: &str,
+    size: u32,
+    stamp: (u64, u64),
+    e: DecodeError,
+) -> String {
+    if let DecodeError::TooLarge { width, height } = e {
+        let entry = CacheEntry {
+            thumbnail: TOO_LARGE.to_string(),
+            created: cache::current_unix_time(),
+            width: Some(width),
+            height: Some(height),
+            preview_box: None,
+            source_mtime: Some(stamp.0),
+            source_size: Some(stamp.1),
+            dhash: None,
+            provisional: false,
+        };
+        if let Err(err) = cache::store_thumbnail_entry(cache_dir, path_str, size, &entry) {
+            eprintln!("thumbnail: not recording {path_str} as too large ({err})");
+        }
+    }
+    e.into()
+}
+
 #[tauri::command]
 pub async fn generate_thumbnail_with_dimensions(
     path: String,
@@ -287,7 +415,7 @@ pub fn open_with_dialog(path: String) -> Result<(), String> {
     }
 }
 
-fn get_image_info(path: &Path) -> Result<ImageInfo, String> {
+pub(crate) fn get_image_info(path: &Path) -> Result<ImageInfo, String> {
     let metadata =
         fs::metadata(path).map_err(|e| format!("Failed to read file metadata: {}", e))?;
 
@@ -297,11 +425,9 @@ fn get_image_info(path: &Path) -> Result<ImageInfo, String> {
         .unwrap_or("unknown")
         .to_string();
 
-    let format = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_lowercase();
+    let detection = format::detect(path)
+        .filter(|d| d.format.is_decodable())
+        .ok_or_else(|| "Unsupported file format".to_string())?;
 
     let modified = metadata
         .modified()
@@ -311,15 +437,18 @@ fn get_image_info(path: &Path) -> Result<ImageInfo, String> {
         .as_secs();
 
     // Note: Image validation is deferred to actual image loading time (spica-img protocol serve,
-    // generate_thumbnail) to avoid opening 900+ files during folder scan, which causes significant
-    // delays. Corrupted images will be detected when actually loaded via image::open() / browser decode.
+    // generate_thumbnail) to avoid decoding 900+ files during folder scan, which causes significant
+    // delays. Only the first bytes are read here (and cached per stamp) to sniff the format.
+    // Corrupted images will be detected when actually loaded via image::open() / browser decode.
 
     Ok(ImageInfo {
         path: path.to_string_lossy().to_string(),
         filename,
         size: metadata.len(),
         modified,
-        format,
+        format: detection.format.name().to_string(),
+        format_mismatch: detection.mismatch,
+        date_taken: None,
     })
 }
 
@@ -379,12 +508,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn generate_and_cache_serves_the_exif_thumbnail_first_then_decodes() {
+        let dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let img = create_jpeg_with_exif_thumbnail(dir.path(), "cam.jpg", (960, 640), (160, 107), 1);
+        let p = img.to_string_lossy().to_string();
+        let first = generate_and_cache(&img, 20, Some("1920x1080"), cache.path()).unwrap();
+        assert!(first.provisional);
+        assert!(!first.preview_available);
+        assert_eq!((first.original_width, first.original_height), (960, 640));
+        assert!(cache::has_provisional_thumbnail(cache.path(), &p, 20));
+        assert!(cache::lookup_thumbnail(cache.path(), &p, 20, None).is_some());
+        let second = generate_and_cache(&img, 20, Some("1920x1080"), cache.path()).unwrap();
+        assert!(!second.provisional);
+        assert!(second.preview_available);
+        assert!(!cache::has_provisional_thumbnail(cache.path(), &p, 20));
+    }
+
+    #[test]
+    fn generate_and_cache_records_an_oversized_source_as_too_large() {
+        let dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let bomb = create_png_bomb(dir.path(), "bomb.png", 60000, 60000);
+        let p = bomb.to_string_lossy().to_string();
+        let err = generate_and_cache(&bomb, 20, Some("1920x1080"), cache.path()).unwrap_err();
+        assert!(err.starts_with(TOO_LARGE), "{err}");
+        assert_eq!(
+            cache::lookup_thumbnail(cache.path(), &p, 20, Some("1920x1080")),
+            Some((TOO_LARGE.to_string(), Some(60000), Some(60000)))
+        );
+    }
+
     #[test]
     fn generate_and_cache_rejects_invalid_box() {
         let dir = create_temp_dir();
         let cache = create_temp_dir();
         let img = create_gradient_jpeg(dir.path(), "a.jpg", 640, 480);
-        assert!(generate_and_cache(&img, 20, Some("999x999"), cache.path()).is_err());
+        assert!(generate_and_cache(&img, 20, Some("1920x1080@9"), cache.path()).is_err());
     }
 
     #[tokio::test]
@@ -407,7 +568,7 @@ mod tests {
         create_test_png(temp_dir.path(), "image2.png");
         create_test_gif(temp_dir.path(), "image3.gif");
 
-        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string()).await;
+        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None).await;
         assert!(result.is_ok());
 
         let images = result.unwrap();
@@ -421,7 +582,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_folder_images_with_invalid_folder() {
-        let result = get_folder_images("/nonexistent/path".to_string()).await;
+        let result = get_folder_images("/nonexistent/path".to_string(), None).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Invalid folder path"));
     }
@@ -430,7 +591,7 @@ mod tests {
     async fn test_get_folder_images_with_empty_folder() {
         let temp_dir = create_temp_dir();
 
-        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string()).await;
+        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None).await;
         assert!(result.is_ok());
 
         let images = result.unwrap();
@@ -446,7 +607,7 @@ mod tests {
         create_invalid_image(temp_dir.path(), "textfile.txt");
         create_test_png(temp_dir.path(), "image2.png");
 
-        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string()).await;
+        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None).await;
         assert!(result.is_ok());
 
         let images = result.unwrap();
@@ -463,7 +624,7 @@ mod tests {
         create_test_jpeg(temp_dir.path(), "valid.jpg");
         create_fake_image(temp_dir.path(), "corrupted.jpg");
 
-        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string()).await;
+        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None).await;
         assert!(result.is_ok());
 
         let images = result.unwrap();
@@ -473,6 +634,31 @@ mod tests {
         assert_eq!(images[1].filename, "valid.jpg");
     }
 
+    #[tokio::test]
+    async fn test_get_folder_images_sniffs_misnamed_and_extensionless_files() {
+        let temp_dir = create_temp_dir();
+        let png = create_test_png(temp_dir.path(), "a.png");
+        fs::copy(&png, temp_dir.path().join("b_png_named.jpg")).unwrap();
+        fs::copy(&png, temp_dir.path().join("c_no_extension")).unwrap();
+        fs::write(temp_dir.path().join("d_not_an_image"), "hello").unwrap();
+
+        let images = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None)
+            .await
+            .unwrap();
+        let names: Vec<_> = images.iter().map(|i| i.filename.as_str()).collect();
+        assert_eq!(names, ["a.png", "b_png_named.jpg", "c_no_extension"]);
+        assert!(images.iter().all(|i| i.format == "png"));
+        assert_eq!(images[0].format_mismatch, None);
+        assert_eq!(
+            images[1].format_mismatch,
+            Some(FormatMismatch {
+                extension: Some("jpg".to_string()),
+                detected: "png".to_string(),
+            })
+        );
+        assert_eq!(images[2].format_mismatch.as_ref().unwrap().extension, None);
+    }
+
     #[tokio::test]
     async fn test_get_folder_images_with_subdirectories() {
         let temp_dir = create_temp_dir();
@@ -485,7 +671,7 @@ mod tests {
         fs::create_dir(&sub_dir).unwrap();
         create_test_png(&sub_dir, "sub.png");
 
-        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string()).await;
+        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None).await;
         assert!(result.is_ok());
 
         let images = result.unwrap();
@@ -503,13 +689,44 @@ mod tests {
         create_test_jpeg(temp_dir.path(), "upper.JPG");
         create_test_jpeg(temp_dir.path(), "mixed.Jpeg");
 
-        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string()).await;
+        let result = get_folder_images(temp_dir.path().to_string_lossy().to_string(), None).await;
         assert!(result.is_ok());
 
         let images = result.unwrap();
         assert_eq!(images.len(), 3);
     }
 
+    #[tokio::test]
+    async fn test_get_folder_images_sorts_naturally_and_by_date_taken() {
+        let temp_dir = create_temp_dir();
+        let dir = temp_dir.path();
+        let shot = |name: &str, date: &str| {
+            let blob = exif_date_blob(date);
+            let mut payload = b"Exif\0\0".to_vec();
+            payload.extend(blob);
+            let jpeg = insert_jpeg_segments(gradient_jpeg_bytes(8, 8), &[(0xE1, payload)]);
+            fs::write(dir.join(name), jpeg).unwrap();
+        };
+        shot("IMG_10.jpg", "2024:01:01 10:00:00");
+        shot("IMG_2.jpg", "2024:01:01 12:00:00");
+        shot("DSC_5.jpg", "2024:01:01 11:00:00");
+        let folder = dir.to_string_lossy().to_string();
+
+        let images = get_folder_images(folder.clone(), None).await.unwrap();
+        let names: Vec<_> = images.iter().map(|i| i.filename.as_str()).collect();
+        assert_eq!(names, ["DSC_5.jpg", "IMG_2.jpg", "IMG_10.jpg"]);
+        assert!(images.iter().all(|i| i.date_taken.is_none()));
+
+        let by_date = SortSpec {
+            key: SortKey::DateTaken,
+            descending: true,
+        };
+        let images = get_folder_images(folder, Some(by_date)).await.unwrap();
+        let names: Vec<_> = images.iter().map(|i| i.filename.as_str()).collect();
+        assert_eq!(names, ["IMG_2.jpg", "DSC_5.jpg", "IMG_10.jpg"]);
+        assert!(images.iter().all(|i| i.date_taken.is_some()));
+    }
+
     #[tokio::test]
     async fn test_handle_dropped_file_with_valid_image() {
         let temp_dir = create_temp_dir();
@@ -520,7 +737,8 @@ mod tests {
 
         let image_info = result.unwrap();
         assert_eq!(image_info.filename, "dropped.jpg");
-        assert_eq!(image_info.format, "jpg");
+        assert_eq!(image_info.format, "jpeg");
+        assert_eq!(image_info.format_mismatch, None);
     }
 
     #[tokio::test]
@@ -0,0 +1,635 @@
+use crate::commands::cache;
+use crate::commands::file::{get_image_info, validate_image_path, ImageInfo};
+use crate::utils::format;
+use crate::utils::scope;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Operations kept for `undo_file_operation`; older ones fall off the end.
+pub const UNDO_JOURNAL_LEN: usize = 50;
+
+/// A completed, undoable file operation. Permanent deletes are never
+/// journaled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileOperation {
+    Trashed { path: String },
+    Renamed { from: String, to: String },
+    Moved { from: String, to: String },
+    Copied { from: String, to: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoneOperation {
+    pub operation: FileOperation,
+    /// The file back at its original path; `None` when undoing a copy.
+    pub image: Option<ImageInfo>,
+}
+
+/// Most recent operation last, capped at `cap` entries.
+#[derive(Debug)]
+pub struct Journal {
+    ops: VecDeque<FileOperation>,
+    cap: usize,
+}
+
+impl Journal {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            ops: VecDeque::new(),
+            cap,
+        }
+    }
+
+    pub fn push(&mut self, op: FileOperation) {
+        if self.cap == 0 {
+            return;
+        }
+        if self.ops.len() == self.cap {
+            self.ops.pop_front();
+        }
+        self.ops.push_back(op);
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// The session's journal; lost on exit like any other undo history.
+fn journal() -> &'static Mutex<Journal> {
+    static JOURNAL: OnceLock<Mutex<Journal>> = OnceLock::new();
+    JOURNAL.get_or_init(|| Mutex::new(Journal::new(UNDO_JOURNAL_LEN)))
+}
+
+fn lossy(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+/// Sends `path` to the XDG trash (Linux), the Recycle Bin (Windows) or the
+/// Trash (macOS). Returns the image as it was before it went.
+pub fn trash(path: &Path, cache_dir: &Path, journal: &mut Journal) -> Result<ImageInfo, String> {
+    validate_image_path(path)?;
+    let info = get_image_info(path)?;
+    trash::delete(path).map_err(|e| format!("Failed to move to trash: {e}"))?;
+    cache::invalidate_source(cache_dir, &info.path);
+    journal.push(FileOperation::Trashed {
+        path: info.path.clone(),
+    });
+    Ok(info)
+}
+
+/// Removes `path` for good. Cannot be undone.
+pub fn delete_permanently(path: &Path, cache_dir: &Path) -> Result<ImageInfo, String> {
+    validate_image_path(path)?;
+    let info = get_image_info(path)?;
+    fs::remove_file(path).map_err(|e| format!("Failed to delete file: {e}"))?;
+    cache::invalidate_source(cache_dir, &info.path);
+    Ok(info)
+}
+
+/// Renames `path` within its folder. `new_name` must be a bare file name that
+/// the folder listing would still pick up.
+pub fn rename(
+    path: &Path,
+    new_name: &str,
+    cache_dir: &Path,
+    journal: &mut Journal,
+) -> Result<ImageInfo, String> {
+    validate_image_path(path)?;
+    if new_name.is_empty()
+        || Path::new(new_name).file_name() != Some(new_name.as_ref())
+        || new_name.contains(['/', '\\'])
+    {
+        return Err(format!("Invalid file name: {new_name}"));
+    }
+    if !format::is_candidate(Path::new(new_name)) {
+        return Err(format!("Unsupported file extension: {new_name}"));
+    }
+    let target = path.with_file_name(new_name);
+    relocate(path, &target, cache_dir)?;
+    let (from, to) = (lossy(path), lossy(&target));
+    journal.push(FileOperation::Renamed { from, to });
+    get_image_info(&target)
+}
+
+/// Moves `path` into `dest_dir`, keeping its name.
+pub fn move_to(
+    path: &Path,
+    dest_dir: &Path,
+    cache_dir: &Path,
+    journal: &mut Journal,
+) -> Result<ImageInfo, String> {
+    validate_image_path(path)?;
+    let target = target_in(path, dest_dir)?;
+    if target == path {
+        return Err("File is already in that folder".to_string());
+    }
+    relocate(path, &target, cache_dir)?;
+    let (from, to) = (lossy(path), lossy(&target));
+    journal.push(FileOperation::Moved { from, to });
+    get_image_info(&target)
+}
+
+/// Copies `path` into `dest_dir`, keeping its name and modification time.
+pub fn copy_to(path: &Path, dest_dir: &Path, journal: &mut Journal) -> Result<ImageInfo, String> {
+    validate_image_path(path)?;
+    let target = target_in(path, dest_dir)?;
+    copy_preserving_mtime(path, &target)?;
+    let (from, to) = (lossy(path), lossy(&target));
+    journal.push(FileOperation::Copied { from, to });
+    get_image_info(&target)
+}
+
+/// Reverts the most recent journaled operation. `Ok(None)` when there is
+/// nothing left to undo; on failure the operation stays in the journal.
+pub fn undo(cache_dir: &Path, journal: &mut Journal) -> Result<Option<UndoneOperation>, String> {
+    let Some(op) = journal.ops.pop_back() else {
+        return Ok(None);
+    };
+    match revert(&op, cache_dir) {
+        Ok(image) => Ok(Some(UndoneOperation {
+            operation: op,
+            image,
+        })),
+        Err(e) => {
+            journal.ops.push_back(op);
+            Err(e)
+        }
+    }
+}
+
+fn revert(op: &FileOperation, cache_dir: &Path) -> Result<Option<ImageInfo>, String> {
+    match op {
+        FileOperation::Trashed { path } => {
+            restore_from_trash(Path::new(path))?;
+            get_image_info(Path::new(path)).map(Some)
+        }
+        FileOperation::Renamed { from, to } | FileOperation::Moved { from, to } => {
+            relocate(Path::new(to), Path::new(from), cache_dir)?;
+            get_image_info(Path::new(from)).map(Some)
+        }
+        FileOperation::Copied { to, .. } => {
+            let copy = Path::new(to);
+            if copy.is_file() {
+                fs::remove_file(copy).map_err(|e| format!("Failed to remove copy: {e}"))?;
+            }
+            cache::invalidate_source(cache_dir, to);
+            Ok(None)
+        }
+    }
+}
+
+/// `dest_dir/<file name of path>`, provided `dest_dir` is a folder.
+fn target_in(path: &Path, dest_dir: &Path) -> Result<PathBuf, String> {
+    if !dest_dir.is_dir() {
+        return Err("Invalid folder path".to_string());
+    }
+    let name = path.file_name().ok_or("File not found")?;
+    Ok(dest_dir.join(name))
+}
+
+/// Renames `from` to `to` (copy + delete across volumes) and carries its
+/// cache entries along. Never overwrites: an existing `to` is an error unless
+/// it is `from` itself, i.e. a case-only rename on a case-insensitive volume.
+/// The filesystem enforces that, so a file appearing at `to` after the
+/// checks is not clobbered either.
+fn relocate(from: &Path, to: &Path, cache_dir: &Path) -> Result<(), String> {
+    if !from.is_file() {
+        return Err("File not found".to_string());
+    }
+    let renamed = if to.exists() && is_same_file(from, to) {
+        fs::rename(from, to)
+    } else {
+        rename_no_clobber(from, to)
+    };
+    match renamed {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return Err(format!("{} already exists", to.display()));
+        }
+        // Another volume, or no hard links on this one.
+        Err(_) => {
+            copy_preserving_mtime(from, to)?;
+            if let Err(e) = fs::remove_file(from) {
+                let _ = fs::remove_file(to);
+                return Err(format!("Failed to move file: {e}"));
+            }
+        }
+    }
+    cache::rekey_source(cache_dir, &lossy(from), &lossy(to));
+    Ok(())
+}
+
+/// `fs::rename` without its replace-existing semantics: a new link at `to`
+/// (which fails when anything is there), then `from` unlinked.
+#[cfg(unix)]
+fn rename_no_clobber(from: &Path, to: &Path) -> io::Result<()> {
+    fs::hard_link(from, to)?;
+    if let Err(e) = fs::remove_file(from) {
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// `MoveFileExW` without `MOVEFILE_REPLACE_EXISTING` (which `fs::rename`
+/// passes), so an existing `to` fails the call. Without
+/// `MOVEFILE_COPY_ALLOWED` a move across volumes fails too; the caller copies.
+#[cfg(windows)]
+fn rename_no_clobber(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{MoveFileExW, MOVE_FILE_FLAGS};
+
+    let wide = |p: &Path| -> Vec<u16> { p.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (from, to) = (wide(from), wide(to));
+    unsafe {
+        MoveFileExW(
+            PCWSTR(from.as_ptr()),
+            PCWSTR(to.as_ptr()),
+            MOVE_FILE_FLAGS(0),
+        )
+    }
+    .map_err(io::Error::from)
+}
+
+/// Keeping the mtime keeps the source stamp, so cache entries moved to the
+/// new path stay valid. Like [`rename_no_clobber`], never replaces an
+/// existing `to`.
+fn copy_preserving_mtime(from: &Path, to: &Path) -> Result<(), String> {
+    let mut source = fs::File::open(from).map_err(|e| format!("Failed to copy file: {e}"))?;
+    let mut target = match fs::File::options().write(true).create_new(true).open(to) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return Err(format!("{} already exists", to.display()));
+        }
+        Err(e) => return Err(format!("Failed to copy file: {e}")),
+    };
+    if let Err(e) = io::copy(&mut source, &mut target) {
+        drop(target);
+        let _ = fs::remove_file(to);
+        return Err(format!("Failed to copy file: {e}"));
+    }
+    if let Ok(meta) = source.metadata() {
+        let _ = target.set_permissions(meta.permissions());
+        if let Ok(modified) = meta.modified() {
+            let _ = target.set_modified(modified);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(x), Ok(y)) => x.dev() == y.dev() && x.ino() == y.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => false,
+    }
+}
+
+/// Puts the most recently trashed file that came from `path` back there.
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore_from_trash(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+    let item = trash::os_limited::list()
+        .map_err(|e| format!("Failed to read trash: {e}"))?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| format!("{} is no longer in the trash", path.display()))?;
+    trash::os_limited::restore_all([item]).map_err(|e| format!("Failed to restore from trash: {e}"))
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore_from_trash(_path: &Path) -> Result<(), String> {
+    Err("Restoring from the trash is not supported on this platform".to_string())
+}
+
+/// The commands only touch files in folders the user opened, the same
+/// allowlist the `spica-img` protocol serves from (`utils::scope`).
+fn check_scope(path: &Path) -> Result<(), String> {
+    scope::check(path).map_err(|rejection| {
+        format!(
+            "{} is outside the opened folders ({})",
+            path.display(),
+            rejection.reason()
+        )
+    })
+}
+
+fn with_journal<T>(op: impl FnOnce(&Path, &mut Journal) -> Result<T, String>) -> Result<T, String> {
+    let cache_dir = cache::get_cache_dir()?;
+    let mut journal = journal()
+        .lock()
+        .map_err(|_| "undo journal poisoned".to_string())?;
+    op(&cache_dir, &mut journal)
+}
+
+#[tauri::command]
+pub async fn trash_image(path: String) -> Result<ImageInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("trash", &path);
+        check_scope(Path::new(&path))?;
+        with_journal(|cache_dir, journal| trash(Path::new(&path), cache_dir, journal))
+    })
+    .await
+    .map_err(|e| format!("trash task failed: {e}"))?
+}
+
+#[tauri::command]
+pub async fn delete_image(path: String) -> Result<ImageInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("delete", &path);
+        check_scope(Path::new(&path))?;
+        let cache_dir = cache::get_cache_dir()?;
+        delete_permanently(Path::new(&path), &cache_dir)
+    })
+    .await
+    .map_err(|e| format!("delete task failed: {e}"))?
+}
+
+#[tauri::command]
+pub async fn rename_image(path: String, new_name: String) -> Result<ImageInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("rename", &path);
+        // The new name stays in the same folder.
+        check_scope(Path::new(&path))?;
+        with_journal(|cache_dir, journal| rename(Path::new(&path), &new_name, cache_dir, journal))
+    })
+    .await
+    .map_err(|e| format!("rename task failed: {e}"))?
+}
+
+#[tauri::command]
+pub async fn copy_image(path: String, dest_dir: String) -> Result<ImageInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("copy", &path);
+        check_scope(Path::new(&path))?;
+        check_scope(Path::new(&dest_dir))?;
+        with_journal(|_, journal| copy_to(Path::new(&path), Path::new(&dest_dir), journal))
+    })
+    .await
+    .map_err(|e| format!("copy task failed: {e}"))?
+}
+
+#[tauri::command]
+pub async fn move_image(path: String, dest_dir: String) -> Result<ImageInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("move", &path);
+        check_scope(Path::new(&path))?;
+        check_scope(Path::new(&dest_dir))?;
+        with_journal(|cache_dir, journal| {
+            move_to(Path::new(&path), Path::new(&dest_dir), cache_dir, journal)
+        })
+    })
+    .await
+    .map_err(|e| format!("move task failed: {e}"))?
+}
+
+/// Reverts the most recent trash, rename, move or copy of this session.
+/// Resolves to `null` when there is nothing to undo.
+#[tauri::command]
+pub async fn undo_file_operation() -> Result<Option<UndoneOperation>, String> {
+    tauri::async_runtime::spawn_blocking(|| with_journal(undo))
+        .await
+        .map_err(|e| format!("undo task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::file::generate_and_cache;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_rename_keeps_cache_entries_and_undo_renames_back() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let mut journal = Journal::new(UNDO_JOURNAL_LEN);
+        let img = create_gradient_jpeg(dir.path(), "a.jpg", 64, 48);
+        generate_and_cache(&img, 20, Some("1920x1080"), cache_dir.path()).unwrap();
+
+        let info = rename(&img, "b.jpg", cache_dir.path(), &mut journal).unwrap();
+        assert_eq!(info.filename, "b.jpg");
+        assert!(!img.exists());
+        let renamed = dir.path().join("b.jpg").to_string_lossy().to_string();
+        assert!(
+            cache::lookup_thumbnail(cache_dir.path(), &renamed, 20, Some("1920x1080")).is_some()
+        );
+
+        let undone = undo(cache_dir.path(), &mut journal).unwrap().unwrap();
+        assert!(matches!(undone.operation, FileOperation::Renamed { .. }));
+        assert_eq!(undone.image.unwrap().filename, "a.jpg");
+        let original = img.to_string_lossy().to_string();
+        assert!(
+            cache::lookup_thumbnail(cache_dir.path(), &original, 20, Some("1920x1080")).is_some()
+        );
+        assert!(undo(cache_dir.path(), &mut journal).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rename_rejects_bad_names_and_existing_targets() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let mut journal = Journal::new(UNDO_JOURNAL_LEN);
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        create_test_png(dir.path(), "b.png");
+        for bad in ["", "..", "sub/c.jpg", "..\\c.jpg"] {
+            let err = rename(&img, bad, cache_dir.path(), &mut journal).unwrap_err();
+            assert!(err.contains("Invalid file name"), "{bad}: {err}");
+        }
+        let err = rename(&img, "a.txt", cache_dir.path(), &mut journal).unwrap_err();
+        assert!(err.contains("Unsupported file extension"));
+        let err = rename(&img, "b.png", cache_dir.path(), &mut journal).unwrap_err();
+        assert!(err.contains("already exists"));
+        assert!(img.exists());
+        assert!(journal.is_empty());
+    }
+
+    #[test]
+    fn test_move_and_copy_into_a_folder_then_undo() {
+        let dir = create_temp_dir();
+        let dest = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let mut journal = Journal::new(UNDO_JOURNAL_LEN);
+        let a = create_test_jpeg(dir.path(), "a.jpg");
+        let b = create_test_png(dir.path(), "b.png");
+
+        let copied = copy_to(&a, dest.path(), &mut journal).unwrap();
+        assert!(a.exists());
+        assert_eq!(copied.modified, get_image_info(&a).unwrap().modified);
+        let err = copy_to(&a, dest.path(), &mut journal).unwrap_err();
+        assert!(err.contains("already exists"));
+
+        let moved = move_to(&b, dest.path(), cache_dir.path(), &mut journal).unwrap();
+        assert!(!b.exists());
+        assert_eq!(Path::new(&moved.path), dest.path().join("b.png"));
+        assert_eq!(journal.len(), 2);
+
+        undo(cache_dir.path(), &mut journal).unwrap();
+        assert!(b.exists());
+        undo(cache_dir.path(), &mut journal).unwrap();
+        assert!(!dest.path().join("a.jpg").exists());
+        assert!(a.exists());
+        assert!(journal.is_empty());
+    }
+
+    #[cfg(any(
+        target_os = "windows",
+        all(
+            unix,
+            not(target_os = "macos"),
+            not(target_os = "ios"),
+            not(target_os = "android")
+        )
+    ))]
+    #[test]
+    fn test_trash_then_undo_restores_the_file() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let mut journal = Journal::new(UNDO_JOURNAL_LEN);
+        let img = create_test_jpeg(dir.path(), "trash_me.jpg");
+        let info = trash(&img, cache_dir.path(), &mut journal).unwrap();
+        assert_eq!(info.filename, "trash_me.jpg");
+        assert!(!img.exists());
+
+        let undone = undo(cache_dir.path(), &mut journal).unwrap().unwrap();
+        assert_eq!(undone.image.unwrap().path, info.path);
+        assert!(img.exists());
+    }
+
+    #[test]
+    fn test_failed_undo_stays_in_the_journal() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let mut journal = Journal::new(UNDO_JOURNAL_LEN);
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        rename(&img, "b.jpg", cache_dir.path(), &mut journal).unwrap();
+        // Something else took the old name in the meantime.
+        create_test_jpeg(dir.path(), "a.jpg");
+        let err = undo(cache_dir.path(), &mut journal).unwrap_err();
+        assert!(err.contains("already exists"));
+        assert_eq!(journal.len(), 1);
+    }
+
+    #[test]
+    fn test_no_clobber_rename_and_copy_leave_an_existing_target_alone() {
+        let dir = create_temp_dir();
+        let a = create_test_jpeg(dir.path(), "a.jpg");
+        let b = create_test_png(dir.path(), "b.png");
+        let before = fs::read(&b).unwrap();
+        let err = rename_no_clobber(&a, &b).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        let err = copy_preserving_mtime(&a, &b).unwrap_err();
+        assert!(err.contains("already exists"));
+        assert!(a.exists());
+        assert_eq!(fs::read(&b).unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn test_commands_refuse_files_outside_the_opened_folders() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let path = img.to_string_lossy().to_string();
+        let dest = dir.path().to_string_lossy().to_string();
+        let err = move_image(path.clone(), dest.clone()).await.unwrap_err();
+        assert!(err.contains("outside the opened folders"), "{err}");
+        let err = trash_image(path.clone()).await.unwrap_err();
+        assert!(err.contains("outside the opened folders"), "{err}");
+
+        // An opened source still needs an opened destination.
+        scope::allow_folder(dir.path()).unwrap();
+        let elsewhere = create_temp_dir();
+        let err = copy_image(path, elsewhere.path().to_string_lossy().to_string())
+            .await
+            .unwrap_err();
+        assert!(err.contains("outside the opened folders"), "{err}");
+        assert!(img.exists());
+    }
+
+    #[test]
+    fn test_a_copy_into_an_opened_folder_stays_in_scope() {
+        let dir = create_temp_dir();
+        let dest = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        scope::allow_folder(dir.path()).unwrap();
+        // What pick_destination_folder does with the user's choice.
+        scope::allow_folder(dest.path()).unwrap();
+        // The checks copy_image makes, then its copy, off the real cache
+        // folder and undo journal.
+        check_scope(&img).unwrap();
+        check_scope(dest.path()).unwrap();
+        let mut journal = Journal::new(UNDO_JOURNAL_LEN);
+        let info = copy_to(&img, dest.path(), &mut journal).unwrap();
+        assert_eq!(scope::check(Path::new(&info.path)), Ok(()));
+    }
+
+    #[test]
+    fn test_delete_permanently_drops_file_and_cache() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let img = create_gradient_jpeg(dir.path(), "a.jpg", 64, 48);
+        generate_and_cache(&img, 20, Some("1920x1080"), cache_dir.path()).unwrap();
+        let info = delete_permanently(&img, cache_dir.path()).unwrap();
+        assert_eq!(info.filename, "a.jpg");
+        assert!(!img.exists());
+        assert_eq!(cache_entry_count(cache_dir.path()), 0);
+        let err = delete_permanently(&img, cache_dir.path()).unwrap_err();
+        assert!(err.contains("File not found"));
+    }
+
+    #[test]
+    fn test_journal_keeps_only_the_latest_operations() {
+        let mut journal = Journal::new(2);
+        for i in 0..3 {
+            journal.push(FileOperation::Trashed {
+                path: format!("/{i}.jpg"),
+            });
+        }
+        assert_eq!(journal.len(), 2);
+        assert_eq!(
+            journal.ops.front(),
+            Some(&FileOperation::Trashed {
+                path: "/1.jpg".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::commands::cache::{self, MetadataEntry};
+use crate::commands::file::validate_image_path;
+use crate::utils::metadata::{self, ImageMetadata};
+use std::path::Path;
+
+/// Metadata for `path`, parsed once per source stamp and then served from
+/// `cache_dir`.
+pub fn metadata_cached(path: &Path, cache_dir: &Path) -> Result<ImageMetadata, String> {
+    let path_str = path.to_string_lossy().to_string();
+    if let Some(m) = cache::lookup_metadata(cache_dir, &path_str) {
+        return Ok(m);
+    }
+    let stamp =
+        cache::source_stamp(path).ok_or_else(|| "Failed to stat source file".to_string())?;
+    let metadata = metadata::read_metadata(path)?;
+    cache::store_metadata(
+        cache_dir,
+        &path_str,
+        &MetadataEntry {
+            metadata: metadata.clone(),
+            created: cache::current_unix_time(),
+            source_mtime: stamp.0,
+            source_size: stamp.1,
+        },
+    )?;
+    Ok(metadata)
+}
+
+#[tauri::command]
+pub async fn get_image_metadata(path: String) -> Result<ImageMetadata, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("metadata", &path);
+        validate_image_path(Path::new(&path))?;
+        let cache_dir = cache::get_cache_dir()?;
+        metadata_cached(Path::new(&path), &cache_dir)
+    })
+    .await
+    .map_err(|e| format!("metadata task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs;
+
+    #[test]
+    fn test_metadata_cached_parses_once_per_source_stamp() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let img = create_jpeg_with_metadata(dir.path(), "a.jpg", 16, 16, Some(6), None);
+
+        let first = metadata_cached(&img, cache_dir.path()).unwrap();
+        assert_eq!(first.exif.as_ref().unwrap().orientation, Some(6));
+
+        // A planted entry with the current stamp proves the second call is
+        // served from the cache rather than re-parsed.
+        let stamp = cache::source_stamp(&img).unwrap();
+        let planted = ImageMetadata {
+            icc_profile: Some("from cache".to_string()),
+            ..ImageMetadata::default()
+        };
+        cache::store_metadata(
+            cache_dir.path(),
+            &img.to_string_lossy(),
+            &MetadataEntry {
+                metadata: planted.clone(),
+                created: cache::current_unix_time(),
+                source_mtime: stamp.0,
+                source_size: stamp.1,
+            },
+        )
+        .unwrap();
+        assert_eq!(metadata_cached(&img, cache_dir.path()).unwrap(), planted);
+
+        // Replacing the source invalidates the entry.
+        fs::copy(create_test_png(dir.path(), "b.png"), &img).unwrap();
+        assert_eq!(
+            metadata_cached(&img, cache_dir.path()).unwrap(),
+            ImageMetadata::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_image_metadata_rejects_unsupported_files() {
+        let dir = create_temp_dir();
+        let txt = dir.path().join("notes.txt");
+        fs::write(&txt, "x").unwrap();
+        let result = get_image_metadata(txt.to_string_lossy().to_string()).await;
+        assert!(result.unwrap_err().contains("Unsupported file format"));
+    }
+}
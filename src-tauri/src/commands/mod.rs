@@ -1,3 +1,11 @@
 pub mod cache;
+pub mod dialog;
+pub mod duplicates;
 pub mod file;
+pub mod fileops;
+pub mod metadata;
+pub mod prefetch;
+pub mod scan;
+pub mod transform;
+pub mod watch;
 pub mod window;
@@ -0,0 +1,616 @@
+use crate::commands::cache::{self, CacheEntry, CachedThumbnail};
+use crate::commands::file::{generate_and_cache_image, validate_image_path};
+use crate::utils::limits::TOO_LARGE;
+use crate::utils::preview::PreviewBox;
+use crate::utils::scope;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, OnceLock};
+
+/// Upper bound on prefetch workers. Each holds a full decode in memory, and
+/// the frontend's own requests (the image on screen, `/preview/`) must still
+/// find a free core.
+const MAX_WORKERS: usize = 4;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum PrefetchEvent {
+    /// `path` has been handled: `ok` when its thumbnail and preview are in
+    /// the cache (generated now or already there), false when it failed.
+    Progress {
+        path: String,
+        ok: bool,
+        done: usize,
+        total: usize,
+    },
+    /// Always the last event. `cancelled` jobs stop after the images the
+    /// workers had in hand.
+    Finished {
+        done: usize,
+        total: usize,
+        cancelled: bool,
+    },
+}
+
+type Emit = Box<dyn Fn(PrefetchEvent) -> Result<(), String> + Send + Sync>;
+
+/// Claim state: the folder's images in the frontend's order, which of them
+/// a worker has taken, the index priority follows, and how far out from it
+/// everything is already claimed.
+struct Cursor {
+    /// `None` where the frontend sent a path the job may not open; never
+    /// claimed, so indices stay the frontend's.
+    paths: Vec<Option<PathBuf>>,
+    claimed: Vec<bool>,
+    remaining: usize,
+    center: usize,
+    radius: usize,
+    /// Every path handed to a worker so far, kept across [`PrefetchJob::set_paths`].
+    taken: HashSet<PathBuf>,
+}
+
+impl Cursor {
+    fn new(paths: Vec<Option<PathBuf>>, center: usize, taken: HashSet<PathBuf>) -> Self {
+        let claimed: Vec<bool> = paths
+            .iter()
+            .map(|p| p.as_ref().is_none_or(|p| taken.contains(p)))
+            .collect();
+        Cursor {
+            remaining: claimed.iter().filter(|c| !**c).count(),
+            center: center.min(paths.len().saturating_sub(1)),
+            radius: 0,
+            paths,
+            claimed,
+            taken,
+        }
+    }
+}
+
+/// Warms the cache for one folder: every image's thumbnail and preview,
+/// nearest to the current index first.
+pub struct PrefetchJob {
+    size: u32,
+    preview_box: Option<String>,
+    cache_dir: PathBuf,
+    cursor: Mutex<Cursor>,
+    /// Images claimed and still being worked on.
+    active: AtomicUsize,
+    done: AtomicUsize,
+    cancel: AtomicBool,
+    finished: AtomicBool,
+    emit: Emit,
+}
+
+impl PrefetchJob {
+    pub fn new(
+        paths: Vec<Option<PathBuf>>,
+        current_index: usize,
+        size: u32,
+        preview_box: Option<String>,
+        cache_dir: PathBuf,
+        emit: impl Fn(PrefetchEvent) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        PrefetchJob {
+            cursor: Mutex::new(Cursor::new(paths, current_index, HashSet::new())),
+            size,
+            preview_box,
+            cache_dir,
+            active: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            cancel: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            emit: Box::new(emit),
+        }
+    }
+
+    /// Moves the priority to `index`: the images around it are claimed next.
+    pub fn set_center(&self, index: usize) {
+        if let Ok(mut cursor) = self.cursor.lock() {
+            cursor.center = index.min(cursor.paths.len().saturating_sub(1));
+            cursor.radius = 0;
+        }
+    }
+
+    /// Replaces the folder's image list (a watcher add or remove, a re-sort)
+    /// and moves the priority to `index` in it. Images already handed out are
+    /// not warmed again; new ones are queued. Returns the images left to
+    /// claim.
+    pub fn set_paths(&self, paths: Vec<Option<PathBuf>>, index: usize) -> usize {
+        let Ok(mut cursor) = self.cursor.lock() else {
+            return 0;
+        };
+        let taken = std::mem::take(&mut cursor.taken);
+        *cursor = Cursor::new(paths, index, taken);
+        if cursor.remaining > 0 && !self.cancel.load(Ordering::SeqCst) {
+            // More work after a `Finished`: another one follows it.
+            self.finished.store(false, Ordering::SeqCst);
+        }
+        cursor.remaining
+    }
+
+    /// Images this job has handed out or still will.
+    fn total(&self) -> usize {
+        self.cursor
+            .lock()
+            .map_or(0, |c| c.taken.len() + c.remaining)
+    }
+
+    /// Stops handing out images. Sends `Finished` right away when no worker
+    /// is busy with one, otherwise the last worker to finish does.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        self.finish_if_idle();
+    }
+
+    /// True while there are unclaimed images and the job is not cancelled.
+    fn has_work(&self) -> bool {
+        !self.cancel.load(Ordering::SeqCst) && self.cursor.lock().is_ok_and(|c| c.remaining > 0)
+    }
+
+    /// Claims the unclaimed image nearest the center, in the frontend's
+    /// order: center, +1, -1, +2, -2, ...
+    fn next(&self) -> Option<PathBuf> {
+        let mut cursor = self.cursor.lock().ok()?;
+        let len = cursor.claimed.len();
+        let center = cursor.center;
+        while cursor.remaining > 0 && cursor.radius < len {
+            let r = cursor.radius;
+            let below = if r == 0 { None } else { center.checked_sub(r) };
+            for i in [Some(center + r), below].into_iter().flatten() {
+                if i < len && !cursor.claimed[i] {
+                    cursor.claimed[i] = true;
+                    cursor.remaining -= 1;
+                    let path = cursor.paths[i].clone()?;
+                    cursor.taken.insert(path.clone());
+                    return Some(path);
+                }
+            }
+            cursor.radius += 1;
+        }
+        None
+    }
+
+    /// Claims and warms one image. False when there is nothing left to claim
+    /// (or the job is cancelled), so a worker can move on.
+    pub fn step(&self) -> bool {
+        if self.cancel.load(Ordering::SeqCst) {
+            self.finish_if_idle();
+            return false;
+        }
+        self.active.fetch_add(1, Ordering::SeqCst);
+        let Some(path) = self.next() else {
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            self.finish_if_idle();
+            return false;
+        };
+        let ok = self.warm(&path);
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+        let event = PrefetchEvent::Progress {
+            path: path.to_string_lossy().to_string(),
+            ok,
+            done,
+            total: self.total(),
+        };
+        // The frontend went away: nobody is waiting for the rest.
+        if (self.emit)(event).is_err() {
+            self.cancel.store(true, Ordering::SeqCst);
+        }
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        true
+    }
+
+    /// Sends `Finished` once, after the last image in hand is done.
+    fn finish_if_idle(&self) {
+        if self.active.load(Ordering::SeqCst) != 0 || self.has_work() {
+            return;
+        }
+        if self.finished.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let done = self.done.load(Ordering::SeqCst);
+        let total = self.total();
+        let _ = (self.emit)(PrefetchEvent::Finished {
+            done,
+            total,
+            cancelled: self.cancel.load(Ordering::SeqCst) && done < total,
+        });
+    }
+
+    /// Thumbnail and preview of `path` into the cache through
+    /// [`generate_and_cache`](crate::commands::file::generate_and_cache), so
+    /// I1 holds for whatever the prefetch wrote. A provisional Exif thumbnail
+    /// is followed by the full decode at once; warming is for the preview as
+    /// much as the bar. A failure is recorded as "error", like the frontend
+    /// does, so the next visit skips the file.
+    fn warm(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy().to_string();
+        let preview_box = self.preview_box.as_deref();
+        match cache::lookup_thumbnail_image(&self.cache_dir, &path_str, self.size, preview_box) {
+            Some((CachedThumbnail::Image(_), _, _)) => return true,
+            Some(_) => return false,
+            None => {}
+        }
+        let mut result = generate_and_cache_image(path, self.size, preview_box, &self.cache_dir);
+        if result.as_ref().is_ok_and(|g| g.provisional) {
+            result = generate_and_cache_image(path, self.size, preview_box, &self.cache_dir);
+        }
+        match result {
+            Ok(_) => true,
+            // Already cached as such by generate_and_cache.
+            Err(e) if e.starts_with(TOO_LARGE) => false,
+            Err(e) => {
+                eprintln!("prefetch: {path_str} failed ({e})");
+                self.record_error(path, &path_str);
+                false
+            }
+        }
+    }
+
+    fn record_error(&self, path: &Path, path_str: &str) {
+        let stamp = cache::source_stamp(path);
+        let entry = CacheEntry {
+            thumbnail: "error".to_string(),
+            created: cache::current_unix_time(),
+            width: None,
+            height: None,
+            preview_box: None,
+            source_mtime: stamp.map(|s| s.0),
+            source_size: stamp.map(|s| s.1),
+            dhash: None,
+            provisional: false,
+        };
+        if let Err(e) = cache::store_thumbnail_entry(&self.cache_dir, path_str, self.size, &entry) {
+            eprintln!("prefetch: not recording {path_str} as failed ({e})");
+        }
+    }
+}
+
+/// The job the workers take images from; a new folder replaces it.
+struct Prefetcher {
+    job: Mutex<Option<Arc<PrefetchJob>>>,
+    work: Condvar,
+}
+
+fn prefetcher() -> &'static Prefetcher {
+    static PREFETCHER: OnceLock<Prefetcher> = OnceLock::new();
+    PREFETCHER.get_or_init(|| Prefetcher {
+        job: Mutex::new(None),
+        work: Condvar::new(),
+    })
+}
+
+fn worker_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get() / 2)
+        .unwrap_or(1)
+        .clamp(1, MAX_WORKERS)
+}
+
+/// Starts the worker threads on first use. Each waits for a job with work
+/// left, then claims images from it until there are none.
+fn start_workers() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        for i in 0..worker_count() {
+            let spawned = std::thread::Builder::new()
+                .name(format!("prefetch-{i}"))
+                .spawn(|| loop {
+                    let job = {
+                        let p = prefetcher();
+                        let Ok(mut current) = p.job.lock() else {
+                            return;
+                        };
+                        loop {
+                            if let Some(job) = current.as_ref().filter(|j| j.has_work()) {
+                                break job.clone();
+                            }
+                            current = match p.work.wait(current) {
+                                Ok(guard) => guard,
+                                Err(_) => return,
+                            };
+                        }
+                    };
+                    while job.step() {}
+                });
+            if let Err(e) = spawned {
+                eprintln!("prefetch: worker not started ({e})");
+            }
+        }
+    });
+}
+
+/// Makes `job` the one the workers serve, cancelling the previous folder's.
+fn replace_job(job: Option<Arc<PrefetchJob>>) -> Result<bool, String> {
+    let p = prefetcher();
+    let old = {
+        let mut current = p
+            .job
+            .lock()
+            .map_err(|_| "prefetch queue poisoned".to_string())?;
+        std::mem::replace(&mut *current, job)
+    };
+    p.work.notify_all();
+    Ok(match old {
+        Some(old) => {
+            let running = !old.finished.load(Ordering::SeqCst);
+            old.cancel();
+            running
+        }
+        None => false,
+    })
+}
+
+/// The frontend's paths as the job takes them: `None` for one that is not a
+/// supported image or lies outside the opened folders.
+fn admit(paths: Vec<String>) -> Vec<Option<PathBuf>> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let path = PathBuf::from(path);
+            if let Err(e) = validate_image_path(&path) {
+                eprintln!("prefetch: skipping {} ({e})", path.display());
+                return None;
+            }
+            // `check` logs the rejection itself.
+            scope::check(&path).ok()?;
+            Some(path)
+        })
+        .collect()
+}
+
+/// [`admit`] off the async runtime: it stats every path.
+async fn admit_blocking(paths: Vec<String>) -> Result<Vec<Option<PathBuf>>, String> {
+    tauri::async_runtime::spawn_blocking(move || admit(paths))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Queues thumbnail and preview generation for every image of the opened
+/// folder on the background workers, the images around `current_index`
+/// first. Paths that are not supported images inside the opened folders are
+/// skipped. Replaces (and cancels) the previous folder's prefetch; progress
+/// goes to `on_event`. Resolves at once to the number of images queued.
+#[tauri::command]
+pub async fn prefetch_folder(
+    paths: Vec<String>,
+    current_index: usize,
+    size: u32,
+    preview_box: Option<String>,
+    on_event: tauri::ipc::Channel<PrefetchEvent>,
+) -> Result<usize, String> {
+    if let Some(bk) = preview_box.as_deref() {
+        PreviewBox::parse(bk).ok_or_else(|| format!("unsupported preview box: {bk}"))?;
+    }
+    let cache_dir = cache::get_cache_dir()?;
+    let paths = admit_blocking(paths).await?;
+    let total = paths.iter().flatten().count();
+    let job = PrefetchJob::new(
+        paths,
+        current_index,
+        size,
+        preview_box,
+        cache_dir,
+        move |event| on_event.send(event).map_err(|e| e.to_string()),
+    );
+    if total == 0 {
+        job.cancel();
+        replace_job(None)?;
+        return Ok(0);
+    }
+    start_workers();
+    replace_job(Some(Arc::new(job)))?;
+    Ok(total)
+}
+
+/// Moves the running prefetch's priority to the image now on screen.
+#[tauri::command]
+pub async fn set_prefetch_index(index: usize) -> Result<(), String> {
+    let current = prefetcher()
+        .job
+        .lock()
+        .map_err(|_| "prefetch queue poisoned".to_string())?;
+    if let Some(job) = current.as_ref() {
+        job.set_center(index);
+    }
+    Ok(())
+}
+
+/// Brings the running prefetch in line with the folder's image list after a
+/// watcher add or remove or a re-sort, without starting over: images it has
+/// already handled are not warmed again. Resolves to the number of images
+/// left to warm; 0 when no prefetch is running.
+#[tauri::command]
+pub async fn update_prefetch_paths(
+    paths: Vec<String>,
+    current_index: usize,
+) -> Result<usize, String> {
+    let paths = admit_blocking(paths).await?;
+    let p = prefetcher();
+    let remaining = {
+        let current = p
+            .job
+            .lock()
+            .map_err(|_| "prefetch queue poisoned".to_string())?;
+        match current.as_ref() {
+            Some(job) => job.set_paths(paths, current_index),
+            None => 0,
+        }
+    };
+    if remaining > 0 {
+        p.work.notify_all();
+    }
+    Ok(remaining)
+}
+
+/// Stops the running prefetch. Returns false when it has already finished
+/// (or none was started).
+#[tauri::command]
+pub async fn cancel_prefetch() -> Result<bool, String> {
+    replace_job(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs;
+
+    fn new_job(
+        paths: Vec<PathBuf>,
+        current_index: usize,
+        cache_dir: &Path,
+    ) -> (PrefetchJob, Arc<Mutex<Vec<PrefetchEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let job = PrefetchJob::new(
+            paths.into_iter().map(Some).collect(),
+            current_index,
+            20,
+            Some("1920x1080".to_string()),
+            cache_dir.to_path_buf(),
+            move |e| {
+                sink.lock().unwrap().push(e);
+                Ok(())
+            },
+        );
+        (job, events)
+    }
+
+    fn numbered(range: std::ops::Range<usize>) -> Vec<PathBuf> {
+        range.map(|i| PathBuf::from(format!("{i}.jpg"))).collect()
+    }
+
+    /// The number of the next claimed `<n>.jpg`.
+    fn claim(job: &PrefetchJob) -> Option<usize> {
+        let path = job.next()?;
+        path.file_stem()?.to_str()?.parse().ok()
+    }
+
+    fn claim_all(job: &PrefetchJob) -> Vec<usize> {
+        std::iter::from_fn(|| claim(job)).collect()
+    }
+
+    #[test]
+    fn test_claims_spread_out_from_the_current_index() {
+        let cache = create_temp_dir();
+        let (job, _) = new_job(numbered(0..6), 2, cache.path());
+        assert_eq!(claim(&job), Some(2));
+        assert_eq!(claim(&job), Some(3));
+        // The user moved to the last image: its neighbours come first.
+        job.set_center(5);
+        assert_eq!(claim_all(&job), [5, 4, 1, 0]);
+    }
+
+    #[test]
+    fn test_new_paths_are_queued_without_taking_old_ones_again() {
+        let cache = create_temp_dir();
+        let (job, _) = new_job(numbered(0..4), 0, cache.path());
+        assert_eq!(claim_all(&job), [0, 1, 2, 3]);
+        assert!(!job.has_work());
+        // The watcher added 4.jpg and 5.jpg and removed 1.jpg; a refused
+        // path keeps its index but is never claimed.
+        let mut paths: Vec<_> = numbered(0..6).into_iter().map(Some).collect();
+        paths.remove(1);
+        paths.insert(2, None);
+        assert_eq!(job.set_paths(paths, 5), 2);
+        assert!(job.has_work());
+        assert_eq!(claim_all(&job), [5, 4]);
+        assert_eq!(job.total(), 6);
+    }
+
+    #[test]
+    fn test_admit_refuses_unsupported_and_out_of_scope_paths() {
+        let opened = create_temp_dir();
+        let other = create_temp_dir();
+        scope::allow_folder(opened.path()).unwrap();
+        let inside = create_test_jpeg(opened.path(), "in.jpg");
+        let outside = create_test_jpeg(other.path(), "out.jpg");
+        let text = opened.path().join("notes.txt");
+        fs::write(&text, b"not an image").unwrap();
+        let paths = [&inside, &outside, &text, &opened.path().join("gone.jpg")]
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(admit(paths), [Some(inside), None, None, None]);
+    }
+
+    #[test]
+    fn test_step_warms_thumbnail_and_preview_for_every_image() {
+        let dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let paths: Vec<PathBuf> = (0..5)
+            .map(|i| create_test_jpeg(dir.path(), &format!("{i}.jpg")))
+            .collect();
+        fs::write(dir.path().join("broken.jpg"), b"not a jpeg").unwrap();
+        let mut all = paths.clone();
+        all.push(dir.path().join("broken.jpg"));
+        let (job, events) = new_job(all, 2, cache.path());
+
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| while job.step() {});
+            }
+        });
+
+        for path in &paths {
+            let hit = cache::lookup_thumbnail_image(
+                cache.path(),
+                &path.to_string_lossy(),
+                20,
+                Some("1920x1080"),
+            );
+            assert!(matches!(hit, Some((CachedThumbnail::Image(_), _, _))));
+        }
+        let broken = dir.path().join("broken.jpg");
+        let hit = cache::lookup_thumbnail_image(
+            cache.path(),
+            &broken.to_string_lossy(),
+            20,
+            Some("1920x1080"),
+        );
+        assert!(matches!(hit, Some((CachedThumbnail::Error, _, _))));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 7);
+        let failed: Vec<_> = events
+            .iter()
+            .filter(|e| matches!(e, PrefetchEvent::Progress { ok: false, .. }))
+            .collect();
+        assert_eq!(failed.len(), 1);
+        assert!(matches!(
+            events.last(),
+            Some(PrefetchEvent::Finished {
+                done: 6,
+                total: 6,
+                cancelled: false
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cancel_stops_claiming_and_finishes_once() {
+        let dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let paths: Vec<PathBuf> = (0..4)
+            .map(|i| create_test_jpeg(dir.path(), &format!("{i}.jpg")))
+            .collect();
+        let (job, events) = new_job(paths, 0, cache.path());
+        assert!(job.step());
+        job.cancel();
+        assert!(!job.step());
+        assert!(!job.has_work());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events.last(),
+            Some(PrefetchEvent::Finished {
+                done: 1,
+                total: 4,
+                cancelled: true
+            })
+        ));
+    }
+}
@@ -0,0 +1,316 @@
+use crate::commands::file::{get_image_info, ImageInfo};
+use crate::utils::format;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use walkdir::{DirEntry, WalkDir};
+
+/// Files stat'ed and sent per batch: small enough that the first thumbnails
+/// of a huge tree show up at once, large enough to keep IPC overhead low.
+pub const SCAN_BATCH_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// Descend into subfolders; otherwise only the folder's own files.
+    pub recursive: bool,
+    /// Levels below the folder to descend when recursive (1 = its direct
+    /// subfolders); `None` is unlimited.
+    pub max_depth: Option<usize>,
+    /// Include dot-files and dot-folders (and, on Windows, files and folders
+    /// with the hidden attribute).
+    pub include_hidden: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum ScanEvent {
+    /// Images found since the previous batch, sorted by path within the batch.
+    Batch { images: Vec<ImageInfo> },
+    /// Always the last event. `cancelled` scans stop at a batch boundary.
+    Finished { total: usize, cancelled: bool },
+}
+
+/// Walks `root` per `options`, sending `ScanEvent::Batch`es through `emit` as
+/// they fill up and a final `ScanEvent::Finished`. `cancel` is checked between
+/// directory entries; a failing `emit` (the frontend went away) also stops
+/// the walk.
+pub fn scan_streaming(
+    root: &Path,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    mut emit: impl FnMut(ScanEvent) -> Result<(), String>,
+) -> Result<usize, String> {
+    if !root.is_dir() {
+        return Err("Invalid folder path".to_string());
+    }
+    let depth = match (options.recursive, options.max_depth) {
+        (false, _) => 1,
+        (true, Some(levels)) => levels.saturating_add(1),
+        (true, None) => usize::MAX,
+    };
+    let include_hidden = options.include_hidden;
+    let walker = WalkDir::new(root)
+        .max_depth(depth)
+        .into_iter()
+        // The root itself is never filtered, even when it is a dot-folder.
+        .filter_entry(move |e| include_hidden || e.depth() == 0 || !is_hidden(e));
+
+    let mut total = 0usize;
+    let mut pending: Vec<PathBuf> = Vec::with_capacity(SCAN_BATCH_SIZE);
+    let mut flush = |pending: &mut Vec<PathBuf>, total: &mut usize| {
+        let mut images: Vec<ImageInfo> = pending
+            .par_iter()
+            .filter_map(|path| get_image_info(path).ok())
+            .collect();
+        pending.clear();
+        if images.is_empty() {
+            return Ok(());
+        }
+        images.sort_by(|a, b| a.path.cmp(&b.path));
+        *total += images.len();
+        emit(ScanEvent::Batch { images })
+    };
+    for entry in walker {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(entry) = entry else { continue };
+        if entry.file_type().is_file() && format::is_candidate(entry.path()) {
+            pending.push(entry.into_path());
+            if pending.len() >= SCAN_BATCH_SIZE {
+                flush(&mut pending, &mut total)?;
+            }
+        }
+    }
+    let cancelled = cancel.load(Ordering::Relaxed);
+    if !cancelled {
+        flush(&mut pending, &mut total)?;
+    }
+    emit(ScanEvent::Finished { total, cancelled })?;
+    Ok(total)
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    if entry
+        .file_name()
+        .to_str()
+        .is_some_and(|n| n.starts_with('.'))
+    {
+        return true;
+    }
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        if entry
+            .metadata()
+            .is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Cancellation flags of running scans, by the frontend-chosen scan id.
+fn running_scans() -> &'static Mutex<HashMap<u32, Arc<AtomicBool>>> {
+    static SCANS: OnceLock<Mutex<HashMap<u32, Arc<AtomicBool>>>> = OnceLock::new();
+    SCANS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Streams the images under `path` to `on_event` in batches. `scan_id` is
+/// chosen by the caller so it can cancel the scan (`cancel_folder_scan`)
+/// before this returns. Resolves to the number of images sent.
+#[tauri::command]
+pub async fn scan_folder(
+    path: String,
+    scan_id: u32,
+    options: Option<ScanOptions>,
+    on_event: tauri::ipc::Channel<ScanEvent>,
+) -> Result<usize, String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    if let Ok(mut scans) = running_scans().lock() {
+        // Reusing a live id cancels the older scan rather than orphaning it.
+        if let Some(old) = scans.insert(scan_id, cancel.clone()) {
+            old.store(true, Ordering::Relaxed);
+        }
+    }
+    let flag = cancel.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("scan", &path);
+        scan_streaming(
+            Path::new(&path),
+            &options.unwrap_or_default(),
+            &flag,
+            |event| on_event.send(event).map_err(|e| e.to_string()),
+        )
+    })
+    .await
+    .map_err(|e| format!("scan task failed: {e}"));
+    if let Ok(mut scans) = running_scans().lock() {
+        if scans.get(&scan_id).is_some_and(|c| Arc::ptr_eq(c, &cancel)) {
+            scans.remove(&scan_id);
+        }
+    }
+    result?
+}
+
+/// Stops the scan started with `scan_id`. Returns false when it has already
+/// finished (or never started).
+#[tauri::command]
+pub async fn cancel_folder_scan(scan_id: u32) -> Result<bool, String> {
+    let scans = running_scans()
+        .lock()
+        .map_err(|_| "scan registry poisoned".to_string())?;
+    Ok(match scans.get(&scan_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs;
+
+    /// root.jpg, .hidden.jpg, notes.txt, 2024/a.png, 2024/05/b.jpg and
+    /// .thumbs/c.jpg.
+    fn archive() -> tempfile::TempDir {
+        let dir = create_temp_dir();
+        create_test_jpeg(dir.path(), "root.jpg");
+        create_test_jpeg(dir.path(), ".hidden.jpg");
+        fs::write(dir.path().join("notes.txt"), "x").unwrap();
+        let year = dir.path().join("2024");
+        let month = year.join("05");
+        let thumbs = dir.path().join(".thumbs");
+        fs::create_dir_all(&month).unwrap();
+        fs::create_dir_all(&thumbs).unwrap();
+        create_test_png(&year, "a.png");
+        create_test_jpeg(&month, "b.jpg");
+        create_test_jpeg(&thumbs, "c.jpg");
+        dir
+    }
+
+    fn scan(root: &Path, options: ScanOptions) -> (Vec<String>, Vec<ScanEvent>) {
+        let mut events = Vec::new();
+        scan_streaming(root, &options, &AtomicBool::new(false), |e| {
+            events.push(e);
+            Ok(())
+        })
+        .unwrap();
+        let mut names: Vec<String> = events
+            .iter()
+            .filter_map(|e| match e {
+                ScanEvent::Batch { images } => Some(images),
+                ScanEvent::Finished { .. } => None,
+            })
+            .flatten()
+            .map(|i| i.filename.clone())
+            .collect();
+        names.sort();
+        (names, events)
+    }
+
+    #[test]
+    fn test_scan_defaults_to_the_folder_itself_without_hidden_files() {
+        let dir = archive();
+        let (names, events) = scan(dir.path(), ScanOptions::default());
+        assert_eq!(names, ["root.jpg"]);
+        assert!(matches!(
+            events.last(),
+            Some(ScanEvent::Finished {
+                total: 1,
+                cancelled: false
+            })
+        ));
+    }
+
+    #[test]
+    fn test_scan_recursive_honours_max_depth_and_hidden_policy() {
+        let dir = archive();
+        let recursive = |max_depth, include_hidden| ScanOptions {
+            recursive: true,
+            max_depth,
+            include_hidden,
+        };
+        let (names, _) = scan(dir.path(), recursive(Some(1), false));
+        assert_eq!(names, ["a.png", "root.jpg"]);
+        let (names, _) = scan(dir.path(), recursive(None, false));
+        assert_eq!(names, ["a.png", "b.jpg", "root.jpg"]);
+        let (names, _) = scan(dir.path(), recursive(None, true));
+        assert_eq!(
+            names,
+            [".hidden.jpg", "a.png", "b.jpg", "c.jpg", "root.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_scan_streams_in_batches() {
+        let dir = create_temp_dir();
+        let jpeg = create_test_jpeg(dir.path(), "0000.jpg");
+        for i in 1..SCAN_BATCH_SIZE + 10 {
+            fs::copy(&jpeg, dir.path().join(format!("{i:04}.jpg"))).unwrap();
+        }
+        let (names, events) = scan(dir.path(), ScanOptions::default());
+        assert_eq!(names.len(), SCAN_BATCH_SIZE + 10);
+        let sizes: Vec<usize> = events
+            .iter()
+            .filter_map(|e| match e {
+                ScanEvent::Batch { images } => Some(images.len()),
+                ScanEvent::Finished { .. } => None,
+            })
+            .collect();
+        assert_eq!(sizes, [SCAN_BATCH_SIZE, 10]);
+    }
+
+    #[test]
+    fn test_scan_stops_when_cancelled() {
+        let dir = create_temp_dir();
+        let jpeg = create_test_jpeg(dir.path(), "0000.jpg");
+        for i in 1..SCAN_BATCH_SIZE * 3 {
+            fs::copy(&jpeg, dir.path().join(format!("{i:04}.jpg"))).unwrap();
+        }
+        let cancel = AtomicBool::new(false);
+        let mut events = Vec::new();
+        let total = scan_streaming(dir.path(), &ScanOptions::default(), &cancel, |e| {
+            // The user navigates away as soon as the first batch arrives.
+            cancel.store(true, Ordering::Relaxed);
+            events.push(e);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(total, SCAN_BATCH_SIZE);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[1],
+            ScanEvent::Finished {
+                cancelled: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_scan_rejects_invalid_folder() {
+        let result = scan_streaming(
+            Path::new("/nonexistent/path"),
+            &ScanOptions::default(),
+            &AtomicBool::new(false),
+            |_| Ok(()),
+        );
+        assert!(result.unwrap_err().contains("Invalid folder path"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_folder_scan_reports_unknown_ids() {
+        assert!(!cancel_folder_scan(u32::MAX).await.unwrap());
+    }
+}
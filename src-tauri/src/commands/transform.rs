@@ -0,0 +1,87 @@
+use crate::commands::cache;
+use crate::commands::file::validate_image_path;
+use crate::utils::transform::{self, Transform, TransformMethod};
+use std::path::Path;
+
+/// Rewrites `path` with `t` applied, then drops everything cached for it so
+/// the next thumbnail, preview or metadata request sees the new file.
+pub fn transform_and_invalidate(
+    path: &Path,
+    t: Transform,
+    allow_lossy: bool,
+    cache_dir: &Path,
+) -> Result<TransformMethod, String> {
+    let method = transform::transform_file(path, t, allow_lossy)?;
+    cache::invalidate_source(cache_dir, &path.to_string_lossy());
+    Ok(method)
+}
+
+/// Rotates or flips the image at `path` on disk: losslessly through the Exif
+/// orientation for JPEG, by lossless re-encode for PNG/WebP. A lossy WebP is
+/// only re-encoded with `allow_lossy`.
+#[tauri::command]
+pub async fn transform_image(
+    path: String,
+    transform: Transform,
+    allow_lossy: Option<bool>,
+) -> Result<TransformMethod, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let _t = crate::utils::perf::PerfTimer::start("transform", &path);
+        validate_image_path(Path::new(&path))?;
+        let cache_dir = cache::get_cache_dir()?;
+        transform_and_invalidate(
+            Path::new(&path),
+            transform,
+            allow_lossy.unwrap_or(false),
+            &cache_dir,
+        )
+    })
+    .await
+    .map_err(|e| format!("transform task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::file::generate_and_cache;
+    use crate::commands::metadata::metadata_cached;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_transform_invalidates_cached_thumbnail_preview_and_metadata() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let img = create_jpeg_with_metadata(dir.path(), "a.jpg", 64, 32, Some(1), None);
+        let p = img.to_string_lossy().to_string();
+
+        let before = generate_and_cache(&img, 20, Some("1920x1080"), cache_dir.path()).unwrap();
+        assert_eq!((before.original_width, before.original_height), (64, 32));
+        metadata_cached(&img, cache_dir.path()).unwrap();
+
+        let method =
+            transform_and_invalidate(&img, Transform::Rotate90, false, cache_dir.path()).unwrap();
+        assert_eq!(method, TransformMethod::ExifOrientation);
+        // The patch keeps the file size, so only invalidation can tell.
+        assert!(cache::lookup_thumbnail(cache_dir.path(), &p, 20, Some("1920x1080")).is_none());
+        assert!(cache::load_preview(cache_dir.path(), &p, "1920x1080").is_none());
+        assert!(cache::lookup_metadata(cache_dir.path(), &p).is_none());
+
+        let after = generate_and_cache(&img, 20, Some("1920x1080"), cache_dir.path()).unwrap();
+        assert_eq!((after.original_width, after.original_height), (32, 64));
+        let exif = metadata_cached(&img, cache_dir.path())
+            .unwrap()
+            .exif
+            .unwrap();
+        assert_eq!(exif.orientation, Some(6));
+    }
+
+    #[tokio::test]
+    async fn test_transform_image_rejects_unsupported_files() {
+        let dir = create_temp_dir();
+        let txt = dir.path().join("notes.txt");
+        std::fs::write(&txt, "x").unwrap();
+        let result =
+            transform_image(txt.to_string_lossy().to_string(), Transform::Rotate90, None).await;
+        assert!(result.unwrap_err().contains("Unsupported file format"));
+    }
+}
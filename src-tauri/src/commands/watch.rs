@@ -0,0 +1,352 @@
+use crate::commands::cache;
+use crate::commands::file::{get_image_info, ImageInfo};
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+/// Quiet period before a burst of filesystem events is reported; long enough
+/// that a capture tool's create-then-write arrives as one `image-added`.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// One change in the watched folder. Serialized untagged: the Tauri event
+/// name (`name()`) says which it is, the payload is the inner value.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum WatchEvent {
+    Added(ImageInfo),
+    Modified(ImageInfo),
+    Removed { path: String },
+    Renamed { from: String, image: ImageInfo },
+}
+
+impl WatchEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Added(_) => "image-added",
+            Self::Modified(_) => "image-modified",
+            Self::Removed { .. } => "image-removed",
+            Self::Renamed { .. } => "image-renamed",
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            Self::Added(i) | Self::Modified(i) | Self::Renamed { image: i, .. } => &i.path,
+            Self::Removed { path } => path,
+        }
+    }
+}
+
+/// Non-recursive watch on one folder. Dropping it stops the watch.
+pub struct FolderWatcher {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+}
+
+impl FolderWatcher {
+    /// Starts watching `folder`, whose images are currently `known`. Every
+    /// image that changes or goes away has its thumbnail, previews and
+    /// metadata dropped from `cache_dir` before `emit` runs; a renamed one has
+    /// them moved to its new path.
+    pub fn start(
+        folder: &Path,
+        known: impl IntoIterator<Item = PathBuf>,
+        cache_dir: Option<PathBuf>,
+        debounce: Duration,
+        emit: impl Fn(WatchEvent) + Send + 'static,
+    ) -> Result<Self, String> {
+        let mut known: HashSet<PathBuf> = known.into_iter().collect();
+        let mut debouncer = new_debouncer(debounce, None, move |result: DebounceEventResult| {
+            let Ok(events) = result else { return };
+            for event in translate(&events, &mut known) {
+                if let Some(dir) = &cache_dir {
+                    match &event {
+                        // A rename keeps the stamp, so the entries stay valid.
+                        WatchEvent::Renamed { from, image } => {
+                            cache::rekey_source(dir, from, &image.path);
+                        }
+                        _ => {
+                            cache::invalidate_source(dir, event.path());
+                        }
+                    }
+                }
+                emit(event);
+            }
+        })
+        .map_err(|e| format!("Failed to create folder watcher: {e}"))?;
+        debouncer
+            .watch(folder, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch folder: {e}"))?;
+        Ok(Self {
+            _debouncer: debouncer,
+        })
+    }
+}
+
+/// Debounced notify events → image events. `known` is the set of image paths
+/// in the folder and is kept current; it decides added vs. modified (an
+/// atomic save renames a temp file over an existing image) and which
+/// vanished paths were images at all.
+fn translate(events: &[DebouncedEvent], known: &mut HashSet<PathBuf>) -> Vec<WatchEvent> {
+    let mut out: Vec<WatchEvent> = Vec::new();
+    for event in events {
+        let paths = &event.paths;
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                let (from, to) = (&paths[0], &paths[1]);
+                let was_image = known.remove(from);
+                match get_image_info(to).ok().filter(|_| to.is_file()) {
+                    Some(image) if was_image => {
+                        known.insert(to.clone());
+                        out.push(WatchEvent::Renamed {
+                            from: from.to_string_lossy().to_string(),
+                            image,
+                        });
+                    }
+                    Some(image) => out.push(appeared(known, to, image)),
+                    None if was_image => out.push(removed(from)),
+                    None => {}
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+                for path in paths {
+                    if known.remove(path) {
+                        out.push(removed(path));
+                    }
+                }
+            }
+            EventKind::Create(_) | EventKind::Modify(_) => {
+                for path in paths {
+                    match get_image_info(path).ok().filter(|_| path.is_file()) {
+                        Some(image) => out.push(appeared(known, path, image)),
+                        // Renamed away without a pairing, or no longer an image.
+                        None if known.remove(path) => out.push(removed(path)),
+                        None => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // One event per path and kind per burst; the first one wins.
+    let mut seen = HashSet::new();
+    out.retain(|e| seen.insert((e.name(), e.path().to_string())));
+    out
+}
+
+fn appeared(known: &mut HashSet<PathBuf>, path: &Path, image: ImageInfo) -> WatchEvent {
+    if known.insert(path.to_path_buf()) {
+        WatchEvent::Added(image)
+    } else {
+        WatchEvent::Modified(image)
+    }
+}
+
+fn removed(path: &Path) -> WatchEvent {
+    WatchEvent::Removed {
+        path: path.to_string_lossy().to_string(),
+    }
+}
+
+type Emitter = Arc<dyn Fn(WatchEvent) + Send + Sync>;
+
+fn emitter() -> &'static OnceLock<Emitter> {
+    static EMITTER: OnceLock<Emitter> = OnceLock::new();
+    &EMITTER
+}
+
+fn current() -> &'static Mutex<Option<FolderWatcher>> {
+    static CURRENT: OnceLock<Mutex<Option<FolderWatcher>>> = OnceLock::new();
+    CURRENT.get_or_init(|| Mutex::new(None))
+}
+
+/// Installs the sink for watch events (the app handle's `emit`). Until this
+/// runs, `watch_folder` is a no-op, which keeps command tests watcher-free.
+pub fn set_emitter(emit: impl Fn(WatchEvent) + Send + Sync + 'static) {
+    let _ = emitter().set(Arc::new(emit));
+}
+
+/// Moves the watch to `folder`, the folder `get_folder_images` just listed
+/// as `images`. A failure to watch is logged, not returned: the listing is
+/// still good, it just won't update live.
+pub fn watch_folder(folder: &Path, images: &[ImageInfo]) {
+    let Some(emit) = emitter().get().cloned() else {
+        return;
+    };
+    let Ok(mut slot) = current().lock() else {
+        return;
+    };
+    // Stop the old watch first so its last events cannot land after the
+    // new folder's listing.
+    *slot = None;
+    let known = images.iter().map(|i| PathBuf::from(&i.path));
+    match FolderWatcher::start(
+        folder,
+        known,
+        cache::get_cache_dir().ok(),
+        WATCH_DEBOUNCE,
+        move |e| emit(e),
+    ) {
+        Ok(watcher) => *slot = Some(watcher),
+        Err(e) => eprintln!("Folder watch disabled for {}: {e}", folder.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use notify_debouncer_full::notify::event::{CreateKind, DataChange, RemoveKind};
+    use notify_debouncer_full::notify::Event;
+    use std::fs;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    fn ev(kind: EventKind, paths: &[&Path]) -> DebouncedEvent {
+        let event = paths
+            .iter()
+            .fold(Event::new(kind), |e, p| e.add_path(p.to_path_buf()));
+        DebouncedEvent::new(event, Instant::now())
+    }
+
+    fn names(events: &[WatchEvent]) -> Vec<(&'static str, String)> {
+        events
+            .iter()
+            .map(|e| {
+                let file = Path::new(e.path()).file_name().unwrap();
+                (e.name(), file.to_string_lossy().to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_translate_tells_added_from_modified_by_the_known_set() {
+        let dir = create_temp_dir();
+        let old = create_test_jpeg(dir.path(), "old.jpg");
+        let new = create_test_png(dir.path(), "new.png");
+        let mut known = HashSet::from([old.clone()]);
+        let events = translate(
+            &[
+                ev(EventKind::Create(CreateKind::File), &[&new]),
+                ev(
+                    EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+                    &[&old],
+                ),
+                ev(
+                    EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+                    &[&old],
+                ),
+            ],
+            &mut known,
+        );
+        assert_eq!(
+            names(&events),
+            [
+                ("image-added", "new.png".to_string()),
+                ("image-modified", "old.jpg".to_string())
+            ]
+        );
+        assert!(known.contains(&new));
+    }
+
+    #[test]
+    fn test_translate_renames_and_removals() {
+        let dir = create_temp_dir();
+        let renamed = create_test_jpeg(dir.path(), "b.jpg");
+        let gone = dir.path().join("gone.jpg");
+        let was_image = dir.path().join("a.jpg");
+        let notes = dir.path().join("notes.txt");
+        fs::write(&notes, "x").unwrap();
+        let mut known = HashSet::from([was_image.clone(), gone.clone(), dir.path().join("c.jpg")]);
+        let events = translate(
+            &[
+                ev(
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                    &[&was_image, &renamed],
+                ),
+                ev(EventKind::Remove(RemoveKind::File), &[&gone]),
+                ev(EventKind::Remove(RemoveKind::File), &[&notes]),
+                // Renamed to something that is not an image any more.
+                ev(
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                    &[&dir.path().join("c.jpg"), &notes],
+                ),
+            ],
+            &mut known,
+        );
+        assert_eq!(
+            names(&events),
+            [
+                ("image-renamed", "b.jpg".to_string()),
+                ("image-removed", "gone.jpg".to_string()),
+                ("image-removed", "c.jpg".to_string()),
+            ]
+        );
+        match &events[0] {
+            WatchEvent::Renamed { from, .. } => assert!(from.ends_with("a.jpg")),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(known, HashSet::from([renamed]));
+    }
+
+    #[test]
+    fn test_atomic_save_over_a_known_image_is_a_modification() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let tmp = dir.path().join("a.jpg.tmp-1-2-3");
+        let mut known = HashSet::from([img.clone()]);
+        let events = translate(
+            &[ev(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &[&tmp, &img],
+            )],
+            &mut known,
+        );
+        assert_eq!(names(&events), [("image-modified", "a.jpg".to_string())]);
+    }
+
+    #[test]
+    fn test_watcher_reports_changes_and_invalidates_the_cache() {
+        let dir = create_temp_dir();
+        let cache_dir = create_temp_dir();
+        let img = create_gradient_jpeg(dir.path(), "a.jpg", 64, 48);
+        crate::commands::file::generate_and_cache(&img, 20, Some("1920x1080"), cache_dir.path())
+            .unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let _watcher = FolderWatcher::start(
+            dir.path(),
+            [img.clone()],
+            Some(cache_dir.path().to_path_buf()),
+            Duration::from_millis(50),
+            move |e| {
+                let _ = tx.send(e);
+            },
+        )
+        .unwrap();
+        let next = |name: &str| loop {
+            let e = rx.recv_timeout(Duration::from_secs(10)).expect(name);
+            if e.name() == name {
+                return e;
+            }
+        };
+
+        let added = create_test_png(dir.path(), "b.png");
+        match next("image-added") {
+            WatchEvent::Added(info) => assert_eq!(info.path, added.to_string_lossy()),
+            other => panic!("unexpected {other:?}"),
+        }
+
+        fs::remove_file(&img).unwrap();
+        next("image-removed");
+        let p = img.to_string_lossy().to_string();
+        assert!(cache::load_preview(cache_dir.path(), &p, "1920x1080").is_none());
+        assert_eq!(cache_entry_count(cache_dir.path()), 0);
+    }
+}
@@ -6,15 +6,28 @@ mod utils;
 mod test_utils;
 
 use commands::cache::{
-    clear_old_cache, get_cache_stats, get_cached_thumbnail, set_cached_thumbnail,
+    clear_old_cache, get_cache_limits, get_cache_stats, get_cached_thumbnail, set_cache_limits,
+    set_cached_thumbnail,
 };
+use commands::dialog::{open_image_dialog, pick_destination_folder};
+use commands::duplicates::find_duplicates;
 use commands::file::{
     generate_thumbnail_with_dimensions, get_folder_images, get_startup_file, handle_dropped_file,
     open_with_dialog, validate_image_file,
 };
+use commands::fileops::{
+    copy_image, delete_image, move_image, rename_image, trash_image, undo_file_operation,
+};
+use commands::metadata::get_image_metadata;
+use commands::prefetch::{
+    cancel_prefetch, prefetch_folder, set_prefetch_index, update_prefetch_paths,
+};
+use commands::scan::{cancel_folder_scan, scan_folder};
+use commands::transform::transform_image;
 use commands::window::{
     get_window_position, get_window_state, maximize_window, resize_window_to_image,
 };
+use tauri::Emitter;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -24,52 +37,49 @@ pub fn run() {
 
     // Custom `spica-img` scheme: serves image files straight to the WebView as
     // raw bytes instead of base64 over IPC. On Windows WebView2 reaches it at
-    // http://spica-img.localhost/<encodeURIComponent(absolute path)>.
+    // http://spica-img.localhost/<encodeURIComponent(absolute path)>, with
+    // /thumb/<size>/ (optionally ?box=<W>x<H>), /preview/<box>/ and
+    // /tile/<level>/<x>_<y>/ prefixes for derived images. Only files inside
+    // folders the user opened are served (utils::scope), and only to the
+    // app's own page.
     let builder = builder.register_asynchronous_uri_scheme_protocol(
         "spica-img",
         |_ctx, request, responder| {
             let uri_path = request.uri().path().to_string();
             // File reads are blocking; keep them off the async runtime's core threads.
             tauri::async_runtime::spawn_blocking(move || {
-                let response = if let Some(rest) = uri_path.strip_prefix("/preview/") {
+                let response = if let Some(forbidden) =
+                    crate::protocol::reject_foreign_origin(&request)
+                {
+                    forbidden
+                } else if let Some(rest) = uri_path.strip_prefix("/thumb/") {
+                    let _t = crate::utils::perf::PerfTimer::start("serve_thumb", &uri_path);
+                    match crate::protocol::resolve_thumb_request(rest, request.uri().query()) {
+                        Ok((size, bbox, path)) => {
+                            crate::protocol::thumb_route(&path, size, bbox, &request)
+                        }
+                        Err(msg) => crate::protocol::error_response(404, &msg),
+                    }
+                } else if let Some(rest) = uri_path.strip_prefix("/preview/") {
                     let _t = crate::utils::perf::PerfTimer::start("serve_preview", &uri_path);
                     match crate::protocol::resolve_preview_request(rest) {
-                        Ok((bbox, path)) => {
-                            match crate::commands::cache::get_cache_dir().and_then(|dir| {
-                                crate::protocol::ensure_preview(
-                                    &dir,
-                                    &path,
-                                    bbox,
-                                    crate::utils::preview::DEFAULT_THUMB_SIZE,
-                                )
-                            }) {
-                                Ok(served) => crate::protocol::preview_response(&served),
-                                Err(e) => crate::protocol::error_response(500, &e),
-                            }
-                        }
+                        Ok((bbox, path)) => crate::protocol::preview_route(&path, bbox, &request),
+                        Err(msg) => crate::protocol::error_response(404, &msg),
+                    }
+                } else if let Some(rest) = uri_path.strip_prefix("/tile/") {
+                    let _t = crate::utils::perf::PerfTimer::start("serve_tile", &uri_path);
+                    match crate::protocol::resolve_tile_request(rest) {
+                        Ok((id, path)) => crate::protocol::tile_route(&path, id, &request),
                         Err(msg) => crate::protocol::error_response(404, &msg),
                     }
                 } else {
                     let _t = crate::utils::perf::PerfTimer::start("serve", &uri_path);
                     match crate::protocol::resolve_image_path(&uri_path) {
-                        Ok(path) => match std::fs::read(&path) {
-                            Ok(bytes) => tauri::http::Response::builder()
-                                .status(200)
-                                .header("Content-Type", crate::protocol::mime_for(&path))
-                                .header(
-                                    "Access-Control-Allow-Origin",
-                                    crate::protocol::ALLOW_ORIGIN,
-                                )
-                                .body(bytes)
-                                .unwrap_or_else(|_| {
-                                    crate::protocol::error_response(500, "response build failed")
-                                }),
-                            Err(e) => crate::protocol::error_response(500, &e.to_string()),
-                        },
+                        Ok(path) => crate::protocol::image_response(&path, &request),
                         Err(msg) => crate::protocol::error_response(404, &msg),
                     }
                 };
-                responder.respond(response);
+                responder.respond(crate::protocol::with_request_origin(&request, response));
             });
         },
     );
@@ -80,17 +90,54 @@ pub fn run() {
     let builder = builder.plugin(tauri_plugin_wdio_webdriver::init());
 
     builder
+        .on_window_event(|_window, event| {
+            // A file or folder dropped on the window opens its folder to the
+            // spica-img scope, whatever the page then does with the drop.
+            if let tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) = event {
+                for path in paths {
+                    let _ = utils::scope::allow_opened(path);
+                }
+            }
+        })
+        .setup(|app| {
+            // Folder watch events go to every window as `image-added` etc.
+            let handle = app.handle().clone();
+            commands::watch::set_emitter(move |event| {
+                let _ = handle.emit(event.name(), &event);
+            });
+            commands::cache::start_maintenance();
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_folder_images,
+            scan_folder,
+            cancel_folder_scan,
+            prefetch_folder,
+            set_prefetch_index,
+            update_prefetch_paths,
+            cancel_prefetch,
             handle_dropped_file,
             validate_image_file,
             generate_thumbnail_with_dimensions,
             get_startup_file,
+            open_image_dialog,
+            pick_destination_folder,
             open_with_dialog,
+            get_image_metadata,
+            transform_image,
+            trash_image,
+            delete_image,
+            rename_image,
+            copy_image,
+            move_image,
+            undo_file_operation,
+            find_duplicates,
             get_cached_thumbnail,
             set_cached_thumbnail,
             clear_old_cache,
             get_cache_stats,
+            get_cache_limits,
+            set_cache_limits,
             get_window_state,
             get_window_position,
             resize_window_to_image,
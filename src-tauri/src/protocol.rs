@@ -3,50 +3,473 @@
 //! replacing the decode→re-encode→base64→IPC pipeline. Windows WebView2
 //! exposes the scheme as http://spica-img.localhost/<percent-encoded path>.
 
-use crate::commands::cache::{self, PreviewSidecar};
-use crate::utils::image::is_supported_image;
+use crate::commands::cache::{self, CacheEntry, PreviewSidecar};
+use crate::utils::color;
+use crate::utils::encode::{PreviewFormat, PreviewProfile};
+use crate::utils::format::{self, SourceFormat};
+use crate::utils::limits::{DecodeError, TOO_LARGE};
+use crate::utils::metadata::days_from_civil;
 use crate::utils::preview::{self, PreviewBox};
+use crate::utils::raw;
+use crate::utils::scope::{self, Rejection};
+use crate::utils::single_flight::SingleFlight;
+use crate::utils::tiles::{self, TileId};
 use percent_encoding::percent_decode_str;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::UNIX_EPOCH;
+use tauri::http::header::{
+    HeaderMap, HeaderValue, ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, ORIGIN, RANGE, VARY,
+};
+use tauri::http::response::Builder;
+use tauri::http::{Method, Request, Response};
 
 /// The webview page lives on a different origin than the custom scheme
 /// (`http://tauri.localhost` vs `http://spica-img.localhost` on Windows), so
 /// every response needs an explicit CORS header for `fetch`/`XMLHttpRequest`
-/// to be able to read it. Tauri's own built-in protocols do the same thing.
-pub const ALLOW_ORIGIN: &str = "*";
+/// to be able to read it. Only the app's own page is named; see
+/// [`with_request_origin`] for the dev server.
+#[cfg(any(windows, target_os = "android"))]
+pub const ALLOW_ORIGIN: &str = "http://tauri.localhost";
+#[cfg(not(any(windows, target_os = "android")))]
+pub const ALLOW_ORIGIN: &str = "tauri://localhost";
+
+/// `build.devUrl` in tauri.conf.json; debug builds load the page from here.
+pub const DEV_ORIGIN: &str = "http://localhost:1420";
+
+fn is_allowed_origin(origin: &str) -> bool {
+    origin == ALLOW_ORIGIN || (cfg!(debug_assertions) && origin == DEV_ORIGIN)
+}
+
+/// 403 for a request that names another page as its origin. Requests
+/// without an `Origin` header (plain `<img>` loads) pass.
+pub fn reject_foreign_origin(request: &Request<Vec<u8>>) -> Option<Response<Vec<u8>>> {
+    let origin = request.headers().get(ORIGIN)?;
+    if origin.to_str().is_ok_and(is_allowed_origin) {
+        return None;
+    }
+    eprintln!(
+        r#"{{"scope":"reject","reason":"foreign_origin","origin":{}}}"#,
+        serde_json::to_string(&String::from_utf8_lossy(origin.as_bytes()))
+            .unwrap_or_else(|_| "\"?\"".into())
+    );
+    Some(error_response(403, "origin not allowed"))
+}
+
+/// Echoes an allowed `Origin` back in place of [`ALLOW_ORIGIN`], so the dev
+/// server page can read responses too.
+pub fn with_request_origin(
+    request: &Request<Vec<u8>>,
+    mut response: Response<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    if let Some(origin) = request.headers().get(ORIGIN) {
+        if origin.to_str().is_ok_and(is_allowed_origin) {
+            response
+                .headers_mut()
+                .insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        }
+    }
+    response
+        .headers_mut()
+        .insert(VARY, HeaderValue::from_static("Origin"));
+    response
+}
 
 /// Decodes a `spica-img` URI path back into the absolute file path it points
-/// at, applying the same validation as the IPC image commands: the extension
-/// must be in the supported allowlist and the file must actually exist.
+/// at, applying the same validation as the IPC image commands: the file must
+/// exist and be a supported image by content (see `utils::format`). It must
+/// also lie inside a folder the user opened (see `utils::scope`). The path
+/// is returned as requested, not canonicalized, so cache keys match the ones
+/// the IPC commands use for the same file.
 pub fn resolve_image_path(uri_path: &str) -> Result<PathBuf, String> {
     let trimmed = uri_path.trim_start_matches('/');
     let decoded = percent_decode_str(trimmed)
         .decode_utf8()
         .map_err(|e| format!("invalid encoding: {}", e))?;
     let path = PathBuf::from(decoded.as_ref());
-    if !is_supported_image(&path) {
+    scope::check(&path).map_err(|rejection| match rejection {
+        Rejection::Unresolvable => "file not found".to_string(),
+        other => format!("path not allowed: {}", other.reason()),
+    })?;
+    if !format::is_candidate(&path) {
         return Err("unsupported file type".to_string());
     }
     if !path.is_file() {
         return Err("file not found".to_string());
     }
+    if !format::is_supported_source(&path) {
+        return Err("unsupported file type".to_string());
+    }
     Ok(path)
 }
 
-/// Content-Type for a path already accepted by [`resolve_image_path`].
+/// Content-Type for a path already accepted by [`resolve_image_path`], from
+/// the sniffed content so a misnamed file is still labelled correctly.
 pub fn mime_for(path: &Path) -> &'static str {
-    match path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_lowercase())
-        .as_deref()
+    format::detect(path)
+        .map(|d| d.format.mime())
+        .unwrap_or("application/octet-stream")
+}
+
+/// Box for the stand-in the raw route sends for formats the WebView can't
+/// display (see [`SourceFormat::needs_transcode`]). Square so portrait phone shots get the
+/// same long edge as landscape ones. A `/preview/3840x3840/` request shares
+/// its cache entry, which is fine: both are generated the same way.
+pub const TRANSCODE_BOX: PreviewBox = PreviewBox {
+    width: 3840,
+    height: 3840,
+};
+
+/// Response for the raw `/<path>` route: the file's own bytes, for HEIC/HEIF
+/// a cached JPEG transcode (served like a `/preview/` response, so the
+/// natural-size headers come along), and for camera RAW the embedded preview
+/// JPEG with the RAW's orientation written into its Exif. Conditional and
+/// range requests are honoured for all three (see [`respond`]).
+pub fn image_response(path: &Path, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let detected = format::detect(path).map(|d| d.format);
+    let validators = if detected.is_some_and(SourceFormat::is_camera_raw) {
+        Validators::for_output(path, "raw")
+    } else if detected.is_some_and(SourceFormat::needs_transcode) {
+        Validators::for_output(path, &PreviewProfile::DEFAULT.cache_key(TRANSCODE_BOX))
+    } else {
+        Validators::for_source(path)
+    };
+    if let Some(v) = validators.as_ref() {
+        if v.not_modified(request.headers()) {
+            return not_modified_response(v);
+        }
+    }
+    if detected.is_some_and(SourceFormat::is_camera_raw) {
+        return match raw::extract_preview(path) {
+            Ok(preview) => respond(
+                request,
+                Response::builder()
+                    .header(CONTENT_TYPE, "image/jpeg")
+                    .header(ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW_ORIGIN),
+                validators.as_ref(),
+                Body::Bytes(raw::served_jpeg(&preview)),
+            ),
+            Err(e) => error_response(500, &e),
+        };
+    }
+    if detected.is_some_and(SourceFormat::needs_transcode) {
+        return match cache::get_cache_dir().and_then(|dir| {
+            ensure_preview(
+                &dir,
+                path,
+                TRANSCODE_BOX,
+                &PreviewProfile::DEFAULT,
+                preview::DEFAULT_THUMB_SIZE,
+            )
+        }) {
+            Ok(served) => preview_response(served, request, validators.as_ref()),
+            Err(e) => error_response(500, &e),
+        };
+    }
+    respond(
+        request,
+        Response::builder()
+            .header(CONTENT_TYPE, mime_for(path))
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW_ORIGIN),
+        validators.as_ref(),
+        Body::File(path),
+    )
+}
+
+/// Response for `/preview/<box>/<path>[?format=..&quality=..&chroma=..]`;
+/// the query picks the encoding (see [`PreviewProfile::from_query`]). A
+/// revalidation whose validators still match is answered before the preview
+/// is even looked up.
+pub fn preview_route(
+    path: &Path,
+    bbox: PreviewBox,
+    request: &Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    preview_route_in(cache::get_cache_dir, path, bbox, request)
+}
+
+/// [`preview_route`] against the cache folder `cache_dir` resolves to, which
+/// is only asked for once the preview has to be looked up.
+fn preview_route_in(
+    cache_dir: impl FnOnce() -> Result<PathBuf, String>,
+    path: &Path,
+    bbox: PreviewBox,
+    request: &Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    let profile = match PreviewProfile::from_query(request.uri().query()) {
+        Ok(profile) => profile,
+        Err(e) => return error_response(400, &e),
+    };
+    let validators = Validators::for_output(path, &profile.cache_key(bbox));
+    if let Some(v) = validators.as_ref() {
+        if v.not_modified(request.headers()) {
+            return not_modified_response(v);
+        }
+    }
+    match cache_dir()
+        .and_then(|dir| ensure_preview(&dir, path, bbox, &profile, preview::DEFAULT_THUMB_SIZE))
     {
-        Some("jpg") | Some("jpeg") => "image/jpeg",
-        Some("png") => "image/png",
-        Some("webp") => "image/webp",
-        Some("gif") => "image/gif",
-        _ => "application/octet-stream",
+        Ok(served) => preview_response(served, request, validators.as_ref()),
+        Err(e) => error_response(500, &e),
+    }
+}
+
+/// Source files change under the same URL (an in-app rotate, an edit in
+/// another program), so responses are never reused unchecked: the WebView
+/// revalidates each time, which costs a stat and usually ends in a 304.
+pub const CACHE_CONTROL_VALUE: &str = "no-cache";
+
+/// `ETag` / `Last-Modified` for every representation of one source file.
+/// The ETag uses the source's full-precision mtime (so two edits within one
+/// second still differ) and size, plus for a rendered representation what
+/// it was rendered as (see [`Self::for_output`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: String,
+    mtime_secs: u64,
+}
+
+impl Validators {
+    pub fn for_source(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            etag: format!("\"{:x}-{:x}\"", mtime.as_nanos(), meta.len()),
+            last_modified: http_date(mtime.as_secs()),
+            mtime_secs: mtime.as_secs(),
+        })
+    }
+
+    /// Validators for bytes rendered from the source rather than read from
+    /// it: `variant` (the cache key: box, encoding, display profile) and the
+    /// app version join the ETag, so a different encoding or display profile,
+    /// or a new encoder, is never confirmed as the copy the client holds.
+    pub fn for_output(path: &Path, variant: &str) -> Option<Self> {
+        let mut validators = Self::for_source(path)?;
+        validators.etag = format!(
+            "{}-{variant}-{}\"",
+            validators.etag.trim_end_matches('"'),
+            env!("CARGO_PKG_VERSION")
+        );
+        Some(validators)
+    }
+
+    /// Whether the client's copy is current. `If-None-Match` takes precedence
+    /// over `If-Modified-Since` (RFC 9110 §13.2.2).
+    pub fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = headers.get(IF_NONE_MATCH) {
+            return value.to_str().is_ok_and(|list| {
+                list.trim() == "*" || list.split(',').any(|tag| weak_eq(tag, &self.etag))
+            });
+        }
+        headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_http_date)
+            .is_some_and(|since| self.mtime_secs <= since)
+    }
+
+    fn apply(&self, builder: Builder) -> Builder {
+        builder
+            .header(ETAG, &self.etag)
+            .header(LAST_MODIFIED, &self.last_modified)
+    }
+}
+
+/// Weak comparison: a `W/` prefix on either tag is ignored.
+fn weak_eq(a: &str, b: &str) -> bool {
+    let opaque = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    opaque(a) == opaque(b)
+}
+
+pub fn not_modified_response(validators: &Validators) -> Response<Vec<u8>> {
+    validators
+        .apply(Response::builder().status(304))
+        .header(CACHE_CONTROL, CACHE_CONTROL_VALUE)
+        .header(ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW_ORIGIN)
+        .body(Vec::new())
+        .unwrap_or_else(|_| error_response(500, "response build failed"))
+}
+
+/// What [`respond`] sends: a file on disk, read only for the requested range
+/// (and not at all for `HEAD`), or bytes already in memory.
+pub enum Body<'a> {
+    File(&'a Path),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    Full,
+    /// Inclusive byte offsets.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Finishes `builder` (content headers already set) as a 200, a 206 for a
+/// single satisfiable `Range`, or a 416. `HEAD` gets the same headers and no
+/// body. Multi-range requests are answered with the whole body, as RFC 9110
+/// allows.
+pub fn respond(
+    request: &Request<Vec<u8>>,
+    builder: Builder,
+    validators: Option<&Validators>,
+    body: Body,
+) -> Response<Vec<u8>> {
+    let len = match &body {
+        Body::File(path) => match std::fs::metadata(path) {
+            Ok(meta) => meta.len(),
+            Err(e) => return error_response(500, &e.to_string()),
+        },
+        Body::Bytes(bytes) => bytes.len() as u64,
+    };
+    let mut builder = builder
+        .header(CACHE_CONTROL, CACHE_CONTROL_VALUE)
+        .header(ACCEPT_RANGES, "bytes");
+    if let Some(v) = validators {
+        builder = v.apply(builder);
     }
+    let (status, start, end) = match requested_range(request.headers(), validators, len) {
+        RangeRequest::Full => (200, 0, len),
+        RangeRequest::Partial(first, last) => {
+            builder = builder.header(CONTENT_RANGE, format!("bytes {first}-{last}/{len}"));
+            (206, first, last + 1)
+        }
+        RangeRequest::Unsatisfiable => {
+            return builder
+                .status(416)
+                .header(CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Vec::new())
+                .unwrap_or_else(|_| error_response(500, "response build failed"));
+        }
+    };
+    let bytes = if request.method() == Method::HEAD {
+        Vec::new()
+    } else {
+        match body {
+            Body::File(path) => match read_span(path, start, end) {
+                Ok(bytes) => bytes,
+                Err(e) => return error_response(500, &e.to_string()),
+            },
+            Body::Bytes(mut bytes) => {
+                bytes.truncate(end as usize);
+                bytes.drain(..start as usize);
+                bytes
+            }
+        }
+    };
+    builder
+        .status(status)
+        .header(CONTENT_LENGTH, end - start)
+        .body(bytes)
+        .unwrap_or_else(|_| error_response(500, "response build failed"))
+}
+
+fn read_span(path: &Path, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::with_capacity((end - start) as usize);
+    file.take(end - start).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Parses a single `bytes=` range against a body of `len` bytes. An
+/// `If-Range` that no longer matches, a syntax error or several ranges mean
+/// the whole body.
+fn requested_range(headers: &HeaderMap, validators: Option<&Validators>, len: u64) -> RangeRequest {
+    let Some(spec) = headers.get(RANGE).and_then(|v| v.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+    if let Some(if_range) = headers.get(IF_RANGE).and_then(|v| v.to_str().ok()) {
+        let current = validators.is_some_and(|v| {
+            // Strong comparison for entity tags; a date must match exactly.
+            if_range.trim() == v.etag || if_range.trim() == v.last_modified
+        });
+        if !current {
+            return RangeRequest::Full;
+        }
+    }
+    let Some(spec) = spec.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let parse = |s: &str| s.trim().parse::<u64>().ok();
+    match (first.trim().is_empty(), parse(first), parse(last)) {
+        // "-N": the last N bytes.
+        (true, _, Some(n)) if n > 0 && len > 0 => {
+            RangeRequest::Partial(len.saturating_sub(n), len - 1)
+        }
+        (true, _, Some(_)) => RangeRequest::Unsatisfiable,
+        (false, Some(first), _) if first >= len => RangeRequest::Unsatisfiable,
+        (false, Some(first), None) if last.trim().is_empty() => {
+            RangeRequest::Partial(first, len - 1)
+        }
+        (false, Some(first), Some(last)) if first <= last => {
+            RangeRequest::Partial(first, last.min(len - 1))
+        }
+        _ => RangeRequest::Full,
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT".
+pub fn http_date(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{}, {d:02} {} {y} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        MONTHS[(m - 1) as usize],
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Parses an IMF-fixdate (the only format senders may generate; the
+/// obsolete RFC 850 and asctime forms are not accepted).
+pub fn parse_http_date(s: &str) -> Option<u64> {
+    let (_, rest) = s.trim().split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let d: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let m = MONTHS.iter().position(|&name| name == month)? as i64 + 1;
+    let y: i64 = parts.next()?.parse().ok()?;
+    let mut hms = parts.next()?.split(':').map(|p| p.parse::<u64>().ok());
+    let (h, min, sec) = (hms.next()??, hms.next()??, hms.next()??);
+    if parts.next()? != "GMT" || h > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(y, m, d)).ok()?;
+    Some(days * 86_400 + h * 3600 + min * 60 + sec)
+}
+
+/// Inverse of `days_from_civil` (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
 }
 
 /// Plain-text error response. Never fails to build: the status codes and
@@ -62,13 +485,6 @@ pub fn error_response(status: u16, msg: &str) -> tauri::http::Response<Vec<u8>>
 
 pub const EXPOSE_HEADERS: &str = "X-Spica-Natural-Width, X-Spica-Natural-Height";
 
-fn is_gif_path(path: &Path) -> bool {
-    path.extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.eq_ignore_ascii_case("gif"))
-        .unwrap_or(false)
-}
-
 /// `rest` = everything after "/preview/": "<W>x<H>/<percent-encoded absolute path>".
 pub fn resolve_preview_request(rest: &str) -> Result<(PreviewBox, PathBuf), String> {
     let (box_part, path_part) = rest
@@ -78,7 +494,7 @@ pub fn resolve_preview_request(rest: &str) -> Result<(PreviewBox, PathBuf), Stri
     let path = resolve_image_path(path_part)?;
     // F2: GIF has no preview (design spec) — reject here rather than caching a
     // static JPEG of frame 1 under a box key.
-    if is_gif_path(&path) {
+    if format::is_gif(&path) {
         return Err("no preview for gif".to_string());
     }
     Ok((bbox, path))
@@ -86,6 +502,7 @@ pub fn resolve_preview_request(rest: &str) -> Result<(PreviewBox, PathBuf), Stri
 
 pub struct ServedPreview {
     pub bytes: Vec<u8>,
+    pub format: PreviewFormat,
     pub natural_width: u32,
     pub natural_height: u32,
     // Distinguishes a fresh generation from a cache hit; asserted on directly
@@ -97,17 +514,21 @@ pub struct ServedPreview {
 
 /// Serve from the cache when the preview exists and its source stamp still
 /// matches; otherwise generate it now (self-healing, e.g. after a cap sweep)
-/// and store it for the next request.
+/// and store it for the next request. Each encoding of a box is cached under
+/// its own key.
 pub fn ensure_preview(
     cache_dir: &Path,
     path: &Path,
     bbox: PreviewBox,
+    profile: &PreviewProfile,
     thumb_size: u32,
 ) -> Result<ServedPreview, String> {
     let path_str = path.to_string_lossy().to_string();
-    if let Some((bytes, side)) = cache::load_preview(cache_dir, &path_str, &bbox.key()) {
+    let key = profile.cache_key(bbox);
+    if let Some((bytes, side)) = cache::load_preview(cache_dir, &path_str, &key) {
         return Ok(ServedPreview {
             bytes,
+            format: side.format,
             natural_width: side.natural_width,
             natural_height: side.natural_height,
             generated: false,
@@ -115,38 +536,380 @@ pub fn ensure_preview(
     }
     let stamp =
         cache::source_stamp(path).ok_or_else(|| "Failed to stat source file".to_string())?;
-    let g = preview::generate(path, bbox, thumb_size)?;
-    cache::store_preview(
+    // Stored inside the flight: callers that wait for this decode find the
+    // preview cached when they wake up.
+    let g = preview::generate_with(path, bbox, profile, thumb_size, |g| {
+        cache::store_preview(
+            cache_dir,
+            &path_str,
+            &key,
+            &g.preview_bytes,
+            &PreviewSidecar {
+                natural_width: g.natural_width,
+                natural_height: g.natural_height,
+                source_mtime: stamp.0,
+                source_size: stamp.1,
+                created: cache::current_unix_time(),
+                format: profile.format,
+            },
+        )
+    })?;
+    // The decode also made a full thumbnail: it replaces an Exif stand-in.
+    let upgraded = cache::upgrade_provisional_thumbnail(
         cache_dir,
         &path_str,
-        &bbox.key(),
-        &g.preview_jpeg,
-        &PreviewSidecar {
-            natural_width: g.natural_width,
-            natural_height: g.natural_height,
-            source_mtime: stamp.0,
-            source_size: stamp.1,
+        thumb_size,
+        &g.thumbnail_jpeg,
+        &CacheEntry {
+            thumbnail: String::new(),
             created: cache::current_unix_time(),
+            width: Some(g.natural_width),
+            height: Some(g.natural_height),
+            preview_box: (*profile == PreviewProfile::DEFAULT).then(|| bbox.key()),
+            source_mtime: Some(stamp.0),
+            source_size: Some(stamp.1),
+            dhash: Some(g.dhash),
+            provisional: false,
         },
-    )?;
+    );
+    if let Err(e) = upgraded {
+        eprintln!("preview: keeping the provisional thumbnail ({e})");
+    }
+    // Moved out unless callers that shared the flight still hold it.
+    let g = Arc::unwrap_or_clone(g);
     Ok(ServedPreview {
-        bytes: g.preview_jpeg,
+        bytes: g.preview_bytes,
+        format: profile.format,
         natural_width: g.natural_width,
         natural_height: g.natural_height,
         generated: true,
     })
 }
 
-pub fn preview_response(served: &ServedPreview) -> tauri::http::Response<Vec<u8>> {
-    tauri::http::Response::builder()
-        .status(200)
-        .header("Content-Type", "image/jpeg")
-        .header("Access-Control-Allow-Origin", ALLOW_ORIGIN)
-        .header("Access-Control-Expose-Headers", EXPOSE_HEADERS)
-        .header("X-Spica-Natural-Width", served.natural_width.to_string())
-        .header("X-Spica-Natural-Height", served.natural_height.to_string())
-        .body(served.bytes.clone())
-        .unwrap_or_else(|_| error_response(500, "response build failed"))
+pub fn preview_response(
+    served: ServedPreview,
+    request: &Request<Vec<u8>>,
+    validators: Option<&Validators>,
+) -> Response<Vec<u8>> {
+    respond(
+        request,
+        Response::builder()
+            .header(CONTENT_TYPE, served.format.mime())
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW_ORIGIN)
+            .header(ACCESS_CONTROL_EXPOSE_HEADERS, EXPOSE_HEADERS)
+            .header("X-Spica-Natural-Width", served.natural_width.to_string())
+            .header("X-Spica-Natural-Height", served.natural_height.to_string()),
+        validators,
+        Body::Bytes(served.bytes),
+    )
+}
+
+/// `rest` = everything after "/tile/": "<level>/<x>_<y>/<percent-encoded
+/// absolute path>".
+pub fn resolve_tile_request(rest: &str) -> Result<(TileId, PathBuf), String> {
+    let mut parts = rest.splitn(3, '/');
+    let (level, xy, path_part) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(level), Some(xy), Some(path)) => (level, xy, path),
+        _ => return Err("missing path".to_string()),
+    };
+    let id = TileId::parse(&format!("{level}/{xy}")).ok_or_else(|| "invalid tile".to_string())?;
+    let path = resolve_image_path(path_part)?;
+    if format::is_gif(&path) {
+        return Err("no tiles for gif".to_string());
+    }
+    Ok((id, path))
+}
+
+/// Tile `id` of `path` from the preview cache. On a miss its row of tiles
+/// is rendered and stored (see [`tiles::render_band`]), and nothing else;
+/// concurrent misses on one row of one source wait for that render rather
+/// than decoding again. `Ok(None)` when the tile is outside the pyramid.
+pub fn ensure_tile(
+    cache_dir: &Path,
+    path: &Path,
+    id: TileId,
+) -> Result<Option<ServedPreview>, String> {
+    let path_str = path.to_string_lossy().to_string();
+    let key = color::keyed(id.key());
+    if let Some(served) = load_tile(cache_dir, &path_str, &key) {
+        return Ok(Some(served));
+    }
+    // Every row starts with a tile x = 0, and its sidecar holds the natural
+    // size: a request past the edge of a rendered row needs no decode to
+    // refuse.
+    let first = color::keyed(TileId { x: 0, ..id }.key());
+    if let Some(side) = cache::preview_is_fresh(cache_dir, &path_str, &first) {
+        if !tiles::in_pyramid(side.natural_width, side.natural_height, id) {
+            return Ok(None);
+        }
+    }
+    let stamp =
+        cache::source_stamp(path).ok_or_else(|| "Failed to stat source file".to_string())?;
+    let rendered = tile_flights().run((path.to_path_buf(), stamp, id.level, id.y), || {
+        store_band(cache_dir, path, &path_str, stamp, id)
+    })?;
+    if !rendered {
+        return Ok(None);
+    }
+    Ok(
+        load_tile(cache_dir, &path_str, &key).map(|served| ServedPreview {
+            generated: true,
+            ..served
+        }),
+    )
+}
+
+fn load_tile(cache_dir: &Path, path_str: &str, key: &str) -> Option<ServedPreview> {
+    let (bytes, side) = cache::load_preview(cache_dir, path_str, key)?;
+    Some(ServedPreview {
+        bytes,
+        format: PreviewFormat::Jpeg,
+        natural_width: side.natural_width,
+        natural_height: side.natural_height,
+        generated: false,
+    })
+}
+
+/// Source (at its current stamp), pyramid level and tile row of one tile
+/// render.
+type TileFlightKey = (PathBuf, (u64, u64), u32, u32);
+
+/// Tile renders in flight, one per source, level and row: renders of
+/// different rows or images never wait for each other.
+fn tile_flights() -> &'static SingleFlight<TileFlightKey, Result<bool, String>> {
+    static FLIGHTS: OnceLock<SingleFlight<TileFlightKey, Result<bool, String>>> = OnceLock::new();
+    FLIGHTS.get_or_init(SingleFlight::default)
+}
+
+/// Renders the row of tiles holding `id` into the cache. `Ok(false)` when
+/// the row is outside the pyramid.
+fn store_band(
+    cache_dir: &Path,
+    path: &Path,
+    path_str: &str,
+    stamp: (u64, u64),
+    id: TileId,
+) -> Result<bool, String> {
+    let created = cache::current_unix_time();
+    let rendered = tiles::render_band(path, id.level, id.y, |tile| {
+        cache::store_preview(
+            cache_dir,
+            path_str,
+            &color::keyed(tile.id.key()),
+            &tile.jpeg,
+            &PreviewSidecar {
+                natural_width: tile.natural_width,
+                natural_height: tile.natural_height,
+                source_mtime: stamp.0,
+                source_size: stamp.1,
+                created,
+                format: PreviewFormat::Jpeg,
+            },
+        )
+    })?;
+    Ok(rendered)
+}
+
+/// Response for `/tile/<level>/<x>_<y>/<path>`: a JPEG tile with the same
+/// natural-size headers as a preview; 404 outside the pyramid.
+pub fn tile_route(path: &Path, id: TileId, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let validators = Validators::for_output(path, &color::keyed(id.key()));
+    if let Some(v) = validators.as_ref() {
+        if v.not_modified(request.headers()) {
+            return not_modified_response(v);
+        }
+    }
+    match cache::get_cache_dir().and_then(|dir| ensure_tile(&dir, path, id)) {
+        Ok(Some(served)) => preview_response(served, request, validators.as_ref()),
+        Ok(None) => error_response(404, "tile out of range"),
+        Err(e) => error_response(500, &e),
+    }
+}
+
+/// `rest` = everything after "/thumb/": "<size>/<percent-encoded absolute
+/// path>". Only the sizes the cache stores are accepted. `query` may name a
+/// preview box ("box=<W>x<H>") to make alongside the thumbnail.
+pub fn resolve_thumb_request(
+    rest: &str,
+    query: Option<&str>,
+) -> Result<(u32, Option<PreviewBox>, PathBuf), String> {
+    let (size_part, path_part) = rest
+        .split_once('/')
+        .ok_or_else(|| "missing path".to_string())?;
+    let size = size_part
+        .parse::<u32>()
+        .ok()
+        .filter(|s| cache::THUMBNAIL_SIZES.contains(s))
+        .ok_or_else(|| "unsupported thumbnail size".to_string())?;
+    let bbox = query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .find_map(|pair| pair.strip_prefix("box="))
+        .map(|b| PreviewBox::parse(b).ok_or_else(|| "unsupported preview box".to_string()))
+        .transpose()?;
+    Ok((size, bbox, resolve_image_path(path_part)?))
+}
+
+pub struct ServedThumbnail {
+    pub served: ServedPreview,
+    /// An Exif stand-in that a full decode replaces on a later request.
+    pub provisional: bool,
+}
+
+/// The `size` thumbnail of `path` from the binary cache, generated and
+/// stored on a miss. With `bbox`, a hit also needs that box's preview, and a
+/// miss makes both from one decode (the thumbnail-implies-preview rule). A
+/// decode failure is an error, and is remembered until the file changes;
+/// the "too_large" one included.
+pub fn ensure_thumbnail(
+    cache_dir: &Path,
+    path: &Path,
+    size: u32,
+    bbox: Option<PreviewBox>,
+) -> Result<ServedThumbnail, String> {
+    let path_str = path.to_string_lossy().to_string();
+    let box_key = bbox.map(|b| b.key());
+    // Asked before the lookup: a stand-in replaced in between is still
+    // labelled provisional, never the other way round.
+    let provisional = cache::has_provisional_thumbnail(cache_dir, &path_str, size);
+    if let Some((thumbnail, width, height)) =
+        cache::lookup_thumbnail_image(cache_dir, &path_str, size, box_key.as_deref())
+    {
+        match (thumbnail, width, height) {
+            (cache::CachedThumbnail::Image(bytes), Some(w), Some(h)) => {
+                return Ok(ServedThumbnail {
+                    served: ServedPreview {
+                        bytes,
+                        format: PreviewFormat::Jpeg,
+                        natural_width: w,
+                        natural_height: h,
+                        generated: false,
+                    },
+                    provisional,
+                })
+            }
+            (cache::CachedThumbnail::TooLarge, Some(width), Some(height)) => {
+                return Err(DecodeError::TooLarge { width, height }.into())
+            }
+            // Stored without the natural size: made again below.
+            (cache::CachedThumbnail::Image(_), _, _) => {}
+            _ => return Err("Failed to decode image".to_string()),
+        }
+    }
+    let g =
+        crate::commands::file::generate_and_cache_image(path, size, box_key.as_deref(), cache_dir)
+            .inspect_err(|e| {
+                // A source over the limits was recorded as such already.
+                if !e.starts_with(TOO_LARGE) {
+                    remember_failure(cache_dir, &path_str, size);
+                }
+            })?;
+    Ok(ServedThumbnail {
+        served: ServedPreview {
+            bytes: g.jpeg,
+            format: PreviewFormat::Jpeg,
+            natural_width: g.original_width,
+            natural_height: g.original_height,
+            generated: true,
+        },
+        provisional: g.provisional,
+    })
+}
+
+/// Records `path` as undecodable at `size`, stamped so that a changed file
+/// is tried again.
+fn remember_failure(cache_dir: &Path, path_str: &str, size: u32) {
+    let stamp = cache::source_stamp(Path::new(path_str));
+    let entry = CacheEntry {
+        thumbnail: "error".to_string(),
+        created: cache::current_unix_time(),
+        width: None,
+        height: None,
+        preview_box: None,
+        source_mtime: stamp.map(|s| s.0),
+        source_size: stamp.map(|s| s.1),
+        dhash: None,
+        provisional: false,
+    };
+    if let Err(e) = cache::store_thumbnail_entry(cache_dir, path_str, size, &entry) {
+        eprintln!("thumbnail: not recording {path_str} as failed ({e})");
+    }
+}
+
+/// What the `/thumb/` validators name: a provisional thumbnail gets a tag of
+/// its own, so the full decode that replaces it is never answered with 304.
+fn thumb_variant(size: u32, provisional: bool) -> String {
+    let prov = if provisional { "-prov" } else { "" };
+    color::keyed(format!("thumb{size}{prov}"))
+}
+
+/// Thumbnails are JPEG unless the frontend stored another encoding through
+/// `set_cached_thumbnail`.
+fn thumbnail_mime(bytes: &[u8]) -> &'static str {
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Response for `/thumb/<size>/<path>[?box=<W>x<H>]`: the cached thumbnail
+/// bytes with the source's natural size in the `X-Spica-Natural-*` headers.
+/// See [`ensure_thumbnail`] for the box.
+pub fn thumb_route(
+    path: &Path,
+    size: u32,
+    bbox: Option<PreviewBox>,
+    request: &Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    thumb_route_in(cache::get_cache_dir, path, size, bbox, request)
+}
+
+/// [`thumb_route`] against the cache folder `cache_dir` resolves to.
+fn thumb_route_in(
+    cache_dir: impl FnOnce() -> Result<PathBuf, String>,
+    path: &Path,
+    size: u32,
+    bbox: Option<PreviewBox>,
+    request: &Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    let cache_dir = match cache_dir() {
+        Ok(dir) => dir,
+        Err(e) => return error_response(500, &e),
+    };
+    // A tag matches what the cache holds now only if it was issued for it.
+    // With a box the preview may still have to be made, so the check waits.
+    if bbox.is_none() {
+        let provisional =
+            cache::has_provisional_thumbnail(&cache_dir, &path.to_string_lossy(), size);
+        if let Some(v) = Validators::for_output(path, &thumb_variant(size, provisional)) {
+            if v.not_modified(request.headers()) {
+                return not_modified_response(&v);
+            }
+        }
+    }
+    let thumb = match ensure_thumbnail(&cache_dir, path, size, bbox) {
+        Ok(thumb) => thumb,
+        Err(e) => return error_response(500, &e),
+    };
+    let validators = Validators::for_output(path, &thumb_variant(size, thumb.provisional));
+    if let Some(v) = validators.as_ref() {
+        if v.not_modified(request.headers()) {
+            return not_modified_response(v);
+        }
+    }
+    let served = thumb.served;
+    respond(
+        request,
+        Response::builder()
+            .header(CONTENT_TYPE, thumbnail_mime(&served.bytes))
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW_ORIGIN)
+            .header(ACCESS_CONTROL_EXPOSE_HEADERS, EXPOSE_HEADERS)
+            .header("X-Spica-Natural-Width", served.natural_width.to_string())
+            .header("X-Spica-Natural-Height", served.natural_height.to_string()),
+        validators.as_ref(),
+        Body::Bytes(served.bytes),
+    )
 }
 
 #[cfg(test)]
@@ -155,7 +918,9 @@ mod tests {
     use crate::test_utils::*;
     use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
+    /// Also puts `path`'s folder in scope, as opening it in the app would.
     fn encode(path: &std::path::Path) -> String {
+        scope::allow_opened(path).unwrap();
         // encodeURIComponent 相当（英数字以外すべてエンコード）
         format!(
             "/{}",
@@ -177,11 +942,9 @@ mod tests {
     fn test_resolve_preview_request_rejects_bad_box_and_bad_path() {
         let temp_dir = create_temp_dir();
         let img = create_test_jpeg(temp_dir.path(), "p.jpg");
-        assert!(
-            resolve_preview_request(&format!("1234x567{}", encode(&img)))
-                .unwrap_err()
-                .contains("box")
-        );
+        assert!(resolve_preview_request(&format!("1234x0{}", encode(&img)))
+            .unwrap_err()
+            .contains("box"));
         assert!(resolve_preview_request("1920x1080").is_err());
         assert!(
             resolve_preview_request("1920x1080/C%3A%5Cnope%5Cmissing.jpg")
@@ -207,15 +970,64 @@ mod tests {
         let cache = create_temp_dir();
         let img = create_gradient_jpeg(temp_dir.path(), "big.jpg", 2400, 1600);
         let bbox = PreviewBox::parse("1920x1080").unwrap();
-        let first = ensure_preview(cache.path(), &img, bbox, 20).unwrap();
+        let first = ensure_preview(cache.path(), &img, bbox, &PreviewProfile::DEFAULT, 20).unwrap();
         assert!(first.generated);
         assert_eq!((first.natural_width, first.natural_height), (2400, 1600));
         assert_eq!(image::load_from_memory(&first.bytes).unwrap().width(), 1620);
-        let second = ensure_preview(cache.path(), &img, bbox, 20).unwrap();
+        let second =
+            ensure_preview(cache.path(), &img, bbox, &PreviewProfile::DEFAULT, 20).unwrap();
         assert!(!second.generated);
         assert_eq!(second.bytes, first.bytes);
     }
 
+    #[test]
+    fn test_preview_route_serves_the_requested_encoding() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let cache_dir = || Ok(cache.path().to_path_buf());
+        let img = create_gradient_jpeg(temp_dir.path(), "big.jpg", 640, 480);
+        let bbox = PreviewBox::parse("320x320").unwrap();
+        for (query, mime) in [
+            ("", "image/jpeg"),
+            ("?format=webp&quality=70", "image/webp"),
+            ("?format=webp_lossless", "image/webp"),
+            ("?format=avif", "image/avif"),
+        ] {
+            let uri = format!("spica-img://localhost/preview/320x320/x{query}");
+            let request = Request::builder().uri(uri).body(Vec::new()).unwrap();
+            let response = preview_route_in(cache_dir, &img, bbox, &request);
+            assert_eq!(response.status(), 200, "{query}");
+            assert_eq!(header(&response, "Content-Type"), Some(mime), "{query}");
+        }
+        let request = Request::builder()
+            .uri("spica-img://localhost/preview/320x320/x?format=bmp")
+            .body(Vec::new())
+            .unwrap();
+        assert_eq!(
+            preview_route_in(cache_dir, &img, bbox, &request).status(),
+            400
+        );
+    }
+
+    #[test]
+    fn test_ensure_preview_caches_each_encoding_separately() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let img = create_gradient_jpeg(temp_dir.path(), "big.jpg", 640, 480);
+        let bbox = PreviewBox::parse("320x320").unwrap();
+        let webp = PreviewProfile::new(PreviewFormat::Webp);
+        let jpeg = ensure_preview(cache.path(), &img, bbox, &PreviewProfile::DEFAULT, 20).unwrap();
+        let first = ensure_preview(cache.path(), &img, bbox, &webp, 20).unwrap();
+        assert!(first.generated);
+        assert_eq!(first.format, PreviewFormat::Webp);
+        assert_ne!(first.bytes, jpeg.bytes);
+        let second = ensure_preview(cache.path(), &img, bbox, &webp, 20).unwrap();
+        assert!(!second.generated);
+        assert_eq!(second.format, PreviewFormat::Webp);
+        let decoded = image::load_from_memory(&second.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (320, 240));
+    }
+
     #[test]
     fn test_resolve_roundtrips_windows_path_with_spaces_and_japanese() {
         let temp_dir = create_temp_dir();
@@ -233,6 +1045,26 @@ mod tests {
         assert!(err.contains("unsupported"));
     }
 
+    #[test]
+    fn test_resolve_accepts_extensionless_image_and_labels_it_by_content() {
+        let temp_dir = create_temp_dir();
+        let png = create_test_png(temp_dir.path(), "a.png");
+        let bare = temp_dir.path().join("scan0001");
+        std::fs::copy(&png, &bare).unwrap();
+        let resolved = resolve_image_path(&encode(&bare)).unwrap();
+        assert_eq!(resolved, bare);
+        assert_eq!(mime_for(&resolved), "image/png");
+    }
+
+    #[test]
+    fn test_mime_for_follows_content_over_extension() {
+        let temp_dir = create_temp_dir();
+        let png = create_test_png(temp_dir.path(), "a.png");
+        let misnamed = temp_dir.path().join("really_png.jpg");
+        std::fs::copy(&png, &misnamed).unwrap();
+        assert_eq!(mime_for(&misnamed), "image/png");
+    }
+
     #[test]
     fn test_resolve_rejects_missing_file() {
         let err = resolve_image_path("/C%3A%5Cnope%5Cmissing.jpg").unwrap_err();
@@ -247,6 +1079,358 @@ mod tests {
         assert_eq!(mime_for(Path::new("a.png")), "image/png");
         assert_eq!(mime_for(Path::new("a.webp")), "image/webp");
         assert_eq!(mime_for(Path::new("a.gif")), "image/gif");
+        assert_eq!(mime_for(Path::new("a.HEIC")), "image/heic");
+        assert_eq!(mime_for(Path::new("a.heif")), "image/heif");
+        assert_eq!(mime_for(Path::new("a.avif")), "image/avif");
+    }
+
+    #[test]
+    fn test_image_response_serves_the_file_bytes() {
+        let temp_dir = create_temp_dir();
+        let img = create_test_png(temp_dir.path(), "a.png");
+        let response = image_response(&img, &get(&[]));
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), &std::fs::read(&img).unwrap());
+        assert_eq!(
+            response
+                .headers()
+                .get("Content-Type")
+                .and_then(|v| v.to_str().ok()),
+            Some("image/png")
+        );
+    }
+
+    #[test]
+    fn test_image_response_serves_the_embedded_raw_preview() {
+        let temp_dir = create_temp_dir();
+        let src = create_fake_tiff_raw(temp_dir.path(), "shot.arw", 8, 64, 48);
+        let response = image_response(&src, &get(&[]));
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response
+                .headers()
+                .get("Content-Type")
+                .and_then(|v| v.to_str().ok()),
+            Some("image/jpeg")
+        );
+        let mut decoder = image::ImageReader::with_format(
+            std::io::Cursor::new(response.body().clone()),
+            image::ImageFormat::Jpeg,
+        )
+        .into_decoder()
+        .unwrap();
+        use image::ImageDecoder;
+        assert_eq!(decoder.dimensions(), (64, 48));
+        assert_eq!(
+            decoder.orientation().unwrap(),
+            image::metadata::Orientation::Rotate270
+        );
+    }
+
+    fn get(headers: &[(&str, &str)]) -> Request<Vec<u8>> {
+        request(Method::GET, headers)
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> Request<Vec<u8>> {
+        let mut builder = Request::builder().method(method).uri("/x");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Vec::new()).unwrap()
+    }
+
+    fn header<'a>(response: &'a Response<Vec<u8>>, name: &str) -> Option<&'a str> {
+        response.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+
+    #[test]
+    fn test_image_response_answers_revalidation_with_304() {
+        let temp_dir = create_temp_dir();
+        let img = create_test_png(temp_dir.path(), "a.png");
+        let first = image_response(&img, &get(&[]));
+        let etag = header(&first, "ETag").unwrap().to_string();
+        let last_modified = header(&first, "Last-Modified").unwrap().to_string();
+        assert_eq!(header(&first, "Cache-Control"), Some(CACHE_CONTROL_VALUE));
+
+        let by_etag = image_response(&img, &get(&[("If-None-Match", &format!("W/{etag}"))]));
+        assert_eq!(by_etag.status(), 304);
+        assert!(by_etag.body().is_empty());
+        assert_eq!(header(&by_etag, "ETag"), Some(etag.as_str()));
+        let by_date = image_response(&img, &get(&[("If-Modified-Since", &last_modified)]));
+        assert_eq!(by_date.status(), 304);
+        // If-None-Match wins: a stale tag means a full response whatever the date.
+        let stale = image_response(
+            &img,
+            &get(&[
+                ("If-None-Match", "\"0-0\""),
+                ("If-Modified-Since", &last_modified),
+            ]),
+        );
+        assert_eq!(stale.status(), 200);
+
+        // Rewriting the file changes the tag even within the same second.
+        std::fs::write(&img, std::fs::read(&img).unwrap().repeat(2)).unwrap();
+        let changed = image_response(&img, &get(&[("If-None-Match", &etag)]));
+        assert_eq!(changed.status(), 200);
+    }
+
+    #[test]
+    fn test_image_response_serves_byte_ranges_and_head() {
+        let temp_dir = create_temp_dir();
+        let img = create_test_png(temp_dir.path(), "a.png");
+        let bytes = std::fs::read(&img).unwrap();
+        let len = bytes.len();
+
+        let first = image_response(&img, &get(&[("Range", "bytes=0-9")]));
+        assert_eq!(first.status(), 206);
+        assert_eq!(first.body(), &bytes[..10]);
+        assert_eq!(
+            header(&first, "Content-Range"),
+            Some(format!("bytes 0-9/{len}").as_str())
+        );
+        let tail = image_response(&img, &get(&[("Range", "bytes=-4")]));
+        assert_eq!(tail.body(), &bytes[len - 4..]);
+        let open = image_response(&img, &get(&[("Range", "bytes=5-")]));
+        assert_eq!(open.body(), &bytes[5..]);
+        let clamped = image_response(&img, &get(&[("Range", "bytes=5-999999")]));
+        assert_eq!(clamped.body(), &bytes[5..]);
+
+        let past_end = image_response(&img, &get(&[("Range", &format!("bytes={len}-"))]));
+        assert_eq!(past_end.status(), 416);
+        assert_eq!(
+            header(&past_end, "Content-Range"),
+            Some(format!("bytes */{len}").as_str())
+        );
+        let multi = image_response(&img, &get(&[("Range", "bytes=0-1,4-5")]));
+        assert_eq!((multi.status().as_u16(), multi.body().len()), (200, len));
+        let stale = image_response(
+            &img,
+            &get(&[("Range", "bytes=0-9"), ("If-Range", "\"0-0\"")]),
+        );
+        assert_eq!((stale.status().as_u16(), stale.body().len()), (200, len));
+
+        let head = image_response(&img, &request(Method::HEAD, &[]));
+        assert_eq!(head.status(), 200);
+        assert!(head.body().is_empty());
+        assert_eq!(
+            header(&head, "Content-Length"),
+            Some(len.to_string().as_str())
+        );
+        assert_eq!(header(&head, "Accept-Ranges"), Some("bytes"));
+    }
+
+    #[test]
+    fn test_preview_route_revalidates_without_touching_the_cache() {
+        let temp_dir = create_temp_dir();
+        let img = create_gradient_jpeg(temp_dir.path(), "a.jpg", 64, 48);
+        let bbox = PreviewBox::parse("1920x1080").unwrap();
+        let etag = Validators::for_output(&img, &PreviewProfile::DEFAULT.cache_key(bbox))
+            .unwrap()
+            .etag;
+        let response = preview_route(&img, bbox, &get(&[("If-None-Match", &etag)]));
+        assert_eq!(response.status(), 304);
+        assert_eq!(acao_header(&response), Some(ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn test_preview_etag_names_the_encoding_it_was_rendered_as() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let cache_dir = || Ok(cache.path().to_path_buf());
+        let img = create_gradient_jpeg(temp_dir.path(), "a.jpg", 64, 48);
+        let bbox = PreviewBox::parse("1920x1080").unwrap();
+        let jpeg = preview_route_in(cache_dir, &img, bbox, &get(&[]));
+        let etag = header(&jpeg, "ETag").unwrap().to_string();
+        assert_ne!(etag, Validators::for_source(&img).unwrap().etag);
+
+        // Same source, another encoding: the JPEG's tag must not match.
+        let webp = Request::builder()
+            .uri("spica-img://localhost/preview/1920x1080/x?format=webp")
+            .header("If-None-Match", &etag)
+            .body(Vec::new())
+            .unwrap();
+        let response = preview_route_in(cache_dir, &img, bbox, &webp);
+        assert_eq!(response.status(), 200);
+        assert_ne!(header(&response, "ETag"), Some(etag.as_str()));
+    }
+
+    #[test]
+    fn test_resolve_tile_request_parses_level_tile_and_path() {
+        let temp_dir = create_temp_dir();
+        let img = create_test_jpeg(temp_dir.path(), "a.jpg");
+        let rest = format!("3/1_2{}", encode(&img));
+        let (id, path) = resolve_tile_request(&rest).unwrap();
+        assert_eq!(
+            id,
+            TileId {
+                level: 3,
+                x: 1,
+                y: 2
+            }
+        );
+        assert_eq!(path, img);
+        assert!(resolve_tile_request(&format!("3/1-2{}", encode(&img))).is_err());
+        assert!(resolve_tile_request("3/1_2").is_err());
+        let gif = create_test_gif(temp_dir.path(), "a.gif");
+        assert!(resolve_tile_request(&format!("0/0_0{}", encode(&gif))).is_err());
+    }
+
+    #[test]
+    fn test_ensure_tile_generates_then_hits_cache() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let img = create_gradient_jpeg(temp_dir.path(), "big.jpg", 1300, 600);
+        let id = TileId::parse("1/1_0").unwrap();
+        let first = ensure_tile(cache.path(), &img, id).unwrap().unwrap();
+        assert!(first.generated);
+        assert_eq!((first.natural_width, first.natural_height), (1300, 600));
+        let second = ensure_tile(cache.path(), &img, id).unwrap().unwrap();
+        assert!(!second.generated);
+        assert_eq!(second.bytes, first.bytes);
+        // The render filled the tile's row and nothing else.
+        let tile = |id: &str| {
+            ensure_tile(cache.path(), &img, TileId::parse(id).unwrap())
+                .unwrap()
+                .unwrap()
+        };
+        assert!(!tile("1/0_0").generated);
+        assert!(tile("2/0_0").generated);
+        assert!(tile("0/2_1").generated);
+        assert!(!tile("0/0_1").generated);
+        assert!(tile("0/0_0").generated);
+        for outside in ["1/5_0", "1/0_1", "3/0_0"] {
+            let outside = TileId::parse(outside).unwrap();
+            assert!(ensure_tile(cache.path(), &img, outside).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_resolve_thumb_request_accepts_cached_sizes_only() {
+        let temp_dir = create_temp_dir();
+        let img = create_test_jpeg(temp_dir.path(), "a.jpg");
+        let size = cache::THUMBNAIL_SIZES[0];
+        let rest = format!("{size}{}", encode(&img));
+        let (parsed, bbox, path) = resolve_thumb_request(&rest, None).unwrap();
+        assert_eq!((parsed, bbox, path), (size, None, img.clone()));
+        let (_, bbox, _) = resolve_thumb_request(&rest, Some("box=1920x1080")).unwrap();
+        assert_eq!(bbox.map(|b| b.key()), Some("1920x1080".to_string()));
+        assert!(resolve_thumb_request(&rest, Some("box=huge")).is_err());
+        assert!(resolve_thumb_request(&format!("17{}", encode(&img)), None).is_err());
+        assert!(resolve_thumb_request("abc", None).is_err());
+    }
+
+    #[test]
+    fn test_ensure_thumbnail_generates_then_hits_binary_cache() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let img = create_gradient_jpeg(temp_dir.path(), "a.jpg", 320, 240);
+        let size = cache::THUMBNAIL_SIZES[0];
+        let first = ensure_thumbnail(cache.path(), &img, size, None).unwrap();
+        assert!(!first.provisional);
+        let first = first.served;
+        assert!(first.generated);
+        assert_eq!((first.natural_width, first.natural_height), (320, 240));
+        assert_eq!(thumbnail_mime(&first.bytes), "image/jpeg");
+        let second = ensure_thumbnail(cache.path(), &img, size, None)
+            .unwrap()
+            .served;
+        assert!(!second.generated);
+        assert_eq!(second.bytes, first.bytes);
+
+        let broken = create_invalid_image(temp_dir.path(), "broken.jpg");
+        assert!(ensure_thumbnail(cache.path(), &broken, size, None).is_err());
+        // The failure is remembered rather than decoded again.
+        assert!(matches!(
+            cache::lookup_thumbnail_image(cache.path(), &broken.to_string_lossy(), size, None),
+            Some((cache::CachedThumbnail::Error, None, None))
+        ));
+        assert!(ensure_thumbnail(cache.path(), &broken, size, None).is_err());
+    }
+
+    #[test]
+    fn test_thumb_route_with_a_box_also_makes_the_preview() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let cache_dir = || Ok(cache.path().to_path_buf());
+        let img = create_gradient_jpeg(temp_dir.path(), "a.jpg", 3000, 2000);
+        let size = cache::THUMBNAIL_SIZES[0];
+        let bbox = PreviewBox::parse("1920x1080").unwrap();
+        let preview_key = PreviewProfile::DEFAULT.cache_key(bbox);
+        let path_str = img.to_string_lossy();
+
+        // Without a box only the thumbnail is made...
+        let plain = thumb_route_in(cache_dir, &img, size, None, &get(&[]));
+        assert_eq!(plain.status(), 200);
+        assert_eq!(header(&plain, "X-Spica-Natural-Width"), Some("3000"));
+        assert!(cache::preview_is_fresh(cache.path(), &path_str, &preview_key).is_none());
+
+        // ...and a current tag is not enough once a box is asked for.
+        let etag = header(&plain, "ETag").unwrap().to_string();
+        let boxed = thumb_route_in(
+            cache_dir,
+            &img,
+            size,
+            Some(bbox),
+            &get(&[("If-None-Match", &etag)]),
+        );
+        assert!(cache::preview_is_fresh(cache.path(), &path_str, &preview_key).is_some());
+        assert_eq!(boxed.status(), 304);
+        assert_eq!(header(&boxed, "ETag"), Some(etag.as_str()));
+    }
+
+    #[test]
+    fn test_thumb_route_does_not_revalidate_a_provisional_thumbnail_into_the_full_one() {
+        let temp_dir = create_temp_dir();
+        let cache = create_temp_dir();
+        let cache_dir = || Ok(cache.path().to_path_buf());
+        let img =
+            create_jpeg_with_exif_thumbnail(temp_dir.path(), "cam.jpg", (960, 640), (160, 107), 1);
+        let size = cache::THUMBNAIL_SIZES[0];
+        // First request: the Exif thumbnail, as a stand-in.
+        let provisional = thumb_route_in(cache_dir, &img, size, None, &get(&[]));
+        assert_eq!(provisional.status(), 200);
+        let etag = header(&provisional, "ETag").unwrap().to_string();
+        assert!(cache::has_provisional_thumbnail(
+            cache.path(),
+            &img.to_string_lossy(),
+            size
+        ));
+        assert_eq!(
+            thumb_route_in(
+                cache_dir,
+                &img,
+                size,
+                None,
+                &get(&[("If-None-Match", &etag)])
+            )
+            .status(),
+            304
+        );
+        // The full decode replaces it; the stand-in's tag no longer matches.
+        crate::commands::file::generate_and_cache_image(&img, size, None, cache.path()).unwrap();
+        let full = thumb_route_in(
+            cache_dir,
+            &img,
+            size,
+            None,
+            &get(&[("If-None-Match", &etag)]),
+        );
+        assert_eq!(full.status(), 200);
+        assert_ne!(header(&full, "ETag"), Some(etag.as_str()));
+        assert_ne!(full.body(), provisional.body());
+    }
+
+    #[test]
+    fn test_http_date_roundtrips() {
+        assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        let now = cache::current_unix_time();
+        assert_eq!(parse_http_date(&http_date(now)), Some(now));
     }
 
     #[test]
@@ -265,6 +1449,41 @@ mod tests {
         assert_eq!(acao_header(&response), Some(ALLOW_ORIGIN));
     }
 
+    #[test]
+    fn test_resolve_rejects_files_outside_opened_folders() {
+        let opened = create_temp_dir();
+        let elsewhere = create_temp_dir();
+        let img = create_test_jpeg(opened.path(), "a.jpg");
+        let outside = create_test_jpeg(elsewhere.path(), "b.jpg");
+        assert_eq!(resolve_image_path(&encode(&img)).unwrap(), img);
+        let rest = format!(
+            "/{}",
+            utf8_percent_encode(&outside.to_string_lossy(), NON_ALPHANUMERIC)
+        );
+        let err = resolve_image_path(&rest).unwrap_err();
+        assert!(err.contains("outside_roots"), "{err}");
+        let dotted = opened.path().join("..").join("x.jpg");
+        let rest = format!(
+            "/{}",
+            utf8_percent_encode(&dotted.to_string_lossy(), NON_ALPHANUMERIC)
+        );
+        assert!(resolve_image_path(&rest).unwrap_err().contains("traversal"));
+    }
+
+    #[test]
+    fn test_origin_checks_name_the_app_page_only() {
+        assert!(reject_foreign_origin(&get(&[])).is_none());
+        assert!(reject_foreign_origin(&get(&[("Origin", ALLOW_ORIGIN)])).is_none());
+        let foreign = reject_foreign_origin(&get(&[("Origin", "https://evil.example")])).unwrap();
+        assert_eq!(foreign.status(), 403);
+
+        let answered = with_request_origin(&get(&[]), error_response(404, "x"));
+        assert_eq!(acao_header(&answered), Some(ALLOW_ORIGIN));
+        assert_eq!(header(&answered, "Vary"), Some("Origin"));
+        let dev = with_request_origin(&get(&[("Origin", DEV_ORIGIN)]), error_response(404, "x"));
+        assert_eq!(acao_header(&dev), Some(DEV_ORIGIN));
+    }
+
     /// `response.headers().get(...)` returns an `Option<&HeaderValue>`;
     /// convert to `&str` for a plain string comparison in assertions above.
     fn acao_header(response: &tauri::http::Response<Vec<u8>>) -> Option<&str> {
@@ -115,6 +115,45 @@ pub fn create_half_transparent_png(dir: &Path, filename: &str, width: u32, heigh
     file_path
 }
 
+/// Decompression bomb: an RGB PNG whose header claims `width`x`height` but
+/// whose image data is an empty zlib stream. Only a decoder that trusts the
+/// header gets as far as allocating for it.
+pub fn create_png_bomb(dir: &Path, filename: &str, width: u32, height: u32) -> PathBuf {
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &b in bytes {
+            crc ^= u32::from(b);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut chunk = |kind: &[u8; 4], data: &[u8]| {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    };
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlace
+    chunk(b"IHDR", &ihdr);
+    chunk(b"IDAT", &[0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    chunk(b"IEND", &[]);
+    let file_path = dir.join(filename);
+    fs::write(&file_path, png).expect("Failed to write PNG bomb");
+    file_path
+}
+
 /// Minimal little-endian TIFF/Exif blob carrying only the Orientation tag.
 /// The JPEG encoder prepends the "Exif\0\0" APP1 header itself.
 pub fn exif_orientation_blob(orientation: u16) -> Vec<u8> {
@@ -131,6 +170,22 @@ pub fn exif_orientation_blob(orientation: u16) -> Vec<u8> {
     v
 }
 
+/// TIFF/Exif blob whose Exif IFD holds only DateTimeOriginal
+/// ("YYYY:MM:DD HH:MM:SS").
+pub fn exif_date_blob(date: &str) -> Vec<u8> {
+    use exif::{Field, In, Tag, Value};
+    let field = Field {
+        tag: Tag::DateTimeOriginal,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![date.as_bytes().to_vec()]),
+    };
+    let mut writer = exif::experimental::Writer::new();
+    writer.push_field(&field);
+    let mut out = std::io::Cursor::new(Vec::new());
+    writer.write(&mut out, false).expect("write exif");
+    out.into_inner()
+}
+
 /// Gradient JPEG with an Exif Orientation tag (e.g. 6 = rotate 90 CW on display)
 /// and, optionally, an ICC profile segment.
 pub fn create_jpeg_with_metadata(
@@ -168,3 +223,155 @@ pub fn create_jpeg_with_metadata(
         .expect("write jpeg");
     file_path
 }
+
+/// Gradient JPEG whose Exif carries an Orientation tag in IFD0 and a
+/// `thumb_width`x`thumb_height` JPEG thumbnail in IFD1, as cameras write.
+pub fn create_jpeg_with_exif_thumbnail(
+    dir: &Path,
+    filename: &str,
+    (width, height): (u32, u32),
+    (thumb_width, thumb_height): (u32, u32),
+    orientation: u16,
+) -> PathBuf {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{ExtendedColorType, ImageBuffer, ImageEncoder, Rgb};
+    let thumb = gradient_jpeg_bytes(thumb_width, thumb_height);
+    let entry = |v: &mut Vec<u8>, tag: u16, typ: u16, value: u32| {
+        v.extend_from_slice(&tag.to_le_bytes());
+        v.extend_from_slice(&typ.to_le_bytes());
+        v.extend_from_slice(&1u32.to_le_bytes());
+        v.extend_from_slice(&value.to_le_bytes());
+    };
+    let mut exif = Vec::new();
+    exif.extend_from_slice(b"II\x2A\x00");
+    exif.extend_from_slice(&8u32.to_le_bytes());
+    // IFD0 at 8: Orientation; IFD1 follows at 26, the thumbnail at 56.
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    entry(&mut exif, 0x0112, 3, u32::from(orientation));
+    exif.extend_from_slice(&26u32.to_le_bytes());
+    exif.extend_from_slice(&2u16.to_le_bytes());
+    entry(&mut exif, 0x0201, 4, 56); // JPEGInterchangeFormat
+    entry(&mut exif, 0x0202, 4, thumb.len() as u32); // JPEGInterchangeFormatLength
+    exif.extend_from_slice(&0u32.to_le_bytes());
+    exif.extend_from_slice(&thumb);
+
+    let file_path = dir.join(filename);
+    let img = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb([
+            (x * 255 / width.max(1)) as u8,
+            (y * 255 / height.max(1)) as u8,
+            32u8,
+        ])
+    });
+    let file = fs::File::create(&file_path).expect("create jpeg");
+    let mut encoder = JpegEncoder::new_with_quality(std::io::BufWriter::new(file), 90);
+    encoder
+        .set_exif_metadata(exif)
+        .expect("jpeg encoder supports exif");
+    encoder
+        .write_image(img.as_raw(), width, height, ExtendedColorType::Rgb8)
+        .expect("write jpeg");
+    file_path
+}
+
+/// In-memory gradient JPEG bytes (for fixtures that embed a JPEG in a container).
+pub fn gradient_jpeg_bytes(width: u32, height: u32) -> Vec<u8> {
+    use image::{ImageBuffer, ImageFormat, Rgb};
+    let img = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb([
+            (x * 255 / width.max(1)) as u8,
+            (y * 255 / height.max(1)) as u8,
+            32u8,
+        ])
+    });
+    let mut out = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut out), ImageFormat::Jpeg)
+        .expect("encode jpeg");
+    out
+}
+
+/// `jpeg` with the given (marker, payload) APPn segments inserted right after
+/// SOI, in order. Payloads include their identifier ("Exif\0\0", ...).
+pub fn insert_jpeg_segments(jpeg: Vec<u8>, segments: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut out = jpeg[..2].to_vec();
+    for (marker, payload) in segments {
+        out.extend_from_slice(&[0xFF, *marker]);
+        out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        out.extend_from_slice(payload);
+    }
+    out.extend_from_slice(&jpeg[2..]);
+    out
+}
+
+/// TIFF-based RAW look-alike (NEF/CR2/ARW/DNG layout): IFD0 carries the
+/// orientation, a 16x12 JPEGInterchangeFormat thumbnail and one SubIFD whose
+/// single strip is lossless-JPEG "sensor data" (SOF3, 4000x3000, not
+/// decodable); IFD1 on the chain carries the `width`x`height` preview.
+pub fn create_fake_tiff_raw(
+    dir: &Path,
+    filename: &str,
+    orientation: u16,
+    width: u32,
+    height: u32,
+) -> PathBuf {
+    fn entry(v: &mut Vec<u8>, tag: u16, typ: u16, value: u32) {
+        v.extend_from_slice(&tag.to_le_bytes());
+        v.extend_from_slice(&typ.to_le_bytes());
+        v.extend_from_slice(&1u32.to_le_bytes());
+        if typ == 3 {
+            v.extend_from_slice(&(value as u16).to_le_bytes());
+            v.extend_from_slice(&[0, 0]);
+        } else {
+            v.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    let thumb = gradient_jpeg_bytes(16, 12);
+    let preview = gradient_jpeg_bytes(width, height);
+    let mut lossless = vec![0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x0B, 0x08];
+    lossless.extend_from_slice(&3000u16.to_be_bytes());
+    lossless.extend_from_slice(&4000u16.to_be_bytes());
+    lossless.extend_from_slice(&[1, 1, 0x11, 0]);
+    lossless.extend(std::iter::repeat_n(0x55u8, 256));
+
+    // Layout: header(8) | IFD0 (4 entries) | SubIFD (3) | IFD1 (2) | data
+    let ifd_len = |n: u32| 2 + 12 * n + 4;
+    let ifd0 = 8u32;
+    let sub = ifd0 + ifd_len(4);
+    let ifd1 = sub + ifd_len(3);
+    let thumb_at = ifd1 + ifd_len(2);
+    let lossless_at = thumb_at + thumb.len() as u32;
+    let preview_at = lossless_at + lossless.len() as u32;
+
+    let mut v = b"II\x2A\x00".to_vec();
+    v.extend_from_slice(&ifd0.to_le_bytes());
+    v.extend_from_slice(&4u16.to_le_bytes());
+    entry(&mut v, 0x0112, 3, u32::from(orientation));
+    entry(&mut v, 0x014A, 4, sub);
+    entry(&mut v, 0x0201, 4, thumb_at);
+    entry(&mut v, 0x0202, 4, thumb.len() as u32);
+    v.extend_from_slice(&ifd1.to_le_bytes());
+    v.extend_from_slice(&3u16.to_le_bytes());
+    entry(&mut v, 0x0103, 3, 7);
+    entry(&mut v, 0x0111, 4, lossless_at);
+    entry(&mut v, 0x0117, 4, lossless.len() as u32);
+    v.extend_from_slice(&0u32.to_le_bytes());
+    v.extend_from_slice(&2u16.to_le_bytes());
+    entry(&mut v, 0x0201, 4, preview_at);
+    entry(&mut v, 0x0202, 4, preview.len() as u32);
+    v.extend_from_slice(&0u32.to_le_bytes());
+    v.extend(thumb);
+    v.extend(lossless);
+    v.extend(preview);
+
+    let file_path = dir.join(filename);
+    fs::write(&file_path, v).expect("Failed to create fake RAW");
+    file_path
+}
+
+/// Entries of every kind (thumbnails, previews and tiles, metadata) in the
+/// cache store under `cache_dir`.
+pub fn cache_entry_count(cache_dir: &Path) -> u64 {
+    use crate::commands::cache;
+    let s = cache::stats(cache_dir, cache::current_unix_time(), cache::CACHE_DURATION);
+    s["total_files"] + s["preview_files"] + s["metadata_files"]
+}
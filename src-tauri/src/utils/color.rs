@@ -0,0 +1,203 @@
+//! Colour management. Decoded pixels are converted from their embedded ICC
+//! profile to the output space before resize and encode, so Adobe RGB,
+//! ProPhoto and CMYK sources look right whatever the WebView does with
+//! profiles. The output space is sRGB (previews go out untagged), or the
+//! display profile named by `SPICA_DISPLAY_ICC` (an .icc/.icm file, read once
+//! per process; previews then carry it).
+
+use image::{DynamicImage, RgbImage, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+use rayon::prelude::*;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Rows per transform job.
+const BAND_ROWS: usize = 64;
+
+struct Output {
+    profile: ColorProfile,
+    /// The display profile's bytes; `None` for sRGB.
+    icc: Option<Vec<u8>>,
+    /// Cache-key suffix; empty for sRGB.
+    key: String,
+}
+
+fn output() -> &'static Output {
+    static OUTPUT: OnceLock<Output> = OnceLock::new();
+    OUTPUT.get_or_init(|| {
+        let srgb = Output {
+            profile: ColorProfile::new_srgb(),
+            icc: None,
+            key: String::new(),
+        };
+        let Some(path) = std::env::var_os("SPICA_DISPLAY_ICC") else {
+            return srgb;
+        };
+        match load_display_profile(Path::new(&path)) {
+            Ok(display) => display,
+            Err(e) => {
+                eprintln!("color: ignoring SPICA_DISPLAY_ICC ({e})");
+                srgb
+            }
+        }
+    })
+}
+
+fn load_display_profile(path: &Path) -> Result<Output, String> {
+    use sha2::{Digest, Sha256};
+    let icc = std::fs::read(path).map_err(|e| format!("read: {e}"))?;
+    let profile = ColorProfile::new_from_slice(&icc).map_err(|e| format!("parse: {e}"))?;
+    if profile.color_space != DataColorSpace::Rgb {
+        return Err("not an RGB profile".to_string());
+    }
+    // Stable across toolchains, like the cache's own keys.
+    let digest: String = Sha256::digest(&icc)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok(Output {
+        profile,
+        key: format!("icc{digest}"),
+        icc: Some(icc),
+    })
+}
+
+/// `key` for pixels in the output space: unchanged for sRGB, suffixed with
+/// the display profile otherwise, so switching profiles never serves
+/// previews or tiles rendered for the old one.
+pub fn keyed(key: String) -> String {
+    match output().key.as_str() {
+        "" => key,
+        suffix => format!("{key}.{suffix}"),
+    }
+}
+
+/// Converts 8-bit RGB or RGBA pixels described by `icc` (an RGB profile;
+/// sRGB when `None`) to the output space, returning the profile the result
+/// carries. A profile the CMS cannot read is passed through with the pixels
+/// untouched, as before colour management, for the WebView to interpret.
+pub fn rgb_to_output(image: DynamicImage, icc: Option<Vec<u8>>) -> (DynamicImage, Option<Vec<u8>>) {
+    let out = output();
+    let source = match icc.as_deref() {
+        None if out.icc.is_none() => return (image, None),
+        None => ColorProfile::new_srgb(),
+        Some(bytes) => match ColorProfile::new_from_slice(bytes) {
+            Ok(profile) if profile.color_space == DataColorSpace::Rgb => profile,
+            _ => return (image, icc),
+        },
+    };
+    let (width, height) = (image.width(), image.height());
+    let converted = match &image {
+        DynamicImage::ImageRgb8(rgb) => transform(&source, Layout::Rgb, rgb.as_raw(), width, 3)
+            .and_then(|px| {
+                RgbImage::from_raw(width, height, px)
+                    .map(DynamicImage::ImageRgb8)
+                    .ok_or_else(|| "buffer size mismatch".to_string())
+            }),
+        DynamicImage::ImageRgba8(rgba) => transform(&source, Layout::Rgba, rgba.as_raw(), width, 4)
+            .and_then(|px| {
+                RgbaImage::from_raw(width, height, px)
+                    .map(DynamicImage::ImageRgba8)
+                    .ok_or_else(|| "buffer size mismatch".to_string())
+            }),
+        _ => Err("expected 8-bit RGB or RGBA".to_string()),
+    };
+    match converted {
+        Ok(converted) => (converted, out.icc.clone()),
+        Err(e) => {
+            eprintln!("color: keeping source colours ({e})");
+            (image, icc)
+        }
+    }
+}
+
+/// Converts raw CMYK pixels (0 = no ink, four bytes a pixel) through their
+/// CMYK profile `icc` to RGB in the output space, returning the profile the
+/// result carries.
+pub fn cmyk_to_output(
+    cmyk: &[u8],
+    width: u32,
+    height: u32,
+    icc: &[u8],
+) -> Result<(RgbImage, Option<Vec<u8>>), String> {
+    let source = ColorProfile::new_from_slice(icc).map_err(|e| format!("icc: {e}"))?;
+    if source.color_space != DataColorSpace::Cmyk {
+        return Err("icc: not a CMYK profile".to_string());
+    }
+    let out = output();
+    let options = TransformOptions::default();
+    // moxcms takes four-channel CMYK under the RGBA layout.
+    let transform = source
+        .create_transform_8bit(Layout::Rgba, &out.profile, Layout::Rgb, options)
+        .map_err(|e| format!("icc: {e}"))?;
+    let mut rgb = vec![0u8; width as usize * height as usize * 3];
+    let band = width as usize * BAND_ROWS;
+    cmyk.par_chunks(band * 4)
+        .zip(rgb.par_chunks_mut(band * 3))
+        .try_for_each(|(src, dst)| transform.transform(src, dst))
+        .map_err(|e| format!("icc: {e}"))?;
+    let rgb = RgbImage::from_raw(width, height, rgb)
+        .ok_or_else(|| "icc: buffer size mismatch".to_string())?;
+    Ok((rgb, out.icc.clone()))
+}
+
+/// `pixels` (`channels` per pixel, `width` per row) from `source` to the
+/// output space in the same layout.
+fn transform(
+    source: &ColorProfile,
+    layout: Layout,
+    pixels: &[u8],
+    width: u32,
+    channels: usize,
+) -> Result<Vec<u8>, String> {
+    let transform = source
+        .create_transform_8bit(
+            layout,
+            &output().profile,
+            layout,
+            TransformOptions::default(),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut out = vec![0u8; pixels.len()];
+    let band = width as usize * channels * BAND_ROWS;
+    pixels
+        .par_chunks(band)
+        .zip(out.par_chunks_mut(band))
+        .try_for_each(|(src, dst)| transform.transform(src, dst))
+        .map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn rgb_to_output_converts_wide_gamut_to_srgb() {
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, Rgb([191, 32, 64])));
+        let (converted, icc) = rgb_to_output(image, Some(p3));
+        assert_eq!(icc, None);
+        let px = converted.to_rgb8().get_pixel(4, 4).0;
+        // Display P3 red is more saturated than sRGB's, so it reads redder.
+        assert!(px[0] > 199, "{px:?}");
+        assert!(px[1] < 32, "{px:?}");
+    }
+
+    #[test]
+    fn rgb_to_output_leaves_unreadable_and_untagged_pixels_alone() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([10, 20, 30])));
+        let (same, icc) = rgb_to_output(image.clone(), None);
+        assert_eq!((same.as_bytes(), icc), (image.as_bytes(), None));
+        let mut junk: Vec<u8> = (0..600u32).map(|i| (i % 251) as u8).collect();
+        junk[16..20].copy_from_slice(b"RGB ");
+        let (same, icc) = rgb_to_output(image.clone(), Some(junk.clone()));
+        assert_eq!((same.as_bytes(), icc), (image.as_bytes(), Some(junk)));
+    }
+
+    #[test]
+    fn keyed_is_the_identity_for_srgb() {
+        assert_eq!(keyed("1920x1080".to_string()), "1920x1080");
+    }
+}
@@ -0,0 +1,421 @@
+//! Preview encoders. A [`PreviewProfile`] names the output format and its
+//! quality knobs; it is part of the preview cache key and sidecar, so one
+//! source can hold previews in several encodings side by side. The default
+//! profile is the 4:2:0 JPEG previews have always been, under the same keys.
+
+use crate::utils::color;
+use crate::utils::preview::{PreviewBox, PREVIEW_JPEG_QUALITY};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageEncoder};
+use jpeg_encoder::{ColorType as JpegColorType, Encoder as JpegEncoderFast, SamplingFactor};
+use serde::{Deserialize, Serialize};
+
+/// rav1e speed (1 slowest - 10 fastest). Previews are made while the user
+/// waits, so size is traded for time.
+const AVIF_SPEED: u8 = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewFormat {
+    #[default]
+    Jpeg,
+    /// Lossy WebP (always 4:2:0).
+    Webp,
+    WebpLossless,
+    /// AVIF (always 4:4:4).
+    Avif,
+}
+
+impl PreviewFormat {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "jpeg" | "jpg" => Self::Jpeg,
+            "webp" => Self::Webp,
+            "webp_lossless" => Self::WebpLossless,
+            "avif" => Self::Avif,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpeg",
+            Self::Webp => "webp",
+            Self::WebpLossless => "webp_lossless",
+            Self::Avif => "avif",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Webp | Self::WebpLossless => "image/webp",
+            Self::Avif => "image/avif",
+        }
+    }
+
+    /// Whether the encoder is handed RGBA: everything but JPEG keeps alpha.
+    pub fn keeps_alpha(self) -> bool {
+        self != Self::Jpeg
+    }
+
+    fn default_quality(self) -> u8 {
+        match self {
+            Self::Jpeg => PREVIEW_JPEG_QUALITY,
+            Self::Webp => 80,
+            Self::WebpLossless => 100,
+            Self::Avif => 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    Yuv420,
+    Yuv422,
+    Yuv444,
+}
+
+impl ChromaSubsampling {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "420" => Self::Yuv420,
+            "422" => Self::Yuv422,
+            "444" => Self::Yuv444,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Yuv420 => "420",
+            Self::Yuv422 => "422",
+            Self::Yuv444 => "444",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewProfile {
+    pub format: PreviewFormat,
+    /// 1-100; fixed at 100 for lossless WebP.
+    pub quality: u8,
+    /// Honoured by JPEG only; the other formats have a fixed layout and carry
+    /// it here so equal encodings compare (and key) equal.
+    pub chroma: ChromaSubsampling,
+}
+
+impl Default for PreviewProfile {
+    fn default() -> Self {
+        PreviewProfile::DEFAULT
+    }
+}
+
+impl PreviewProfile {
+    pub const DEFAULT: PreviewProfile = PreviewProfile {
+        format: PreviewFormat::Jpeg,
+        quality: PREVIEW_JPEG_QUALITY,
+        chroma: ChromaSubsampling::Yuv420,
+    };
+
+    /// `format` with its default quality.
+    #[cfg(test)]
+    pub fn new(format: PreviewFormat) -> Self {
+        PreviewProfile {
+            format,
+            quality: format.default_quality(),
+            chroma: ChromaSubsampling::Yuv420,
+        }
+        .normalized()
+    }
+
+    /// Reads `format`, `quality` and `chroma` from a `/preview/` query string
+    /// (`?format=avif&quality=55`); each is optional and other parameters
+    /// are ignored. Quality is clamped to 1-100.
+    pub fn from_query(query: Option<&str>) -> Result<Self, String> {
+        let mut format = PreviewFormat::Jpeg;
+        let mut quality = None;
+        let mut chroma = ChromaSubsampling::Yuv420;
+        for pair in query.unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match name {
+                "format" => {
+                    format = PreviewFormat::parse(value)
+                        .ok_or_else(|| format!("unsupported preview format: {value}"))?;
+                }
+                "quality" => {
+                    let q = value
+                        .parse::<u32>()
+                        .map_err(|_| format!("invalid preview quality: {value}"))?;
+                    quality = Some(q.clamp(1, 100) as u8);
+                }
+                "chroma" => {
+                    chroma = ChromaSubsampling::parse(value)
+                        .ok_or_else(|| format!("unsupported chroma subsampling: {value}"))?;
+                }
+                _ => {}
+            }
+        }
+        Ok(PreviewProfile {
+            format,
+            quality: quality.unwrap_or(format.default_quality()),
+            chroma,
+        }
+        .normalized())
+    }
+
+    fn normalized(mut self) -> Self {
+        match self.format {
+            PreviewFormat::Jpeg => {}
+            PreviewFormat::Webp => self.chroma = ChromaSubsampling::Yuv420,
+            PreviewFormat::WebpLossless => {
+                self.quality = 100;
+                self.chroma = ChromaSubsampling::Yuv444;
+            }
+            PreviewFormat::Avif => self.chroma = ChromaSubsampling::Yuv444,
+        }
+        self
+    }
+
+    /// Suffix for the preview cache key; empty for [`Self::DEFAULT`] so
+    /// existing JPEG previews keep their keys.
+    pub fn key(&self) -> String {
+        if *self == PreviewProfile::DEFAULT {
+            return String::new();
+        }
+        match self.format {
+            PreviewFormat::Jpeg => format!("jpeg-q{}-{}", self.quality, self.chroma.name()),
+            PreviewFormat::WebpLossless => self.format.name().to_string(),
+            PreviewFormat::Webp | PreviewFormat::Avif => {
+                format!("{}-q{}", self.format.name(), self.quality)
+            }
+        }
+    }
+
+    /// Preview cache key for `bbox` in this encoding: the box key alone for
+    /// the default profile, `<box>.<profile>` otherwise (plus the display
+    /// profile, see [`color::keyed`]).
+    pub fn cache_key(&self, bbox: PreviewBox) -> String {
+        color::keyed(match self.key() {
+            key if key.is_empty() => bbox.key(),
+            key => format!("{}.{key}", bbox.key()),
+        })
+    }
+
+    pub fn encoder(&self) -> Box<dyn PreviewEncoder> {
+        match self.format {
+            PreviewFormat::Jpeg => Box::new(JpegPreviewEncoder {
+                quality: self.quality,
+                chroma: self.chroma,
+            }),
+            PreviewFormat::Webp => Box::new(WebpPreviewEncoder {
+                quality: self.quality,
+            }),
+            PreviewFormat::WebpLossless => Box::new(WebpLosslessPreviewEncoder),
+            PreviewFormat::Avif => Box::new(AvifPreviewEncoder {
+                quality: self.quality,
+            }),
+        }
+    }
+}
+
+/// Encodes an 8-bit RGB or RGBA preview. `icc` is embedded where the format
+/// and encoder allow it (JPEG, lossless WebP) and dropped otherwise.
+pub trait PreviewEncoder {
+    fn encode(&self, image: &DynamicImage, icc: Option<&[u8]>) -> Result<Vec<u8>, String>;
+}
+
+struct JpegPreviewEncoder {
+    quality: u8,
+    chroma: ChromaSubsampling,
+}
+
+impl PreviewEncoder for JpegPreviewEncoder {
+    fn encode(&self, image: &DynamicImage, icc: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let rgb;
+        let rgb = match image {
+            DynamicImage::ImageRgb8(rgb) => rgb,
+            other => {
+                rgb = other.to_rgb8();
+                &rgb
+            }
+        };
+        encode_jpeg(rgb, self.quality, self.chroma, icc)
+    }
+}
+
+/// JPEG via the `jpeg-encoder` crate: its SIMD path makes it several times
+/// faster than the `image` crate's encoder, which dominated thumb_preview
+/// (see the Phase 2 gate numbers in the plan ledger).
+pub(crate) fn encode_jpeg(
+    rgb: &image::RgbImage,
+    quality: u8,
+    chroma: ChromaSubsampling,
+    icc: Option<&[u8]>,
+) -> Result<Vec<u8>, String> {
+    let (w, h) = (rgb.width(), rgb.height());
+    let width =
+        u16::try_from(w).map_err(|_| format!("encode: width {w} exceeds the JPEG limit"))?;
+    let height =
+        u16::try_from(h).map_err(|_| format!("encode: height {h} exceeds the JPEG limit"))?;
+    let mut out: Vec<u8> = Vec::with_capacity((w as usize * h as usize) / 4);
+    let mut encoder = JpegEncoderFast::new(&mut out, quality);
+    encoder.set_sampling_factor(match chroma {
+        ChromaSubsampling::Yuv420 => SamplingFactor::F_2_2,
+        ChromaSubsampling::Yuv422 => SamplingFactor::F_2_1,
+        ChromaSubsampling::Yuv444 => SamplingFactor::F_1_1,
+    });
+    if let Some(icc) = icc {
+        // X2: a profile the encoder refuses (e.g. > 254 APP2 chunks, so over
+        // ~15.9 MB) must not fail the whole preview — degrade to no ICC
+        // rather than lose the bar thumbnail generated alongside it.
+        if let Err(e) = encoder.add_icc_profile(icc) {
+            eprintln!("preview: dropping ICC profile ({e})");
+        }
+    }
+    encoder
+        .encode(rgb.as_raw(), width, height, JpegColorType::Rgb)
+        .map_err(|e| format!("encode: {e}"))?;
+    Ok(out)
+}
+
+struct WebpPreviewEncoder {
+    quality: u8,
+}
+
+impl PreviewEncoder for WebpPreviewEncoder {
+    fn encode(&self, image: &DynamicImage, _icc: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let (w, h) = (image.width(), image.height());
+        let encoder = match image {
+            DynamicImage::ImageRgb8(rgb) => webp::Encoder::from_rgb(rgb.as_raw(), w, h),
+            DynamicImage::ImageRgba8(rgba) => webp::Encoder::from_rgba(rgba.as_raw(), w, h),
+            _ => return Err("encode: webp needs 8-bit RGB or RGBA".to_string()),
+        };
+        let encoded = encoder
+            .encode_simple(false, f32::from(self.quality))
+            .map_err(|e| format!("encode: webp {e:?}"))?;
+        Ok(encoded.to_vec())
+    }
+}
+
+struct WebpLosslessPreviewEncoder;
+
+impl PreviewEncoder for WebpLosslessPreviewEncoder {
+    fn encode(&self, image: &DynamicImage, icc: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let mut encoder = WebPEncoder::new_lossless(&mut out);
+        if let Some(icc) = icc {
+            if let Err(e) = encoder.set_icc_profile(icc.to_vec()) {
+                eprintln!("preview: dropping ICC profile ({e})");
+            }
+        }
+        encoder
+            .write_image(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().into(),
+            )
+            .map_err(|e| format!("encode: {e}"))?;
+        Ok(out)
+    }
+}
+
+struct AvifPreviewEncoder {
+    quality: u8,
+}
+
+impl PreviewEncoder for AvifPreviewEncoder {
+    fn encode(&self, image: &DynamicImage, _icc: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        AvifEncoder::new_with_speed_quality(&mut out, AVIF_SPEED, self.quality)
+            .write_image(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().into(),
+            )
+            .map_err(|e| format!("encode: {e}"))?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn translucent() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(48, 32, |x, y| {
+            Rgba([
+                (x * 5) as u8,
+                (y * 7) as u8,
+                90,
+                if x < 24 { 255 } else { 64 },
+            ])
+        }))
+    }
+
+    #[test]
+    fn from_query_parses_and_normalizes() {
+        assert_eq!(
+            PreviewProfile::from_query(None).unwrap(),
+            PreviewProfile::DEFAULT
+        );
+        assert_eq!(PreviewProfile::DEFAULT.key(), "");
+        let avif = PreviewProfile::from_query(Some("format=avif&quality=250&v=3")).unwrap();
+        assert_eq!((avif.format, avif.quality), (PreviewFormat::Avif, 100));
+        assert_eq!(avif.key(), "avif-q100");
+        let jpeg = PreviewProfile::from_query(Some("quality=92&chroma=444")).unwrap();
+        assert_eq!(jpeg.key(), "jpeg-q92-444");
+        // Knobs a format ignores don't split its cache entries.
+        assert_eq!(
+            PreviewProfile::from_query(Some("format=webp_lossless&quality=10&chroma=422")).unwrap(),
+            PreviewProfile::new(PreviewFormat::WebpLossless)
+        );
+        let bbox = PreviewBox::parse("1920x1080").unwrap();
+        assert_eq!(PreviewProfile::DEFAULT.cache_key(bbox), "1920x1080");
+        assert_eq!(avif.cache_key(bbox), "1920x1080.avif-q100");
+        assert!(PreviewProfile::from_query(Some("format=bmp")).is_err());
+        assert!(PreviewProfile::from_query(Some("quality=high")).is_err());
+        assert!(PreviewProfile::from_query(Some("chroma=411")).is_err());
+    }
+
+    #[test]
+    fn every_format_encodes_what_it_names() {
+        let image = translucent();
+        for (format, expected) in [
+            (PreviewFormat::Jpeg, image::ImageFormat::Jpeg),
+            (PreviewFormat::Webp, image::ImageFormat::WebP),
+            (PreviewFormat::WebpLossless, image::ImageFormat::WebP),
+            (PreviewFormat::Avif, image::ImageFormat::Avif),
+        ] {
+            let bytes = PreviewProfile::new(format)
+                .encoder()
+                .encode(&image, None)
+                .unwrap();
+            assert_eq!(image::guess_format(&bytes).unwrap(), expected, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn webp_keeps_alpha() {
+        let image = translucent();
+        for format in [PreviewFormat::Webp, PreviewFormat::WebpLossless] {
+            let bytes = PreviewProfile::new(format)
+                .encoder()
+                .encode(&image, None)
+                .unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+            assert_eq!(decoded.get_pixel(2, 2)[3], 255, "{format:?}");
+            assert!(decoded.get_pixel(40, 2)[3] < 100, "{format:?}");
+        }
+        let lossless = PreviewProfile::new(PreviewFormat::WebpLossless)
+            .encoder()
+            .encode(&image, None)
+            .unwrap();
+        let decoded = image::load_from_memory(&lossless).unwrap().to_rgba8();
+        assert_eq!(decoded.as_raw(), image.to_rgba8().as_raw());
+    }
+}
@@ -0,0 +1,465 @@
+//! Content-sniffed source format detection. The extension only nominates a
+//! file as a candidate (or says nothing, for extensionless files); the magic
+//! bytes decide what it actually is. Results are cached per path and source
+//! stamp so a folder rescan costs one `stat` per file, not an `open`.
+
+use crate::commands::cache::source_stamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Enough for every signature below, including a few ftyp compatible brands.
+const SNIFF_LEN: usize = 64;
+
+/// The cache is dropped wholesale past this many paths; a rescan refills it.
+const SNIFF_CACHE_CAP: usize = 16_384;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceFormat {
+    Jpeg,
+    Png,
+    WebP,
+    Gif,
+    Heic,
+    Heif,
+    Avif,
+    Cr2,
+    Cr3,
+    Nef,
+    Nrw,
+    Arw,
+    Dng,
+}
+
+impl SourceFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        Some(match ext.as_str() {
+            "jpg" | "jpeg" => Self::Jpeg,
+            "png" => Self::Png,
+            "webp" => Self::WebP,
+            "gif" => Self::Gif,
+            "heic" => Self::Heic,
+            "heif" | "hif" => Self::Heif,
+            "avif" => Self::Avif,
+            "cr2" => Self::Cr2,
+            "cr3" => Self::Cr3,
+            "nef" => Self::Nef,
+            "nrw" => Self::Nrw,
+            "arw" => Self::Arw,
+            "dng" => Self::Dng,
+            _ => return None,
+        })
+    }
+
+    /// Lower-case name as sent to the frontend in `ImageInfo.format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpeg",
+            Self::Png => "png",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+            Self::Heic => "heic",
+            Self::Heif => "heif",
+            Self::Avif => "avif",
+            Self::Cr2 => "cr2",
+            Self::Cr3 => "cr3",
+            Self::Nef => "nef",
+            Self::Nrw => "nrw",
+            Self::Arw => "arw",
+            Self::Dng => "dng",
+        }
+    }
+
+    /// Content-Type of the file's own bytes. RAW sources are never served
+    /// as-is (the raw route sends their embedded JPEG), hence the generic type.
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::WebP => "image/webp",
+            Self::Gif => "image/gif",
+            Self::Heic => "image/heic",
+            Self::Heif => "image/heif",
+            Self::Avif => "image/avif",
+            _ => "application/octet-stream",
+        }
+    }
+
+    /// HEIF-container sources (HEIC/HEIF from phones, AVIF). `image` cannot
+    /// decode these; they go through `utils::heif` when the `heif` feature is on.
+    pub fn is_heif_family(self) -> bool {
+        matches!(self, Self::Heic | Self::Heif | Self::Avif)
+    }
+
+    /// Camera RAW, shown through its embedded JPEG preview (`utils::raw`).
+    pub fn is_camera_raw(self) -> bool {
+        matches!(
+            self,
+            Self::Cr2 | Self::Cr3 | Self::Nef | Self::Nrw | Self::Arw | Self::Dng
+        )
+    }
+
+    /// Sources the WebView cannot display natively, so the raw `spica-img`
+    /// route sends a transcoded preview instead of the file's bytes. AVIF is
+    /// not in here: every WebView Spica runs on decodes it.
+    pub fn needs_transcode(self) -> bool {
+        matches!(self, Self::Heic | Self::Heif)
+    }
+
+    /// Whether this build can decode the format at all.
+    pub fn is_decodable(self) -> bool {
+        !self.is_heif_family() || cfg!(feature = "heif")
+    }
+
+    /// Same decoder, different label: a `.heif` holding HEVC data is not a
+    /// mismatch worth reporting.
+    fn same_family(self, other: Self) -> bool {
+        self == other
+            || (matches!(self, Self::Heic | Self::Heif) && matches!(other, Self::Heic | Self::Heif))
+    }
+}
+
+/// What the magic bytes say. NEF/NRW/ARW/DNG are plain TIFF containers with
+/// no signature of their own, so they only come out as `Tiff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sniffed {
+    Format(SourceFormat),
+    Tiff,
+}
+
+/// The extension and the content disagree. Reported on `ImageInfo` so the
+/// frontend can flag it; the content wins for decoding and Content-Type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatMismatch {
+    /// Lower-cased extension, `None` for an extensionless file.
+    pub extension: Option<String>,
+    pub detected: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub format: SourceFormat,
+    pub mismatch: Option<FormatMismatch>,
+}
+
+/// Files worth sniffing: a known image extension, or none at all. Anything
+/// else (`.txt`, `.mp4`, ...) is rejected without being opened.
+pub fn is_candidate(path: &Path) -> bool {
+    path.extension().is_none() || SourceFormat::from_extension(path).is_some()
+}
+
+/// Format of `path`, content first. A candidate whose content is unreadable
+/// or unrecognised keeps its extension's format, so a corrupt `.jpg` still
+/// lists and fails at decode time as before; an extensionless file needs a
+/// positive sniff.
+pub fn detect(path: &Path) -> Option<Detection> {
+    if !is_candidate(path) {
+        return None;
+    }
+    let by_ext = SourceFormat::from_extension(path);
+    let sniffed = sniff_cached(path);
+    let format = match (sniffed, by_ext) {
+        (Some(Sniffed::Format(f)), _) => f,
+        // A TIFF container named as a TIFF-based RAW is what it says it is.
+        (Some(Sniffed::Tiff), Some(e)) if e.is_camera_raw() && e != SourceFormat::Cr3 => {
+            return Some(Detection {
+                format: e,
+                mismatch: None,
+            })
+        }
+        (Some(Sniffed::Tiff), _) => return None,
+        (None, Some(e)) => {
+            return Some(Detection {
+                format: e,
+                mismatch: None,
+            })
+        }
+        (None, None) => return None,
+    };
+    let mismatch = match by_ext {
+        Some(e) if e.same_family(format) => None,
+        _ => Some(FormatMismatch {
+            extension: path
+                .extension()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_lowercase()),
+            detected: format.name().to_string(),
+        }),
+    };
+    Some(Detection { format, mismatch })
+}
+
+/// A candidate this build can decode.
+pub fn is_supported_source(path: &Path) -> bool {
+    detect(path).is_some_and(|d| d.format.is_decodable())
+}
+
+pub fn is_gif(path: &Path) -> bool {
+    detect(path).is_some_and(|d| d.format == SourceFormat::Gif)
+}
+
+/// Path → (source stamp, sniff result).
+type SniffCache = HashMap<PathBuf, ((u64, u64), Option<Sniffed>)>;
+
+fn sniff_cache() -> &'static Mutex<SniffCache> {
+    static CACHE: OnceLock<Mutex<SniffCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn sniff_cached(path: &Path) -> Option<Sniffed> {
+    let stamp = source_stamp(path)?;
+    if let Ok(cache) = sniff_cache().lock() {
+        if let Some((s, sniffed)) = cache.get(path) {
+            if *s == stamp {
+                return *sniffed;
+            }
+        }
+    }
+    let sniffed = sniff_file(path);
+    if let Ok(mut cache) = sniff_cache().lock() {
+        if cache.len() >= SNIFF_CACHE_CAP {
+            cache.clear();
+        }
+        cache.insert(path.to_path_buf(), (stamp, sniffed));
+    }
+    sniffed
+}
+
+fn sniff_file(path: &Path) -> Option<Sniffed> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    sniff(&head)
+}
+
+fn sniff(head: &[u8]) -> Option<Sniffed> {
+    let f = |f| Some(Sniffed::Format(f));
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return f(SourceFormat::Jpeg);
+    }
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return f(SourceFormat::Png);
+    }
+    if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        return f(SourceFormat::Gif);
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return f(SourceFormat::WebP);
+    }
+    if head.starts_with(b"II\x2A\x00") || head.starts_with(b"MM\x00\x2A") {
+        // CR2 marks itself right after the TIFF header.
+        if head.len() >= 11 && &head[8..10] == b"CR" && head[10] == 2 {
+            return f(SourceFormat::Cr2);
+        }
+        return Some(Sniffed::Tiff);
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return sniff_ftyp(head).map(Sniffed::Format);
+    }
+    None
+}
+
+/// ISO-BMFF: the major brand, then the compatible brands that fit in `head`.
+/// A generic `mif1`/`msf1` major brand defers to a more specific compatible one.
+fn sniff_ftyp(head: &[u8]) -> Option<SourceFormat> {
+    let size = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as usize;
+    let end = size.clamp(12, head.len());
+    let brands = std::iter::once(&head[8..12]).chain(
+        head.get(16..end)
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .filter(|b| *b != b"\0\0\0\0"),
+    );
+    let mut generic = false;
+    for brand in brands {
+        match brand {
+            b"avif" | b"avis" => return Some(SourceFormat::Avif),
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => {
+                return Some(SourceFormat::Heic)
+            }
+            b"crx " => return Some(SourceFormat::Cr3),
+            b"mif1" | b"msf1" => generic = true,
+            _ => {}
+        }
+    }
+    generic.then_some(SourceFormat::Heif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::path::Path;
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut v = size.to_be_bytes().to_vec();
+        v.extend_from_slice(b"ftyp");
+        v.extend_from_slice(major);
+        v.extend_from_slice(&[0, 0, 0, 0]);
+        for b in compatible {
+            v.extend_from_slice(*b);
+        }
+        v
+    }
+
+    #[test]
+    fn sniff_recognises_signatures() {
+        let format = |f| Some(Sniffed::Format(f));
+        assert_eq!(sniff(&[0xFF, 0xD8, 0xFF, 0xE0]), format(SourceFormat::Jpeg));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), format(SourceFormat::Png));
+        assert_eq!(sniff(b"GIF89a..."), format(SourceFormat::Gif));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), format(SourceFormat::WebP));
+        assert_eq!(sniff(b"II*\0\x10\0\0\0CR\x02\0"), format(SourceFormat::Cr2));
+        assert_eq!(sniff(b"MM\0*\0\0\0\x08"), Some(Sniffed::Tiff));
+        assert_eq!(sniff(b"hello world!"), None);
+        assert_eq!(sniff(&[]), None);
+    }
+
+    #[test]
+    fn sniff_ftyp_prefers_specific_brands() {
+        let format = |f| Some(Sniffed::Format(f));
+        assert_eq!(
+            sniff(&ftyp(b"avif", &[b"mif1"])),
+            format(SourceFormat::Avif)
+        );
+        assert_eq!(sniff(&ftyp(b"heic", &[])), format(SourceFormat::Heic));
+        assert_eq!(
+            sniff(&ftyp(b"mif1", &[b"mif1", b"heic"])),
+            format(SourceFormat::Heic)
+        );
+        assert_eq!(
+            sniff(&ftyp(b"mif1", &[b"miaf"])),
+            format(SourceFormat::Heif)
+        );
+        assert_eq!(sniff(&ftyp(b"crx ", &[b"isom"])), format(SourceFormat::Cr3));
+        assert_eq!(sniff(&ftyp(b"isom", &[b"mp41"])), None);
+    }
+
+    #[test]
+    fn detect_trusts_content_over_extension() {
+        let dir = create_temp_dir();
+        let png = create_test_png(dir.path(), "a.png");
+        let misnamed = dir.path().join("really_png.jpg");
+        std::fs::copy(&png, &misnamed).unwrap();
+
+        let d = detect(&misnamed).unwrap();
+        assert_eq!(d.format, SourceFormat::Png);
+        assert_eq!(
+            d.mismatch,
+            Some(FormatMismatch {
+                extension: Some("jpg".to_string()),
+                detected: "png".to_string(),
+            })
+        );
+        assert_eq!(detect(&png).unwrap().mismatch, None);
+    }
+
+    #[test]
+    fn detect_accepts_extensionless_images_only_by_content() {
+        let dir = create_temp_dir();
+        let png = create_test_png(dir.path(), "a.png");
+        let bare = dir.path().join("scan0001");
+        std::fs::copy(&png, &bare).unwrap();
+        let d = detect(&bare).unwrap();
+        assert_eq!(d.format, SourceFormat::Png);
+        assert_eq!(d.mismatch.unwrap().extension, None);
+        assert!(is_supported_source(&bare));
+
+        let text = dir.path().join("README");
+        std::fs::write(&text, "not an image").unwrap();
+        assert!(detect(&text).is_none());
+        assert!(!is_supported_source(&text));
+    }
+
+    #[test]
+    fn detect_keeps_the_extension_for_unrecognised_content() {
+        let dir = create_temp_dir();
+        let bad = create_invalid_image(dir.path(), "broken.jpg");
+        let d = detect(&bad).unwrap();
+        assert_eq!(d.format, SourceFormat::Jpeg);
+        assert_eq!(d.mismatch, None);
+        // Not a candidate at all: never opened.
+        assert!(detect(Path::new("notes.txt")).is_none());
+    }
+
+    #[test]
+    fn detect_names_tiff_raws_by_extension() {
+        let dir = create_temp_dir();
+        let nef = create_fake_tiff_raw(dir.path(), "shot.nef", 1, 64, 48);
+        assert_eq!(detect(&nef).unwrap().format, SourceFormat::Nef);
+        let bare = dir.path().join("shot");
+        std::fs::copy(&nef, &bare).unwrap();
+        assert!(detect(&bare).is_none());
+    }
+
+    #[test]
+    fn detect_resniffs_after_the_file_changes() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("swap");
+        let png = create_test_png(dir.path(), "a.png");
+        let gif = create_test_gif(dir.path(), "a.gif");
+        std::fs::copy(&png, &path).unwrap();
+        assert!(!is_gif(&path));
+        std::fs::copy(&gif, &path).unwrap();
+        // Different size, so the stamp changes even within the same second.
+        assert_ne!(
+            std::fs::metadata(&png).unwrap().len(),
+            std::fs::metadata(&gif).unwrap().len()
+        );
+        assert!(is_gif(&path));
+    }
+
+    #[test]
+    fn from_extension_classifies_families() {
+        let ext = |name| SourceFormat::from_extension(Path::new(name)).unwrap();
+        for name in ["a.heic", "a.HEIF", "a.hif", "a.avif"] {
+            assert!(ext(name).is_heif_family(), "{name}");
+        }
+        for name in ["a.CR2", "a.cr3", "a.nef", "a.nrw", "a.arw", "a.DNG"] {
+            assert!(ext(name).is_camera_raw(), "{name}");
+        }
+        assert!(ext("a.heic").needs_transcode());
+        assert!(ext("a.HEIF").needs_transcode());
+        assert!(ext("a.hif").needs_transcode());
+        assert!(!ext("a.avif").needs_transcode());
+        assert!(!ext("a.jpg").needs_transcode());
+        assert!(!ext("a.jpg").is_camera_raw());
+        assert_eq!(SourceFormat::from_extension(Path::new("a.tif")), None);
+        assert_eq!(SourceFormat::from_extension(Path::new("a")), None);
+    }
+
+    #[test]
+    fn common_formats_are_supported_sources_and_other_files_are_not() {
+        let dir = create_temp_dir();
+        for path in [
+            create_test_jpeg(dir.path(), "a.jpg"),
+            create_test_jpeg(dir.path(), "b.jpeg"),
+            create_test_png(dir.path(), "c.png"),
+            create_test_webp(dir.path(), "d.webp"),
+            create_test_gif(dir.path(), "e.gif"),
+        ] {
+            assert!(is_supported_source(&path), "{}", path.display());
+        }
+        for name in ["a.txt", "a.bmp", "a"] {
+            assert!(!is_supported_source(Path::new(name)), "{name}");
+        }
+    }
+
+    #[test]
+    fn heif_family_is_decodable_only_with_the_feature() {
+        for f in [SourceFormat::Heic, SourceFormat::Heif, SourceFormat::Avif] {
+            assert_eq!(f.is_decodable(), cfg!(feature = "heif"));
+        }
+        assert!(SourceFormat::Jpeg.is_decodable());
+        assert!(SourceFormat::Dng.is_decodable());
+    }
+}
@@ -0,0 +1,116 @@
+//! HEIF/HEIC and AVIF decoding through libheif (`heif` cargo feature).
+//! `image` has no HEIC decoder and its AVIF decoder needs dav1d, so both
+//! container families go through libheif and come back as an ordinary
+//! `DynamicImage` for the preview/thumbnail pipeline.
+
+use crate::utils::limits::{DecodeError, DecodeLimits};
+use image::{DynamicImage, RgbImage, RgbaImage};
+use libheif_rs::{color_profile_types, ColorProfile, ColorSpace, HeifContext, LibHeif, RgbChroma};
+use std::path::Path;
+
+pub struct HeifDecoded {
+    pub image: DynamicImage,
+    pub icc: Option<Vec<u8>>,
+}
+
+/// Decodes the primary image with its `irot`/`imir` transforms applied.
+/// HEIF stores orientation as container transforms rather than an Exif tag
+/// (the Exif block a phone writes alongside is informational and already
+/// reflected in them), so the result is upright and must not be rotated again.
+///
+/// The file is read into memory first: libheif's own file reader takes a
+/// narrow C string, which breaks on non-ASCII Windows paths.
+pub fn decode(path: &Path, limits: &DecodeLimits) -> Result<HeifDecoded, DecodeError> {
+    let bytes = std::fs::read(path).map_err(|e| format!("open: {e}"))?;
+    let ctx = HeifContext::read_from_bytes(&bytes).map_err(|e| format!("heif: {e}"))?;
+    let handle = ctx
+        .primary_image_handle()
+        .map_err(|e| format!("heif: {e}"))?;
+    let has_alpha = handle.has_alpha_channel();
+    let channels = if has_alpha { 4 } else { 3 };
+    let (width, height) = (handle.width(), handle.height());
+    limits.check(
+        width,
+        height,
+        u64::from(width) * u64::from(height) * channels,
+    )?;
+    let chroma = if has_alpha {
+        RgbChroma::Rgba
+    } else {
+        RgbChroma::Rgb
+    };
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(chroma), None)
+        .map_err(|e| format!("decode: {e}"))?;
+    let plane = decoded
+        .planes()
+        .interleaved
+        .ok_or_else(|| "decode: no interleaved plane".to_string())?;
+    let pixels = pack_rows(
+        plane.data,
+        plane.width,
+        plane.height,
+        plane.stride,
+        channels as usize,
+    )?;
+    let image = if has_alpha {
+        RgbaImage::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        RgbImage::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgb8)
+    }
+    .ok_or_else(|| "decode: buffer size mismatch".to_string())?;
+    // nclx (CICP) descriptions carry no ICC bytes; only real profiles are kept.
+    let icc = handle
+        .color_profile_raw()
+        .filter(|p| {
+            let t = p.profile_type();
+            t == color_profile_types::PROF || t == color_profile_types::R_ICC
+        })
+        .map(|p| p.data);
+    Ok(HeifDecoded { image, icc })
+}
+
+/// libheif pads each row out to `stride`; `image` wants tightly packed rows.
+fn pack_rows(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    channels: usize,
+) -> Result<Vec<u8>, String> {
+    let row = width as usize * channels;
+    if stride < row || data.len() < stride * (height as usize).saturating_sub(1) + row {
+        return Err("decode: plane smaller than its dimensions".to_string());
+    }
+    let mut out = Vec::with_capacity(row * height as usize);
+    for y in 0..height as usize {
+        out.extend_from_slice(&data[y * stride..y * stride + row]);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn pack_rows_drops_stride_padding() {
+        // 2x2 RGB with 2 bytes of padding per row.
+        let data = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0];
+        let packed = pack_rows(&data, 2, 2, 8, 3).unwrap();
+        assert_eq!(packed, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn pack_rows_rejects_a_short_plane() {
+        assert!(pack_rows(&[0u8; 10], 2, 2, 8, 3).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_non_heif_files() {
+        let dir = create_temp_dir();
+        let src = create_invalid_image(dir.path(), "bad.heic");
+        assert!(decode(&src, crate::utils::limits::current()).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Decompression-bomb guard. Every decode checks the frame's declared size
+//! against a pixel-count and an allocation limit before any pixel buffer
+//! exists, so a crafted 60000x60000 PNG in a folder fails fast instead of
+//! taking the app down during background thumbnailing. The limits default
+//! to [`DEFAULT_MAX_PIXELS`] and [`DEFAULT_MAX_ALLOC`]; `SPICA_MAX_MEGAPIXELS`
+//! and `SPICA_MAX_DECODE_MB` override them (read once per process). Tile
+//! pyramids get higher limits ([`tiled`]), which those variables can only
+//! lower.
+
+use image::ImageDecoder;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// 16384x16384: above any camera, below where a decode starts to hurt.
+pub const DEFAULT_MAX_PIXELS: u64 = 16384 * 16384;
+/// Largest pixel buffer a decode may produce, and the cap on the buffers
+/// decoders allocate along the way.
+pub const DEFAULT_MAX_ALLOC: u64 = 1024 * 1024 * 1024;
+/// Whole-frame limits for building deep-zoom tiles ([`tiled`]): a 32768px
+/// square, i.e. 1 GP, in 4 GiB. Tiles exist for images past the preview
+/// limits, and they are only built for the image the user zooms into.
+pub const TILED_MAX_PIXELS: u64 = 32768 * 32768;
+pub const TILED_MAX_ALLOC: u64 = 4 * 1024 * 1024 * 1024;
+/// Error-message prefix and cache marker for an image over the limits.
+pub const TOO_LARGE: &str = "too_large";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_pixels: u64,
+    pub max_alloc: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_pixels: DEFAULT_MAX_PIXELS,
+            max_alloc: DEFAULT_MAX_ALLOC,
+        }
+    }
+}
+
+/// Limits set through the environment, `None` where left at the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Overrides {
+    max_pixels: Option<u64>,
+    max_alloc: Option<u64>,
+}
+
+fn overrides() -> &'static Overrides {
+    static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+    OVERRIDES.get_or_init(|| {
+        let var = |name: &str| -> Option<u64> {
+            let value = std::env::var(name).ok()?;
+            match value.trim().parse::<u64>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    eprintln!("limits: ignoring {name}={value:?}");
+                    None
+                }
+            }
+        };
+        Overrides {
+            max_pixels: var("SPICA_MAX_MEGAPIXELS").map(|mp| mp.saturating_mul(1_000_000)),
+            max_alloc: var("SPICA_MAX_DECODE_MB").map(|mb| mb.saturating_mul(1024 * 1024)),
+        }
+    })
+}
+
+/// The process-wide limits.
+pub fn current() -> &'static DecodeLimits {
+    static LIMITS: OnceLock<DecodeLimits> = OnceLock::new();
+    LIMITS.get_or_init(|| DecodeLimits::default().overridden(overrides()))
+}
+
+/// The limits a tile pyramid level is decoded under: [`TILED_MAX_PIXELS`]
+/// and [`TILED_MAX_ALLOC`], lowered to whatever the environment sets.
+pub fn tiled() -> &'static DecodeLimits {
+    static LIMITS: OnceLock<DecodeLimits> = OnceLock::new();
+    LIMITS.get_or_init(|| DecodeLimits::tiled_under(overrides()))
+}
+
+impl DecodeLimits {
+    /// These limits with each set override in place of its default.
+    fn overridden(self, overrides: &Overrides) -> Self {
+        Self {
+            max_pixels: overrides.max_pixels.unwrap_or(self.max_pixels),
+            max_alloc: overrides.max_alloc.unwrap_or(self.max_alloc),
+        }
+    }
+
+    /// The tile limits under `overrides`: an override may lower them, as a
+    /// user who caps decodes means every decode, but never raise them.
+    fn tiled_under(overrides: &Overrides) -> Self {
+        let tiled = Self {
+            max_pixels: TILED_MAX_PIXELS,
+            max_alloc: TILED_MAX_ALLOC,
+        };
+        let overridden = tiled.overridden(overrides);
+        Self {
+            max_pixels: tiled.max_pixels.min(overridden.max_pixels),
+            max_alloc: tiled.max_alloc.min(overridden.max_alloc),
+        }
+    }
+
+    /// `Err(TooLarge)` when a `width`x`height` frame needing `bytes` of
+    /// pixel buffer exceeds either limit.
+    pub fn check(&self, width: u32, height: u32, bytes: u64) -> Result<(), DecodeError> {
+        let pixels = u64::from(width) * u64::from(height);
+        if pixels > self.max_pixels || bytes > self.max_alloc {
+            return Err(DecodeError::TooLarge { width, height });
+        }
+        Ok(())
+    }
+
+    /// [`Self::check`] on what `decoder` is about to produce, and the
+    /// allocation limit handed to it for its own working buffers.
+    pub fn apply(&self, decoder: &mut impl ImageDecoder) -> Result<(), DecodeError> {
+        let (width, height) = decoder.dimensions();
+        self.check(width, height, decoder.total_bytes())?;
+        decoder
+            .set_limits(self.image_limits())
+            .map_err(|e| DecodeError::from(format!("limits: {e}")))
+    }
+
+    /// The allocation limit in `image`'s form, for readers that only look
+    /// at headers and metadata.
+    pub fn image_limits(&self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+}
+
+/// Why a decode failed: an image over the limits is told apart from one
+/// that is broken, so the cache can remember it for good.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    TooLarge { width: u32, height: u32 },
+    Failed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { width, height } => {
+                write!(f, "{TOO_LARGE}: {width}x{height} exceeds the decode limits")
+            }
+            Self::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<String> for DecodeError {
+    fn from(msg: String) -> Self {
+        Self::Failed(msg)
+    }
+}
+
+impl From<DecodeError> for String {
+    fn from(e: DecodeError) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_refuses_pixels_or_bytes_over_the_limits() {
+        let limits = DecodeLimits {
+            max_pixels: 100,
+            max_alloc: 300,
+        };
+        assert_eq!(limits.check(10, 10, 300), Ok(()));
+        assert_eq!(
+            limits.check(11, 10, 300),
+            Err(DecodeError::TooLarge {
+                width: 11,
+                height: 10
+            })
+        );
+        assert!(limits.check(10, 10, 301).is_err());
+        assert!(DecodeLimits::default().check(60000, 60000, 0).is_err());
+    }
+
+    #[test]
+    fn tiled_limits_admit_what_the_preview_limits_refuse() {
+        let bytes = 20_000 * 20_000 * 3;
+        assert!(DecodeLimits::default()
+            .check(20_000, 20_000, bytes)
+            .is_err());
+        assert_eq!(tiled().check(20_000, 20_000, bytes), Ok(()));
+        assert!(tiled().check(60000, 60000, 0).is_err());
+    }
+
+    #[test]
+    fn lowered_limits_also_refuse_tiles() {
+        let bytes = 20_000 * 20_000 * 3;
+        let tiled = |overrides| DecodeLimits::tiled_under(&overrides);
+        assert_eq!(
+            tiled(Overrides::default()).check(20_000, 20_000, bytes),
+            Ok(())
+        );
+
+        // 100 MP or 512 MiB set by the user refuses the 400 MP scan.
+        let pixels = Overrides {
+            max_pixels: Some(100_000_000),
+            max_alloc: None,
+        };
+        assert!(tiled(pixels).check(20_000, 20_000, bytes).is_err());
+        let alloc = Overrides {
+            max_pixels: None,
+            max_alloc: Some(512 * 1024 * 1024),
+        };
+        assert!(tiled(alloc).check(20_000, 20_000, bytes).is_err());
+
+        // A raised limit doesn't lift the tile limits with it.
+        let raised = Overrides {
+            max_pixels: Some(u64::MAX),
+            max_alloc: Some(u64::MAX),
+        };
+        assert!(tiled(raised).check(60000, 60000, 0).is_err());
+    }
+
+    #[test]
+    fn too_large_message_carries_the_marker() {
+        let msg = String::from(DecodeError::TooLarge {
+            width: 60000,
+            height: 60000,
+        });
+        assert!(msg.starts_with(TOO_LARGE), "{msg}");
+        assert_eq!(
+            String::from(DecodeError::from("decode: x".to_string())),
+            "decode: x"
+        );
+    }
+}
@@ -0,0 +1,950 @@
+//! Embedded metadata for the info panel: every EXIF IFD, the XMP packet,
+//! IPTC-IIM core fields and the ICC profile description. The raw blocks come
+//! from `image`'s decoders, which only parse headers to hand them out, so a
+//! container is covered as soon as its `ImageDecoder` exposes them.
+
+use crate::utils::format::{detect, SourceFormat};
+use crate::utils::limits;
+use crate::utils::tiff::{self, TiffReader};
+use exif::{In, Tag, Value};
+use image::{ImageDecoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Longer display values (undefined blobs, long tables) are cut to this many
+/// characters in the raw field list.
+const MAX_FIELD_VALUE_CHARS: usize = 256;
+
+const NS_RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const NS_XML: &str = "http://www.w3.org/XML/1998/namespace";
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const NS_PHOTOSHOP: &str = "http://ns.adobe.com/photoshop/1.0/";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageMetadata {
+    pub exif: Option<ExifMetadata>,
+    pub xmp: Option<XmpMetadata>,
+    pub iptc: Option<IptcMetadata>,
+    /// The embedded ICC profile's own description ("Display P3", ...).
+    pub icc_profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExifMetadata {
+    /// DateTimeOriginal (falling back to DateTime) as `YYYY-MM-DDTHH:MM:SS`,
+    /// with `±HH:MM` appended when the matching OffsetTime tag is present.
+    pub date_taken: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens_make: Option<String>,
+    pub lens_model: Option<String>,
+    pub software: Option<String>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    /// Shutter speed as photographers write it: "1/250", "2.5".
+    pub exposure_time: Option<String>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    pub focal_length: Option<f64>,
+    pub focal_length_35mm: Option<u32>,
+    pub exposure_bias: Option<f64>,
+    pub flash_fired: Option<bool>,
+    pub orientation: Option<u32>,
+    pub pixel_width: Option<u32>,
+    pub pixel_height: Option<u32>,
+    pub gps: Option<GpsPosition>,
+    /// Every field of every IFD (MakerNote excepted), for the raw tag list.
+    pub fields: Vec<ExifField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpsPosition {
+    /// Decimal degrees, south and west negative.
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Metres; negative below sea level.
+    pub altitude: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExifField {
+    /// "primary", "thumbnail", ...
+    pub ifd: String,
+    pub tag: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct XmpMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub creator: Vec<String>,
+    pub rights: Option<String>,
+    pub keywords: Vec<String>,
+    pub rating: Option<i32>,
+    pub label: Option<String>,
+    pub create_date: Option<String>,
+    pub date_created: Option<String>,
+    pub headline: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IptcMetadata {
+    pub object_name: Option<String>,
+    pub headline: Option<String>,
+    pub caption: Option<String>,
+    pub keywords: Vec<String>,
+    pub by_line: Vec<String>,
+    pub credit: Option<String>,
+    pub source: Option<String>,
+    pub copyright_notice: Option<String>,
+    pub city: Option<String>,
+    pub province_state: Option<String>,
+    pub country: Option<String>,
+    /// 2:55 DateCreated (+ 2:60 TimeCreated) as `YYYY-MM-DD[THH:MM:SS±HH:MM]`.
+    pub date_created: Option<String>,
+}
+
+/// Undecoded metadata blocks as the container stores them.
+#[derive(Default)]
+struct RawBlocks {
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    iptc: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+}
+
+pub fn read_metadata(path: &Path) -> Result<ImageMetadata, String> {
+    let format = detect(path)
+        .map(|d| d.format)
+        .ok_or_else(|| "Unsupported file format".to_string())?;
+    let raw = raw_blocks(path, format)?;
+    Ok(ImageMetadata {
+        exif: raw.exif.as_deref().and_then(parse_exif),
+        xmp: raw.xmp.as_deref().and_then(parse_xmp),
+        iptc: raw.iptc.as_deref().and_then(parse_iptc),
+        icc_profile: raw.icc.as_deref().and_then(icc_description),
+    })
+}
+
+/// Containers without an arm here report no metadata (not an error); adding
+/// one is a matter of producing its `RawBlocks`.
+fn raw_blocks(path: &Path, format: SourceFormat) -> Result<RawBlocks, String> {
+    match format {
+        SourceFormat::Jpeg | SourceFormat::Png | SourceFormat::WebP | SourceFormat::Gif => {
+            let mut reader = ImageReader::open(path)
+                .map_err(|e| format!("open: {e}"))?
+                .with_guessed_format()
+                .map_err(|e| format!("format: {e}"))?;
+            // Only headers are read, but a crafted chunk can still claim a
+            // huge allocation.
+            reader.limits(limits::current().image_limits());
+            let mut decoder = reader.into_decoder().map_err(|e| format!("decoder: {e}"))?;
+            // One damaged block must not hide the others.
+            Ok(RawBlocks {
+                exif: decoder.exif_metadata().ok().flatten(),
+                xmp: decoder.xmp_metadata().ok().flatten(),
+                iptc: decoder.iptc_metadata().ok().flatten(),
+                icc: decoder.icc_profile().ok().flatten(),
+            })
+        }
+        _ => Ok(RawBlocks::default()),
+    }
+}
+
+// ---- Date taken (sort key) ----
+
+/// DateTimeOriginal as Unix seconds, for sorting a whole folder: only the
+/// header is read (JPEG marker segments up to the Exif APP1, or the Exif IFD
+/// of a TIFF-based RAW), never the image data. A time without
+/// OffsetTimeOriginal is taken as UTC, which keeps one camera's shots in
+/// order. Other containers return None and sort by mtime instead.
+pub fn read_date_taken(path: &Path) -> Option<u64> {
+    let format = detect(path)?.format;
+    let dt = match format {
+        SourceFormat::Jpeg => {
+            let exif = read_exif(&jpeg_exif_block(path)?)?;
+            exif_datetime(&exif, Tag::DateTimeOriginal, Tag::OffsetTimeOriginal)?
+        }
+        f if f.is_camera_raw() && f != SourceFormat::Cr3 => tiff_date_taken(path)?,
+        _ => return None,
+    };
+    let days = days_from_civil(i64::from(dt.year), dt.month.into(), dt.day.into());
+    let secs = days * 86_400
+        + i64::from(dt.hour) * 3_600
+        + i64::from(dt.minute) * 60
+        + i64::from(dt.second)
+        - i64::from(dt.offset.unwrap_or(0)) * 60;
+    u64::try_from(secs).ok()
+}
+
+/// Payload of the first Exif APP1 (TIFF header onwards), reading segment
+/// headers and seeking past everything else.
+fn jpeg_exif_block(path: &Path) -> Option<Vec<u8>> {
+    let mut f = BufReader::new(File::open(path).ok()?);
+    let mut soi = [0u8; 2];
+    f.read_exact(&mut soi).ok()?;
+    if soi != [0xFF, 0xD8] {
+        return None;
+    }
+    loop {
+        let mut head = [0u8; 4];
+        f.read_exact(&mut head).ok()?;
+        if head[0] != 0xFF || head[1] == 0xDA {
+            return None;
+        }
+        let len = usize::from(u16::from_be_bytes([head[2], head[3]])).checked_sub(2)?;
+        if head[1] == 0xE1 {
+            let mut payload = vec![0u8; len];
+            f.read_exact(&mut payload).ok()?;
+            if let Some(tiff) = payload.strip_prefix(b"Exif\0\0") {
+                return Some(tiff.to_vec());
+            }
+        } else {
+            f.seek_relative(len as i64).ok()?;
+        }
+    }
+}
+
+/// A JPEG's Exif IFD1 thumbnail (JPEGInterchangeFormat) and IFD0's
+/// orientation, from the header alone.
+pub(crate) fn jpeg_exif_thumbnail(path: &Path) -> Option<(Vec<u8>, u8)> {
+    let exif = read_exif(&jpeg_exif_block(path)?)?;
+    let uint = |tag, ifd| exif.get_field(tag, ifd).and_then(|f| f.value.get_uint(0));
+    let offset = uint(Tag::JPEGInterchangeFormat, In::THUMBNAIL)? as usize;
+    let len = uint(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)? as usize;
+    let jpeg = exif.buf().get(offset..offset.checked_add(len)?)?.to_vec();
+    let orientation = uint(Tag::Orientation, In::PRIMARY).unwrap_or(1);
+    Some((jpeg, u8::try_from(orientation).unwrap_or(1)))
+}
+
+/// DateTimeOriginal (+ OffsetTimeOriginal) from IFD0's Exif sub-IFD.
+fn tiff_date_taken(path: &Path) -> Option<exif::DateTime> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let mut t = TiffReader::new(file, 0)?;
+    let ifd0_offset = t.first_ifd_offset()?;
+    let ifd0 = t.read_ifd(ifd0_offset)?;
+    let exif_offset = t.value(ifd0.get(tiff::TAG_EXIF_IFD)?)?;
+    let exif_ifd = t.read_ifd(exif_offset)?;
+    let date = t.bytes(exif_ifd.get(tiff::TAG_DATE_TIME_ORIGINAL)?)?;
+    let mut dt = exif::DateTime::from_ascii(&date).ok()?;
+    if let Some(offset) = exif_ifd
+        .get(tiff::TAG_OFFSET_TIME_ORIGINAL)
+        .and_then(|e| t.bytes(e))
+    {
+        let _ = dt.parse_offset(&offset);
+    }
+    Some(dt)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`).
+pub(crate) fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// ---- EXIF ----
+
+fn read_exif(tiff: &[u8]) -> Option<exif::Exif> {
+    match exif::Reader::new()
+        .continue_on_error(true)
+        .read_raw(tiff.to_vec())
+    {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::PartialResult(partial)) => Some(partial.into_inner().0),
+        Err(_) => None,
+    }
+}
+
+fn parse_exif(block: &[u8]) -> Option<ExifMetadata> {
+    // WebP writers disagree on whether the chunk keeps the JPEG-style prefix.
+    let tiff = block.strip_prefix(b"Exif\0\0").unwrap_or(block);
+    let exif = read_exif(tiff)?;
+    let fields = exif
+        .fields()
+        .filter(|f| f.tag != Tag::MakerNote)
+        .map(|f| ExifField {
+            ifd: f.ifd_num.to_string(),
+            tag: f.tag.to_string(),
+            value: truncate_chars(
+                f.display_value().with_unit(&exif).to_string(),
+                MAX_FIELD_VALUE_CHARS,
+            ),
+        })
+        .collect();
+    Some(ExifMetadata {
+        date_taken: exif_date(&exif, Tag::DateTimeOriginal, Tag::OffsetTimeOriginal)
+            .or_else(|| exif_date(&exif, Tag::DateTime, Tag::OffsetTime)),
+        make: exif_ascii(&exif, Tag::Make),
+        model: exif_ascii(&exif, Tag::Model),
+        lens_make: exif_ascii(&exif, Tag::LensMake),
+        lens_model: exif_ascii(&exif, Tag::LensModel),
+        software: exif_ascii(&exif, Tag::Software),
+        artist: exif_ascii(&exif, Tag::Artist),
+        copyright: exif_ascii(&exif, Tag::Copyright),
+        exposure_time: exif_real(&exif, Tag::ExposureTime)
+            .filter(|t| *t > 0.0)
+            .map(format_exposure_time),
+        f_number: exif_real(&exif, Tag::FNumber),
+        iso: exif_uint(&exif, Tag::PhotographicSensitivity),
+        focal_length: exif_real(&exif, Tag::FocalLength),
+        focal_length_35mm: exif_uint(&exif, Tag::FocalLengthIn35mmFilm),
+        exposure_bias: exif_real(&exif, Tag::ExposureBiasValue),
+        flash_fired: exif_uint(&exif, Tag::Flash).map(|f| f & 1 == 1),
+        orientation: exif_uint(&exif, Tag::Orientation),
+        pixel_width: exif_uint(&exif, Tag::PixelXDimension),
+        pixel_height: exif_uint(&exif, Tag::PixelYDimension),
+        gps: exif_gps(&exif),
+        fields,
+    })
+}
+
+fn exif_ascii(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(v) => non_empty(&String::from_utf8_lossy(v.first()?)),
+        _ => None,
+    }
+}
+
+fn exif_real(exif: &exif::Exif, tag: Tag) -> Option<f64> {
+    let v = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(v) => v.first()?.to_f64(),
+        Value::SRational(v) => v.first()?.to_f64(),
+        _ => return None,
+    };
+    v.is_finite().then_some(v)
+}
+
+fn exif_uint(exif: &exif::Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+}
+
+fn exif_datetime(exif: &exif::Exif, tag: Tag, offset_tag: Tag) -> Option<exif::DateTime> {
+    let Value::Ascii(v) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let mut dt = exif::DateTime::from_ascii(v.first()?).ok()?;
+    if let Some(Value::Ascii(o)) = exif.get_field(offset_tag, In::PRIMARY).map(|f| &f.value) {
+        if let Some(o) = o.first() {
+            let _ = dt.parse_offset(o);
+        }
+    }
+    Some(dt)
+}
+
+fn exif_date(exif: &exif::Exif, tag: Tag, offset_tag: Tag) -> Option<String> {
+    let dt = exif_datetime(exif, tag, offset_tag)?;
+    let mut s = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+    );
+    if let Some(off) = dt.offset {
+        let sign = if off < 0 { '-' } else { '+' };
+        let off = off.unsigned_abs();
+        s.push_str(&format!("{sign}{:02}:{:02}", off / 60, off % 60));
+    }
+    Some(s)
+}
+
+fn format_exposure_time(secs: f64) -> String {
+    if secs >= 1.0 {
+        format!("{}", (secs * 10.0).round() / 10.0)
+    } else {
+        format!("1/{}", (1.0 / secs).round())
+    }
+}
+
+fn exif_gps(exif: &exif::Exif) -> Option<GpsPosition> {
+    let dms = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(v) if v.len() >= 3 => {
+            let d = v[0].to_f64() + v[1].to_f64() / 60.0 + v[2].to_f64() / 3600.0;
+            d.is_finite().then_some(d)
+        }
+        _ => None,
+    };
+    let negative = |tag, neg: u8| match &exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+        Some(Value::Ascii(v)) => v.first().and_then(|s| s.first()) == Some(&neg),
+        _ => false,
+    };
+    let signed = |d: f64, neg: bool| if neg { -d } else { d };
+    let latitude = signed(dms(Tag::GPSLatitude)?, negative(Tag::GPSLatitudeRef, b'S'));
+    let longitude = signed(
+        dms(Tag::GPSLongitude)?,
+        negative(Tag::GPSLongitudeRef, b'W'),
+    );
+    let altitude = exif_real(exif, Tag::GPSAltitude)
+        .map(|a| signed(a, exif_uint(exif, Tag::GPSAltitudeRef) == Some(1)));
+    Some(GpsPosition {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+// ---- XMP ----
+
+fn parse_xmp(packet: &[u8]) -> Option<XmpMetadata> {
+    let text = String::from_utf8_lossy(packet);
+    // Drop anything before the first tag and the NUL/space padding after
+    // the packet trailer.
+    let text = text[text.find('<')?..].trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+    let doc = roxmltree::Document::parse(text).ok()?;
+    let descriptions: Vec<_> = doc
+        .descendants()
+        .filter(|n| n.has_tag_name((NS_RDF, "Description")))
+        .collect();
+    // A property is either an attribute of some rdf:Description or a child
+    // element holding text or an rdf:Alt/Seq/Bag of rdf:li.
+    let values = |ns: &str, name: &str| -> Vec<String> {
+        for d in &descriptions {
+            if let Some(v) = d.attribute((ns, name)) {
+                return non_empty(v).into_iter().collect();
+            }
+            if let Some(el) = d.children().find(|c| c.has_tag_name((ns, name))) {
+                let mut items: Vec<_> = el
+                    .descendants()
+                    .filter(|n| n.has_tag_name((NS_RDF, "li")))
+                    .collect();
+                if items.is_empty() {
+                    return el.text().and_then(non_empty).into_iter().collect();
+                }
+                // Language alternatives: the x-default entry first.
+                items.sort_by_key(|li| li.attribute((NS_XML, "lang")) != Some("x-default"));
+                return items
+                    .iter()
+                    .filter_map(|li| li.text().and_then(non_empty))
+                    .collect();
+            }
+        }
+        Vec::new()
+    };
+    let first = |ns: &str, name: &str| values(ns, name).into_iter().next();
+    Some(XmpMetadata {
+        title: first(NS_DC, "title"),
+        description: first(NS_DC, "description"),
+        creator: values(NS_DC, "creator"),
+        rights: first(NS_DC, "rights"),
+        keywords: values(NS_DC, "subject"),
+        rating: first(NS_XMP, "Rating")
+            .and_then(|r| r.parse::<f64>().ok())
+            .map(|r| r.round() as i32),
+        label: first(NS_XMP, "Label"),
+        create_date: first(NS_XMP, "CreateDate"),
+        date_created: first(NS_PHOTOSHOP, "DateCreated"),
+        headline: first(NS_PHOTOSHOP, "Headline"),
+        city: first(NS_PHOTOSHOP, "City"),
+        state: first(NS_PHOTOSHOP, "State"),
+        country: first(NS_PHOTOSHOP, "Country"),
+    })
+}
+
+// ---- IPTC ----
+
+fn parse_iptc(block: &[u8]) -> Option<IptcMetadata> {
+    let decoded;
+    let block = if block.starts_with(b"\n") {
+        // PNG "Raw profile type iptc": hex text in a zTXt chunk.
+        decoded = decode_raw_profile(block)?;
+        &decoded[..]
+    } else {
+        block
+    };
+    let stream = iim_stream(block)?;
+    let mut m = IptcMetadata::default();
+    let (mut date, mut time) = (None, None);
+    let mut pos = 0;
+    while pos + 5 <= stream.len() && stream[pos] == 0x1C {
+        let (record, dataset) = (stream[pos + 1], stream[pos + 2]);
+        let len = usize::from(u16::from_be_bytes([stream[pos + 3], stream[pos + 4]]));
+        // Extended (>32 KB) datasets never carry the text fields read here.
+        if len & 0x8000 != 0 {
+            break;
+        }
+        let Some(data) = stream.get(pos + 5..pos + 5 + len) else {
+            break;
+        };
+        pos += 5 + len;
+        if record != 2 {
+            continue;
+        }
+        let Some(text) = non_empty(&iim_text(data)) else {
+            continue;
+        };
+        match dataset {
+            5 => m.object_name = Some(text),
+            25 => m.keywords.push(text),
+            55 => date = Some(text),
+            60 => time = Some(text),
+            80 => m.by_line.push(text),
+            90 => m.city = Some(text),
+            95 => m.province_state = Some(text),
+            101 => m.country = Some(text),
+            105 => m.headline = Some(text),
+            110 => m.credit = Some(text),
+            115 => m.source = Some(text),
+            116 => m.copyright_notice = Some(text),
+            120 => m.caption = Some(text),
+            _ => {}
+        }
+    }
+    m.date_created = date.and_then(|d| iim_date(&d, time.as_deref()));
+    Some(m)
+}
+
+/// The IIM dataset stream, either bare or inside a Photoshop image resource
+/// block (JPEG APP13) as resource 0x0404.
+fn iim_stream(block: &[u8]) -> Option<&[u8]> {
+    let block = block.strip_prefix(b"Photoshop 3.0\0").unwrap_or(block);
+    if block.first() == Some(&0x1C) {
+        return Some(block);
+    }
+    let mut pos = 0;
+    while block.get(pos..pos + 4) == Some(b"8BIM") {
+        let id = u16::from_be_bytes([*block.get(pos + 4)?, *block.get(pos + 5)?]);
+        // Pascal-string name, padded to an even length including its size byte.
+        let name_len = usize::from(*block.get(pos + 6)?);
+        let size_at = pos + 6 + (name_len + 2) / 2 * 2;
+        let size = u32::from_be_bytes(block.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+        let data = block.get(size_at + 4..(size_at + 4).checked_add(size)?)?;
+        if id == 0x0404 {
+            return Some(data);
+        }
+        pos = size_at + 4 + size + (size & 1);
+    }
+    None
+}
+
+/// IIM text is UTF-8 when 1:90 says so and usually in practice; anything
+/// else is read as Latin-1.
+fn iim_text(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(s) => s.to_string(),
+        Err(_) => data.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+/// "CCYYMMDD" and optional "HHMMSS±HHMM" → ISO 8601.
+fn iim_date(date: &str, time: Option<&str>) -> Option<String> {
+    if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let mut s = format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8]);
+    if let Some(t) = time.filter(|t| t.len() >= 6 && t.is_ascii()) {
+        s.push_str(&format!("T{}:{}:{}", &t[0..2], &t[2..4], &t[4..6]));
+        if t.len() == 11 {
+            s.push_str(&format!("{}:{}", &t[6..9], &t[9..11]));
+        }
+    }
+    Some(s)
+}
+
+/// ImageMagick's raw profile text: "\n<name>\n<length>\n<hex lines>".
+fn decode_raw_profile(text: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(text).ok()?;
+    let mut lines = text.split('\n').filter(|l| !l.trim().is_empty());
+    lines.next()?; // profile name
+    let len: usize = lines.next()?.trim().parse().ok()?;
+    let hex: Vec<u8> = lines
+        .flat_map(|l| l.bytes())
+        .filter(|b| b.is_ascii_hexdigit())
+        .collect();
+    let bytes: Vec<u8> = hex
+        .chunks_exact(2)
+        .filter_map(|p| u8::from_str_radix(std::str::from_utf8(p).ok()?, 16).ok())
+        .collect();
+    (bytes.len() >= len).then(|| bytes[..len].to_vec())
+}
+
+// ---- ICC ----
+
+/// The profile's `desc` tag: ICC v2 textDescriptionType or v4 mluc (the
+/// English record if there is one).
+fn icc_description(icc: &[u8]) -> Option<String> {
+    let be32 = |b: &[u8], at: usize| -> Option<usize> {
+        Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?) as usize)
+    };
+    let count = be32(icc, 128)?;
+    let entry = (0..count.min(256))
+        .filter_map(|i| icc.get(132 + i * 12..144 + i * 12))
+        .find(|e| &e[0..4] == b"desc")?;
+    let (offset, size) = (be32(entry, 4)?, be32(entry, 8)?);
+    let tag = icc.get(offset..offset.checked_add(size)?)?;
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let len = be32(tag, 8)?;
+            String::from_utf8_lossy(tag.get(12..12 + len)?).into_owned()
+        }
+        b"mluc" => {
+            let (records, record_size) = (be32(tag, 8)?, be32(tag, 12)?);
+            let record = (0..records.min(64))
+                .filter_map(|i| tag.get(16 + i * record_size..16 + i * record_size + 12))
+                .min_by_key(|r| &r[0..2] != b"en")?;
+            let (len, at) = (be32(record, 4)?, be32(record, 8)?);
+            let units: Vec<u16> = tag
+                .get(at..at.checked_add(len)?)?
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    non_empty(text.trim_end_matches('\0'))
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+fn truncate_chars(s: String, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use exif::experimental::Writer;
+    use exif::{Field, Rational};
+
+    fn ascii(tag: Tag, s: &str) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![s.as_bytes().to_vec()]),
+        }
+    }
+
+    fn rational(tag: Tag, v: &[(u32, u32)]) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(
+                v.iter()
+                    .map(|&(num, denom)| Rational { num, denom })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn exif_blob() -> Vec<u8> {
+        let fields = vec![
+            ascii(Tag::Make, "Spica"),
+            ascii(Tag::Model, "Viewer One"),
+            ascii(Tag::DateTimeOriginal, "2024:05:06 07:08:09"),
+            ascii(Tag::OffsetTimeOriginal, "+09:00"),
+            ascii(Tag::LensModel, "50mm F1.8"),
+            ascii(Tag::Copyright, "(c) Someone"),
+            rational(Tag::ExposureTime, &[(1, 250)]),
+            rational(Tag::FNumber, &[(18, 10)]),
+            rational(Tag::FocalLength, &[(50, 1)]),
+            Field {
+                tag: Tag::PhotographicSensitivity,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![400]),
+            },
+            Field {
+                tag: Tag::Flash,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![0x19]),
+            },
+            ascii(Tag::GPSLatitudeRef, "N"),
+            rational(Tag::GPSLatitude, &[(35, 1), (30, 1), (0, 1)]),
+            ascii(Tag::GPSLongitudeRef, "W"),
+            rational(Tag::GPSLongitude, &[(139, 1), (45, 1), (36, 1)]),
+        ];
+        let mut w = Writer::new();
+        for f in &fields {
+            w.push_field(f);
+        }
+        let mut out = std::io::Cursor::new(Vec::new());
+        w.write(&mut out, false).unwrap();
+        out.into_inner()
+    }
+
+    const XMP: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+    xmp:Rating="4" photoshop:City="Kyoto">
+   <dc:title><rdf:Alt>
+    <rdf:li xml:lang="ja">タイトル</rdf:li>
+    <rdf:li xml:lang="x-default">Title</rdf:li>
+   </rdf:Alt></dc:title>
+   <dc:creator><rdf:Seq><rdf:li>Alice</rdf:li><rdf:li>Bob</rdf:li></rdf:Seq></dc:creator>
+   <dc:subject><rdf:Bag><rdf:li>temple</rdf:li><rdf:li>autumn</rdf:li></rdf:Bag></dc:subject>
+   <xmp:Label>Red</xmp:Label>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    fn iim(dataset: u8, text: &str) -> Vec<u8> {
+        let mut v = vec![0x1C, 2, dataset];
+        v.extend_from_slice(&(text.len() as u16).to_be_bytes());
+        v.extend_from_slice(text.as_bytes());
+        v
+    }
+
+    fn photoshop_irb(iim_data: &[u8]) -> Vec<u8> {
+        let mut v = b"Photoshop 3.0\0".to_vec();
+        // An unrelated resource first, with an odd-length name and size.
+        v.extend_from_slice(b"8BIM\x03\xED\x01A");
+        v.extend_from_slice(&3u32.to_be_bytes());
+        v.extend_from_slice(&[1, 2, 3, 0]);
+        v.extend_from_slice(b"8BIM\x04\x04\0\0");
+        v.extend_from_slice(&(iim_data.len() as u32).to_be_bytes());
+        v.extend_from_slice(iim_data);
+        v
+    }
+
+    fn icc_with_desc(tag: &[u8]) -> Vec<u8> {
+        let mut v = vec![0u8; 128];
+        v.extend_from_slice(&1u32.to_be_bytes());
+        v.extend_from_slice(b"desc");
+        v.extend_from_slice(&144u32.to_be_bytes());
+        v.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        v.extend_from_slice(tag);
+        v
+    }
+
+    #[test]
+    fn parse_exif_reads_typed_fields_and_the_raw_list() {
+        let m = parse_exif(&exif_blob()).unwrap();
+        assert_eq!(m.make.as_deref(), Some("Spica"));
+        assert_eq!(m.model.as_deref(), Some("Viewer One"));
+        assert_eq!(m.date_taken.as_deref(), Some("2024-05-06T07:08:09+09:00"));
+        assert_eq!(m.lens_model.as_deref(), Some("50mm F1.8"));
+        assert_eq!(m.copyright.as_deref(), Some("(c) Someone"));
+        assert_eq!(m.exposure_time.as_deref(), Some("1/250"));
+        assert_eq!(m.f_number, Some(1.8));
+        assert_eq!(m.focal_length, Some(50.0));
+        assert_eq!(m.iso, Some(400));
+        assert_eq!(m.flash_fired, Some(true));
+        let gps = m.gps.unwrap();
+        assert!((gps.latitude - 35.5).abs() < 1e-9);
+        assert!((gps.longitude + 139.76).abs() < 1e-9);
+        assert_eq!(gps.altitude, None);
+        assert!(m
+            .fields
+            .iter()
+            .any(|f| f.ifd == "primary" && f.tag == "Model" && f.value.contains("Viewer One")));
+    }
+
+    #[test]
+    fn read_date_taken_from_jpeg_and_tiff_raw_headers() {
+        let dir = create_temp_dir();
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend(exif_blob());
+        let jpeg = dir.path().join("a.jpg");
+        std::fs::write(
+            &jpeg,
+            insert_jpeg_segments(gradient_jpeg_bytes(8, 8), &[(0xE1, payload)]),
+        )
+        .unwrap();
+        // 2024-05-06T07:08:09+09:00
+        assert_eq!(read_date_taken(&jpeg), Some(1_714_946_889));
+
+        // The writer's output is a TIFF with an Exif IFD, i.e. a RAW's layout.
+        let nef = dir.path().join("b.nef");
+        std::fs::write(&nef, exif_date_blob("2021:01:02 03:04:05")).unwrap();
+        // No offset recorded: read as UTC.
+        assert_eq!(read_date_taken(&nef), Some(1_609_556_645));
+
+        let plain = create_test_jpeg(dir.path(), "c.jpg");
+        assert_eq!(read_date_taken(&plain), None);
+        let png = create_test_png(dir.path(), "d.png");
+        assert_eq!(read_date_taken(&png), None);
+    }
+
+    #[test]
+    fn days_from_civil_matches_known_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+    }
+
+    #[test]
+    fn parse_exif_accepts_the_exif_prefix_and_rejects_garbage() {
+        let mut prefixed = b"Exif\0\0".to_vec();
+        prefixed.extend(exif_blob());
+        assert!(parse_exif(&prefixed).is_some());
+        assert!(parse_exif(b"not tiff at all").is_none());
+    }
+
+    #[test]
+    fn exposure_times_read_like_a_camera() {
+        assert_eq!(format_exposure_time(1.0 / 8000.0), "1/8000");
+        assert_eq!(format_exposure_time(0.5), "1/2");
+        assert_eq!(format_exposure_time(2.5), "2.5");
+        assert_eq!(format_exposure_time(30.0), "30");
+    }
+
+    #[test]
+    fn parse_xmp_reads_attributes_and_rdf_containers() {
+        let m = parse_xmp(XMP.as_bytes()).unwrap();
+        assert_eq!(m.title.as_deref(), Some("Title"));
+        assert_eq!(m.creator, ["Alice", "Bob"]);
+        assert_eq!(m.keywords, ["temple", "autumn"]);
+        assert_eq!(m.rating, Some(4));
+        assert_eq!(m.label.as_deref(), Some("Red"));
+        assert_eq!(m.city.as_deref(), Some("Kyoto"));
+        assert_eq!(m.description, None);
+        // Trailing padding as left by in-place editors.
+        let mut padded = XMP.as_bytes().to_vec();
+        padded.extend_from_slice(&[b' '; 64]);
+        padded.extend_from_slice(&[0; 4]);
+        assert_eq!(parse_xmp(&padded), Some(m));
+        assert!(parse_xmp(b"<unclosed").is_none());
+    }
+
+    #[test]
+    fn parse_iptc_reads_a_photoshop_resource_block() {
+        let mut data = vec![0x1C, 1, 90, 0, 3, 0x1B, b'%', b'G'];
+        for (ds, text) in [
+            (5, "Object"),
+            (25, "one"),
+            (25, "two"),
+            (80, "Carol"),
+            (55, "20240506"),
+            (60, "070809+0900"),
+            (116, "© Carol"),
+            (120, "A caption"),
+        ] {
+            data.extend(iim(ds, text));
+        }
+        let m = parse_iptc(&photoshop_irb(&data)).unwrap();
+        assert_eq!(m.object_name.as_deref(), Some("Object"));
+        assert_eq!(m.keywords, ["one", "two"]);
+        assert_eq!(m.by_line, ["Carol"]);
+        assert_eq!(m.copyright_notice.as_deref(), Some("© Carol"));
+        assert_eq!(m.caption.as_deref(), Some("A caption"));
+        assert_eq!(m.date_created.as_deref(), Some("2024-05-06T07:08:09+09:00"));
+    }
+
+    #[test]
+    fn parse_iptc_reads_a_png_raw_profile() {
+        let data = iim(105, "Headline");
+        let hex: String = data.iter().map(|b| format!("{b:02x}")).collect();
+        let text = format!("\niptc\n{:8}\n{hex}\n", data.len());
+        let m = parse_iptc(text.as_bytes()).unwrap();
+        assert_eq!(m.headline.as_deref(), Some("Headline"));
+    }
+
+    #[test]
+    fn icc_description_reads_v2_desc_and_v4_mluc() {
+        let mut v2 = b"desc\0\0\0\0".to_vec();
+        v2.extend_from_slice(&12u32.to_be_bytes());
+        v2.extend_from_slice(b"Adobe RGB\0\0\0");
+        assert_eq!(
+            icc_description(&icc_with_desc(&v2)).as_deref(),
+            Some("Adobe RGB")
+        );
+
+        let utf16: Vec<u8> = "Display P3"
+            .encode_utf16()
+            .flat_map(|u| u.to_be_bytes())
+            .collect();
+        let mut v4 = b"mluc\0\0\0\0".to_vec();
+        v4.extend_from_slice(&1u32.to_be_bytes());
+        v4.extend_from_slice(&12u32.to_be_bytes());
+        v4.extend_from_slice(b"enUS");
+        v4.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+        v4.extend_from_slice(&28u32.to_be_bytes());
+        v4.extend(utf16);
+        assert_eq!(
+            icc_description(&icc_with_desc(&v4)).as_deref(),
+            Some("Display P3")
+        );
+        assert_eq!(icc_description(&[0u8; 64]), None);
+    }
+
+    #[test]
+    fn read_metadata_from_a_jpeg_with_every_block() {
+        let dir = create_temp_dir();
+        let mut v2 = b"desc\0\0\0\0".to_vec();
+        v2.extend_from_slice(&5u32.to_be_bytes());
+        v2.extend_from_slice(b"sRGB\0");
+        let mut xmp = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        xmp.extend_from_slice(XMP.as_bytes());
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend(exif_blob());
+        let mut icc = b"ICC_PROFILE\0\x01\x01".to_vec();
+        icc.extend(icc_with_desc(&v2));
+        let jpeg = insert_jpeg_segments(
+            gradient_jpeg_bytes(16, 16),
+            &[
+                (0xE1, exif),
+                (0xE1, xmp),
+                (0xED, photoshop_irb(&iim(105, "Headline"))),
+                (0xE2, icc),
+            ],
+        );
+        let path = dir.path().join("full.jpg");
+        std::fs::write(&path, jpeg).unwrap();
+
+        let m = read_metadata(&path).unwrap();
+        assert_eq!(m.exif.unwrap().model.as_deref(), Some("Viewer One"));
+        assert_eq!(m.xmp.unwrap().title.as_deref(), Some("Title"));
+        assert_eq!(m.iptc.unwrap().headline.as_deref(), Some("Headline"));
+        assert_eq!(m.icc_profile.as_deref(), Some("sRGB"));
+    }
+
+    #[test]
+    fn read_metadata_from_png_and_webp_exif() {
+        use image::codecs::png::PngEncoder;
+        use image::codecs::webp::WebPEncoder;
+        use image::{ExtendedColorType, ImageEncoder};
+        let dir = create_temp_dir();
+        let pixels = vec![128u8; 8 * 8 * 3];
+
+        let png = dir.path().join("a.png");
+        let mut enc = PngEncoder::new(std::fs::File::create(&png).unwrap());
+        enc.set_exif_metadata(exif_blob()).unwrap();
+        enc.write_image(&pixels, 8, 8, ExtendedColorType::Rgb8)
+            .unwrap();
+
+        let webp = dir.path().join("a.webp");
+        let mut enc = WebPEncoder::new_lossless(std::fs::File::create(&webp).unwrap());
+        enc.set_exif_metadata(exif_blob()).unwrap();
+        enc.write_image(&pixels, 8, 8, ExtendedColorType::Rgb8)
+            .unwrap();
+
+        for path in [png, webp] {
+            let m = read_metadata(&path).unwrap();
+            assert_eq!(m.exif.unwrap().make.as_deref(), Some("Spica"), "{path:?}");
+            assert_eq!(m.xmp, None);
+        }
+    }
+
+    #[test]
+    fn read_metadata_without_blocks_is_empty_not_an_error() {
+        let dir = create_temp_dir();
+        let gif = create_test_gif(dir.path(), "a.gif");
+        assert_eq!(read_metadata(&gif).unwrap(), ImageMetadata::default());
+        let bad = create_invalid_image(dir.path(), "bad.jpg");
+        assert!(read_metadata(&bad).is_err());
+    }
+}
@@ -1,3 +1,18 @@
+pub mod color;
+pub mod encode;
+pub mod format;
+#[cfg(feature = "heif")]
+pub mod heif;
 pub mod image;
+pub mod limits;
+pub mod metadata;
 pub mod perf;
+pub mod phash;
 pub mod preview;
+pub mod raw;
+pub mod scope;
+pub mod single_flight;
+pub mod sort;
+pub mod tiff;
+pub mod tiles;
+pub mod transform;
@@ -0,0 +1,56 @@
+//! Perceptual difference hash (dHash) for duplicate detection. The image is
+//! shrunk to 9x8 grey levels and each bit records whether a pixel is brighter
+//! than its right neighbour, so re-encodes, resizes and mild edits land within
+//! a few bits of the original while unrelated images differ in about half.
+
+use image::DynamicImage;
+
+/// Hamming distance at or below which two images count as near-duplicates
+/// when the caller gives no threshold.
+pub const DEFAULT_DUPLICATE_THRESHOLD: u32 = 10;
+
+/// 64-bit dHash of `image` (orientation should already be applied).
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let grey = image.thumbnail_exact(9, 8).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = grey.get_pixel(x, y)[0] > grey.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn open(path: &std::path::Path) -> DynamicImage {
+        image::open(path).unwrap()
+    }
+
+    #[test]
+    fn dhash_survives_resize_and_reencode() {
+        let dir = create_temp_dir();
+        let big = open(&create_gradient_jpeg(dir.path(), "big.jpg", 640, 480));
+        let small = open(&create_gradient_jpeg(dir.path(), "small.jpg", 160, 120));
+        let png = dir.path().join("big.png");
+        big.save(&png).unwrap();
+        assert!(hamming(dhash(&big), dhash(&small)) <= 4);
+        assert_eq!(dhash(&big), dhash(&open(&png)));
+    }
+
+    #[test]
+    fn dhash_tells_mirrored_images_apart() {
+        let dir = create_temp_dir();
+        let img = open(&create_gradient_jpeg(dir.path(), "a.jpg", 320, 240));
+        let distance = hamming(dhash(&img), dhash(&img.fliph()));
+        assert!(distance > DEFAULT_DUPLICATE_THRESHOLD, "{distance}");
+    }
+}
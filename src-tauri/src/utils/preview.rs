@@ -1,17 +1,32 @@
 //! Display-resolution preview generation (design spec 2026-08-21 §6.1).
-//! One decode produces both the preview JPEG (orientation applied, ICC kept,
-//! alpha flattened onto the viewer's black background, fitted inside the
-//! screen box without upscaling) and the 20px thumbnail derived from it.
+//! One decode produces both the preview (orientation applied, converted to
+//! the output colour space by `utils::color`, fitted inside the screen box
+//! without upscaling, encoded per its [`PreviewProfile`]) and the 20px
+//! thumbnail derived from it. Alpha is flattened onto the viewer's black
+//! background unless the preview format keeps it.
 
+use crate::commands::cache::source_stamp;
+use crate::utils::color;
+use crate::utils::encode::PreviewProfile;
+use crate::utils::format::{detect, SourceFormat};
+use crate::utils::limits::{self, DecodeError, DecodeLimits};
+use crate::utils::metadata;
 use crate::utils::perf::PerfTimer;
-use base64::{engine::general_purpose, Engine as _};
+use crate::utils::phash;
+use crate::utils::single_flight::SingleFlight;
+use crate::utils::tiles;
 use fast_image_resize::{
     images::Image as FirImage, FilterType, PixelType, ResizeAlg, ResizeOptions, Resizer,
 };
-use image::{DynamicImage, ExtendedColorType, ImageDecoder, ImageFormat, ImageReader, RgbImage};
-use jpeg_encoder::{ColorType as JpegColorType, Encoder as JpegEncoderFast, SamplingFactor};
-use std::io::Cursor;
-use std::path::Path;
+use image::metadata::Orientation;
+use image::{
+    DynamicImage, ExtendedColorType, GrayImage, ImageDecoder, ImageFormat, ImageReader, RgbImage,
+};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
 pub const PREVIEW_JPEG_QUALITY: u8 = 85;
 
@@ -19,10 +34,17 @@ pub const PREVIEW_JPEG_QUALITY: u8 = 85;
 /// used when serving a `/preview/` request generates one as a side effect.
 pub const DEFAULT_THUMB_SIZE: u32 = 20;
 
-/// Screen-box buckets (D2). Either orientation of a bucket is accepted so a
-/// portrait monitor gets a portrait box.
-pub const ALLOWED_PREVIEW_BOXES: &[(u32, u32)] = &[(1920, 1080), (2560, 1440), (3840, 2160)];
+/// Preview box edges are rounded up to a multiple of this (physical px).
+/// 40 divides every common screen edge (1080, 1440, 2160, 2560, 3440...), so
+/// real screens keep their exact size.
+pub const PREVIEW_BOX_STEP: u32 = 40;
+pub const PREVIEW_BOX_MIN_EDGE: u32 = 320;
+/// 8K; larger screens get an 8K preview and the original on zoom.
+pub const PREVIEW_BOX_MAX_EDGE: u32 = 7680;
+/// Largest scale factor a box may carry. Mirrored in `src/constants/memory.ts`.
+pub const PREVIEW_BOX_MAX_SCALE: f64 = 4.0;
 
+/// Physical-pixel box a preview is fitted into (D2).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PreviewBox {
     pub width: u32,
@@ -30,27 +52,49 @@ pub struct PreviewBox {
 }
 
 impl PreviewBox {
-    /// Parses "WxH". Only allowlisted buckets are accepted — the box is part of
-    /// a URL and a cache key, so arbitrary sizes must not reach the generator.
+    /// Parses "WxH" or "WxH@S": W x H logical pixels at scale factor S
+    /// (default 1). The box is part of a URL and a cache key, so the physical
+    /// size is normalized — each edge rounded up to [`PREVIEW_BOX_STEP`] and
+    /// clamped to [`PREVIEW_BOX_MIN_EDGE`]..=[`PREVIEW_BOX_MAX_EDGE`] — and
+    /// every spelling of one screen lands on the same bounded set of keys.
     pub fn parse(s: &str) -> Option<PreviewBox> {
-        let (w, h) = s.split_once('x')?;
+        let (dims, scale) = match s.split_once('@') {
+            Some((dims, scale)) => (dims, scale.parse::<f64>().ok()?),
+            None => (s, 1.0),
+        };
+        if !(scale.is_finite() && scale > 0.0 && scale <= PREVIEW_BOX_MAX_SCALE) {
+            return None;
+        }
+        let (w, h) = dims.split_once('x')?;
         let (w, h) = (w.parse::<u32>().ok()?, h.parse::<u32>().ok()?);
-        let allowed = ALLOWED_PREVIEW_BOXES
-            .iter()
-            .any(|&(bw, bh)| (bw == w && bh == h) || (bw == h && bh == w));
-        allowed.then_some(PreviewBox {
-            width: w,
-            height: h,
+        if w == 0 || h == 0 {
+            return None;
+        }
+        Some(PreviewBox {
+            width: physical_edge(w, scale),
+            height: physical_edge(h, scale),
         })
     }
 
+    /// Normalized "WxH" in physical pixels.
     pub fn key(&self) -> String {
         format!("{}x{}", self.width, self.height)
     }
 }
 
+fn physical_edge(logical: u32, scale: f64) -> u32 {
+    let max = f64::from(PREVIEW_BOX_MAX_EDGE);
+    let physical = (f64::from(logical) * scale).ceil().min(max) as u32;
+    physical
+        .div_ceil(PREVIEW_BOX_STEP)
+        .saturating_mul(PREVIEW_BOX_STEP)
+        .clamp(PREVIEW_BOX_MIN_EDGE, PREVIEW_BOX_MAX_EDGE)
+}
+
+#[derive(Debug, Clone)]
 pub struct Generated {
-    pub preview_jpeg: Vec<u8>,
+    /// Encoded per the requested profile.
+    pub preview_bytes: Vec<u8>,
     // preview_width/preview_height/resized are part of the documented Generated
     // contract (asserted on directly by preview.rs's own tests) but no current
     // caller reads them off the struct — decode the JPEG's dimensions or check
@@ -65,7 +109,10 @@ pub struct Generated {
     /// false when the original already fit inside the box (preview == original size).
     #[allow(dead_code)]
     pub resized: bool,
-    pub thumbnail_base64: String,
+    /// Bar thumbnail JPEG.
+    pub thumbnail_jpeg: Vec<u8>,
+    /// Perceptual hash of the preview (`utils::phash`).
+    pub dhash: u64,
 }
 
 /// Size that fits (w, h) inside the box preserving aspect ratio, or None when
@@ -83,6 +130,28 @@ pub fn fit_within(w: u32, h: u32, bbox: PreviewBox) -> Option<(u32, u32)> {
     Some((tw, th))
 }
 
+/// How far a decode may come back reduced (a JPEG's DCT scaling): never
+/// below the size the image is about to be resized to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Reduce {
+    /// Fitted into a preview box.
+    Fit(PreviewBox),
+    /// Level `n` of the tile pyramid, 1/2^n of the full size.
+    Level(u32),
+}
+
+impl Reduce {
+    /// What an oriented `w`x`h` image is resized to, or `None` when it is
+    /// kept at full size.
+    fn target(self, w: u32, h: u32) -> Option<(u32, u32)> {
+        match self {
+            Reduce::Fit(bbox) => fit_within(w, h, bbox),
+            Reduce::Level(0) => None,
+            Reduce::Level(level) => Some(tiles::level_size(w, h, level)),
+        }
+    }
+}
+
 struct Decoded {
     image: DynamicImage,
     icc: Option<Vec<u8>>,
@@ -90,28 +159,228 @@ struct Decoded {
     /// CMYK/YCCK JPEG is already RGB pixels in `image` by the time it's a
     /// `DynamicImage`, but `original_color` still says `Cmyk8`.
     original_color: ExtendedColorType,
+    /// The pixels were already converted to the output colour space (CMYK
+    /// JPEGs) and `icc` is the profile they carry.
+    in_output_space: bool,
+    /// Orientation-applied size of the full-resolution image; larger than
+    /// `image` after a DCT-scaled decode.
+    natural: (u32, u32),
 }
 
 /// Decodes with the Exif orientation applied (what browsers display) and
-/// returns the embedded ICC profile, if any.
-fn decode_oriented(path: &Path) -> Result<Decoded, String> {
+/// returns the embedded ICC profile, if any. With `fit`, a JPEG may come
+/// back DCT-scaled to no less than its target size. A frame over `limits`
+/// is refused before any pixels are decoded.
+fn decode_oriented(
+    path: &Path,
+    fit: Option<Reduce>,
+    limits: &DecodeLimits,
+) -> Result<Decoded, DecodeError> {
+    // Dispatch on content, not extension: a HEIC or RAW saved under another
+    // name still reaches its own decoder.
+    let format = detect(path).map(|d| d.format);
+    #[cfg(feature = "heif")]
+    if format.is_some_and(SourceFormat::is_heif_family) {
+        // libheif hands back upright 8-bit RGB(A); the profile is checked
+        // against that like any other RGB source.
+        let crate::utils::heif::HeifDecoded { image, icc } =
+            crate::utils::heif::decode(path, limits)?;
+        let original_color = image.color().into();
+        let natural = (image.width(), image.height());
+        return Ok(Decoded {
+            image,
+            icc,
+            original_color,
+            in_output_space: false,
+            natural,
+        });
+    }
+    if format.is_some_and(SourceFormat::is_camera_raw) {
+        let raw = crate::utils::raw::extract_preview(path)?;
+        let decoder = ImageReader::with_format(Cursor::new(raw.jpeg), ImageFormat::Jpeg)
+            .into_decoder()
+            .map_err(|e| format!("decoder: {e}"))?;
+        return decode_with(decoder, Some(raw.orientation), limits);
+    }
     let reader = ImageReader::open(path)
         .map_err(|e| format!("open: {e}"))?
         .with_guessed_format()
         .map_err(|e| format!("format: {e}"))?;
     let mut decoder = reader.into_decoder().map_err(|e| format!("decoder: {e}"))?;
-    let orientation = decoder
-        .orientation()
-        .map_err(|e| format!("orientation: {e}"))?;
+    limits.apply(&mut decoder)?;
+    if format == Some(SourceFormat::Jpeg) {
+        let icc = decoder.icc_profile().map_err(|e| format!("icc: {e}"))?;
+        let orientation = decoder
+            .orientation()
+            .map_err(|e| format!("orientation: {e}"))?;
+        if let Some(cmyk_icc) = icc.as_ref().filter(|p| icc_describes_cmyk(p)) {
+            match decode_cmyk_jpeg(path, cmyk_icc, orientation, fit, limits) {
+                Ok(Some(decoded)) => return Ok(decoded),
+                Ok(None) => {}
+                Err(e) => eprintln!("preview: converting CMYK naively ({e})"),
+            }
+        } else if fit.is_some() {
+            match decode_jpeg_scaled(path, icc, orientation, fit, limits) {
+                Ok(Some(decoded)) => return Ok(decoded),
+                Ok(None) => {}
+                Err(e) => eprintln!("preview: decoding at full size ({e})"),
+            }
+        }
+    }
+    decode_with(decoder, None, limits)
+}
+
+fn open_jpeg(
+    path: &Path,
+    limits: &DecodeLimits,
+) -> Result<jpeg_decoder::Decoder<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| format!("open: {e}"))?;
+    let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(file));
+    decoder.set_max_decoding_buffer_size(usize::try_from(limits.max_alloc).unwrap_or(usize::MAX));
+    decoder.read_info().map_err(|e| format!("decode: {e}"))?;
+    Ok(decoder)
+}
+
+/// Orientations that swap width and height.
+fn swaps_axes(orientation: Orientation) -> bool {
+    matches!(
+        orientation,
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    )
+}
+
+/// The oriented full-resolution size and the size a decode will produce.
+type DecodeSizes = ((u32, u32), (u32, u32));
+
+/// Sets up a DCT-scaled decode (1/2, 1/4 or 1/8, straight out of the IDCT)
+/// when the JPEG is at least twice its target size for `fit`. The scaled
+/// image is never smaller than that size, so the Lanczos pass that follows
+/// still has full detail to work from. Returns the oriented full-resolution
+/// size and the size `decode` will produce.
+fn request_dct_scale<R: Read>(
+    decoder: &mut jpeg_decoder::Decoder<R>,
+    orientation: Orientation,
+    fit: Option<Reduce>,
+) -> Result<DecodeSizes, String> {
+    let info = decoder
+        .info()
+        .ok_or_else(|| "decode: no frame header".to_string())?;
+    let (w, h) = (u32::from(info.width), u32::from(info.height));
+    let swaps = swaps_axes(orientation);
+    let natural = if swaps { (h, w) } else { (w, h) };
+    let Some((tw, th)) = fit.and_then(|fit| fit.target(natural.0, natural.1)) else {
+        return Ok((natural, (w, h)));
+    };
+    let (tw, th) = if swaps { (th, tw) } else { (tw, th) };
+    if w < tw * 2 || h < th * 2 {
+        return Ok((natural, (w, h)));
+    }
+    // tw <= w and th <= h, both from u16 header fields.
+    let (sw, sh) = decoder
+        .scale(tw as u16, th as u16)
+        .map_err(|e| format!("scale: {e}"))?;
+    Ok((natural, (u32::from(sw), u32::from(sh))))
+}
+
+/// An RGB or grayscale JPEG decoded DCT-scaled for `fit`; `Ok(None)` when no
+/// reduction applies and the regular full-size decode should run.
+fn decode_jpeg_scaled(
+    path: &Path,
+    icc: Option<Vec<u8>>,
+    orientation: Orientation,
+    fit: Option<Reduce>,
+    limits: &DecodeLimits,
+) -> Result<Option<Decoded>, String> {
+    let mut decoder = open_jpeg(path, limits)?;
+    let Some(info) = decoder.info() else {
+        return Ok(None);
+    };
+    let original_color = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => ExtendedColorType::Rgb8,
+        jpeg_decoder::PixelFormat::L8 => ExtendedColorType::L8,
+        _ => return Ok(None),
+    };
+    let (natural, (w, h)) = request_dct_scale(&mut decoder, orientation, fit)?;
+    if (w, h) == (u32::from(info.width), u32::from(info.height)) {
+        return Ok(None);
+    }
+    let pixels = {
+        let _t = PerfTimer::start("preview_decode_dct", &path.to_string_lossy());
+        decoder.decode().map_err(|e| format!("decode: {e}"))?
+    };
+    let image = match original_color {
+        ExtendedColorType::Rgb8 => RgbImage::from_raw(w, h, pixels).map(DynamicImage::ImageRgb8),
+        _ => GrayImage::from_raw(w, h, pixels).map(DynamicImage::ImageLuma8),
+    };
+    let mut image = image.ok_or_else(|| "decode: buffer size mismatch".to_string())?;
+    image.apply_orientation(orientation);
+    Ok(Some(Decoded {
+        image,
+        icc,
+        original_color,
+        in_output_space: false,
+        natural,
+    }))
+}
+
+/// A CMYK JPEG through its own CMYK profile. `image` only hands back
+/// naively converted RGB, so the raw inks come from `jpeg-decoder` (DCT-
+/// scaled for `fit` like any JPEG) and go through the CMS. `Ok(None)` when
+/// the file is not CMYK after all.
+fn decode_cmyk_jpeg(
+    path: &Path,
+    icc: &[u8],
+    orientation: Orientation,
+    fit: Option<Reduce>,
+    limits: &DecodeLimits,
+) -> Result<Option<Decoded>, String> {
+    let mut decoder = open_jpeg(path, limits)?;
+    if decoder.info().map(|i| i.pixel_format) != Some(jpeg_decoder::PixelFormat::CMYK32) {
+        return Ok(None);
+    }
+    let (natural, (w, h)) = request_dct_scale(&mut decoder, orientation, fit)?;
+    let cmyk = decoder.decode().map_err(|e| format!("decode: {e}"))?;
+    let (rgb, icc) = color::cmyk_to_output(&cmyk, w, h, icc)?;
+    let mut image = DynamicImage::ImageRgb8(rgb);
+    image.apply_orientation(orientation);
+    Ok(Some(Decoded {
+        image,
+        icc,
+        original_color: ExtendedColorType::Cmyk8,
+        in_output_space: true,
+        natural,
+    }))
+}
+
+/// `orientation` overrides the decoder's own: a RAW's embedded preview is
+/// oriented by the RAW container, not by the JPEG's Exif.
+fn decode_with(
+    mut decoder: impl ImageDecoder,
+    orientation: Option<Orientation>,
+    limits: &DecodeLimits,
+) -> Result<Decoded, DecodeError> {
+    limits.apply(&mut decoder)?;
+    let orientation = match orientation {
+        Some(o) => o,
+        None => decoder
+            .orientation()
+            .map_err(|e| format!("orientation: {e}"))?,
+    };
     let icc = decoder.icc_profile().map_err(|e| format!("icc: {e}"))?;
     // X1: must be read before `from_decoder` consumes the decoder.
     let original_color = decoder.original_color_type();
     let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("decode: {e}"))?;
     image.apply_orientation(orientation);
+    let natural = (image.width(), image.height());
     Ok(Decoded {
         image,
         icc,
         original_color,
+        in_output_space: false,
+        natural,
     })
 }
 
@@ -170,7 +439,47 @@ fn icc_describes_rgb(icc: &[u8]) -> bool {
     icc.len() >= 20 && &icc[16..20] == b"RGB "
 }
 
-fn resize_rgb8(src: RgbImage, tw: u32, th: u32) -> Result<RgbImage, String> {
+fn icc_describes_cmyk(icc: &[u8]) -> bool {
+    icc.len() >= 20 && &icc[16..20] == b"CMYK"
+}
+
+/// [`resize_rgb8`] for RGB8 or RGBA8 (the resizer premultiplies alpha).
+pub(crate) fn resize_image(src: DynamicImage, tw: u32, th: u32) -> Result<DynamicImage, String> {
+    let src = match src {
+        DynamicImage::ImageRgb8(rgb) => {
+            return Ok(DynamicImage::ImageRgb8(resize_rgb8(rgb, tw, th)?))
+        }
+        other => DynamicImage::ImageRgba8(other.into_rgba8()),
+    };
+    let mut dst = FirImage::new(tw, th, PixelType::U8x4);
+    Resizer::new()
+        .resize(
+            &src,
+            &mut dst,
+            &ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Lanczos3)),
+        )
+        .map_err(|e| format!("resize: {e}"))?;
+    image::RgbaImage::from_raw(tw, th, dst.into_vec())
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "resize: buffer size mismatch".to_string())
+}
+
+pub(crate) fn resize_rgb8(src: RgbImage, tw: u32, th: u32) -> Result<RgbImage, String> {
+    let height = f64::from(src.height());
+    resize_rgb8_rows(src, 0.0, height, tw, th)
+}
+
+/// Resizes the `height` rows of `src` from `top` (both fractional) to
+/// `tw`x`th`. The filter still reads the rows either side of the cut, so
+/// bands resized this way meet without a seam.
+fn resize_rgb8_rows(
+    src: RgbImage,
+    top: f64,
+    height: f64,
+    tw: u32,
+    th: u32,
+) -> Result<RgbImage, String> {
+    let width = f64::from(src.width());
     let src = DynamicImage::ImageRgb8(src);
     let mut dst = FirImage::new(tw, th, PixelType::U8x3);
     let mut resizer = Resizer::new();
@@ -178,97 +487,431 @@ fn resize_rgb8(src: RgbImage, tw: u32, th: u32) -> Result<RgbImage, String> {
         .resize(
             &src,
             &mut dst,
-            &ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Lanczos3)),
+            &ResizeOptions::new()
+                .resize_alg(ResizeAlg::Convolution(FilterType::Lanczos3))
+                .crop(0.0, top, width, height),
         )
         .map_err(|e| format!("resize: {e}"))?;
     RgbImage::from_raw(tw, th, dst.into_vec())
         .ok_or_else(|| "resize: buffer size mismatch".to_string())
 }
 
-/// Preview JPEG via the `jpeg-encoder` crate: 4:2:0 chroma subsampling and
-/// SIMD make it several times faster than the `image` crate's encoder, which
-/// dominated thumb_preview (see the Phase 2 gate numbers in the plan ledger).
-/// Thumbnails (20 px) keep using `image`'s encoder through `write_to`.
-fn encode_jpeg(rgb: &RgbImage, quality: u8, icc: Option<&[u8]>) -> Result<Vec<u8>, String> {
-    let (w, h) = (rgb.width(), rgb.height());
-    let width =
-        u16::try_from(w).map_err(|_| format!("encode: width {w} exceeds the JPEG limit"))?;
-    let height =
-        u16::try_from(h).map_err(|_| format!("encode: height {h} exceeds the JPEG limit"))?;
-    let mut out: Vec<u8> = Vec::with_capacity((w as usize * h as usize) / 4);
-    let mut encoder = JpegEncoderFast::new(&mut out, quality);
-    encoder.set_sampling_factor(SamplingFactor::F_2_2); // 4:2:0
-    if let Some(icc) = icc {
-        // X2: a profile the encoder refuses (e.g. > 254 APP2 chunks, so over
-        // ~15.9 MB) must not fail the whole preview — degrade to no ICC
-        // rather than lose the bar thumbnail generated alongside it.
-        if let Err(e) = encoder.add_icc_profile(icc) {
-            eprintln!("preview: dropping ICC profile ({e})");
-        }
-    }
-    encoder
-        .encode(rgb.as_raw(), width, height, JpegColorType::Rgb)
-        .map_err(|e| format!("encode: {e}"))?;
-    Ok(out)
-}
-
-fn thumbnail_base64(image: &DynamicImage, thumb_size: u32) -> Result<String, String> {
+fn thumbnail_jpeg(image: &DynamicImage, thumb_size: u32) -> Result<Vec<u8>, String> {
     let thumb = image.thumbnail(thumb_size, thumb_size);
+    let thumb = if thumb.color().has_alpha() {
+        DynamicImage::ImageRgb8(flatten_to_rgb8(thumb))
+    } else {
+        thumb
+    };
     let mut buf = Vec::new();
     thumb
         .write_to(&mut Cursor::new(&mut buf), ImageFormat::Jpeg)
         .map_err(|e| format!("thumbnail: {e}"))?;
-    Ok(general_purpose::STANDARD.encode(&buf))
+    Ok(buf)
+}
+
+/// A tile pyramid level as displayed, from [`decode_level_rgb`].
+pub(crate) struct LevelRgb {
+    /// The level's size or, when the decoder could only reduce part of the
+    /// way, larger.
+    pub image: RgbImage,
+    /// The profile `image` carries, if any.
+    pub icc: Option<Vec<u8>>,
+    /// Orientation-applied size of the full-resolution image.
+    pub natural: (u32, u32),
+}
+
+/// Tile pyramid `level` as displayed: orientation applied, alpha flattened
+/// onto black, in the output colour space. Decoded under [`limits::tiled`].
+pub(crate) fn decode_level_rgb(path: &Path, level: u32) -> Result<LevelRgb, DecodeError> {
+    let display = decode_display(path, false, Some(Reduce::Level(level)), limits::tiled())?;
+    Ok(LevelRgb {
+        image: display.image.into_rgb8(),
+        icc: display.icc,
+        natural: display.natural,
+    })
+}
+
+/// Tile row `y` of pyramid `level` as displayed (see [`decode_level_rgb`]):
+/// the level's full width and the band's rows, no more. A PNG that can be
+/// streamed is read row by row and only the rows the band is resampled
+/// from are kept; anything else is decoded whole, reduced where the decoder
+/// can, and the band cut from that. `Ok(None)` when the band is outside the
+/// pyramid.
+pub(crate) fn decode_band_rgb(
+    path: &Path,
+    level: u32,
+    y: u32,
+) -> Result<Option<LevelRgb>, DecodeError> {
+    let limits = limits::tiled();
+    if let Some(reader) = open_png_rows(path, limits)? {
+        let (width, height) = (reader.info().width, reader.info().height);
+        let Some(rows) = tiles::band_rows(width, height, level, y) else {
+            return Ok(None);
+        };
+        return decode_png_band(path, reader, level, rows, limits).map(Some);
+    }
+    let LevelRgb {
+        image,
+        icc,
+        natural,
+    } = decode_level_rgb(path, level)?;
+    let Some(rows) = tiles::band_rows(natural.0, natural.1, level, y) else {
+        return Ok(None);
+    };
+    let level_size = tiles::level_size(natural.0, natural.1, level);
+    let (width, height) = image.dimensions();
+    Ok(Some(LevelRgb {
+        image: cut_band(image, 0, (width, height), level_size, rows)?,
+        icc,
+        natural,
+    }))
+}
+
+type PngRows = png::Reader<BufReader<File>>;
+
+/// A reader positioned at the first row of `path` when it is a PNG whose
+/// rows come out in display order: not interlaced, no Exif orientation.
+/// `Ok(None)` for everything else.
+fn open_png_rows(path: &Path, limits: &DecodeLimits) -> Result<Option<PngRows>, DecodeError> {
+    if detect(path).map(|d| d.format) != Some(SourceFormat::Png) {
+        return Ok(None);
+    }
+    let file = File::open(path).map_err(|e| format!("open: {e}"))?;
+    let mut decoder = png::Decoder::new_with_limits(
+        BufReader::new(file),
+        png::Limits {
+            bytes: usize::try_from(limits.max_alloc).unwrap_or(usize::MAX),
+        },
+    );
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let reader = decoder.read_info().map_err(|e| format!("decode: {e}"))?;
+    let info = reader.info();
+    let oriented = info
+        .exif_metadata
+        .as_deref()
+        .and_then(Orientation::from_exif_chunk)
+        .is_some_and(|o| o != Orientation::NoTransforms);
+    if info.interlaced || oriented {
+        return Ok(None);
+    }
+    Ok(Some(reader))
+}
+
+/// The `rows` of `level` from a PNG streamed by [`open_png_rows`]. Rows
+/// below the band are never decoded and rows above it are dropped as they
+/// go, so only the band and the filter's reach around it are ever held.
+fn decode_png_band(
+    path: &Path,
+    mut reader: PngRows,
+    level: u32,
+    rows: Range<u32>,
+    limits: &DecodeLimits,
+) -> Result<LevelRgb, DecodeError> {
+    let _t = PerfTimer::start("tile_decode_band", &path.to_string_lossy());
+    let info = reader.info();
+    let (width, height) = (info.width, info.height);
+    let icc = info.icc_profile.as_deref().map(<[u8]>::to_vec);
+    let (color, _) = reader.output_color_type();
+    let level_size = tiles::level_size(width, height, level);
+
+    // Lanczos3 reaches three destination pixels either side, `scale` source
+    // rows each.
+    let scale = f64::from(height) / f64::from(level_size.1);
+    let reach = 3.0 * scale;
+    let top = (f64::from(rows.start) * scale - reach).floor().max(0.0) as u32;
+    let bottom = ((f64::from(rows.end) * scale + reach).ceil() as u32).min(height);
+    let line = reader
+        .output_line_size(width)
+        .ok_or_else(|| "decode: row too large".to_string())?;
+    limits.check(width, height, line as u64 * u64::from(bottom - top))?;
+
+    let mut slab = Vec::with_capacity(line * (bottom - top) as usize);
+    for index in 0..bottom {
+        let row = reader
+            .next_row()
+            .map_err(|e| format!("decode: {e}"))?
+            .ok_or_else(|| "decode: image data ends early".to_string())?;
+        if index >= top {
+            slab.extend_from_slice(row.data());
+        }
+    }
+    let slab_height = bottom - top;
+    let image = match color {
+        png::ColorType::Grayscale => {
+            GrayImage::from_raw(width, slab_height, slab).map(DynamicImage::ImageLuma8)
+        }
+        png::ColorType::GrayscaleAlpha => {
+            image::GrayAlphaImage::from_raw(width, slab_height, slab).map(DynamicImage::ImageLumaA8)
+        }
+        png::ColorType::Rgb => {
+            RgbImage::from_raw(width, slab_height, slab).map(DynamicImage::ImageRgb8)
+        }
+        png::ColorType::Rgba => {
+            image::RgbaImage::from_raw(width, slab_height, slab).map(DynamicImage::ImageRgba8)
+        }
+        png::ColorType::Indexed => None,
+    };
+    let image = image.ok_or_else(|| "decode: buffer size mismatch".to_string())?;
+    // Palettes expand to RGB(A), so only a gray source drops its profile.
+    let icc = icc.filter(|p| image.color().has_color() && icc_describes_rgb(p));
+    let rgb = DynamicImage::ImageRgb8(flatten_to_rgb8(image));
+    let (rgb, icc) = color::rgb_to_output(rgb, icc);
+    Ok(LevelRgb {
+        image: cut_band(rgb.into_rgb8(), top, (width, height), level_size, rows)?,
+        icc,
+        natural: (width, height),
+    })
+}
+
+/// The `rows` of a `level_size` level, from `slab`: rows `slab_top..` of
+/// the whole image at `full` size (the level's or larger).
+fn cut_band(
+    slab: RgbImage,
+    slab_top: u32,
+    full: (u32, u32),
+    level_size: (u32, u32),
+    rows: Range<u32>,
+) -> Result<RgbImage, String> {
+    let band_height = rows.end - rows.start;
+    if full == level_size {
+        let band =
+            image::imageops::crop_imm(&slab, 0, rows.start - slab_top, level_size.0, band_height);
+        return Ok(band.to_image());
+    }
+    let scale = f64::from(full.1) / f64::from(level_size.1);
+    let top = f64::from(rows.start) * scale - f64::from(slab_top);
+    let height = f64::from(band_height) * scale;
+    resize_rgb8_rows(slab, top, height, level_size.0, band_height)
+}
+
+struct Display {
+    image: DynamicImage,
+    icc: Option<Vec<u8>>,
+    /// See [`Decoded::natural`].
+    natural: (u32, u32),
 }
 
-/// Preview + thumbnail from ONE decode. `path` must already be validated.
-pub fn generate(path: &Path, bbox: PreviewBox, thumb_size: u32) -> Result<Generated, String> {
+/// The pixels as displayed (see [`decode_level_rgb`]) as 8-bit RGB or, with
+/// `keep_alpha` and an alpha channel in the source, RGBA; reduced for `fit`
+/// where the decoder can.
+fn decode_display(
+    path: &Path,
+    keep_alpha: bool,
+    fit: Option<Reduce>,
+    limits: &DecodeLimits,
+) -> Result<Display, DecodeError> {
     let path_str = path.to_string_lossy();
     let Decoded {
         image,
         icc,
         original_color,
+        in_output_space,
+        natural,
     } = {
         let _t = PerfTimer::start("preview_decode", &path_str);
-        decode_oriented(path)?
+        decode_oriented(path, fit, limits)?
     };
-    let (natural_width, natural_height) = (image.width(), image.height());
+    if in_output_space {
+        return Ok(Display {
+            image,
+            icc,
+            natural,
+        });
+    }
     // X1 + coordinator follow-up: the decoder's original color type is
     // checked (correct for formats whose decoders do report CMYK, e.g.
     // TIFF), AND the profile's own header is checked, because `image`
     // 0.25's JPEG decoder reports CMYK/YCCK sources as RGB regardless.
     let icc = icc.filter(|p| icc_applies(original_color) && icc_describes_rgb(p));
-    let rgb = flatten_to_rgb8(image);
+    let image = if keep_alpha && image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.into_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(flatten_to_rgb8(image))
+    };
+    let _t = PerfTimer::start("preview_color", &path_str);
+    let (image, icc) = color::rgb_to_output(image, icc);
+    Ok(Display {
+        image,
+        icc,
+        natural,
+    })
+}
+
+/// Preview + thumbnail from ONE decode, the preview as the default JPEG.
+/// `path` must already be validated.
+#[cfg(test)]
+pub fn generate(path: &Path, bbox: PreviewBox, thumb_size: u32) -> Result<Generated, DecodeError> {
+    generate_uncoalesced(path, bbox, &PreviewProfile::DEFAULT, thumb_size)
+}
+
+/// Source (at its current stamp), preview cache key and thumbnail size of
+/// one generation: equal keys produce equal [`Generated`]s.
+type FlightKey = (PathBuf, (u64, u64), String, u32);
+
+/// Generations in flight. The thumbnail command, the `/preview/` route and
+/// the prefetch workers often miss the cache for the same image at once;
+/// late arrivals wait for the running decode and store instead of starting
+/// another, and share its result rather than copying it.
+fn in_flight() -> &'static SingleFlight<FlightKey, Result<Arc<Generated>, DecodeError>> {
+    type Flights = SingleFlight<FlightKey, Result<Arc<Generated>, DecodeError>>;
+    static FLIGHTS: OnceLock<Flights> = OnceLock::new();
+    FLIGHTS.get_or_init(Flights::default)
+}
+
+fn flight_key(
+    path: &Path,
+    bbox: PreviewBox,
+    profile: &PreviewProfile,
+    thumb_size: u32,
+) -> Option<FlightKey> {
+    Some((
+        path.to_path_buf(),
+        source_stamp(path)?,
+        profile.cache_key(bbox),
+        thumb_size,
+    ))
+}
+
+/// Preview (encoded per `profile`) + thumbnail from ONE decode, handed to
+/// `store` before anyone gets them. Concurrent calls for the same source,
+/// box, encoding and thumbnail size share one decode and one `store`: the
+/// rest wait for it, skip their own `store` (the result is cached by then)
+/// and get the same `Generated`. A source that can't be stat'ed is decoded
+/// by each caller. `path` must already be validated.
+pub fn generate_with(
+    path: &Path,
+    bbox: PreviewBox,
+    profile: &PreviewProfile,
+    thumb_size: u32,
+    store: impl FnOnce(&Generated) -> Result<(), String>,
+) -> Result<Arc<Generated>, DecodeError> {
+    let work = || {
+        let generated = generate_uncoalesced(path, bbox, profile, thumb_size)?;
+        store(&generated)?;
+        Ok(Arc::new(generated))
+    };
+    match flight_key(path, bbox, profile, thumb_size) {
+        Some(key) => in_flight().run(key, work),
+        None => work(),
+    }
+}
+
+fn generate_uncoalesced(
+    path: &Path,
+    bbox: PreviewBox,
+    profile: &PreviewProfile,
+    thumb_size: u32,
+) -> Result<Generated, DecodeError> {
+    let path_str = path.to_string_lossy();
+    let Display {
+        image,
+        icc,
+        natural: (natural_width, natural_height),
+    } = decode_display(
+        path,
+        profile.format.keeps_alpha(),
+        Some(Reduce::Fit(bbox)),
+        limits::current(),
+    )?;
     let (preview, resized) = match fit_within(natural_width, natural_height, bbox) {
+        // A DCT-scaled decode can land on the target size exactly.
+        Some((tw, th)) if (image.width(), image.height()) == (tw, th) => (image, true),
         Some((tw, th)) => {
             let _t = PerfTimer::start("preview_resize", &path_str);
-            (resize_rgb8(rgb, tw, th)?, true)
+            (resize_image(image, tw, th)?, true)
         }
-        None => (rgb, false),
+        None => (image, false),
     };
     let (preview_width, preview_height) = (preview.width(), preview.height());
-    let preview_jpeg = {
+    let preview_bytes = {
         let _t = PerfTimer::start("preview_encode", &path_str);
-        encode_jpeg(&preview, PREVIEW_JPEG_QUALITY, icc.as_deref())?
+        profile.encoder().encode(&preview, icc.as_deref())?
     };
-    let thumbnail_base64 = thumbnail_base64(&DynamicImage::ImageRgb8(preview), thumb_size)?;
+    let thumbnail_jpeg = thumbnail_jpeg(&preview, thumb_size)?;
+    let dhash = phash::dhash(&preview);
     Ok(Generated {
-        preview_jpeg,
+        preview_bytes,
         preview_width,
         preview_height,
         natural_width,
         natural_height,
         resized,
-        thumbnail_base64,
+        thumbnail_jpeg,
+        dhash,
     })
 }
 
 /// Thumbnail without a preview (GIF keeps its `<img>` path; the first frame
-/// is enough for the bar). Returns (base64, natural width, natural height).
-pub fn thumbnail_only(path: &Path, thumb_size: u32) -> Result<(String, u32, u32), String> {
-    let Decoded { image, .. } = decode_oriented(path)?;
-    let (w, h) = (image.width(), image.height());
-    Ok((thumbnail_base64(&image, thumb_size)?, w, h))
+/// is enough for the bar). Returns (JPEG, natural width, natural height,
+/// dHash). JPEGs are decoded at the smallest DCT scale that still covers
+/// the thumbnail.
+pub fn thumbnail_only(
+    path: &Path,
+    thumb_size: u32,
+) -> Result<(Vec<u8>, u32, u32, u64), DecodeError> {
+    let thumb_box = PreviewBox {
+        width: thumb_size,
+        height: thumb_size,
+    };
+    let Display {
+        image,
+        natural: (w, h),
+        ..
+    } = decode_display(path, true, Some(Reduce::Fit(thumb_box)), limits::current())?;
+    Ok((
+        thumbnail_jpeg(&image, thumb_size)?,
+        w,
+        h,
+        phash::dhash(&image),
+    ))
+}
+
+/// Largest relative difference between the Exif thumbnail's aspect ratio and
+/// the image's for the thumbnail to stand in for it. Cameras letterbox 3:2
+/// shots into 160x120 thumbnails; those are refused.
+const EXIF_THUMB_ASPECT_TOLERANCE: f64 = 0.02;
+
+/// Header-only bar thumbnail for a JPEG: its Exif IFD1 thumbnail, oriented
+/// like the main image, when it has the main image's aspect ratio and is at
+/// least `thumb_size`. Same result as [`thumbnail_only`]; the dHash is the
+/// small thumbnail's.
+pub fn exif_thumbnail(path: &Path, thumb_size: u32) -> Option<(Vec<u8>, u32, u32, u64)> {
+    if detect(path)?.format != SourceFormat::Jpeg {
+        return None;
+    }
+    let _t = PerfTimer::start("exif_thumb", &path.to_string_lossy());
+    let (jpeg, orientation) = metadata::jpeg_exif_thumbnail(path)?;
+    let orientation = Orientation::from_exif(orientation).unwrap_or(Orientation::NoTransforms);
+    let info = open_jpeg(path, limits::current()).ok()?.info()?;
+    let (w, h) = (u32::from(info.width), u32::from(info.height));
+    // An image too large to decode gets no stand-in either: the full path
+    // records it as such.
+    limits::current()
+        .check(w, h, u64::from(w) * u64::from(h) * 3)
+        .ok()?;
+    let (w, h) = if swaps_axes(orientation) {
+        (h, w)
+    } else {
+        (w, h)
+    };
+    let mut thumb = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg).ok()?;
+    thumb.apply_orientation(orientation);
+    let (tw, th) = (thumb.width(), thumb.height());
+    if tw.max(th) < thumb_size || w == 0 || h == 0 || th == 0 {
+        return None;
+    }
+    let ratio = (f64::from(tw) / f64::from(th)) / (f64::from(w) / f64::from(h));
+    if (ratio - 1.0).abs() > EXIF_THUMB_ASPECT_TOLERANCE {
+        return None;
+    }
+    let thumb = DynamicImage::ImageRgb8(thumb.into_rgb8());
+    Some((
+        thumbnail_jpeg(&thumb, thumb_size).ok()?,
+        w,
+        h,
+        phash::dhash(&thumb),
+    ))
 }
 
 #[cfg(test)]
@@ -276,13 +919,14 @@ mod tests {
     use super::*;
     use crate::test_utils::*;
     use image::{GenericImageView, ImageDecoder, ImageReader};
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     fn box_1080p() -> PreviewBox {
         PreviewBox::parse("1920x1080").unwrap()
     }
 
     #[test]
-    fn parse_accepts_allowlisted_boxes_in_both_orientations() {
+    fn parse_accepts_screen_boxes_in_both_orientations() {
         assert_eq!(
             PreviewBox::parse("1920x1080"),
             Some(PreviewBox {
@@ -301,9 +945,28 @@ mod tests {
             PreviewBox::parse("3840x2160").map(|b| b.key()),
             Some("3840x2160".to_string())
         );
-        assert_eq!(PreviewBox::parse("1920x1200"), None);
+        assert_eq!(
+            PreviewBox::parse("3440x1440").map(|b| b.key()),
+            Some("3440x1440".to_string())
+        );
         assert_eq!(PreviewBox::parse("abc"), None);
         assert_eq!(PreviewBox::parse("1920x"), None);
+        assert_eq!(PreviewBox::parse("0x1080"), None);
+    }
+
+    #[test]
+    fn parse_normalizes_scale_step_and_bounds() {
+        let key = |s: &str| PreviewBox::parse(s).map(|b| b.key());
+        // 1080p at 125% is a 1080p screen.
+        assert_eq!(key("1536x864@1.25"), key("1920x1080"));
+        assert_eq!(key("1710x1107@2"), Some("3440x2240".to_string()));
+        assert_eq!(key("1921x1081"), Some("1960x1120".to_string()));
+        assert_eq!(key("100x50"), Some("320x320".to_string()));
+        assert_eq!(key("5120x2880@2"), Some("7680x5760".to_string()));
+        assert_eq!(key("4294967295x1@4"), Some("7680x320".to_string()));
+        assert_eq!(key("1920x1080@0"), None);
+        assert_eq!(key("1920x1080@5"), None);
+        assert_eq!(key("1920x1080@NaN"), None);
     }
 
     #[test]
@@ -358,18 +1021,106 @@ mod tests {
         assert!(g.resized);
         assert_eq!((g.natural_width, g.natural_height), (2400, 1600));
         assert_eq!((g.preview_width, g.preview_height), (1620, 1080));
-        let decoded = image::load_from_memory(&g.preview_jpeg).unwrap();
+        let decoded = image::load_from_memory(&g.preview_bytes).unwrap();
         assert_eq!(decoded.dimensions(), (1620, 1080));
-        let thumb = image::load_from_memory(
-            &base64::engine::general_purpose::STANDARD
-                .decode(&g.thumbnail_base64)
-                .unwrap(),
-        )
-        .unwrap();
+        let thumb = image::load_from_memory(&g.thumbnail_jpeg).unwrap();
         assert!(thumb.width() <= 20 && thumb.height() <= 20);
         assert!(thumb.width() == 20 || thumb.height() == 20);
     }
 
+    #[test]
+    fn generate_dct_scales_jpegs_much_larger_than_the_box() {
+        let dir = create_temp_dir();
+        let src = create_gradient_jpeg(dir.path(), "huge.jpg", 4000, 3000);
+        let small = PreviewBox::parse("320x320").unwrap();
+        let mut decoder = open_jpeg(&src, limits::current()).unwrap();
+        let (natural, scaled) = request_dct_scale(
+            &mut decoder,
+            Orientation::NoTransforms,
+            Some(Reduce::Fit(small)),
+        )
+        .unwrap();
+        assert_eq!((natural, scaled), ((4000, 3000), (500, 375)));
+        let g = generate(&src, small, 20).unwrap();
+        assert!(g.resized);
+        assert_eq!((g.natural_width, g.natural_height), (4000, 3000));
+        assert_eq!((g.preview_width, g.preview_height), (320, 240));
+        let (_, w, h, _) = thumbnail_only(&src, 20).unwrap();
+        assert_eq!((w, h), (4000, 3000));
+    }
+
+    #[test]
+    fn dct_scale_follows_orientation_and_stays_at_or_above_the_target() {
+        let dir = create_temp_dir();
+        // Displayed 1600x2400; fitted into 1920x1080 that is 720x1080, so the
+        // stored 2400x1600 may only halve (1200x800 still covers 1080x720).
+        let src = create_jpeg_with_metadata(dir.path(), "rot.jpg", 2400, 1600, Some(6), None);
+        let mut decoder = open_jpeg(&src, limits::current()).unwrap();
+        let (natural, scaled) = request_dct_scale(
+            &mut decoder,
+            Orientation::Rotate90,
+            Some(Reduce::Fit(box_1080p())),
+        )
+        .unwrap();
+        assert_eq!((natural, scaled), ((1600, 2400), (1200, 800)));
+        let g = generate(&src, box_1080p(), 20).unwrap();
+        assert_eq!((g.natural_width, g.natural_height), (1600, 2400));
+        assert_eq!((g.preview_width, g.preview_height), (720, 1080));
+        // Less than twice the target: full-size decode.
+        let mut decoder = open_jpeg(&src, limits::current()).unwrap();
+        let near = PreviewBox::parse("1600x1600").unwrap();
+        let (_, full) =
+            request_dct_scale(&mut decoder, Orientation::Rotate90, Some(Reduce::Fit(near)))
+                .unwrap();
+        assert_eq!(full, (2400, 1600));
+    }
+
+    #[test]
+    fn generate_waits_for_the_decode_and_store_in_flight_instead_of_starting_another() {
+        let dir = create_temp_dir();
+        let img = create_test_jpeg(dir.path(), "a.jpg");
+        let bbox = box_1080p();
+        // A result no decode of this file produces, landed by a stand-in
+        // generation that stays in flight until all four callers joined it.
+        let mut marker = generate(&img, bbox, 20).unwrap();
+        marker.dhash = !marker.dhash;
+        let marker = Arc::new(marker);
+        let key = flight_key(&img, bbox, &PreviewProfile::DEFAULT, 20).unwrap();
+        let stores = AtomicUsize::new(0);
+        let count_store = |_: &Generated| -> Result<(), String> {
+            stores.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        };
+        let path = img.as_path();
+        let results: Vec<Arc<Generated>> = std::thread::scope(|s| {
+            let mut callers = Vec::new();
+            in_flight()
+                .run(key.clone(), || {
+                    for _ in 0..4 {
+                        callers.push(s.spawn(move || {
+                            generate_with(path, bbox, &PreviewProfile::DEFAULT, 20, count_store)
+                        }));
+                    }
+                    while in_flight().callers(&key) < 5 {
+                        std::thread::sleep(std::time::Duration::from_millis(1));
+                    }
+                    Ok(marker.clone())
+                })
+                .unwrap();
+            callers
+                .into_iter()
+                .map(|c| c.join().unwrap().unwrap())
+                .collect()
+        });
+        // The waiters got the leader's result itself, and stored nothing.
+        assert!(results.iter().all(|g| Arc::ptr_eq(g, &marker)));
+        assert_eq!(stores.load(Ordering::SeqCst), 0);
+        // Landed: the next call decodes and stores again.
+        let g = generate_with(path, bbox, &PreviewProfile::DEFAULT, 20, count_store).unwrap();
+        assert_eq!(g.dhash, !marker.dhash);
+        assert_eq!(stores.load(Ordering::SeqCst), 1);
+    }
+
     #[test]
     fn generate_keeps_small_images_at_native_size() {
         let dir = create_temp_dir();
@@ -389,7 +1140,7 @@ mod tests {
         assert_eq!((g.natural_width, g.natural_height), (800, 1200));
         assert_eq!((g.preview_width, g.preview_height), (720, 1080));
         // The preview must carry no Exif orientation of its own (it is already upright).
-        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_jpeg))
+        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_bytes))
             .with_guessed_format()
             .unwrap()
             .into_decoder()
@@ -403,11 +1154,13 @@ mod tests {
     #[test]
     fn generate_carries_the_icc_profile_into_the_preview() {
         let dir = create_temp_dir();
+        // Not a profile the CMS can read, so the pixels are left alone and
+        // the profile is passed on for the WebView to interpret.
         let mut icc: Vec<u8> = (0..600u32).map(|i| (i % 251) as u8).collect();
         icc[16..20].copy_from_slice(b"RGB ");
         let src = create_jpeg_with_metadata(dir.path(), "icc.jpg", 2400, 1600, None, Some(&icc));
         let g = generate(&src, box_1080p(), 20).unwrap();
-        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_jpeg))
+        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_bytes))
             .with_guessed_format()
             .unwrap()
             .into_decoder()
@@ -415,6 +1168,32 @@ mod tests {
         assert_eq!(dec.icc_profile().unwrap(), Some(icc));
     }
 
+    #[test]
+    fn generate_converts_wide_gamut_sources_to_srgb() {
+        let dir = create_temp_dir();
+        let p3 = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+        let plain = create_jpeg_with_metadata(dir.path(), "plain.jpg", 200, 100, None, None);
+        let tagged = create_jpeg_with_metadata(dir.path(), "p3.jpg", 200, 100, None, Some(&p3));
+        let plain = generate(&plain, box_1080p(), 20).unwrap();
+        let tagged = generate(&tagged, box_1080p(), 20).unwrap();
+        let mut dec = ImageReader::new(std::io::Cursor::new(&tagged.preview_bytes))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        assert_eq!(dec.icc_profile().unwrap(), None);
+        let px = |g: &Generated| {
+            image::load_from_memory(&g.preview_bytes)
+                .unwrap()
+                .to_rgb8()
+                .get_pixel(150, 10)
+                .0
+        };
+        // Same source pixels; as Display P3 they are redder in sRGB.
+        let (before, after) = (px(&plain), px(&tagged));
+        assert!(after[0] > before[0] + 8, "{before:?} -> {after:?}");
+    }
+
     #[test]
     fn generate_drops_cmyk_icc_profiles() {
         let dir = create_temp_dir();
@@ -426,7 +1205,7 @@ mod tests {
         let src =
             create_jpeg_with_metadata(dir.path(), "cmyk_icc.jpg", 2400, 1600, None, Some(&icc));
         let g = generate(&src, box_1080p(), 20).unwrap();
-        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_jpeg))
+        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_bytes))
             .with_guessed_format()
             .unwrap()
             .into_decoder()
@@ -452,9 +1231,9 @@ mod tests {
         icc[16..20].copy_from_slice(b"RGB ");
         let src = create_jpeg_with_metadata(dir.path(), "huge_icc.jpg", 200, 100, None, Some(&icc));
         let g = generate(&src, box_1080p(), 20).unwrap();
-        let decoded = image::load_from_memory(&g.preview_jpeg).unwrap();
+        let decoded = image::load_from_memory(&g.preview_bytes).unwrap();
         assert_eq!(decoded.dimensions(), (200, 100));
-        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_jpeg))
+        let mut dec = ImageReader::new(std::io::Cursor::new(&g.preview_bytes))
             .with_guessed_format()
             .unwrap()
             .into_decoder()
@@ -467,7 +1246,7 @@ mod tests {
         let dir = create_temp_dir();
         let src = create_half_transparent_png(dir.path(), "alpha.png", 200, 100);
         let g = generate(&src, box_1080p(), 20).unwrap();
-        let decoded = image::load_from_memory(&g.preview_jpeg).unwrap().to_rgb8();
+        let decoded = image::load_from_memory(&g.preview_bytes).unwrap().to_rgb8();
         let left = decoded.get_pixel(50, 50);
         let right = decoded.get_pixel(150, 50);
         assert!(
@@ -483,14 +1262,115 @@ mod tests {
     }
 
     #[test]
-    fn thumbnail_only_returns_base64_and_dimensions() {
+    fn thumbnail_only_returns_jpeg_and_dimensions() {
         let dir = create_temp_dir();
         let src = create_test_gif(dir.path(), "anim.gif");
-        let (b64, w, h) = thumbnail_only(&src, 20).unwrap();
-        assert!(!b64.is_empty());
+        let (jpeg, w, h, _) = thumbnail_only(&src, 20).unwrap();
+        assert!(jpeg.starts_with(&[0xFF, 0xD8]));
         assert_eq!((w, h), (1, 1));
     }
 
+    #[test]
+    fn generate_uses_the_embedded_raw_preview_with_the_raw_orientation() {
+        let dir = create_temp_dir();
+        // Orientation 6 on a 640x480 preview: displayed upright as 480x640.
+        let src = create_fake_tiff_raw(dir.path(), "shot.nef", 6, 640, 480);
+        let g = generate(&src, box_1080p(), 20).unwrap();
+        assert_eq!((g.natural_width, g.natural_height), (480, 640));
+        assert!(!g.resized);
+        let (_, w, h, _) = thumbnail_only(&src, 20).unwrap();
+        assert_eq!((w, h), (480, 640));
+    }
+
+    #[test]
+    fn exif_thumbnail_uses_ifd1_when_its_aspect_matches() {
+        let dir = create_temp_dir();
+        let src = create_jpeg_with_exif_thumbnail(dir.path(), "cam.jpg", (960, 640), (160, 107), 1);
+        let (jpeg, w, h, _) = exif_thumbnail(&src, 20).unwrap();
+        assert_eq!((w, h), (960, 640));
+        let thumb = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!(thumb.dimensions(), (20, 13));
+        // Orientation 6: both the size and the thumbnail turn upright.
+        let src = create_jpeg_with_exif_thumbnail(dir.path(), "rot.jpg", (960, 640), (160, 107), 6);
+        let (jpeg, w, h, _) = exif_thumbnail(&src, 20).unwrap();
+        assert_eq!((w, h), (640, 960));
+        assert_eq!(
+            image::load_from_memory(&jpeg).unwrap().dimensions(),
+            (13, 20)
+        );
+    }
+
+    #[test]
+    fn exif_thumbnail_refuses_letterboxed_and_missing_thumbnails() {
+        let dir = create_temp_dir();
+        let boxed =
+            create_jpeg_with_exif_thumbnail(dir.path(), "3x2.jpg", (960, 640), (160, 120), 1);
+        assert!(exif_thumbnail(&boxed, 20).is_none());
+        let plain = create_gradient_jpeg(dir.path(), "plain.jpg", 960, 640);
+        assert!(exif_thumbnail(&plain, 20).is_none());
+    }
+
+    #[test]
+    fn decode_refuses_a_png_bomb_before_allocating() {
+        let dir = create_temp_dir();
+        let bomb = create_png_bomb(dir.path(), "bomb.png", 60000, 60000);
+        let too_large = DecodeError::TooLarge {
+            width: 60000,
+            height: 60000,
+        };
+        assert_eq!(
+            generate(&bomb, box_1080p(), 20).err(),
+            Some(too_large.clone())
+        );
+        assert_eq!(thumbnail_only(&bomb, 20).err(), Some(too_large));
+    }
+
+    #[test]
+    fn png_bands_stream_and_meet_without_a_seam() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("scan.png");
+        image::RgbImage::from_fn(1300, 3000, |x, y| {
+            image::Rgb([
+                (x * 31 + y * 17) as u8,
+                (x ^ y) as u8,
+                (y * 255 / 3000) as u8,
+            ])
+        })
+        .save(&path)
+        .unwrap();
+
+        // Level 1 (650x1500) as one resize of the whole frame...
+        let whole = decode_level_rgb(&path, 1).unwrap();
+        assert_eq!(whole.image.dimensions(), (1300, 3000));
+        let whole = resize_rgb8(whole.image, 650, 1500).unwrap();
+
+        // ...and as its three bands, each streamed under a limit the whole
+        // frame would not fit in.
+        let limits = DecodeLimits {
+            max_pixels: limits::DEFAULT_MAX_PIXELS,
+            max_alloc: 1300 * 3000 * 3 / 2,
+        };
+        let band = |y| {
+            let reader = open_png_rows(&path, &limits).unwrap().unwrap();
+            let rows = tiles::band_rows(1300, 3000, 1, y).unwrap();
+            decode_png_band(&path, reader, 1, rows, &limits).unwrap()
+        };
+        let bands = [band(0), band(1), band(2)];
+        assert!(bands.iter().all(|b| b.natural == (1300, 3000)));
+        let sizes: Vec<_> = bands.iter().map(|b| b.image.dimensions()).collect();
+        assert_eq!(sizes, [(650, 512), (650, 512), (650, 476)]);
+        let stitched = bands.iter().flat_map(|b| b.image.pixels());
+        for (a, b) in stitched.zip(whole.pixels()) {
+            for (a, b) in a.0.iter().zip(b.0) {
+                assert!(a.abs_diff(b) <= 1, "{a} vs {b}");
+            }
+        }
+
+        // Only PNGs are streamed.
+        let jpeg = create_gradient_jpeg(dir.path(), "scan.jpg", 64, 64);
+        assert!(open_png_rows(&jpeg, &limits).unwrap().is_none());
+    }
+
     #[test]
     fn generate_rejects_invalid_files() {
         let dir = create_temp_dir();
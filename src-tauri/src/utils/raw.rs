@@ -0,0 +1,470 @@
+//! Camera RAW support through the JPEG previews cameras embed in every RAW
+//! file. Demosaicing the sensor data is out of scope; the largest embedded
+//! preview (usually full size or close to it) is what gets decoded, fitted
+//! and served instead.
+//!
+//! CR2/NEF/ARW/DNG are TIFF containers: previews hang off IFD0, the IFD
+//! chain or SubIFDs, either as JPEGInterchangeFormat or as a single
+//! JPEG-compressed strip. CR3 is ISOBMFF: Canon stores a full-size JPEG as
+//! the first track, a ~1620 px `PRVW` box and a 160 px `THMB` box, with the
+//! orientation in the `CMT1` (IFD0) box.
+
+use crate::utils::limits;
+use crate::utils::tiff::{self, read_exact_at, TiffReader};
+use image::metadata::Orientation;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+/// Canon's metadata box inside `moov` (holds CMT1..CMT4 and THMB).
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+/// Top-level box wrapping the `PRVW` preview.
+const PREVIEW_UUID: [u8; 16] = [
+    0xea, 0xf4, 0x2b, 0x5e, 0x1c, 0x98, 0x4b, 0x88, 0xb9, 0xfb, 0xb7, 0xdc, 0x40, 0x6e, 0x4d, 0x16,
+];
+
+/// Guards against corrupt or cyclic IFD/box structures.
+const MAX_IFDS: usize = 32;
+const MAX_BOXES: usize = 256;
+
+pub struct RawPreview {
+    pub jpeg: Vec<u8>,
+    /// The RAW's own orientation. Embedded previews are stored as shot
+    /// (sensor-up) and rarely carry a usable orientation of their own.
+    pub orientation: Orientation,
+}
+
+/// A candidate preview: absolute byte range plus its frame size.
+struct Candidate {
+    offset: u64,
+    len: u64,
+    area: u64,
+}
+
+pub fn extract_preview(path: &Path) -> Result<RawPreview, String> {
+    let file = File::open(path).map_err(|e| format!("open: {e}"))?;
+    extract_from(BufReader::new(file))
+}
+
+pub fn extract_from<R: Read + Seek>(mut src: R) -> Result<RawPreview, String> {
+    let head = read_exact_at(&mut src, 0, 12).ok_or_else(|| "raw: file too short".to_string())?;
+    let (mut src, ranges, orientation) = if &head[4..8] == b"ftyp" {
+        let (ranges, orientation) = cr3_candidates(&mut src);
+        (src, ranges, orientation)
+    } else {
+        let mut t =
+            TiffReader::new(src, 0).ok_or_else(|| "raw: not a TIFF or CR3 file".to_string())?;
+        let (ranges, orientation) = tiff_candidates(&mut t);
+        (t.into_inner(), ranges, orientation)
+    };
+    // A preview over the decode limits is skipped like an unreadable one:
+    // it is served to the WebView as is, and read into memory here.
+    let limits = limits::current();
+    let best = ranges
+        .into_iter()
+        .filter(|&(_, len)| len <= limits.max_alloc)
+        .filter_map(|(offset, len)| {
+            let (w, h) = jpeg_frame_size(&mut src, offset, len)?;
+            limits.check(w, h, u64::from(w) * u64::from(h) * 3).ok()?;
+            Some(Candidate {
+                offset,
+                len,
+                area: u64::from(w) * u64::from(h),
+            })
+        })
+        .max_by_key(|c| c.area)
+        .ok_or_else(|| "raw: no embedded JPEG preview".to_string())?;
+    let jpeg = read_exact_at(&mut src, best.offset, best.len as usize)
+        .ok_or_else(|| "raw: preview out of range".to_string())?;
+    Ok(RawPreview {
+        jpeg,
+        orientation: Orientation::from_exif(orientation).unwrap_or(Orientation::NoTransforms),
+    })
+}
+
+/// The preview as a standalone JPEG the WebView shows upright: any Exif
+/// segment of its own is replaced by one carrying the RAW's orientation.
+pub fn served_jpeg(preview: &RawPreview) -> Vec<u8> {
+    let mut out = Vec::with_capacity(preview.jpeg.len() + 64);
+    out.extend_from_slice(&preview.jpeg[..2]);
+    out.extend(exif_orientation_segment(preview.orientation.to_exif()));
+    let mut pos = 2;
+    // Copy every marker segment up to SOS except Exif APP1; the entropy-coded
+    // data after SOS goes across untouched.
+    while pos + 4 <= preview.jpeg.len() && preview.jpeg[pos] == 0xFF {
+        let marker = preview.jpeg[pos + 1];
+        if marker == 0xDA {
+            break;
+        }
+        let seg_len = usize::from(u16::from_be_bytes([
+            preview.jpeg[pos + 2],
+            preview.jpeg[pos + 3],
+        ]));
+        let end = (pos + 2 + seg_len).min(preview.jpeg.len());
+        if end < pos + 4 {
+            break;
+        }
+        let is_exif = marker == 0xE1 && preview.jpeg[pos + 4..end].starts_with(b"Exif\0\0");
+        if !is_exif {
+            out.extend_from_slice(&preview.jpeg[pos..end]);
+        }
+        pos = end;
+    }
+    out.extend_from_slice(&preview.jpeg[pos..]);
+    out
+}
+
+/// APP1 segment with a one-entry little-endian IFD0 holding Orientation.
+pub(crate) fn exif_orientation_segment(orientation: u8) -> Vec<u8> {
+    let mut tiff = Vec::with_capacity(26);
+    tiff.extend_from_slice(b"II\x2A\x00");
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    tiff.extend_from_slice(&tiff::TAG_ORIENTATION.to_le_bytes());
+    tiff.extend_from_slice(&3u16.to_le_bytes()); // SHORT
+    tiff.extend_from_slice(&1u32.to_le_bytes());
+    tiff.extend_from_slice(&u16::from(orientation).to_le_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    let mut seg = vec![0xFF, 0xE1];
+    seg.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+    seg.extend_from_slice(b"Exif\0\0");
+    seg.extend(tiff);
+    seg
+}
+
+/// Walks IFD0, its chain and every SubIFD collecting JPEG byte ranges.
+/// Returns the ranges and IFD0's orientation (1 when absent).
+fn tiff_candidates<R: Read + Seek>(t: &mut TiffReader<R>) -> (Vec<(u64, u64)>, u8) {
+    let mut ranges = Vec::new();
+    let mut orientation = 1u8;
+    let Some(first) = t.first_ifd_offset() else {
+        return (ranges, orientation);
+    };
+    let mut queue = vec![first];
+    let mut seen = HashSet::new();
+    while let Some(offset) = queue.pop() {
+        if offset == 0 || seen.len() >= MAX_IFDS || !seen.insert(offset) {
+            continue;
+        }
+        let Some(ifd) = t.read_ifd(offset) else {
+            continue;
+        };
+        if offset == first {
+            if let Some(o) = ifd.get(tiff::TAG_ORIENTATION).and_then(|e| t.value(e)) {
+                orientation = u8::try_from(o).unwrap_or(1);
+            }
+        }
+        let single = |t: &mut TiffReader<R>, tag| ifd.get(tag).and_then(|e| t.value(e));
+        if let (Some(o), Some(l)) = (
+            single(t, tiff::TAG_JPEG_OFFSET),
+            single(t, tiff::TAG_JPEG_LENGTH),
+        ) {
+            ranges.push((u64::from(o), u64::from(l)));
+        }
+        // 6 = old-style JPEG, 7 = JPEG. Lossless-JPEG raw data (CR2, DNG)
+        // looks the same here; `jpeg_frame_size` weeds it out by its SOF.
+        if matches!(single(t, tiff::TAG_COMPRESSION), Some(6 | 7)) {
+            let offsets = ifd.get(tiff::TAG_STRIP_OFFSETS).and_then(|e| t.values(e));
+            let counts = ifd
+                .get(tiff::TAG_STRIP_BYTE_COUNTS)
+                .and_then(|e| t.values(e));
+            if let (Some([o]), Some([l])) = (offsets.as_deref(), counts.as_deref()) {
+                ranges.push((u64::from(*o), u64::from(*l)));
+            }
+        }
+        if let Some(subs) = ifd.get(tiff::TAG_SUB_IFDS).and_then(|e| t.values(e)) {
+            queue.extend(subs);
+        }
+        queue.push(ifd.next);
+    }
+    (ranges, orientation)
+}
+
+struct BoxHeader {
+    typ: [u8; 4],
+    /// Absolute position of the payload (after size/type/largesize).
+    body: u64,
+    end: u64,
+}
+
+fn boxes<R: Read + Seek>(src: &mut R, start: u64, end: u64) -> Vec<BoxHeader> {
+    let mut out = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end && out.len() < MAX_BOXES {
+        let Some(h) = read_exact_at(src, pos, 8) else {
+            break;
+        };
+        let size32 = u32::from_be_bytes([h[0], h[1], h[2], h[3]]);
+        let typ = [h[4], h[5], h[6], h[7]];
+        let (size, header) = match size32 {
+            0 => (end - pos, 8),
+            1 => match read_exact_at(src, pos + 8, 8) {
+                Some(l) => (
+                    u64::from_be_bytes([l[0], l[1], l[2], l[3], l[4], l[5], l[6], l[7]]),
+                    16,
+                ),
+                None => break,
+            },
+            n => (u64::from(n), 8),
+        };
+        if size < header || pos + size > end {
+            break;
+        }
+        out.push(BoxHeader {
+            typ,
+            body: pos + header,
+            end: pos + size,
+        });
+        pos += size;
+    }
+    out
+}
+
+fn find<'a>(list: &'a [BoxHeader], typ: &[u8; 4]) -> Option<&'a BoxHeader> {
+    list.iter().find(|b| &b.typ == typ)
+}
+
+fn is_uuid<R: Read + Seek>(src: &mut R, b: &BoxHeader, uuid: &[u8; 16]) -> bool {
+    &b.typ == b"uuid" && read_exact_at(src, b.body, 16).as_deref() == Some(&uuid[..])
+}
+
+/// A JPEG embedded after a small fixed header inside `b` (PRVW/THMB):
+/// the SOI is searched for rather than trusting per-model header layouts.
+fn embedded_jpeg<R: Read + Seek>(src: &mut R, b: &BoxHeader) -> Option<(u64, u64)> {
+    let window = read_exact_at(src, b.body, 64.min((b.end - b.body) as usize))?;
+    let soi = window.windows(3).position(|w| w == [0xFF, 0xD8, 0xFF])? as u64;
+    Some((b.body + soi, b.end - b.body - soi))
+}
+
+fn cr3_candidates<R: Read + Seek>(src: &mut R) -> (Vec<(u64, u64)>, u8) {
+    let mut ranges = Vec::new();
+    let mut orientation = 1u8;
+    let Ok(len) = src.seek(std::io::SeekFrom::End(0)) else {
+        return (ranges, orientation);
+    };
+    let top = boxes(src, 0, len);
+    if let Some(moov) = find(&top, b"moov") {
+        let moov_children = boxes(src, moov.body, moov.end);
+        for b in &moov_children {
+            if !is_uuid(src, b, &CANON_UUID) {
+                continue;
+            }
+            let canon = boxes(src, b.body + 16, b.end);
+            if let Some(thmb) = find(&canon, b"THMB") {
+                ranges.extend(embedded_jpeg(src, thmb));
+            }
+            if let Some(cmt1) = find(&canon, b"CMT1") {
+                let mut t = TiffReader::new(&mut *src, cmt1.body);
+                let o = t.as_mut().and_then(|t| {
+                    let first = t.first_ifd_offset()?;
+                    let ifd = t.read_ifd(first)?;
+                    t.value(ifd.get(tiff::TAG_ORIENTATION)?)
+                });
+                orientation = o.and_then(|o| u8::try_from(o).ok()).unwrap_or(1);
+            }
+        }
+        // Track 1 holds the full-size JPEG: first sample of its stbl.
+        if let Some(trak) = find(&moov_children, b"trak") {
+            ranges.extend(first_sample(src, trak));
+        }
+    }
+    for b in &top {
+        if is_uuid(src, b, &PREVIEW_UUID) {
+            // 16-byte uuid, then 8 bytes Canon doesn't document, then PRVW.
+            let inner = boxes(src, b.body + 24, b.end);
+            if let Some(prvw) = find(&inner, b"PRVW") {
+                ranges.extend(embedded_jpeg(src, prvw));
+            }
+        }
+    }
+    (ranges, orientation)
+}
+
+fn first_sample<R: Read + Seek>(src: &mut R, trak: &BoxHeader) -> Option<(u64, u64)> {
+    let mut level = boxes(src, trak.body, trak.end);
+    for name in [b"mdia", b"minf", b"stbl"] {
+        let b = find(&level, name)?;
+        level = boxes(src, b.body, b.end);
+    }
+    let stsz = find(&level, b"stsz")?;
+    // version/flags, sample_size, sample_count, then per-sample sizes.
+    let s = read_exact_at(src, stsz.body, 16)?;
+    let fixed = u32::from_be_bytes([s[4], s[5], s[6], s[7]]);
+    let size = if fixed != 0 {
+        fixed
+    } else {
+        u32::from_be_bytes([s[12], s[13], s[14], s[15]])
+    };
+    let offset = if let Some(co64) = find(&level, b"co64") {
+        let c = read_exact_at(src, co64.body + 8, 8)?;
+        u64::from_be_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])
+    } else {
+        let stco = find(&level, b"stco")?;
+        let c = read_exact_at(src, stco.body + 8, 4)?;
+        u64::from(u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+    };
+    Some((offset, u64::from(size)))
+}
+
+/// Frame size of a baseline/progressive JPEG occupying `offset..offset+len`,
+/// or None for anything else — notably the lossless-JPEG (SOF3) sensor data
+/// CR2 and DNG store exactly like their previews.
+fn jpeg_frame_size<R: Read + Seek>(src: &mut R, offset: u64, len: u64) -> Option<(u32, u32)> {
+    let end = offset.checked_add(len)?;
+    if read_exact_at(src, offset, 2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut pos = offset + 2;
+    for _ in 0..64 {
+        if pos + 4 > end {
+            return None;
+        }
+        let seg = read_exact_at(src, pos, 4)?;
+        if seg[0] != 0xFF {
+            return None;
+        }
+        match seg[1] {
+            0xC0..=0xC2 => {
+                let sof = read_exact_at(src, pos + 4, 5)?;
+                let h = u32::from(u16::from_be_bytes([sof[1], sof[2]]));
+                let w = u32::from(u16::from_be_bytes([sof[3], sof[4]]));
+                return (w > 0 && h > 0).then_some((w, h));
+            }
+            // Other SOFn (lossless, hierarchical, arithmetic) or scan data
+            // before any frame header: nothing `image` can decode.
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return None,
+            _ => pos += 2 + u64::from(u16::from_be_bytes([seg[2], seg[3]])),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use image::{ImageDecoder, ImageReader};
+    use std::io::Cursor;
+
+    fn be_box(typ: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut v = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+        v.extend_from_slice(typ);
+        v.extend_from_slice(body);
+        v
+    }
+
+    /// CR3-shaped file: THMB 16x12 + CMT1 (orientation 8) in the Canon uuid,
+    /// track 1 pointing at a 64x48 JPEG in mdat, and a 32x24 PRVW.
+    fn fake_cr3() -> Vec<u8> {
+        let thmb = {
+            let mut b = vec![0u8; 16];
+            b.extend(gradient_jpeg_bytes(16, 12));
+            be_box(b"THMB", &b)
+        };
+        let cmt1 = be_box(b"CMT1", &exif_orientation_blob(8));
+        let mut canon = CANON_UUID.to_vec();
+        canon.extend(thmb);
+        canon.extend(cmt1);
+        let canon = be_box(b"uuid", &canon);
+
+        let full = gradient_jpeg_bytes(64, 48);
+        let prvw = {
+            let mut b = vec![0u8; 16];
+            b.extend(gradient_jpeg_bytes(32, 24));
+            be_box(b"PRVW", &b)
+        };
+        let mut preview_uuid = PREVIEW_UUID.to_vec();
+        preview_uuid.extend([0u8; 8]);
+        preview_uuid.extend(prvw);
+        let preview_uuid = be_box(b"uuid", &preview_uuid);
+
+        let ftyp = be_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        // The mdat offset depends on moov's size, which doesn't depend on
+        // the offset value itself — build once to measure, then for real.
+        let build_moov = |mdat_offset: u64| {
+            let mut stsz = vec![0u8; 4];
+            stsz.extend(0u32.to_be_bytes());
+            stsz.extend(1u32.to_be_bytes());
+            stsz.extend((full.len() as u32).to_be_bytes());
+            let mut co64 = vec![0u8; 4];
+            co64.extend(1u32.to_be_bytes());
+            co64.extend(mdat_offset.to_be_bytes());
+            let stbl = be_box(
+                b"stbl",
+                &[be_box(b"stsz", &stsz), be_box(b"co64", &co64)].concat(),
+            );
+            let trak = be_box(b"trak", &be_box(b"mdia", &be_box(b"minf", &stbl)));
+            be_box(b"moov", &[canon.clone(), trak].concat())
+        };
+        let moov_len = build_moov(0).len();
+        let mdat_offset = (ftyp.len() + moov_len + preview_uuid.len() + 8) as u64;
+        [
+            ftyp,
+            build_moov(mdat_offset),
+            preview_uuid,
+            be_box(b"mdat", &full),
+        ]
+        .concat()
+    }
+
+    fn dims(jpeg: &[u8]) -> (u32, u32) {
+        let img = image::load_from_memory(jpeg).unwrap();
+        (img.width(), img.height())
+    }
+
+    #[test]
+    fn tiff_raw_yields_the_largest_decodable_preview_and_orientation() {
+        let dir = create_temp_dir();
+        let src = create_fake_tiff_raw(dir.path(), "a.nef", 6, 64, 48);
+        let p = extract_preview(&src).unwrap();
+        // The 4000x3000 lossless strip in the fixture must be skipped.
+        assert_eq!(dims(&p.jpeg), (64, 48));
+        assert_eq!(p.orientation, Orientation::Rotate90);
+    }
+
+    #[test]
+    fn cr3_yields_the_full_size_track_jpeg_and_cmt1_orientation() {
+        let p = extract_from(Cursor::new(fake_cr3())).unwrap();
+        assert_eq!(dims(&p.jpeg), (64, 48));
+        assert_eq!(p.orientation, Orientation::Rotate270);
+    }
+
+    #[test]
+    fn cr3_candidates_include_prvw_and_thmb() {
+        let mut src = Cursor::new(fake_cr3());
+        let (ranges, _) = cr3_candidates(&mut src);
+        let mut sizes: Vec<_> = ranges
+            .into_iter()
+            .filter_map(|(o, l)| jpeg_frame_size(&mut src, o, l))
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![(16, 12), (32, 24), (64, 48)]);
+    }
+
+    #[test]
+    fn non_raw_input_is_rejected() {
+        assert!(extract_from(Cursor::new(b"not a raw file at all".to_vec())).is_err());
+        // Valid TIFF, but nothing JPEG inside.
+        assert!(extract_from(Cursor::new(exif_orientation_blob(1))).is_err());
+    }
+
+    #[test]
+    fn served_jpeg_carries_the_raw_orientation() {
+        let preview = RawPreview {
+            jpeg: gradient_jpeg_bytes(40, 20),
+            orientation: Orientation::Rotate90,
+        };
+        let served = served_jpeg(&preview);
+        let mut dec = ImageReader::new(Cursor::new(&served))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        assert_eq!(dec.orientation().unwrap(), Orientation::Rotate90);
+        assert_eq!(dims(&served), (40, 20));
+    }
+}
@@ -0,0 +1,263 @@
+//! Folders the `spica-img` protocol may serve from. A folder is added only
+//! when the user opens something in it: the startup file, a dropped file or
+//! folder, or a dialog selection. Requests are checked against the roots by
+//! canonical path, so symlinks and `..` cannot lead outside them.
+
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::{OnceLock, RwLock};
+
+/// Why a path was refused; `reason()` is what gets logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The request path contains a `..` component.
+    Traversal,
+    /// UNC share, `\\?\` / `\\.\` namespace or a reserved DOS device name.
+    DevicePath,
+    /// The path is relative, does not exist or cannot be canonicalized.
+    Unresolvable,
+    /// Resolves to a location under none of the roots.
+    OutsideRoots,
+}
+
+impl Rejection {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Traversal => "traversal",
+            Self::DevicePath => "device_path",
+            Self::Unresolvable => "unresolvable",
+            Self::OutsideRoots => "outside_roots",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scope {
+    /// Canonical folders; none is inside another.
+    roots: Vec<PathBuf>,
+}
+
+impl Scope {
+    /// Adds folder `dir`. A folder already covered by a root is a no-op; one
+    /// that covers existing roots replaces them.
+    pub fn allow(&mut self, dir: &Path) -> Result<(), String> {
+        let dir = dir
+            .canonicalize()
+            .map_err(|e| format!("Cannot resolve folder: {e}"))?;
+        if !dir.is_dir() {
+            return Err("Not a folder".to_string());
+        }
+        if has_device_prefix(&dir) {
+            return Err("Network and device paths are not allowed".to_string());
+        }
+        if self.roots.iter().any(|root| dir.starts_with(root)) {
+            return Ok(());
+        }
+        self.roots.retain(|root| !root.starts_with(&dir));
+        self.roots.push(dir);
+        Ok(())
+    }
+
+    /// Accepts `path` when it is absolute, free of traversal and device
+    /// syntax, and its canonical form lies under a root.
+    pub fn check(&self, path: &Path) -> Result<(), Rejection> {
+        if path.components().any(|c| c == Component::ParentDir) {
+            return Err(Rejection::Traversal);
+        }
+        if !path.is_absolute() {
+            return Err(Rejection::Unresolvable);
+        }
+        if has_device_prefix(path) || is_reserved_device_name(path) {
+            return Err(Rejection::DevicePath);
+        }
+        let canonical = path.canonicalize().map_err(|_| Rejection::Unresolvable)?;
+        if has_device_prefix(&canonical) {
+            return Err(Rejection::DevicePath);
+        }
+        if self.roots.iter().any(|root| canonical.starts_with(root)) {
+            Ok(())
+        } else {
+            Err(Rejection::OutsideRoots)
+        }
+    }
+}
+
+/// True for UNC shares and the `\\.\` device namespace, and for any path
+/// spelled with a leading double separator. Drive paths — including the
+/// `\\?\C:\` form that `canonicalize` returns on Windows — pass.
+fn has_device_prefix(path: &Path) -> bool {
+    let s = path.as_os_str().to_string_lossy();
+    let verbatim_disk = matches!(
+        path.components().next(),
+        Some(Component::Prefix(p)) if matches!(p.kind(), Prefix::VerbatimDisk(_))
+    );
+    if verbatim_disk {
+        return false;
+    }
+    let double_sep = s.starts_with("//") || s.starts_with("\\\\");
+    double_sep
+        || path.components().any(|c| match c {
+            Component::Prefix(p) => !matches!(p.kind(), Prefix::Disk(_)),
+            _ => false,
+        })
+}
+
+/// `CON`, `NUL.jpg`, `com1.png`...: Windows opens the device, whatever the
+/// extension and folder.
+fn is_reserved_device_name(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let stem = name.split('.').next().unwrap_or("").trim_end();
+    let upper = stem.to_ascii_uppercase();
+    match upper.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => {
+            let bytes = upper.as_bytes();
+            bytes.len() == 4
+                && (upper.starts_with("COM") || upper.starts_with("LPT"))
+                && (b'1'..=b'9').contains(&bytes[3])
+        }
+    }
+}
+
+fn scope() -> &'static RwLock<Scope> {
+    static SCOPE: OnceLock<RwLock<Scope>> = OnceLock::new();
+    SCOPE.get_or_init(|| RwLock::new(Scope::default()))
+}
+
+/// Adds `dir` to the process-wide scope.
+pub fn allow_folder(dir: &Path) -> Result<(), String> {
+    scope()
+        .write()
+        .map_err(|_| "scope lock poisoned".to_string())?
+        .allow(dir)
+}
+
+/// Adds the folder a user-opened `path` belongs to: the path itself for a
+/// folder, its parent for a file.
+pub fn allow_opened(path: &Path) -> Result<(), String> {
+    if path.is_dir() {
+        return allow_folder(path);
+    }
+    let parent = path
+        .parent()
+        .ok_or_else(|| "Path has no parent folder".to_string())?;
+    allow_folder(parent)
+}
+
+/// Checks `path` against the process-wide scope, logging a refusal as one
+/// JSON line on stderr.
+pub fn check(path: &Path) -> Result<(), Rejection> {
+    let result = match scope().read() {
+        Ok(scope) => scope.check(path),
+        Err(_) => Err(Rejection::OutsideRoots),
+    };
+    if let Err(rejection) = result {
+        eprintln!("{}", format_rejection_line(path, rejection));
+    }
+    result
+}
+
+pub fn format_rejection_line(path: &Path, rejection: Rejection) -> String {
+    format!(
+        r#"{{"scope":"reject","reason":"{}","path":{}}}"#,
+        rejection.reason(),
+        serde_json::to_string(&path.to_string_lossy()).unwrap_or_else(|_| "\"?\"".into())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn check_accepts_files_under_a_root_only() {
+        let allowed = create_temp_dir();
+        let other = create_temp_dir();
+        let inside = create_test_jpeg(allowed.path(), "a.jpg");
+        std::fs::create_dir(allowed.path().join("sub")).unwrap();
+        let nested = create_test_jpeg(&allowed.path().join("sub"), "b.jpg");
+        let outside = create_test_jpeg(other.path(), "c.jpg");
+
+        let mut scope = Scope::default();
+        assert_eq!(scope.check(&inside), Err(Rejection::OutsideRoots));
+        scope.allow(allowed.path()).unwrap();
+        assert_eq!(scope.check(&inside), Ok(()));
+        assert_eq!(scope.check(&nested), Ok(()));
+        assert_eq!(scope.check(&outside), Err(Rejection::OutsideRoots));
+        assert_eq!(
+            scope.check(&allowed.path().join("missing.jpg")),
+            Err(Rejection::Unresolvable)
+        );
+    }
+
+    #[test]
+    fn check_rejects_traversal_and_relative_paths() {
+        let allowed = create_temp_dir();
+        std::fs::create_dir(allowed.path().join("sub")).unwrap();
+        let img = create_test_jpeg(allowed.path(), "a.jpg");
+        let mut scope = Scope::default();
+        scope.allow(allowed.path()).unwrap();
+        // Lands back inside the root, but `..` is refused outright.
+        let dotted = allowed.path().join("sub").join("..").join("a.jpg");
+        assert_eq!(scope.check(&dotted), Err(Rejection::Traversal));
+        assert_eq!(
+            scope.check(Path::new("a.jpg")),
+            Err(Rejection::Unresolvable)
+        );
+        assert_eq!(scope.check(&img), Ok(()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_follows_symlinks_out_of_the_root() {
+        let allowed = create_temp_dir();
+        let other = create_temp_dir();
+        let secret = create_test_jpeg(other.path(), "secret.jpg");
+        let link = allowed.path().join("link.jpg");
+        std::os::unix::fs::symlink(&secret, &link).unwrap();
+        let mut scope = Scope::default();
+        scope.allow(allowed.path()).unwrap();
+        assert_eq!(scope.check(&link), Err(Rejection::OutsideRoots));
+    }
+
+    #[test]
+    fn check_rejects_device_paths() {
+        let allowed = create_temp_dir();
+        let mut scope = Scope::default();
+        scope.allow(allowed.path()).unwrap();
+        for name in ["NUL.jpg", "con", "com1.png", "LPT9.jpeg"] {
+            let path = allowed.path().join(name);
+            assert_eq!(scope.check(&path), Err(Rejection::DevicePath), "{name}");
+        }
+        assert_eq!(
+            scope.check(&allowed.path().join("console.jpg")),
+            Err(Rejection::Unresolvable)
+        );
+        assert!(has_device_prefix(Path::new("//server/share/a.jpg")));
+        assert!(has_device_prefix(Path::new(r"\\server\share\a.jpg")));
+        assert!(!has_device_prefix(allowed.path()));
+    }
+
+    #[test]
+    fn allow_keeps_only_the_outermost_roots() {
+        let outer = create_temp_dir();
+        let inner = outer.path().join("inner");
+        std::fs::create_dir(&inner).unwrap();
+        let mut scope = Scope::default();
+        scope.allow(&inner).unwrap();
+        scope.allow(outer.path()).unwrap();
+        scope.allow(&inner).unwrap();
+        assert_eq!(scope.roots.len(), 1);
+        assert!(scope.allow(&outer.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn rejection_line_is_json() {
+        let line = format_rejection_line(Path::new("/a \"b\".jpg"), Rejection::OutsideRoots);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["reason"], "outside_roots");
+        assert_eq!(value["path"], "/a \"b\".jpg");
+    }
+}
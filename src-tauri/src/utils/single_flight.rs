@@ -0,0 +1,204 @@
+//! In-process request coalescing. Callers that ask for the same key while
+//! its work is running wait for that run's result instead of starting their
+//! own; the key is forgotten once the run lands, so later callers (usually
+//! served from the disk cache by then) start afresh.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+enum State<V> {
+    Running,
+    Done(V),
+    /// The leader panicked; waiters retry on their own.
+    Abandoned,
+}
+
+struct Flight<V> {
+    state: Mutex<State<V>>,
+    landed: Condvar,
+}
+
+pub struct SingleFlight<K, V> {
+    flights: Mutex<HashMap<K, Arc<Flight<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        SingleFlight {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    /// Runs `work` for `key`, or waits for the run already in flight and
+    /// returns a clone of its result. The result is cloned once to land and
+    /// once per waiter, so `V` should be cheap to clone: put anything large
+    /// behind an `Arc`.
+    pub fn run(&self, key: K, work: impl FnOnce() -> V) -> V {
+        loop {
+            let (flight, leader) = {
+                let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+                match flights.get(&key) {
+                    Some(flight) => (flight.clone(), false),
+                    None => {
+                        let flight = Arc::new(Flight {
+                            state: Mutex::new(State::Running),
+                            landed: Condvar::new(),
+                        });
+                        flights.insert(key.clone(), flight.clone());
+                        (flight, true)
+                    }
+                }
+            };
+            if leader {
+                let landing = Landing {
+                    owner: self,
+                    key: &key,
+                    flight: &flight,
+                    value: None,
+                };
+                let value = work();
+                landing.land(value.clone());
+                return value;
+            }
+            let mut state = flight.state.lock().unwrap_or_else(PoisonError::into_inner);
+            while matches!(*state, State::Running) {
+                state = flight
+                    .landed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            if let State::Done(value) = &*state {
+                return value.clone();
+            }
+        }
+    }
+
+    /// Callers currently sharing the flight for `key`, leader included.
+    #[cfg(test)]
+    pub fn callers(&self, key: &K) -> usize {
+        let flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+        // The registry holds one reference of its own.
+        flights.get(key).map_or(0, |f| Arc::strong_count(f) - 1)
+    }
+}
+
+/// Ends the leader's flight: with its result, or (dropped while unwinding)
+/// as abandoned so the waiters are not left hanging.
+struct Landing<'a, K: Eq + Hash, V> {
+    owner: &'a SingleFlight<K, V>,
+    key: &'a K,
+    flight: &'a Arc<Flight<V>>,
+    value: Option<V>,
+}
+
+impl<K: Eq + Hash, V> Landing<'_, K, V> {
+    fn land(mut self, value: V) {
+        self.value = Some(value);
+    }
+}
+
+impl<K: Eq + Hash, V> Drop for Landing<'_, K, V> {
+    fn drop(&mut self) {
+        {
+            let mut flights = self
+                .owner
+                .flights
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if flights
+                .get(self.key)
+                .is_some_and(|f| Arc::ptr_eq(f, self.flight))
+            {
+                flights.remove(self.key);
+            }
+        }
+        let mut state = self
+            .flight
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *state = match self.value.take() {
+            Some(value) => State::Done(value),
+            None => State::Abandoned,
+        };
+        self.flight.landed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Blocks until `n` callers share the flight for `key`.
+    fn wait_for_callers<'a>(flights: &SingleFlight<&'a str, u32>, key: &'a str, n: usize) {
+        while flights.callers(&key) < n {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn concurrent_callers_of_one_key_share_one_run() {
+        let flights = SingleFlight::default();
+        let runs = AtomicUsize::new(0);
+        let results = std::thread::scope(|s| {
+            let leader = s.spawn(|| {
+                flights.run("a", || {
+                    // Hold the flight open until every other caller joined it.
+                    wait_for_callers(&flights, "a", 8);
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    7
+                })
+            });
+            wait_for_callers(&flights, "a", 1);
+            let waiters: Vec<_> = (0..7)
+                .map(|_| {
+                    s.spawn(|| {
+                        flights.run("a", || {
+                            runs.fetch_add(1, Ordering::SeqCst);
+                            0
+                        })
+                    })
+                })
+                .collect();
+            let mut results = vec![leader.join().unwrap()];
+            results.extend(waiters.into_iter().map(|w| w.join().unwrap()));
+            results
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(results, [7; 8]);
+        // Landed: the next caller runs the work again.
+        assert_eq!(flights.callers(&"a"), 0);
+        assert_eq!(flights.run("a", || 9), 9);
+    }
+
+    #[test]
+    fn different_keys_do_not_wait_for_each_other() {
+        let flights = SingleFlight::default();
+        let inner = flights.run("a", || flights.run("b", || 2) + 1);
+        assert_eq!(inner, 3);
+    }
+
+    #[test]
+    fn waiters_of_a_panicking_run_do_the_work_themselves() {
+        let flights = SingleFlight::default();
+        let retried = std::thread::scope(|s| {
+            let leader = s.spawn(|| {
+                flights.run("a", || {
+                    wait_for_callers(&flights, "a", 2);
+                    panic!("decoder blew up");
+                })
+            });
+            wait_for_callers(&flights, "a", 1);
+            let waiter = s.spawn(|| flights.run("a", || 5));
+            assert!(leader.join().is_err());
+            waiter.join().unwrap()
+        });
+        assert_eq!(retried, 5);
+        assert_eq!(flights.callers(&"a"), 0);
+    }
+}
@@ -0,0 +1,195 @@
+//! Folder list ordering. Names compare "naturally" (digit runs by value,
+//! letters case-insensitively) so `IMG_2.jpg` comes before `IMG_10.jpg`;
+//! every other key falls back to that name order on ties.
+
+use crate::commands::file::ImageInfo;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Modified,
+    Size,
+    Format,
+    /// EXIF DateTimeOriginal, or the modification time when there is none.
+    DateTaken,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SortSpec {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+pub fn sort_images(images: &mut [ImageInfo], spec: SortSpec) {
+    images.sort_by(|a, b| {
+        let primary = match spec.key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Modified => a.modified.cmp(&b.modified),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Format => a.format.cmp(&b.format),
+            SortKey::DateTaken => a
+                .date_taken
+                .unwrap_or(a.modified)
+                .cmp(&b.date_taken.unwrap_or(b.modified)),
+        };
+        let order = primary.then_with(|| natural_cmp(&a.filename, &b.filename));
+        if spec.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+}
+
+/// Natural order: runs of ASCII digits compare by numeric value (any
+/// length), everything else by lower-cased character. Names that only differ
+/// in case or leading zeros fall back to plain comparison, so the order is
+/// total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let (c, d) = match (x.peek(), y.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&c), Some(&d)) => (c, d),
+        };
+        let order = if c.is_ascii_digit() && d.is_ascii_digit() {
+            let (n, m) = (digit_run(&mut x), digit_run(&mut y));
+            let (n, m) = (n.trim_start_matches('0'), m.trim_start_matches('0'));
+            n.len().cmp(&m.len()).then_with(|| n.cmp(m))
+        } else {
+            x.next();
+            y.next();
+            c.to_lowercase().cmp(d.to_lowercase())
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+fn digit_run(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(filename: &str, modified: u64, size: u64, format: &str) -> ImageInfo {
+        ImageInfo {
+            path: format!("/photos/{filename}"),
+            filename: filename.to_string(),
+            size,
+            modified,
+            format: format.to_string(),
+            format_mismatch: None,
+            date_taken: None,
+        }
+    }
+
+    fn names(images: &[ImageInfo]) -> Vec<&str> {
+        images.iter().map(|i| i.filename.as_str()).collect()
+    }
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        let mut v = vec![
+            "IMG_10.jpg",
+            "img_2.jpg",
+            "IMG_1.jpg",
+            "IMG_002.jpg",
+            "IMG_10a.jpg",
+            "IMG_9.jpg",
+            "IMG.jpg",
+            "IMG_99999999999999999999999.jpg",
+        ];
+        v.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            v,
+            [
+                "IMG.jpg",
+                "IMG_1.jpg",
+                "IMG_002.jpg",
+                "img_2.jpg",
+                "IMG_9.jpg",
+                "IMG_10.jpg",
+                "IMG_10a.jpg",
+                "IMG_99999999999999999999999.jpg",
+            ]
+        );
+        assert_eq!(natural_cmp("a.jpg", "a.jpg"), Ordering::Equal);
+        assert_ne!(natural_cmp("A.jpg", "a.jpg"), Ordering::Equal);
+    }
+
+    #[test]
+    fn sort_images_by_each_key_and_direction() {
+        let base = vec![
+            info("IMG_10.jpg", 300, 10, "jpeg"),
+            info("IMG_2.png", 100, 30, "png"),
+            info("IMG_3.jpg", 200, 20, "jpeg"),
+        ];
+        let sorted = |key, descending| {
+            let mut v = base.clone();
+            sort_images(&mut v, SortSpec { key, descending });
+            names(&v).join(" ")
+        };
+        assert_eq!(
+            sorted(SortKey::Name, false),
+            "IMG_2.png IMG_3.jpg IMG_10.jpg"
+        );
+        assert_eq!(
+            sorted(SortKey::Name, true),
+            "IMG_10.jpg IMG_3.jpg IMG_2.png"
+        );
+        assert_eq!(
+            sorted(SortKey::Modified, false),
+            "IMG_2.png IMG_3.jpg IMG_10.jpg"
+        );
+        assert_eq!(
+            sorted(SortKey::Size, true),
+            "IMG_2.png IMG_3.jpg IMG_10.jpg"
+        );
+        assert_eq!(
+            sorted(SortKey::Format, false),
+            "IMG_3.jpg IMG_10.jpg IMG_2.png"
+        );
+    }
+
+    #[test]
+    fn date_taken_falls_back_to_modified() {
+        // Two cameras' shots interleave by capture time, not by name.
+        let mut v = vec![
+            info("A_0001.jpg", 0, 1, "jpeg"),
+            info("A_0002.jpg", 0, 1, "jpeg"),
+            info("B_0001.jpg", 0, 1, "jpeg"),
+            info("no_exif.png", 150, 1, "png"),
+        ];
+        v[0].date_taken = Some(100);
+        v[1].date_taken = Some(200);
+        v[2].date_taken = Some(120);
+        sort_images(
+            &mut v,
+            SortSpec {
+                key: SortKey::DateTaken,
+                descending: false,
+            },
+        );
+        assert_eq!(
+            names(&v),
+            ["A_0001.jpg", "B_0001.jpg", "no_exif.png", "A_0002.jpg"]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Minimal TIFF/IFD reader. Camera RAW files (CR2/NEF/ARW/DNG) are TIFF
+//! containers and Exif is a TIFF structure too; this reads just enough of one
+//! (IFD chains, SHORT/LONG values) to locate tags and embedded JPEGs without
+//! pulling a whole multi-megabyte file into memory.
+
+use std::io::{Read, Seek, SeekFrom};
+
+pub const TAG_COMPRESSION: u16 = 0x0103;
+pub const TAG_STRIP_OFFSETS: u16 = 0x0111;
+pub const TAG_ORIENTATION: u16 = 0x0112;
+pub const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+pub const TAG_SUB_IFDS: u16 = 0x014A;
+pub const TAG_JPEG_OFFSET: u16 = 0x0201;
+pub const TAG_JPEG_LENGTH: u16 = 0x0202;
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+pub const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+
+/// Upper bound on entries per IFD and values per tag — real files stay far
+/// below this, and it keeps a corrupt count from allocating gigabytes.
+const MAX_ENTRIES: u16 = 1024;
+const MAX_VALUES: u32 = 4096;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+pub const TYPE_SHORT: u16 = 3;
+pub const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+const TYPE_IFD: u16 = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub tag: u16,
+    pub typ: u16,
+    pub count: u32,
+    /// The raw 4-byte value/offset field, in file byte order.
+    raw: [u8; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ifd {
+    pub entries: Vec<Entry>,
+    /// Offset of the next IFD in the chain (0 = none), relative to the header.
+    pub next: u32,
+}
+
+impl Ifd {
+    pub fn get(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+}
+
+pub struct TiffReader<R> {
+    src: R,
+    /// Absolute position of the "II*\0"/"MM\0*" header; every offset inside
+    /// the structure is relative to it (non-zero for Exif inside a JPEG).
+    base: u64,
+    little_endian: bool,
+}
+
+impl<R: Read + Seek> TiffReader<R> {
+    /// Checks the byte-order mark and magic number at `base`.
+    pub fn new(mut src: R, base: u64) -> Option<Self> {
+        let mut header = [0u8; 4];
+        src.seek(SeekFrom::Start(base)).ok()?;
+        src.read_exact(&mut header).ok()?;
+        let little_endian = match &header {
+            b"II\x2A\x00" => true,
+            b"MM\x00\x2A" => false,
+            _ => return None,
+        };
+        Some(Self {
+            src,
+            base,
+            little_endian,
+        })
+    }
+
+    pub fn first_ifd_offset(&mut self) -> Option<u32> {
+        let b = self.read_at(4, 4)?;
+        Some(self.u32(&b))
+    }
+
+    pub fn read_ifd(&mut self, offset: u32) -> Option<Ifd> {
+        let count = {
+            let b = self.read_at(u64::from(offset), 2)?;
+            self.u16(&b)
+        };
+        if count == 0 || count > MAX_ENTRIES {
+            return None;
+        }
+        let body = self.read_at(u64::from(offset) + 2, usize::from(count) * 12 + 4)?;
+        let entries = body
+            .chunks_exact(12)
+            .map(|c| Entry {
+                tag: self.u16(&c[0..2]),
+                typ: self.u16(&c[2..4]),
+                count: self.u32(&c[4..8]),
+                raw: [c[8], c[9], c[10], c[11]],
+            })
+            .collect();
+        let next = self.u32(&body[usize::from(count) * 12..]);
+        Some(Ifd { entries, next })
+    }
+
+    /// SHORT/LONG/IFD values of `entry` widened to u32; other types → None.
+    pub fn values(&mut self, entry: &Entry) -> Option<Vec<u32>> {
+        let width = match entry.typ {
+            TYPE_SHORT => 2usize,
+            TYPE_LONG | TYPE_IFD => 4,
+            _ => return None,
+        };
+        if entry.count == 0 || entry.count > MAX_VALUES {
+            return None;
+        }
+        let len = width * entry.count as usize;
+        let bytes = if len <= 4 {
+            entry.raw[..len].to_vec()
+        } else {
+            let offset = self.u32(&entry.raw);
+            self.read_at(u64::from(offset), len)?
+        };
+        Some(
+            bytes
+                .chunks_exact(width)
+                .map(|c| match width {
+                    2 => u32::from(self.u16(c)),
+                    _ => self.u32(c),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn value(&mut self, entry: &Entry) -> Option<u32> {
+        self.values(entry)?.first().copied()
+    }
+
+    /// BYTE/ASCII/UNDEFINED payload of `entry` as stored (ASCII keeps its
+    /// NUL); other types → None.
+    pub fn bytes(&mut self, entry: &Entry) -> Option<Vec<u8>> {
+        if !matches!(entry.typ, TYPE_BYTE | TYPE_ASCII | TYPE_UNDEFINED) || entry.count > MAX_VALUES
+        {
+            return None;
+        }
+        let len = entry.count as usize;
+        if len <= 4 {
+            Some(entry.raw[..len].to_vec())
+        } else {
+            let offset = self.u32(&entry.raw);
+            self.read_at(u64::from(offset), len)
+        }
+    }
+
+    pub fn is_little_endian(&self) -> bool {
+        self.little_endian
+    }
+
+    pub fn into_inner(self) -> R {
+        self.src
+    }
+
+    /// Reads `len` bytes at `offset` relative to the TIFF header.
+    pub fn read_at(&mut self, offset: u64, len: usize) -> Option<Vec<u8>> {
+        read_exact_at(&mut self.src, self.base + offset, len)
+    }
+
+    fn u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        if self.little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        }
+    }
+
+    fn u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        }
+    }
+}
+
+/// `len` bytes at absolute position `pos`, or None when the source is
+/// shorter. `len` is checked against the stream length first so a corrupt
+/// offset/count pair cannot trigger a huge allocation.
+pub fn read_exact_at<R: Read + Seek>(src: &mut R, pos: u64, len: usize) -> Option<Vec<u8>> {
+    let end = src.seek(SeekFrom::End(0)).ok()?;
+    if pos.checked_add(len as u64)? > end {
+        return None;
+    }
+    src.seek(SeekFrom::Start(pos)).ok()?;
+    let mut buf = vec![0u8; len];
+    src.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// IFD0 with Orientation=8 and a two-value LONG array stored out of line.
+    fn sample(little_endian: bool) -> Vec<u8> {
+        let u16b = |v: u16| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let u32b = |v: u32| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let mut v = Vec::new();
+        v.extend_from_slice(if little_endian {
+            b"II\x2A\x00"
+        } else {
+            b"MM\x00\x2A"
+        });
+        v.extend_from_slice(&u32b(8));
+        v.extend_from_slice(&u16b(2));
+        // Orientation SHORT 1 = 8 (inline, left-justified)
+        v.extend_from_slice(&u16b(TAG_ORIENTATION));
+        v.extend_from_slice(&u16b(3));
+        v.extend_from_slice(&u32b(1));
+        v.extend_from_slice(&u16b(8));
+        v.extend_from_slice(&[0, 0]);
+        // StripOffsets LONG 2 → out of line at 38
+        v.extend_from_slice(&u16b(TAG_STRIP_OFFSETS));
+        v.extend_from_slice(&u16b(4));
+        v.extend_from_slice(&u32b(2));
+        v.extend_from_slice(&u32b(38));
+        v.extend_from_slice(&u32b(0)); // next IFD
+        v.extend_from_slice(&u32b(1000));
+        v.extend_from_slice(&u32b(2000));
+        v
+    }
+
+    #[test]
+    fn reads_entries_in_both_byte_orders() {
+        for le in [true, false] {
+            let mut t = TiffReader::new(Cursor::new(sample(le)), 0).unwrap();
+            let off = t.first_ifd_offset().unwrap();
+            let ifd = t.read_ifd(off).unwrap();
+            assert_eq!(ifd.next, 0);
+            let o = *ifd.get(TAG_ORIENTATION).unwrap();
+            assert_eq!(t.value(&o), Some(8));
+            let s = *ifd.get(TAG_STRIP_OFFSETS).unwrap();
+            assert_eq!(t.values(&s), Some(vec![1000, 2000]));
+        }
+    }
+
+    #[test]
+    fn honours_a_non_zero_base() {
+        let mut data = b"Exif\0\0".to_vec();
+        data.extend(sample(true));
+        let mut t = TiffReader::new(Cursor::new(data), 6).unwrap();
+        let off = t.first_ifd_offset().unwrap();
+        let ifd = t.read_ifd(off).unwrap();
+        let s = *ifd.get(TAG_STRIP_OFFSETS).unwrap();
+        assert_eq!(t.values(&s), Some(vec![1000, 2000]));
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_out_of_range_reads() {
+        assert!(TiffReader::new(Cursor::new(b"PK\x03\x04....".to_vec()), 0).is_none());
+        let mut data = sample(true);
+        data.truncate(40); // cut the out-of-line LONG array short
+        let mut t = TiffReader::new(Cursor::new(data), 0).unwrap();
+        let ifd = t.read_ifd(8).unwrap();
+        let s = *ifd.get(TAG_STRIP_OFFSETS).unwrap();
+        assert_eq!(t.values(&s), None);
+        assert!(t.read_ifd(4000).is_none());
+    }
+}
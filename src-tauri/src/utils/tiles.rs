@@ -0,0 +1,234 @@
+//! Deep-zoom tile pyramid for images too large to send whole. Level 0 is the
+//! full-resolution image; each level above halves the one below, up to the
+//! first level that fits in a single tile. Tile (x, y) of a level covers
+//! pixels `[x * TILE_SIZE, (x + 1) * TILE_SIZE)` of that level, clipped at
+//! the right and bottom edges.
+//!
+//! Tiles are rendered on demand a row band at a time: the first tile asked
+//! for in row y of a level renders that row and nothing else, and the row's
+//! tiles are cached like previews. A PNG is streamed, so only the band's
+//! source rows are held; other sources have no region decode, so the band
+//! is cut from a whole-frame decode, reduced on the way in where the
+//! decoder can (a JPEG's DCT scaling), and the rest dropped.
+
+use crate::utils::encode::{self, ChromaSubsampling};
+use crate::utils::limits::DecodeError;
+use crate::utils::preview::{self, PREVIEW_JPEG_QUALITY};
+use image::RgbImage;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::path::Path;
+
+/// Edge of a full tile, in pixels of its level. Mirrored in
+/// `src/constants/memory.ts`.
+pub const TILE_SIZE: u32 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileId {
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileId {
+    /// Parses "<level>/<x>_<y>".
+    pub fn parse(s: &str) -> Option<TileId> {
+        let (level, xy) = s.split_once('/')?;
+        let (x, y) = xy.split_once('_')?;
+        Some(TileId {
+            level: level.parse().ok()?,
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+        })
+    }
+
+    /// Cache key; tiles share the preview cache (sidecar, stamp, sweep and
+    /// cap) under keys no preview box can take.
+    pub fn key(&self) -> String {
+        format!("tile-{}-{}_{}", self.level, self.x, self.y)
+    }
+}
+
+/// Size of the whole image at `level` (never below 1x1).
+pub fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    let scale = 1u64 << level.min(32);
+    let shrink = |d: u32| (u64::from(d).div_ceil(scale) as u32).max(1);
+    (shrink(width), shrink(height))
+}
+
+/// Highest level: the first at which the whole image fits in one tile.
+pub fn max_level(width: u32, height: u32) -> u32 {
+    let mut level = 0;
+    loop {
+        let (w, h) = level_size(width, height, level);
+        if w.max(h) <= TILE_SIZE {
+            return level;
+        }
+        level += 1;
+    }
+}
+
+/// Whether tile `id` exists in the pyramid of a `width`x`height` image.
+pub fn in_pyramid(width: u32, height: u32, id: TileId) -> bool {
+    let (lw, lh) = level_size(width, height, id.level);
+    id.level <= max_level(width, height)
+        && u64::from(id.x) * u64::from(TILE_SIZE) < u64::from(lw)
+        && u64::from(id.y) * u64::from(TILE_SIZE) < u64::from(lh)
+}
+
+/// Rows of `level` covered by tile row `y`, or `None` when the row is
+/// outside the pyramid of a `width`x`height` image.
+pub fn band_rows(width: u32, height: u32, level: u32, y: u32) -> Option<Range<u32>> {
+    if !in_pyramid(width, height, TileId { level, x: 0, y }) {
+        return None;
+    }
+    let (_, lh) = level_size(width, height, level);
+    let top = y * TILE_SIZE;
+    Some(top..lh.min(top + TILE_SIZE))
+}
+
+pub struct RenderedTile {
+    pub id: TileId,
+    pub jpeg: Vec<u8>,
+    /// Orientation-applied size of the whole image.
+    pub natural_width: u32,
+    pub natural_height: u32,
+}
+
+/// Renders the tiles of row `y` of `level` of `path`, handing each to
+/// `emit`. `Ok(false)` when the row is outside the pyramid. `path` must
+/// already be validated.
+pub fn render_band(
+    path: &Path,
+    level: u32,
+    y: u32,
+    emit: impl FnMut(RenderedTile) -> Result<(), String>,
+) -> Result<bool, DecodeError> {
+    let Some(preview::LevelRgb {
+        image: band,
+        icc,
+        natural: (width, height),
+    }) = preview::decode_band_rgb(path, level, y)?
+    else {
+        return Ok(false);
+    };
+    let tiles = (0..band.width().div_ceil(TILE_SIZE))
+        .into_par_iter()
+        .map(|x| {
+            let id = TileId { level, x, y };
+            encode_tile(&band, x, icc.as_deref()).map(|jpeg| RenderedTile {
+                id,
+                jpeg,
+                natural_width: width,
+                natural_height: height,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    tiles.into_iter().try_for_each(emit)?;
+    Ok(true)
+}
+
+/// Tile `x` cut from `band`, the whole of its row.
+fn encode_tile(band: &RgbImage, x: u32, icc: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let x0 = x * TILE_SIZE;
+    let tw = TILE_SIZE.min(band.width() - x0);
+    let tile = image::imageops::crop_imm(band, x0, 0, tw, band.height()).to_image();
+    encode::encode_jpeg(&tile, PREVIEW_JPEG_QUALITY, ChromaSubsampling::Yuv420, icc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn parse_and_key() {
+        let id = TileId::parse("2/3_4").unwrap();
+        assert_eq!(
+            id,
+            TileId {
+                level: 2,
+                x: 3,
+                y: 4
+            }
+        );
+        assert_eq!(id.key(), "tile-2-3_4");
+        assert_eq!(TileId::parse("2/3-4"), None);
+        assert_eq!(TileId::parse("x/3_4"), None);
+    }
+
+    #[test]
+    fn pyramid_geometry() {
+        assert_eq!(max_level(512, 300), 0);
+        assert_eq!(max_level(20_000, 20_000), 6);
+        assert_eq!(level_size(1025, 3, 1), (513, 2));
+        assert_eq!(level_size(1025, 3, 2), (257, 1));
+
+        // Level 1 of a 1300x600 image is 650x300: two columns, one row.
+        assert!(in_pyramid(1300, 600, TileId::parse("1/1_0").unwrap()));
+        assert!(!in_pyramid(1300, 600, TileId::parse("1/2_0").unwrap()));
+        assert!(!in_pyramid(1300, 600, TileId::parse("1/0_1").unwrap()));
+        assert!(!in_pyramid(1300, 600, TileId::parse("3/0_0").unwrap()));
+        assert!(!in_pyramid(
+            1300,
+            600,
+            TileId::parse(&format!("0/{}_0", u32::MAX)).unwrap()
+        ));
+    }
+
+    fn render(path: &Path, level: u32, y: u32) -> (bool, Vec<RenderedTile>) {
+        let mut tiles = Vec::new();
+        let rendered = render_band(path, level, y, |tile| {
+            tiles.push(tile);
+            Ok(())
+        })
+        .unwrap();
+        (rendered, tiles)
+    }
+
+    fn tile_size(tiles: &[RenderedTile], id: &str) -> (u32, u32) {
+        let id = TileId::parse(id).unwrap();
+        let tile = tiles.iter().find(|t| t.id == id).unwrap();
+        let decoded = image::load_from_memory(&tile.jpeg).unwrap();
+        (decoded.width(), decoded.height())
+    }
+
+    #[test]
+    fn band_rows_clip_at_the_bottom_edge() {
+        assert_eq!(band_rows(1300, 600, 0, 0), Some(0..512));
+        assert_eq!(band_rows(1300, 600, 0, 1), Some(512..600));
+        assert_eq!(band_rows(1300, 600, 1, 0), Some(0..300));
+        assert_eq!(band_rows(1300, 600, 1, 1), None);
+        assert_eq!(band_rows(1300, 600, 3, 0), None);
+    }
+
+    #[test]
+    fn render_band_cuts_only_the_tiles_of_its_row() {
+        let dir = create_temp_dir();
+        let img = create_gradient_jpeg(dir.path(), "big.jpg", 1300, 600);
+
+        // Level 0 is 3x2 tiles; its bottom row is three tiles, 88px high.
+        let (rendered, tiles) = render(&img, 0, 1);
+        assert!(rendered);
+        let ids: Vec<_> = tiles.iter().map(|t| t.id.key()).collect();
+        assert_eq!(ids, ["tile-0-0_1", "tile-0-1_1", "tile-0-2_1"]);
+        assert!(tiles
+            .iter()
+            .all(|t| (t.natural_width, t.natural_height) == (1300, 600)));
+        assert_eq!(tile_size(&tiles, "0/2_1"), (276, 88));
+
+        // Higher levels decode reduced and are cut to their own size.
+        let (_, tiles) = render(&img, 1, 0);
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(tile_size(&tiles, "1/0_0"), (512, 300));
+        assert_eq!(tile_size(&tiles, "1/1_0"), (138, 300));
+        let (_, tiles) = render(&img, 2, 0);
+        assert_eq!(tile_size(&tiles, "2/0_0"), (325, 150));
+
+        for (level, y) in [(1, 1), (3, 0)] {
+            let (rendered, tiles) = render(&img, level, y);
+            assert!(!rendered);
+            assert!(tiles.is_empty());
+        }
+    }
+}
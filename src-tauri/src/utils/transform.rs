@@ -0,0 +1,505 @@
+//! Permanent rotate/flip written back to the source file. JPEGs are never
+//! re-encoded: the transform is folded into the Exif Orientation tag, which
+//! every decoder Spica uses (and the WebView) already honours. PNG and WebP
+//! have no such escape hatch in practice, so they are decoded, transformed
+//! and re-encoded losslessly; a lossy WebP only with the caller's opt-in,
+//! since its compressed form cannot be kept and the file grows several-fold.
+
+use crate::commands::cache::write_atomic;
+use crate::utils::format::{self, SourceFormat};
+use crate::utils::limits;
+use crate::utils::raw::exif_orientation_segment;
+use crate::utils::tiff::{self, TiffReader};
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageEncoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// A user-requested edit. Rotations are clockwise, as seen on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Transform {
+    fn orientation(self) -> Orientation {
+        match self {
+            Self::Rotate90 => Orientation::Rotate90,
+            Self::Rotate180 => Orientation::Rotate180,
+            Self::Rotate270 => Orientation::Rotate270,
+            Self::FlipHorizontal => Orientation::FlipHorizontal,
+            Self::FlipVertical => Orientation::FlipVertical,
+        }
+    }
+}
+
+/// How the file was rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformMethod {
+    /// Only the Exif Orientation tag changed; pixel data is byte-identical.
+    ExifOrientation,
+    /// Decoded, transformed and written back as lossless PNG/WebP.
+    Reencoded,
+}
+
+/// Orientation as `(k, flip)`: mirror horizontally if `flip`, then rotate
+/// `k` quarter turns clockwise. Every orientation has exactly one such form.
+fn quarter_turns(o: Orientation) -> (u8, bool) {
+    match o {
+        Orientation::NoTransforms => (0, false),
+        Orientation::Rotate90 => (1, false),
+        Orientation::Rotate180 => (2, false),
+        Orientation::Rotate270 => (3, false),
+        Orientation::FlipHorizontal => (0, true),
+        Orientation::FlipVertical => (2, true),
+        // `image` applies these as rotate-then-flip; a mirror reverses the
+        // direction of the rotation it is moved past.
+        Orientation::Rotate90FlipH => (3, true),
+        Orientation::Rotate270FlipH => (1, true),
+    }
+}
+
+fn from_quarter_turns(k: u8, flip: bool) -> Orientation {
+    match (k % 4, flip) {
+        (0, false) => Orientation::NoTransforms,
+        (1, false) => Orientation::Rotate90,
+        (2, false) => Orientation::Rotate180,
+        (3, false) => Orientation::Rotate270,
+        (0, true) => Orientation::FlipHorizontal,
+        (2, true) => Orientation::FlipVertical,
+        (3, true) => Orientation::Rotate90FlipH,
+        _ => Orientation::Rotate270FlipH,
+    }
+}
+
+/// The orientation that displays like `current` followed by `t`.
+pub fn compose(current: Orientation, t: Transform) -> Orientation {
+    let (k1, f1) = quarter_turns(current);
+    let (k2, f2) = quarter_turns(t.orientation());
+    // R^k2 F^f2 R^k1 F^f1 = R^(k2 ± k1) F^(f1 ^ f2), with − when f2 mirrors.
+    let k = if f2 { k2 + 4 - k1 } else { k2 + k1 };
+    from_quarter_turns(k, f1 ^ f2)
+}
+
+/// Applies `t` to the image at `path` and atomically replaces the file.
+pub fn transform_file(
+    path: &Path,
+    t: Transform,
+    allow_lossy: bool,
+) -> Result<TransformMethod, String> {
+    let format = format::detect(path)
+        .map(|d| d.format)
+        .ok_or_else(|| "Unsupported file format".to_string())?;
+    let bytes = fs::read(path).map_err(|e| format!("read: {e}"))?;
+    let (out, method) = match format {
+        SourceFormat::Jpeg => (transform_jpeg(&bytes, t)?, TransformMethod::ExifOrientation),
+        SourceFormat::Png => {
+            if has_chunk_png(&bytes, b"acTL") {
+                return Err("animated PNG cannot be transformed".to_string());
+            }
+            (reencode(&bytes, format, t)?, TransformMethod::Reencoded)
+        }
+        SourceFormat::WebP => {
+            if has_chunk_webp(&bytes, b"ANIM") {
+                return Err("animated WebP cannot be transformed".to_string());
+            }
+            if has_chunk_webp(&bytes, b"VP8 ") && !allow_lossy {
+                return Err(
+                    "lossy WebP must be re-encoded; pass allow_lossy to confirm".to_string()
+                );
+            }
+            (reencode(&bytes, format, t)?, TransformMethod::Reencoded)
+        }
+        other => return Err(format!("{} cannot be transformed", other.name())),
+    };
+    write_atomic(path, &out).map_err(|e| format!("write: {e}"))?;
+    Ok(method)
+}
+
+/// `jpeg` with `t` folded into its Exif orientation. The Exif APP1 is edited
+/// in place when it has an Orientation tag, extended when it lacks one, and
+/// created right after SOI/JFIF when there is none; every other byte is kept.
+pub fn transform_jpeg(jpeg: &[u8], t: Transform) -> Result<Vec<u8>, String> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err("not a JPEG file".to_string());
+    }
+    let mut insert_at = 2;
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        if marker == 0xDA {
+            break;
+        }
+        let seg_len = usize::from(u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]));
+        let end = pos + 2 + seg_len;
+        if seg_len < 2 || end > jpeg.len() {
+            return Err("corrupt JPEG marker segment".to_string());
+        }
+        if marker == 0xE0 && pos == 2 {
+            insert_at = end;
+        }
+        if marker == 0xE1 && jpeg[pos + 4..end].starts_with(b"Exif\0\0") {
+            let mut tiff = jpeg[pos + 10..end].to_vec();
+            let current = tiff_orientation(&tiff)
+                .and_then(|o| Orientation::from_exif(u8::try_from(o).ok()?))
+                .unwrap_or(Orientation::NoTransforms);
+            set_tiff_orientation(&mut tiff, u16::from(compose(current, t).to_exif()))
+                .ok_or_else(|| "Exif orientation could not be rewritten".to_string())?;
+            let len = u16::try_from(2 + 6 + tiff.len())
+                .map_err(|_| "Exif segment too large to rewrite".to_string())?;
+            let mut out = Vec::with_capacity(jpeg.len() + 16);
+            out.extend_from_slice(&jpeg[..pos]);
+            out.extend_from_slice(&[0xFF, 0xE1]);
+            out.extend_from_slice(&len.to_be_bytes());
+            out.extend_from_slice(b"Exif\0\0");
+            out.extend(tiff);
+            out.extend_from_slice(&jpeg[end..]);
+            return Ok(out);
+        }
+        pos = end;
+    }
+    let orientation = compose(Orientation::NoTransforms, t).to_exif();
+    let mut out = Vec::with_capacity(jpeg.len() + 40);
+    out.extend_from_slice(&jpeg[..insert_at]);
+    out.extend(exif_orientation_segment(orientation));
+    out.extend_from_slice(&jpeg[insert_at..]);
+    Ok(out)
+}
+
+/// IFD0's Orientation value in a bare TIFF/Exif blob.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let mut t = TiffReader::new(Cursor::new(tiff), 0)?;
+    let ifd0 = t.first_ifd_offset()?;
+    let ifd = t.read_ifd(ifd0)?;
+    let entry = *ifd.get(tiff::TAG_ORIENTATION)?;
+    u16::try_from(t.value(&entry)?).ok()
+}
+
+/// Sets IFD0's Orientation in a bare TIFF/Exif blob. An existing inline value
+/// is overwritten; otherwise IFD0 is copied to the end of the blob with the
+/// tag added in sorted position and the header repointed at the copy. Every
+/// other offset in the blob stays valid because nothing before the end moves.
+fn set_tiff_orientation(tiff: &mut Vec<u8>, value: u16) -> Option<()> {
+    let (ifd0, ifd, little_endian) = {
+        let mut t = TiffReader::new(Cursor::new(tiff.as_slice()), 0)?;
+        let offset = t.first_ifd_offset()?;
+        let ifd = t.read_ifd(offset)?;
+        (offset as usize, ifd, t.is_little_endian())
+    };
+    let u16b = |v: u16| {
+        if little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    };
+    let u32b = |v: u32| {
+        if little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    };
+    if let Some(i) = ifd
+        .entries
+        .iter()
+        .position(|e| e.tag == tiff::TAG_ORIENTATION)
+    {
+        let entry = ifd.entries[i];
+        let at = ifd0 + 2 + i * 12 + 8;
+        match (entry.typ, entry.count) {
+            (tiff::TYPE_SHORT, 1) => tiff[at..at + 2].copy_from_slice(&u16b(value)),
+            (tiff::TYPE_LONG, 1) => tiff[at..at + 4].copy_from_slice(&u32b(u32::from(value))),
+            _ => return None,
+        }
+        return Some(());
+    }
+
+    let mut record = Vec::with_capacity(12);
+    record.extend_from_slice(&u16b(tiff::TAG_ORIENTATION));
+    record.extend_from_slice(&u16b(tiff::TYPE_SHORT));
+    record.extend_from_slice(&u32b(1));
+    record.extend_from_slice(&u16b(value));
+    record.extend_from_slice(&[0, 0]);
+    let split = ifd
+        .entries
+        .iter()
+        .take_while(|e| e.tag < tiff::TAG_ORIENTATION)
+        .count();
+    let entries = &tiff[ifd0 + 2..ifd0 + 2 + ifd.entries.len() * 12];
+    let mut copy = Vec::with_capacity(entries.len() + 18);
+    copy.extend_from_slice(&u16b(u16::try_from(ifd.entries.len() + 1).ok()?));
+    copy.extend_from_slice(&entries[..split * 12]);
+    copy.extend(record);
+    copy.extend_from_slice(&entries[split * 12..]);
+    copy.extend_from_slice(&u32b(ifd.next));
+
+    // IFDs start on a word boundary.
+    if tiff.len() % 2 == 1 {
+        tiff.push(0);
+    }
+    let new_offset = u32::try_from(tiff.len()).ok()?;
+    tiff.extend(copy);
+    tiff[4..8].copy_from_slice(&u32b(new_offset));
+    Some(())
+}
+
+/// Decodes `bytes`, bakes the current orientation and `t` into the pixels
+/// and encodes losslessly in the same format. The ICC profile and Exif carry
+/// over (with Orientation reset to 1); XMP and text chunks do not.
+fn reencode(bytes: &[u8], format: SourceFormat, t: Transform) -> Result<Vec<u8>, String> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("format: {e}"))?
+        .into_decoder()
+        .map_err(|e| format!("decoder: {e}"))?;
+    limits::current().apply(&mut decoder)?;
+    let current = decoder
+        .orientation()
+        .map_err(|e| format!("orientation: {e}"))?;
+    let icc = decoder.icc_profile().map_err(|e| format!("icc: {e}"))?;
+    let exif = decoder
+        .exif_metadata()
+        .map_err(|e| format!("exif: {e}"))?
+        .and_then(|mut exif| {
+            if tiff_orientation(&exif).is_some() {
+                set_tiff_orientation(&mut exif, 1)?;
+            }
+            Some(exif)
+        });
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("decode: {e}"))?;
+    image.apply_orientation(compose(current, t));
+
+    let mut out = Vec::new();
+    let result = match format {
+        SourceFormat::Png => {
+            let mut encoder = PngEncoder::new(&mut out);
+            attach_metadata(&mut encoder, icc, exif);
+            image.write_with_encoder(encoder)
+        }
+        _ => {
+            let mut encoder = WebPEncoder::new_lossless(&mut out);
+            attach_metadata(&mut encoder, icc, exif);
+            image.write_with_encoder(encoder)
+        }
+    };
+    result.map_err(|e| format!("encode: {e}"))?;
+    Ok(out)
+}
+
+fn attach_metadata(encoder: &mut impl ImageEncoder, icc: Option<Vec<u8>>, exif: Option<Vec<u8>>) {
+    // Both encoders accept both; a rejection would only lose metadata.
+    if let Some(icc) = icc {
+        let _ = encoder.set_icc_profile(icc);
+    }
+    if let Some(exif) = exif {
+        let _ = encoder.set_exif_metadata(exif);
+    }
+}
+
+/// Whether a PNG has a chunk of type `typ` before its image data.
+fn has_chunk_png(bytes: &[u8], typ: &[u8; 4]) -> bool {
+    let mut pos = 8;
+    while pos + 8 <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+        let kind = &bytes[pos + 4..pos + 8];
+        if kind == typ {
+            return true;
+        }
+        if kind == b"IDAT" {
+            return false;
+        }
+        pos += 12 + len as usize;
+    }
+    false
+}
+
+/// Whether a RIFF/WebP file has a chunk of type `typ`.
+fn has_chunk_webp(bytes: &[u8], typ: &[u8; 4]) -> bool {
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        if &bytes[pos..pos + 4] == typ {
+            return true;
+        }
+        let len = u32::from_le_bytes([
+            bytes[pos + 4],
+            bytes[pos + 5],
+            bytes[pos + 6],
+            bytes[pos + 7],
+        ]);
+        // Chunks are padded to an even length.
+        pos += 8 + len as usize + (len as usize & 1);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use image::{GenericImageView, RgbImage};
+
+    const ALL: [Orientation; 8] = [
+        Orientation::NoTransforms,
+        Orientation::Rotate90,
+        Orientation::Rotate180,
+        Orientation::Rotate270,
+        Orientation::FlipHorizontal,
+        Orientation::FlipVertical,
+        Orientation::Rotate90FlipH,
+        Orientation::Rotate270FlipH,
+    ];
+    const TRANSFORMS: [Transform; 5] = [
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+    ];
+
+    /// 3x2 with every pixel distinct, so any two orientations differ.
+    fn asymmetric() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| {
+            image::Rgb([x as u8 * 80, y as u8 * 120, 7])
+        }))
+    }
+
+    fn decoded_orientation(jpeg: &[u8]) -> Orientation {
+        ImageReader::with_format(Cursor::new(jpeg), image::ImageFormat::Jpeg)
+            .into_decoder()
+            .unwrap()
+            .orientation()
+            .unwrap()
+    }
+
+    /// Everything from the first DQT on: tables and entropy-coded data.
+    fn image_data(jpeg: &[u8]) -> &[u8] {
+        let at = jpeg.windows(2).position(|w| w == [0xFF, 0xDB]).unwrap();
+        &jpeg[at..]
+    }
+
+    #[test]
+    fn compose_matches_applying_both_orientations() {
+        for current in ALL {
+            for t in TRANSFORMS {
+                let mut stepwise = asymmetric();
+                stepwise.apply_orientation(current);
+                stepwise.apply_orientation(t.orientation());
+                let mut composed = asymmetric();
+                composed.apply_orientation(compose(current, t));
+                assert_eq!(stepwise, composed, "{current:?} then {t:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn jpeg_orientation_tag_is_rewritten_in_place() {
+        let dir = create_temp_dir();
+        let path = create_jpeg_with_metadata(dir.path(), "a.jpg", 32, 16, Some(6), None);
+        let original = fs::read(&path).unwrap();
+
+        let out = transform_jpeg(&original, Transform::Rotate90).unwrap();
+        assert_eq!(out.len(), original.len());
+        assert_eq!(decoded_orientation(&out), Orientation::Rotate180);
+        assert_eq!(image_data(&out), image_data(&original));
+
+        let back = transform_jpeg(&out, Transform::Rotate270).unwrap();
+        assert_eq!(back, original);
+    }
+
+    #[test]
+    fn jpeg_without_exif_gets_an_orientation_segment() {
+        let original = gradient_jpeg_bytes(32, 16);
+        let out = transform_jpeg(&original, Transform::FlipHorizontal).unwrap();
+        assert_eq!(decoded_orientation(&out), Orientation::FlipHorizontal);
+        assert_eq!(image_data(&out), image_data(&original));
+    }
+
+    #[test]
+    fn exif_without_orientation_keeps_its_other_tags() {
+        use exif::{Field, In, Tag, Value};
+        let make = Field {
+            tag: Tag::Make,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"Spica".to_vec()]),
+        };
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&make);
+        let mut blob = Cursor::new(Vec::new());
+        writer.write(&mut blob, false).unwrap();
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend(blob.into_inner());
+        let jpeg = insert_jpeg_segments(gradient_jpeg_bytes(8, 8), &[(0xE1, payload)]);
+
+        let out = transform_jpeg(&jpeg, Transform::Rotate270).unwrap();
+        assert_eq!(decoded_orientation(&out), Orientation::Rotate270);
+        let parsed = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&out))
+            .unwrap();
+        let field = parsed.get_field(Tag::Make, In::PRIMARY).unwrap();
+        assert_eq!(field.display_value().to_string(), "\"Spica\"");
+    }
+
+    #[test]
+    fn png_is_reencoded_with_the_pixels_moved() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("a.png");
+        asymmetric().save(&path).unwrap();
+
+        let method = transform_file(&path, Transform::Rotate90, false).unwrap();
+        assert_eq!(method, TransformMethod::Reencoded);
+        let mut expected = asymmetric();
+        expected.apply_orientation(Orientation::Rotate90);
+        let rotated = image::open(&path).unwrap();
+        assert_eq!(rotated.dimensions(), (2, 3));
+        assert_eq!(rotated.to_rgb8(), expected.to_rgb8());
+    }
+
+    #[test]
+    fn lossless_webp_needs_no_opt_in() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("a.webp");
+        let mut bytes = Vec::new();
+        asymmetric()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut bytes))
+            .unwrap();
+        fs::write(&path, bytes).unwrap();
+
+        transform_file(&path, Transform::FlipVertical, false).unwrap();
+        let mut expected = asymmetric();
+        expected.apply_orientation(Orientation::FlipVertical);
+        assert_eq!(image::open(&path).unwrap().to_rgb8(), expected.to_rgb8());
+    }
+
+    #[test]
+    fn lossy_webp_is_refused_without_opt_in() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("lossy.webp");
+        let mut bytes = b"RIFF\x14\0\0\0WEBPVP8 \x08\0\0\0".to_vec();
+        bytes.extend_from_slice(&[0; 8]);
+        fs::write(&path, &bytes).unwrap();
+
+        let err = transform_file(&path, Transform::Rotate90, false).unwrap_err();
+        assert!(err.contains("allow_lossy"), "{err}");
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn gif_and_raw_are_refused() {
+        let dir = create_temp_dir();
+        let gif = create_test_gif(dir.path(), "a.gif");
+        assert!(transform_file(&gif, Transform::Rotate90, true).is_err());
+        let nef = create_fake_tiff_raw(dir.path(), "a.nef", 1, 64, 48);
+        assert!(transform_file(&nef, Transform::Rotate90, true).is_err());
+    }
+}
@@ -9,6 +9,7 @@ import {
 } from "react";
 import { FULL_UPGRADE_DEBOUNCE_MS } from "../constants/memory";
 import { IMAGE_LOAD_DEBOUNCE_MS } from "../constants/timing";
+import { useFolderPrefetch } from "../hooks/useFolderPrefetch";
 import { useImagePreloader } from "../hooks/useImagePreloader";
 import { useThumbnailGenerator } from "../hooks/useThumbnailGenerator";
 import { thumbnailToImageData, useAppStore } from "../store";
@@ -56,7 +57,8 @@ const ImageViewer: React.FC<ImageViewerProps> = ({ className = "" }) => {
     setThumbnailDisplayed,
   } = useAppStore();
 
-  // Initialize thumbnail generation and preloading
+  // Initialize cache warming, thumbnail generation and preloading
+  useFolderPrefetch();
   useThumbnailGenerator();
   useImagePreloader();
 
@@ -9,6 +9,7 @@ interface ThumbnailItemProps {
   index: number;
   isActive: boolean;
   onClick: (index: number) => void;
+  /** `/thumb/` URL, "error", or null while not generated yet. */
   thumbnailData: string | null;
 }
 
@@ -24,7 +25,7 @@ const ThumbnailItem: React.FC<ThumbnailItemProps> = memo(
     if (hasData) {
       content = (
         <img
-          src={`data:image/jpeg;base64,${thumbnailData}`}
+          src={thumbnailData}
           alt={image.filename}
           className="thumbnail-image"
         />
@@ -70,7 +71,7 @@ const ThumbnailBar: React.FC = () => {
       if (thumbnail === "error") {
         return "error";
       }
-      return thumbnail.base64;
+      return thumbnail.src;
     },
     [cache.thumbnails],
   );
@@ -57,6 +57,11 @@ vi.mock("../../hooks/useThumbnailGenerator", () => ({
   useThumbnailGenerator: vi.fn(),
 }));
 
+// Mock the useFolderPrefetch hook
+vi.mock("../../hooks/useFolderPrefetch", () => ({
+  useFolderPrefetch: vi.fn(),
+}));
+
 // Mock the useImagePreloader hook
 vi.mock("../../hooks/useImagePreloader", () => ({
   useImagePreloader: vi.fn(),
@@ -117,10 +122,10 @@ vi.mock("../../store", () => {
     // branch is exercised instead of throwing on an undefined import.
     thumbnailToImageData: (
       path: string,
-      thumbnailCache: { base64: string; width: number; height: number },
+      thumbnailCache: { src: string; width: number; height: number },
     ) => ({
       path,
-      src: `data:jpeg;base64,${thumbnailCache.base64}`,
+      src: thumbnailCache.src,
       width: thumbnailCache.width,
       height: thumbnailCache.height,
       format: "jpeg",
@@ -797,7 +802,7 @@ describe("ImageViewer", () => {
       mockStore.currentImage.path = "/test/image.jpg";
       mockStore.currentImage.data = null;
       mockStore.cache.thumbnails.set("/test/image.jpg", {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       });
@@ -817,7 +822,7 @@ describe("ImageViewer", () => {
       expect(mockStore.setImageData.mock.calls[0][0]).toEqual(
         expect.objectContaining({
           path: "/test/image.jpg",
-          src: "data:jpeg;base64,thumbnailBase64",
+          src: "thumbnailSrc",
         }),
       );
 
@@ -844,14 +849,14 @@ describe("ImageViewer", () => {
       mockStore.currentImage.path = "/test/image.jpg";
       mockStore.currentImage.data = {
         path: "/test/image.jpg",
-        src: "data:jpeg;base64,thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
         format: "jpeg",
       };
       mockStore.ui.thumbnailDisplayed = true;
       mockStore.cache.thumbnails.set("/test/image.jpg", {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       });
@@ -883,7 +888,7 @@ describe("ImageViewer", () => {
       mockStore.currentImage.data = null;
       mockStore.ui.thumbnailDisplayed = false;
       mockStore.cache.thumbnails.set("/test/image.jpg", {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       });
@@ -922,7 +927,7 @@ describe("ImageViewer", () => {
       mockStore.currentImage.path = "/test/image.jpg";
       mockStore.currentImage.data = null;
       mockStore.cache.thumbnails.set("/test/image.jpg", {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 3840,
         height: 2160,
       });
@@ -1141,7 +1146,7 @@ describe("ImageViewer", () => {
     const path = "/test/image.jpg";
     const thumbnailData: AppImageData = {
       path,
-      src: "data:jpeg;base64,thumbnailBase64",
+      src: "thumbnailSrc",
       width: 800,
       height: 600,
       format: "jpeg",
@@ -1149,7 +1154,7 @@ describe("ImageViewer", () => {
 
     const cacheThumbnail = () =>
       mockStore.cache.thumbnails.set(path, {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       });
@@ -1329,7 +1334,7 @@ describe("ImageViewer", () => {
       // PHASE 1 is the cached thumbnail, PHASE 2 the preview - the
       // full-resolution original is never fetched.
       expect(mockStore.setImageData.mock.calls[0][0]).toEqual(
-        expect.objectContaining({ src: "data:jpeg;base64,thumbnailBase64" }),
+        expect.objectContaining({ src: "thumbnailSrc" }),
       );
       expect(mockStore.setImageData.mock.calls.at(-1)?.[0]).toEqual(
         expect.objectContaining({ src: PREVIEW_SRC(path), tier: "preview" }),
@@ -1355,7 +1360,7 @@ describe("ImageViewer", () => {
         format: "gif",
       });
       mockStore.cache.thumbnails.set(gifPath, {
-        base64: "gifThumb",
+        src: "gifThumb",
         width: 320,
         height: 240,
       });
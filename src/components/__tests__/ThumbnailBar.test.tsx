@@ -2,7 +2,11 @@ import { describe, it, expect, beforeEach, vi, afterEach } from "vitest";
 import { render, screen, fireEvent, act } from "@testing-library/react";
 import "@testing-library/jest-dom";
 import type { ImageInfo, ImageData as AppImageData } from "../../types";
-import { THUMBNAIL_SCROLL_DEBOUNCE_MS } from "../../constants/timing";
+import {
+  THUMBNAIL_SCROLL_DEBOUNCE_MS,
+  THUMBNAIL_SIZE,
+} from "../../constants/timing";
+import { thumbSrc } from "../../utils/imageSrc";
 
 // Mock ResizeObserver before component imports
 class MockResizeObserver {
@@ -39,7 +43,7 @@ const createDefaultMockStore = () => ({
   cache: {
     thumbnails: new Map<
       string,
-      { base64: string; width: number; height: number } | "error"
+      { src: string; width: number; height: number } | "error"
     >(),
   },
   navigateToImage: vi.fn(),
@@ -88,7 +92,7 @@ describe("ThumbnailBar", () => {
       mockStoreState.folder.images = images;
       mockStoreState.currentImage.index = 0;
       mockStoreState.cache.thumbnails.set("/test/image0.jpg", {
-        base64: "base64data",
+        src: thumbSrc("/test/image0.jpg", THUMBNAIL_SIZE),
         width: 800,
         height: 600,
       });
@@ -96,7 +100,10 @@ describe("ThumbnailBar", () => {
       render(<ThumbnailBar />);
 
       const image = screen.getByRole("img");
-      expect(image).toHaveAttribute("src", "data:image/jpeg;base64,base64data");
+      expect(image).toHaveAttribute(
+        "src",
+        "http://spica-img.localhost/thumb/20/%2Ftest%2Fimage0.jpg",
+      );
       expect(image).toHaveAttribute("alt", "image0.jpg");
     });
 
@@ -275,7 +282,7 @@ describe("ThumbnailBar", () => {
       mockStoreState.currentImage.path = "/test/image0.jpg";
       mockStoreState.currentImage.data = {
         path: "/test/image0.jpg",
-        base64: "data",
+        src: "data",
         width: 1920,
         height: 1080,
         format: "jpeg",
@@ -363,7 +370,7 @@ describe("ThumbnailBar", () => {
 
       // Set different states for thumbnails
       mockStoreState.cache.thumbnails.set("/test/image0.jpg", {
-        base64: "data0",
+        src: "data0",
         width: 800,
         height: 600,
       });
@@ -7,14 +7,19 @@
 export const BITMAP_CACHE_BUDGET_BYTES = 500 * 1024 * 1024;
 
 /**
- * Screen-box buckets for display-resolution previews (design spec D2).
- * Mirrors ALLOWED_PREVIEW_BOXES in src-tauri/src/utils/preview.rs.
+ * Largest scale factor a preview box may carry ("WxH@S").
+ * Mirrors PREVIEW_BOX_MAX_SCALE in src-tauri/src/utils/preview.rs.
  */
-export const PREVIEW_BOXES: ReadonlyArray<readonly [number, number]> = [
-  [1920, 1080],
-  [2560, 1440],
-  [3840, 2160],
-];
+export const PREVIEW_BOX_MAX_SCALE = 4;
+
+/** Box sent when the screen size is unknown. */
+export const FALLBACK_PREVIEW_BOX = [1920, 1080] as const;
+
+/**
+ * Edge in px of a full deep-zoom tile served by the `/tile/` route.
+ * Mirrors TILE_SIZE in src-tauri/src/utils/tiles.rs.
+ */
+export const TILE_SIZE = 512;
 
 /**
  * Width in px of one `.thumbnail-item` including its horizontal margins
@@ -37,11 +37,11 @@ const previewData = (path: string): ImageData => ({
 });
 
 type ThumbnailEntry =
-  | { base64: string; width: number; height: number }
+  | { src: string; width: number; height: number }
   | "error";
 
 const thumbEntry = (): ThumbnailEntry => ({
-  base64: "AAA",
+  src: "AAA",
   width: 30,
   height: 20,
 });
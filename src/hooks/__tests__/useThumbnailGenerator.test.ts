@@ -21,11 +21,6 @@ const createMockImageInfo = (
   ...overrides,
 });
 
-// Mock the invoke function before importing
-vi.mock("@tauri-apps/api/core", () => ({
-  invoke: vi.fn(),
-}));
-
 // Mock the store
 const mockStore = {
   folder: {
@@ -37,7 +32,7 @@ const mockStore = {
   cache: {
     thumbnails: new Map<
       string,
-      { base64: string; width: number; height: number } | "error"
+      { src: string; width: number; height: number } | "error"
     >(),
   },
   setCachedThumbnail: vi.fn(),
@@ -62,9 +57,24 @@ vi.mock("../../store", () => {
 });
 
 import { useThumbnailGenerator } from "../useThumbnailGenerator";
-import { invoke } from "@tauri-apps/api/core";
-
-const mockInvoke = vi.mocked(invoke);
+import { thumbSrc } from "../../utils/imageSrc";
+
+// The generator asks the /thumb/ route for each thumbnail.
+const mockFetch = vi.fn<typeof fetch>();
+
+/** Source path named by a /thumb/<size>/<path> URL. */
+const pathOf = (url: string | URL | Request): string =>
+  decodeURIComponent(new URL(String(url)).pathname.split("/")[3]);
+
+/** What the route answers for a thumbnail of a `width`x`height` source. */
+const thumbResponse = (width = 800, height = 600): Response =>
+  new Response(new Uint8Array([0xff, 0xd8, 0xff, 0xd9]), {
+    headers: {
+      "Content-Type": "image/jpeg",
+      "X-Spica-Natural-Width": String(width),
+      "X-Spica-Natural-Height": String(height),
+    },
+  });
 
 describe("useThumbnailGenerator", () => {
   beforeEach(() => {
@@ -72,12 +82,14 @@ describe("useThumbnailGenerator", () => {
     mockStore.folder.images = [];
     mockStore.currentImage.index = -1;
     mockStore.cache.thumbnails = new Map();
-    mockInvoke.mockClear();
+    mockFetch.mockReset();
+    vi.stubGlobal("fetch", mockFetch);
     vi.useFakeTimers();
   });
 
   afterEach(() => {
     vi.useRealTimers();
+    vi.unstubAllGlobals();
   });
 
   describe("initialization", () => {
@@ -94,7 +106,7 @@ describe("useThumbnailGenerator", () => {
 
       renderHook(() => useThumbnailGenerator());
 
-      expect(mockInvoke).not.toHaveBeenCalled();
+      expect(mockFetch).not.toHaveBeenCalled();
     });
 
     it("should not start generation when no images in folder", () => {
@@ -103,7 +115,7 @@ describe("useThumbnailGenerator", () => {
 
       renderHook(() => useThumbnailGenerator());
 
-      expect(mockInvoke).not.toHaveBeenCalled();
+      expect(mockFetch).not.toHaveBeenCalled();
     });
   });
 
@@ -114,31 +126,19 @@ describe("useThumbnailGenerator", () => {
       );
       mockStore.folder.images = images;
       mockStore.currentImage.index = 2;
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") return null;
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        if (cmd === "set_cached_thumbnail") return null;
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       renderHook(() => useThumbnailGenerator());
 
       // Should not have called immediately
-      expect(mockInvoke).not.toHaveBeenCalled();
+      expect(mockFetch).not.toHaveBeenCalled();
 
       // Fast-forward just before debounce - still should not have called
       await act(async () => {
         vi.advanceTimersByTime(THUMBNAIL_GENERATION_DEBOUNCE_MS - 1);
         await Promise.resolve();
       });
-      expect(mockInvoke).not.toHaveBeenCalled();
+      expect(mockFetch).not.toHaveBeenCalled();
 
       // Fast-forward remaining 1ms - now should start
       await act(async () => {
@@ -147,18 +147,9 @@ describe("useThumbnailGenerator", () => {
         await vi.runAllTimersAsync();
       });
 
-      expect(mockInvoke).toHaveBeenCalled();
-      expect(mockInvoke).toHaveBeenCalledWith(
-        "generate_thumbnail_with_dimensions",
-        {
-          path: expect.any(String),
-          size: THUMBNAIL_SIZE,
-          previewBox: expect.any(String),
-        },
-      );
-      expect(mockInvoke).not.toHaveBeenCalledWith(
-        "set_cached_thumbnail",
-        expect.anything(),
+      expect(mockFetch).toHaveBeenCalled();
+      expect(String(mockFetch.mock.calls[0][0])).toMatch(
+        new RegExp(`/thumb/${THUMBNAIL_SIZE}/[^?]+\\?box=\\d+x\\d+$`),
       );
     });
 
@@ -168,19 +159,7 @@ describe("useThumbnailGenerator", () => {
       );
       mockStore.folder.images = images;
       mockStore.currentImage.index = 2;
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") return null;
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        if (cmd === "set_cached_thumbnail") return null;
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       const { rerender } = renderHook(() => useThumbnailGenerator());
 
@@ -189,7 +168,7 @@ describe("useThumbnailGenerator", () => {
         vi.advanceTimersByTime(250);
         await Promise.resolve();
       });
-      expect(mockInvoke).not.toHaveBeenCalled();
+      expect(mockFetch).not.toHaveBeenCalled();
 
       // Navigate to different image - should reset debounce
       mockStore.currentImage.index = 3;
@@ -200,14 +179,14 @@ describe("useThumbnailGenerator", () => {
         vi.advanceTimersByTime(250);
         await Promise.resolve();
       });
-      expect(mockInvoke).not.toHaveBeenCalled();
+      expect(mockFetch).not.toHaveBeenCalled();
 
       // Complete the new debounce
       await act(async () => {
         vi.advanceTimersByTime(THUMBNAIL_GENERATION_DEBOUNCE_MS);
         await Promise.resolve();
       });
-      expect(mockInvoke).toHaveBeenCalled();
+      expect(mockFetch).toHaveBeenCalled();
     });
   });
 
@@ -220,18 +199,9 @@ describe("useThumbnailGenerator", () => {
       mockStore.currentImage.index = 2;
 
       const callOrder: string[] = [];
-      mockInvoke.mockImplementation(async (cmd, args) => {
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          const typedArgs = args as { path: string };
-          callOrder.push(typedArgs.path);
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
+      mockFetch.mockImplementation(async (url) => {
+        callOrder.push(pathOf(url));
+        return thumbResponse();
       });
 
       renderHook(() => useThumbnailGenerator());
@@ -257,17 +227,9 @@ describe("useThumbnailGenerator", () => {
       mockStore.currentImage.index = 15;
 
       let generateCount = 0;
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          generateCount++;
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
+      mockFetch.mockImplementation(async () => {
+        generateCount++;
+        return thumbResponse();
       });
 
       const consoleLogSpy = vi
@@ -299,12 +261,12 @@ describe("useThumbnailGenerator", () => {
 
       // Pre-cache some thumbnails
       mockStore.cache.thumbnails.set("/test/image2.jpg", {
-        base64: "cached",
+        src: "cached",
         width: 800,
         height: 600,
       });
       mockStore.cache.thumbnails.set("/test/image3.jpg", {
-        base64: "cached",
+        src: "cached",
         width: 800,
         height: 600,
       });
@@ -319,18 +281,9 @@ describe("useThumbnailGenerator", () => {
       });
 
       const generatedPaths: string[] = [];
-      mockInvoke.mockImplementation(async (cmd, args) => {
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          const typedArgs = args as { path: string };
-          generatedPaths.push(typedArgs.path);
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
+      mockFetch.mockImplementation(async (url) => {
+        generatedPaths.push(pathOf(url));
+        return thumbResponse();
       });
 
       renderHook(() => useThumbnailGenerator());
@@ -358,21 +311,13 @@ describe("useThumbnailGenerator", () => {
       let concurrentCalls = 0;
       let maxConcurrentCalls = 0;
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          concurrentCalls++;
-          maxConcurrentCalls = Math.max(maxConcurrentCalls, concurrentCalls);
-          // Simulate some processing time
-          await new Promise((resolve) => setTimeout(resolve, 10));
-          concurrentCalls--;
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
+      mockFetch.mockImplementation(async () => {
+        concurrentCalls++;
+        maxConcurrentCalls = Math.max(maxConcurrentCalls, concurrentCalls);
+        // Simulate some processing time
+        await new Promise((resolve) => setTimeout(resolve, 10));
+        concurrentCalls--;
+        return thumbResponse();
       });
 
       renderHook(() => useThumbnailGenerator());
@@ -388,80 +333,45 @@ describe("useThumbnailGenerator", () => {
     });
   });
 
-  describe("backend cache handling", () => {
-    it("should use cached thumbnail from backend if available with dimensions", async () => {
+  describe("thumbnail route", () => {
+    it("should ask for the thumbnail with the current preview box", async () => {
       const images = [createMockImageInfo(0)];
       mockStore.folder.images = images;
       mockStore.currentImage.index = 0;
-
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") {
-          // Return cached thumbnail with dimensions
-          return ["cachedBase64", 1920, 1080];
-        }
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       renderHook(() => useThumbnailGenerator());
 
-      // Trigger generation
       await act(async () => {
         vi.advanceTimersByTime(THUMBNAIL_GENERATION_DEBOUNCE_MS);
         await vi.runAllTimersAsync();
       });
 
-      expect(mockStore.setCachedThumbnail).toHaveBeenCalledWith(
-        "/test/image0.jpg",
-        { base64: "cachedBase64", width: 1920, height: 1080 },
-      );
-      expect(mockInvoke).toHaveBeenCalledWith("get_cached_thumbnail", {
-        path: "/test/image0.jpg",
-        size: THUMBNAIL_SIZE,
-        previewBox: expect.stringMatching(/^\d+x\d+$/),
-      });
-      // Should not call generate_thumbnail_with_dimensions
-      expect(mockInvoke).not.toHaveBeenCalledWith(
-        "generate_thumbnail_with_dimensions",
-        expect.anything(),
-      );
+      const [url, init] = mockFetch.mock.calls[0];
+      expect(pathOf(url)).toBe("/test/image0.jpg");
+      expect(String(url)).toMatch(/\/thumb\/20\/[^?]+\?box=\d+x\d+$/);
+      expect(init?.signal).toBeInstanceOf(AbortSignal);
     });
 
-    it("should regenerate thumbnail if backend cache lacks dimensions", async () => {
+    it("should cache the thumbnail URL with the natural size from the headers", async () => {
       const images = [createMockImageInfo(0)];
       mockStore.folder.images = images;
       mockStore.currentImage.index = 0;
-
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") {
-          // Return cached thumbnail without dimensions
-          return ["cachedBase64", null, null];
-        }
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "newBase64",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse(1920, 1080));
 
       renderHook(() => useThumbnailGenerator());
 
-      // Trigger generation
       await act(async () => {
         vi.advanceTimersByTime(THUMBNAIL_GENERATION_DEBOUNCE_MS);
         await vi.runAllTimersAsync();
       });
 
-      // Should have regenerated the thumbnail
-      expect(mockInvoke).toHaveBeenCalledWith(
-        "generate_thumbnail_with_dimensions",
+      expect(mockStore.setCachedThumbnail).toHaveBeenCalledWith(
+        "/test/image0.jpg",
         {
-          path: "/test/image0.jpg",
-          size: THUMBNAIL_SIZE,
-          previewBox: expect.any(String),
+          src: thumbSrc("/test/image0.jpg", THUMBNAIL_SIZE),
+          width: 1920,
+          height: 1080,
         },
       );
     });
@@ -477,45 +387,55 @@ describe("useThumbnailGenerator", () => {
         .spyOn(console, "warn")
         .mockImplementation(() => {});
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") {
-          return null;
-        }
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          throw new Error("Failed to generate thumbnail");
-        }
-        if (cmd === "set_cached_thumbnail") {
-          return null;
-        }
-        return null;
-      });
+      // The route has already recorded the failure in the backend cache.
+      mockFetch.mockImplementation(
+        async () => new Response("decode: invalid JPEG", { status: 500 }),
+      );
 
       renderHook(() => useThumbnailGenerator());
 
-      // Trigger generation
       await act(async () => {
         vi.advanceTimersByTime(THUMBNAIL_GENERATION_DEBOUNCE_MS);
         await vi.runAllTimersAsync();
       });
 
-      // Should cache error in backend
-      expect(mockInvoke).toHaveBeenCalledWith("set_cached_thumbnail", {
-        path: "/test/image0.jpg",
-        thumbnail: "error",
-        size: THUMBNAIL_SIZE,
-        width: null,
-        height: null,
+      expect(mockStore.setCachedThumbnail).toHaveBeenCalledWith(
+        "/test/image0.jpg",
+        "error",
+      );
+      expect(consoleWarnSpy).toHaveBeenCalledWith(
+        expect.stringContaining("Failed to generate thumbnail"),
+        "decode: invalid JPEG",
+      );
+
+      consoleWarnSpy.mockRestore();
+    });
+
+    it("should cache error when the request itself fails", async () => {
+      const images = [createMockImageInfo(0)];
+      mockStore.folder.images = images;
+      mockStore.currentImage.index = 0;
+
+      const consoleWarnSpy = vi
+        .spyOn(console, "warn")
+        .mockImplementation(() => {});
+
+      mockFetch.mockRejectedValue(new TypeError("Failed to fetch"));
+
+      renderHook(() => useThumbnailGenerator());
+
+      await act(async () => {
+        vi.advanceTimersByTime(THUMBNAIL_GENERATION_DEBOUNCE_MS);
+        await vi.runAllTimersAsync();
       });
 
-      // Should cache error in frontend
       expect(mockStore.setCachedThumbnail).toHaveBeenCalledWith(
         "/test/image0.jpg",
         "error",
       );
-
       expect(consoleWarnSpy).toHaveBeenCalledWith(
         expect.stringContaining("Failed to generate thumbnail"),
-        expect.any(Error),
+        expect.any(TypeError),
       );
 
       consoleWarnSpy.mockRestore();
@@ -530,18 +450,10 @@ describe("useThumbnailGenerator", () => {
       mockStore.folder.images = images;
       mockStore.currentImage.index = 2;
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          // Simulate slow generation
-          await new Promise((resolve) => setTimeout(resolve, 1000));
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
+      mockFetch.mockImplementation(async () => {
+        // Simulate slow generation
+        await new Promise((resolve) => setTimeout(resolve, 1000));
+        return thumbResponse();
       });
 
       const { rerender } = renderHook(() => useThumbnailGenerator());
@@ -565,7 +477,7 @@ describe("useThumbnailGenerator", () => {
 
       // Previous generation should have been aborted
       // New generation should be in progress
-      expect(mockInvoke).toHaveBeenCalled();
+      expect(mockFetch).toHaveBeenCalled();
     });
 
     it("should cleanup on unmount", async () => {
@@ -575,18 +487,10 @@ describe("useThumbnailGenerator", () => {
       mockStore.folder.images = images;
       mockStore.currentImage.index = 2;
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          // Simulate slow generation
-          await new Promise((resolve) => setTimeout(resolve, 1000));
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        return null;
+      mockFetch.mockImplementation(async () => {
+        // Simulate slow generation
+        await new Promise((resolve) => setTimeout(resolve, 1000));
+        return thumbResponse();
       });
 
       const { unmount } = renderHook(() => useThumbnailGenerator());
@@ -601,10 +505,7 @@ describe("useThumbnailGenerator", () => {
       });
 
       // No thumbnails should have been generated
-      expect(mockInvoke).not.toHaveBeenCalledWith(
-        "generate_thumbnail_with_dimensions",
-        expect.anything(),
-      );
+      expect(mockFetch).not.toHaveBeenCalled();
     });
   });
 
@@ -620,19 +521,7 @@ describe("useThumbnailGenerator", () => {
         .spyOn(console, "log")
         .mockImplementation(() => {});
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") return null;
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        if (cmd === "set_cached_thumbnail") return null;
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       renderHook(() => useThumbnailGenerator());
 
@@ -660,7 +549,7 @@ describe("useThumbnailGenerator", () => {
       // Pre-cache all thumbnails
       for (const img of images) {
         mockStore.cache.thumbnails.set(img.path, {
-          base64: "cached",
+          src: "cached",
           width: 800,
           height: 600,
         });
@@ -700,19 +589,7 @@ describe("useThumbnailGenerator", () => {
       mockStore.folder.images = images;
       mockStore.currentImage.index = 0;
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") return null;
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        if (cmd === "set_cached_thumbnail") return null;
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       renderHook(() => useThumbnailGenerator());
 
@@ -732,19 +609,7 @@ describe("useThumbnailGenerator", () => {
       mockStore.folder.images = images;
       mockStore.currentImage.index = 0;
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") return null;
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        if (cmd === "set_cached_thumbnail") return null;
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       renderHook(() => useThumbnailGenerator());
 
@@ -763,19 +628,7 @@ describe("useThumbnailGenerator", () => {
       mockStore.folder.images = images;
       mockStore.currentImage.index = 0;
 
-      mockInvoke.mockImplementation(async (cmd) => {
-        if (cmd === "get_cached_thumbnail") return null;
-        if (cmd === "generate_thumbnail_with_dimensions") {
-          return {
-            thumbnail_base64: "base64data",
-            original_width: 800,
-            original_height: 600,
-            preview_available: true,
-          };
-        }
-        if (cmd === "set_cached_thumbnail") return null;
-        return null;
-      });
+      mockFetch.mockImplementation(async () => thumbResponse());
 
       renderHook(() => useThumbnailGenerator());
 
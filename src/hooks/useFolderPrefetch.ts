@@ -0,0 +1,104 @@
+import { useEffect, useRef } from "react";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import { useAppStore } from "../store";
+import { THUMBNAIL_SIZE } from "../constants/timing";
+import { currentPreviewBox } from "../utils/previewBox";
+import type { ImageInfo, PrefetchEvent } from "../types";
+
+const currentIndex = () =>
+  Math.max(0, useAppStore.getState().currentImage.index);
+
+const startPrefetch = async (images: ImageInfo[]) => {
+  const onEvent = new Channel<PrefetchEvent>();
+  onEvent.onmessage = (message) => {
+    if (message.event === "finished") {
+      const { done, total, cancelled } = message.data;
+      console.log(
+        `Folder prefetch ${cancelled ? "cancelled" : "finished"}: ${done}/${total}`,
+      );
+    }
+  };
+  try {
+    // Replaces (and cancels) the previous folder's prefetch in Rust.
+    await invoke("prefetch_folder", {
+      paths: images.map((image) => image.path),
+      currentIndex: currentIndex(),
+      size: THUMBNAIL_SIZE,
+      previewBox: currentPreviewBox(),
+      onEvent,
+    });
+  } catch (error) {
+    console.warn("Failed to start folder prefetch:", error);
+  }
+};
+
+const updatePrefetch = async (images: ImageInfo[]) => {
+  try {
+    await invoke("update_prefetch_paths", {
+      paths: images.map((image) => image.path),
+      currentIndex: currentIndex(),
+    });
+  } catch (error) {
+    console.warn("Failed to update folder prefetch:", error);
+  }
+};
+
+/**
+ * Warms the disk cache for the whole folder in Rust (prefetch_folder):
+ * thumbnail + preview of every image, neighbours of the current image first.
+ * A new folder replaces the previous prefetch; watcher adds/removes and
+ * re-sorts edit the running one (update_prefetch_paths) and navigation moves
+ * its priority, so the thumbnail bar mostly finds cache hits.
+ */
+export const useFolderPrefetch = () => {
+  const { folder, currentImage } = useAppStore();
+  // The image list the running prefetch was last given (null: none started
+  // for this folder), and the last call to Rust: each call waits for the one
+  // before, so an update never overtakes its start or lands on a later one.
+  const sent = useRef<ImageInfo[] | null>(null);
+  const pending = useRef<Promise<void>>(Promise.resolve());
+
+  useEffect(() => {
+    const { images } = useAppStore.getState().folder;
+    sent.current = null;
+    if (folder.path === "" || images.length === 0) {
+      return;
+    }
+    sent.current = images;
+    pending.current = pending.current.then(() => startPrefetch(images));
+  }, [folder.path]);
+
+  useEffect(() => {
+    const images = folder.images;
+    if (sent.current === images || images.length === 0) {
+      return;
+    }
+    const started = sent.current !== null;
+    sent.current = images;
+    pending.current = pending.current.then(() =>
+      started ? updatePrefetch(images) : startPrefetch(images),
+    );
+  }, [folder.images]);
+
+  useEffect(() => {
+    if (currentImage.index === -1) {
+      return;
+    }
+    const follow = async () => {
+      try {
+        await invoke("set_prefetch_index", { index: currentImage.index });
+      } catch (error) {
+        console.warn("Failed to move folder prefetch:", error);
+      }
+    };
+    void follow();
+  }, [currentImage.index]);
+
+  // Cancelling on every folder change could land after the next
+  // prefetch_folder; only unmount stops the workers outright.
+  useEffect(() => {
+    return () => {
+      invoke("cancel_prefetch").catch(() => {});
+    };
+  }, []);
+};
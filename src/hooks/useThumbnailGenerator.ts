@@ -1,5 +1,4 @@
 import { useEffect, useRef, useCallback } from "react";
-import { invoke } from "@tauri-apps/api/core";
 import { useAppStore } from "../store";
 import {
   THUMBNAIL_GENERATION_DEBOUNCE_MS,
@@ -10,7 +9,7 @@ import {
 } from "../constants/timing";
 import { getFilename } from "../utils/path";
 import { currentPreviewBox } from "../utils/previewBox";
-import type { ThumbnailWithDimensions } from "../types";
+import { thumbSrc } from "../utils/imageSrc";
 
 /**
  * Hook for centralized thumbnail generation with priority queue
@@ -46,45 +45,36 @@ export const useThumbnailGenerator = () => {
       try {
         setThumbnailGeneration({ currentGeneratingPath: imagePath });
 
-        const previewBox = currentPreviewBox();
-
-        // First, try to get from backend cache (thumbnail + matching preview)
-        const cachedThumbnail = await invoke<
-          [string, number | null, number | null] | null
-        >("get_cached_thumbnail", {
-          path: imagePath,
-          size: THUMBNAIL_SIZE,
-          previewBox,
-        });
-
-        if (signal.aborted) return false;
-
-        if (cachedThumbnail) {
-          const [base64, width, height] = cachedThumbnail;
-          // Only use cached thumbnail if it has dimensions
-          if (width !== null && height !== null) {
-            setCachedThumbnail(imagePath, { base64, width, height });
-            return true;
-          }
-          // If cached entry lacks dimensions, regenerate
-        }
-
-        // Generate thumbnail + preview from one decode; the command writes
-        // both to the disk cache before returning (I1), so no write-back here.
-        const result = await invoke<ThumbnailWithDimensions>(
-          "generate_thumbnail_with_dimensions",
-          { path: imagePath, size: THUMBNAIL_SIZE, previewBox },
+        // One request answers from the backend cache or generates thumbnail
+        // + preview from one decode, written to disk before it returns (I1).
+        // The response carries the natural size; the bar shows the bytes
+        // through the same route.
+        const response = await fetch(
+          thumbSrc(imagePath, THUMBNAIL_SIZE, currentPreviewBox()),
+          { signal },
         );
 
+        if (!response.ok) {
+          const message = await response.text();
+          if (signal.aborted) return false;
+          // The backend remembers the failure until the file changes, a
+          // source over the decode limits included.
+          console.warn(
+            `Failed to generate thumbnail for ${getFilename(imagePath)}:`,
+            message,
+          );
+          setCachedThumbnail(imagePath, "error");
+          return false;
+        }
         if (signal.aborted) return false;
 
         setCachedThumbnail(imagePath, {
-          base64: result.thumbnail_base64,
-          width: result.original_width,
-          height: result.original_height,
+          src: thumbSrc(imagePath, THUMBNAIL_SIZE),
+          width: Number(response.headers.get("X-Spica-Natural-Width")),
+          height: Number(response.headers.get("X-Spica-Natural-Height")),
         });
 
-        console.log(`Generated thumbnail: ${getFilename(imagePath)}`);
+        console.log(`Thumbnail ready: ${getFilename(imagePath)}`);
         return true;
       } catch (error) {
         if (!signal.aborted) {
@@ -92,21 +82,8 @@ export const useThumbnailGenerator = () => {
             `Failed to generate thumbnail for ${getFilename(imagePath)}:`,
             error,
           );
-
-          // Cache error to avoid retry
-          try {
-            await invoke("set_cached_thumbnail", {
-              path: imagePath,
-              thumbnail: "error",
-              size: THUMBNAIL_SIZE,
-              width: null,
-              height: null,
-            });
-            // Get fresh setCachedThumbnail in case it changed
-            useAppStore.getState().setCachedThumbnail(imagePath, "error");
-          } catch (cacheErr) {
-            console.warn("Failed to cache thumbnail error:", cacheErr);
-          }
+          // Not retried this session.
+          useAppStore.getState().setCachedThumbnail(imagePath, "error");
         }
         return false;
       } finally {
@@ -1457,7 +1457,7 @@ describe("AppStore", () => {
       const { setCachedThumbnail } = useAppStore.getState();
 
       setCachedThumbnail("/test/image.jpg", {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 1920,
         height: 1080,
       });
@@ -1465,7 +1465,7 @@ describe("AppStore", () => {
       const state = useAppStore.getState();
       const thumbnail = state.cache.thumbnails.get("/test/image.jpg");
       expect(thumbnail).toEqual({
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 1920,
         height: 1080,
       });
@@ -1487,7 +1487,7 @@ describe("AppStore", () => {
 
       // Add thumbnail first
       setCachedThumbnail("/test/image.jpg", {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       });
@@ -1506,7 +1506,7 @@ describe("AppStore", () => {
       const { setCachedThumbnail, removeCachedThumbnails } =
         useAppStore.getState();
 
-      const thumb = { base64: "data", width: 800, height: 600 };
+      const thumb = { src: "data", width: 800, height: 600 };
       setCachedThumbnail("/test/a.jpg", thumb);
       setCachedThumbnail("/test/b.jpg", thumb);
       setCachedThumbnail("/test/c.jpg", thumb);
@@ -1527,7 +1527,7 @@ describe("AppStore", () => {
         useAppStore.getState();
 
       setCachedThumbnail("/test/a.jpg", {
-        base64: "data",
+        src: "data",
         width: 800,
         height: 600,
       });
@@ -1544,7 +1544,7 @@ describe("AppStore", () => {
       const initialMap = useAppStore.getState().cache.thumbnails;
 
       setCachedThumbnail("/test/image.jpg", {
-        base64: "data",
+        src: "data",
         width: 800,
         height: 600,
       });
@@ -1573,7 +1573,7 @@ describe("AppStore", () => {
 
     it("should display cached thumbnail instantly when navigating", () => {
       const cachedThumbnail = {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       };
@@ -1599,7 +1599,7 @@ describe("AppStore", () => {
       // Should have data immediately from thumbnail
       expect(state.currentImage.data).not.toBeNull();
       expect(state.currentImage.data?.src).toBe(
-        "data:jpeg;base64,thumbnailBase64",
+        "thumbnailSrc",
       );
       expect(state.currentImage.data?.width).toBe(800);
       expect(state.currentImage.data?.height).toBe(600);
@@ -1608,7 +1608,7 @@ describe("AppStore", () => {
 
     it("should set thumbnailDisplayed flag when using thumbnail", () => {
       const cachedThumbnail = {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       };
@@ -1668,7 +1668,7 @@ describe("AppStore", () => {
         src: "data:jpeg;base64,fullResBase64",
       };
       const cachedThumbnail = {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 800,
         height: 600,
       };
@@ -1724,7 +1724,7 @@ describe("AppStore", () => {
 
     it("should calculate fit-to-window zoom for thumbnail dimensions", () => {
       const cachedThumbnail = {
-        base64: "thumbnailBase64",
+        src: "thumbnailSrc",
         width: 3840, // Large image
         height: 2160,
       };
@@ -1813,7 +1813,7 @@ describe("AppStore", () => {
       // Set up folder with thumbnails
       setFolderImages("/test/folder1", mockImageList);
       setCachedThumbnail("/test/image1.jpg", {
-        base64: "data",
+        src: "data",
         width: 800,
         height: 600,
       });
@@ -1833,7 +1833,7 @@ describe("AppStore", () => {
       // Set up folder with thumbnails
       setFolderImages("/test/folder", mockImageList);
       setCachedThumbnail("/test/image1.jpg", {
-        base64: "data",
+        src: "data",
         width: 800,
         height: 600,
       });
@@ -51,10 +51,10 @@ const calculateFitToWindowZoom = (
 
 export const thumbnailToImageData = (
   path: string,
-  thumbnailCache: { base64: string; width: number; height: number },
+  thumbnailCache: { src: string; width: number; height: number },
 ): ImageData => ({
   path,
-  src: `data:jpeg;base64,${thumbnailCache.base64}`,
+  src: thumbnailCache.src,
   width: thumbnailCache.width,
   height: thumbnailCache.height,
   format: "jpeg",
@@ -94,7 +94,7 @@ interface AppActions {
   removePreloadedImages: (paths: readonly string[]) => void;
   setCachedThumbnail: (
     path: string,
-    thumbnail: { base64: string; width: number; height: number } | "error",
+    thumbnail: { src: string; width: number; height: number } | "error",
   ) => void;
   removeCachedThumbnail: (path: string) => void;
   removeCachedThumbnails: (paths: readonly string[]) => void;
@@ -866,17 +866,8 @@ export const useAppStore = create<AppStore>((set, get) => ({
         },
       }));
 
-      const { open } = await import("@tauri-apps/plugin-dialog");
-
-      const selected = await open({
-        multiple: false,
-        filters: [
-          {
-            name: "Images",
-            extensions: ["jpg", "jpeg", "png", "webp", "gif"],
-          },
-        ],
-      });
+      // Picked in Rust so the folder joins the spica-img scope.
+      const selected = await invoke<string | null>("open_image_dialog");
 
       if (selected && typeof selected === "string") {
         // Use openImageFromPath to handle the rest
@@ -3,12 +3,41 @@ export interface ImageInfo {
   filename: string;
   size: number;
   modified: number;
-  format: "jpeg" | "png" | "webp" | "gif";
+  format:
+    | "jpeg"
+    | "png"
+    | "webp"
+    | "gif"
+    | "heic"
+    | "heif"
+    | "avif"
+    | "cr2"
+    | "cr3"
+    | "nef"
+    | "nrw"
+    | "arw"
+    | "dng";
+  /** Set when the file's extension disagrees with its sniffed content. */
+  format_mismatch?: FormatMismatch;
+  /** EXIF DateTimeOriginal (Unix seconds); only set when sorted by date taken. */
+  date_taken?: number;
+}
+
+// Order for get_folder_images; names compare naturally ("IMG_2" < "IMG_10")
+export interface SortSpec {
+  key?: "name" | "modified" | "size" | "format" | "date_taken";
+  descending?: boolean;
+}
+
+export interface FormatMismatch {
+  /** Lower-cased extension, null for an extensionless file. */
+  extension: string | null;
+  detected: string;
 }
 
 export interface ImageData {
   path: string;
-  /** Renderable URL: a data: URL (thumbnails / legacy pipeline) or a spica-img protocol URL */
+  /** Renderable spica-img protocol URL (/thumb/, /preview/ or full resolution) */
   src: string;
   width: number;
   height: number;
@@ -24,11 +53,144 @@ export interface ProgressiveImageData {
   is_high_resolution: boolean;
 }
 
-export interface ThumbnailWithDimensions {
-  thumbnail_base64: string;
-  original_width: number;
-  original_height: number;
-  preview_available: boolean;
+/** Result of the `get_image_metadata` command (info panel). */
+export interface ImageMetadata {
+  exif: ExifMetadata | null;
+  xmp: XmpMetadata | null;
+  iptc: IptcMetadata | null;
+  /** Description of the embedded ICC profile. */
+  icc_profile: string | null;
+}
+
+export interface ExifMetadata {
+  /** ISO 8601, with the UTC offset when the file records one. */
+  date_taken: string | null;
+  make: string | null;
+  model: string | null;
+  lens_make: string | null;
+  lens_model: string | null;
+  software: string | null;
+  artist: string | null;
+  copyright: string | null;
+  /** "1/250", "2.5" */
+  exposure_time: string | null;
+  f_number: number | null;
+  iso: number | null;
+  focal_length: number | null;
+  focal_length_35mm: number | null;
+  exposure_bias: number | null;
+  flash_fired: boolean | null;
+  orientation: number | null;
+  pixel_width: number | null;
+  pixel_height: number | null;
+  gps: GpsPosition | null;
+  fields: ExifField[];
+}
+
+export interface GpsPosition {
+  latitude: number;
+  longitude: number;
+  altitude: number | null;
+}
+
+export interface ExifField {
+  ifd: string;
+  tag: string;
+  value: string;
+}
+
+export interface XmpMetadata {
+  title: string | null;
+  description: string | null;
+  creator: string[];
+  rights: string | null;
+  keywords: string[];
+  rating: number | null;
+  label: string | null;
+  create_date: string | null;
+  date_created: string | null;
+  headline: string | null;
+  city: string | null;
+  state: string | null;
+  country: string | null;
+}
+
+export interface IptcMetadata {
+  object_name: string | null;
+  headline: string | null;
+  caption: string | null;
+  keywords: string[];
+  by_line: string[];
+  credit: string | null;
+  source: string | null;
+  copyright_notice: string | null;
+  city: string | null;
+  province_state: string | null;
+  country: string | null;
+  date_created: string | null;
+}
+
+// Clockwise rotations, as seen on screen (transform_image)
+export type ImageTransform =
+  | "rotate90"
+  | "rotate180"
+  | "rotate270"
+  | "flip_horizontal"
+  | "flip_vertical";
+
+// "exif_orientation": JPEG tag rewrite, pixels untouched; "reencoded": PNG/WebP
+export type TransformMethod = "exif_orientation" | "reencoded";
+
+// scan_folder options; max_depth counts levels below the folder (null = unlimited)
+export interface ScanOptions {
+  recursive?: boolean;
+  max_depth?: number | null;
+  include_hidden?: boolean;
+}
+
+// Messages on the scan_folder channel; "finished" is always the last one
+export type ScanEvent =
+  | { event: "batch"; data: { images: ImageInfo[] } }
+  | { event: "finished"; data: { total: number; cancelled: boolean } };
+
+// Messages on the prefetch_folder channel; "finished" is always the last one
+export type PrefetchEvent =
+  | {
+      event: "progress";
+      data: { path: string; ok: boolean; done: number; total: number };
+    }
+  | {
+      event: "finished";
+      data: { done: number; total: number; cancelled: boolean };
+    };
+
+// Folder watch events: "image-added" and "image-modified" carry an ImageInfo
+export interface ImageRemovedPayload {
+  path: string;
+}
+
+export interface ImageRenamedPayload {
+  from: string;
+  image: ImageInfo;
+}
+
+export type FileOperation =
+  | { kind: "trashed"; path: string }
+  | { kind: "renamed"; from: string; to: string }
+  | { kind: "moved"; from: string; to: string }
+  | { kind: "copied"; from: string; to: string };
+
+export interface UndoneOperation {
+  operation: FileOperation;
+  image: ImageInfo | null;
+}
+
+export interface DuplicateMember extends ImageInfo {
+  distance: number;
+}
+
+export interface DuplicateGroup {
+  images: DuplicateMember[];
 }
 
 export interface UIState {
@@ -79,7 +241,7 @@ export interface AppState {
   cache: {
     thumbnails: Map<
       string,
-      { base64: string; width: number; height: number } | "error"
+      { src: string; width: number; height: number } | "error"
     >;
     preloaded: Map<string, ImageData>;
     imageViewStates: Map<string, ImageViewState>;
@@ -126,7 +288,7 @@ export interface ThumbnailGenerationState {
 export interface CacheState {
   thumbnails: Map<
     string,
-    { base64: string; width: number; height: number } | "error"
+    { src: string; width: number; height: number } | "error"
   >;
   preloaded: Map<string, ImageData>;
   imageViewStates: Map<string, ImageViewState>;
@@ -1,5 +1,5 @@
 import { describe, expect, it } from "vitest";
-import { imageFormat, imageSrc, previewSrc } from "../imageSrc";
+import { imageFormat, imageSrc, previewSrc, thumbSrc } from "../imageSrc";
 
 describe("imageSrc", () => {
   it("builds a spica-img URL with the path fully encoded", () => {
@@ -23,4 +23,13 @@ describe("imageSrc", () => {
       "http://spica-img.localhost/preview/1920x1080/C%3A%5Cpics%5Ca%20b.jpg",
     );
   });
+
+  it("builds thumbnail URLs under /thumb/<size>/", () => {
+    expect(thumbSrc("C:\\pics\\a b.jpg", 20)).toBe(
+      "http://spica-img.localhost/thumb/20/C%3A%5Cpics%5Ca%20b.jpg",
+    );
+    expect(thumbSrc("C:\\pics\\a.jpg", 20, "1920x1080")).toBe(
+      "http://spica-img.localhost/thumb/20/C%3A%5Cpics%5Ca.jpg?box=1920x1080",
+    );
+  });
 });
@@ -14,6 +14,16 @@ export const imageSrc = (path: string): string =>
 export const previewSrc = (path: string, box: string): string =>
   `${IMAGE_PROTOCOL_ORIGIN}/preview/${box}/${encodeURIComponent(path)}`;
 
+/**
+ * URL for a cached thumbnail of `size` px, served as raw bytes by the Rust
+ * `/thumb/<size>/<path>` route; the natural size is in the
+ * `X-Spica-Natural-Width`/`-Height` headers. With `box`, the route also makes
+ * the preview for that box if it is missing (thumbnail implies preview).
+ */
+export const thumbSrc = (path: string, size: number, box?: string): string =>
+  `${IMAGE_PROTOCOL_ORIGIN}/thumb/${size}/${encodeURIComponent(path)}` +
+  (box ? `?box=${box}` : "");
+
 export const imageFormat = (path: string): string => {
   const name = path.split(/[\\/]/).pop() ?? "";
   const dot = name.lastIndexOf(".");
@@ -1,28 +1,28 @@
-import { PREVIEW_BOXES } from "../constants/memory";
+import {
+  FALLBACK_PREVIEW_BOX,
+  PREVIEW_BOX_MAX_SCALE,
+} from "../constants/memory";
 
 /**
- * "WxH" of the smallest preview bucket that contains the screen in physical
- * pixels (CSS size × devicePixelRatio), oriented like the screen. fit-to-window
- * never exceeds the screen, so a preview fitted into this box is never upscaled.
+ * "WxH@S" preview box for the screen: its CSS size and the scale factor
+ * (devicePixelRatio), "@S" omitted at 1. Rust normalizes the physical size
+ * into a bounded set of cache keys; fit-to-window never exceeds the screen,
+ * so a preview fitted into this box is never upscaled.
  */
 export const previewBoxForScreen = (
   width: number,
   height: number,
   dpr: number,
 ): string => {
-  const scale = dpr > 0 ? dpr : 1;
-  const w = Math.ceil(
-    (Number.isFinite(width) ? Math.max(0, width) : 0) * scale,
-  );
-  const h = Math.ceil(
-    (Number.isFinite(height) ? Math.max(0, height) : 0) * scale,
-  );
-  const long = Math.max(w, h);
-  const short = Math.min(w, h);
-  const [bl, bs] =
-    PREVIEW_BOXES.find(([l, s]) => l >= long && s >= short) ??
-    PREVIEW_BOXES[PREVIEW_BOXES.length - 1];
-  return h > w ? `${bs}x${bl}` : `${bl}x${bs}`;
+  const scale =
+    Number.isFinite(dpr) && dpr > 0 ? Math.min(dpr, PREVIEW_BOX_MAX_SCALE) : 1;
+  const w = Math.ceil(Number.isFinite(width) ? Math.max(0, width) : 0);
+  const h = Math.ceil(Number.isFinite(height) ? Math.max(0, height) : 0);
+  if (w === 0 || h === 0) {
+    const [long, short] = FALLBACK_PREVIEW_BOX;
+    return h > w ? `${short}x${long}` : `${long}x${short}`;
+  }
+  return scale === 1 ? `${w}x${h}` : `${w}x${h}@${scale}`;
 };
 
 let sessionPreviewBox: string | null = null;
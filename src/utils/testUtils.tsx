@@ -96,7 +96,7 @@ export const createMockStore = (overrides: Record<string, unknown> = {}) => ({
   cache: {
     thumbnails: new Map<
       string,
-      { base64: string; width: number; height: number } | "error"
+      { src: string; width: number; height: number } | "error"
     >(),
     preloaded: new Map(),
     imageViewStates: new Map(),